@@ -0,0 +1,443 @@
+use tracing::{debug, info};
+use crate::display::Rect;
+use crate::error::CaptureError;
+
+/// Scroll positions this close to 100% count as the bottom, some apps stop a fraction short
+const BOTTOM_TOLERANCE: f64 = 0.5;
+
+/// How far one scroll step goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollStep {
+    Page,
+    Line,
+}
+
+/// The scrolling pane under a point of the screen, as the platform accessibility API sees it:
+/// UI Automation on Windows, AT-SPI2 on Linux and the Accessibility API on macOS
+#[derive(Clone)]
+pub struct ScrollPane {
+    target: platform::Target,
+}
+
+impl ScrollPane {
+    /// The pane under the center of `region`, if the app there exposes a vertical scroll position
+    pub fn at(region: &Rect) -> Option<Self> {
+        let (x, y) = (region.x + region.width as i32 / 2, region.y + region.height as i32 / 2);
+        let pane = platform::find(x, y).map(|target| Self { target });
+        match pane.as_ref().and_then(|pane| pane.vertical_percent()) {
+            Some(percent) => {
+                info!("Found a scroll pane under the region at {:.1}%", percent);
+                pane
+            }
+            None => {
+                debug!("No scroll position exposed under the region");
+                None
+            }
+        }
+    }
+
+    /// Vertical scroll position, 0 at the top and 100 at the bottom.
+    /// `None` when the pane is gone or its content fits without scrolling.
+    pub fn vertical_percent(&self) -> Option<f64> {
+        platform::vertical_percent(&self.target)
+    }
+
+    pub fn at_bottom(&self) -> bool {
+        self.vertical_percent().is_some_and(|percent| percent >= 100.0 - BOTTOM_TOLERANCE)
+    }
+
+    /// Scroll down by `count` steps
+    pub fn scroll_down(&self, step: ScrollStep, count: u32) -> Result<(), CaptureError> {
+        platform::scroll_down(&self.target, step, count)
+    }
+}
+
+fn pane_gone() -> CaptureError {
+    CaptureError::InvalidState("the scroll pane under the region is gone".to_string())
+}
+
+/// UI Automation, through the ScrollPattern of the element under the point or its nearest ancestor
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED};
+    use windows::Win32::UI::Accessibility::{
+        CUIAutomation, IUIAutomation, IUIAutomationScrollPattern, ScrollAmount_LargeIncrement, ScrollAmount_NoAmount,
+        ScrollAmount_SmallIncrement, UIA_ScrollPatternId,
+    };
+    use super::{pane_gone, ScrollStep};
+    use crate::error::CaptureError;
+
+    /// Ancestors searched for a scrolling one, the element under the point is usually a line of text or an item
+    const MAX_DEPTH: usize = 16;
+
+    /// Only the point is kept and the element looked up again on every call: that costs a few ms,
+    /// but COM objects are tied to their apartment and the capture loop hops threads
+    #[derive(Clone)]
+    pub struct Target {
+        point: POINT,
+    }
+
+    pub fn find(x: i32, y: i32) -> Option<Target> {
+        let target = Target { point: POINT { x, y } };
+        scroll_pattern(&target).map(|_| target)
+    }
+
+    pub fn vertical_percent(target: &Target) -> Option<f64> {
+        let pattern = scroll_pattern(target)?;
+        let percent = unsafe { pattern.CurrentVerticalScrollPercent() }.ok()?;
+        // UIA_ScrollPatternNoScroll (-1) when the content fits
+        (percent >= 0.0).then_some(percent)
+    }
+
+    pub fn scroll_down(target: &Target, step: ScrollStep, count: u32) -> Result<(), CaptureError> {
+        let pattern = scroll_pattern(target).ok_or_else(pane_gone)?;
+        let amount = match step {
+            ScrollStep::Page => ScrollAmount_LargeIncrement,
+            ScrollStep::Line => ScrollAmount_SmallIncrement,
+        };
+        for _ in 0..count {
+            unsafe { pattern.Scroll(ScrollAmount_NoAmount, amount) }
+                .map_err(|e| CaptureError::Internal(format!("UI Automation scroll failed: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn scroll_pattern(target: &Target) -> Option<IUIAutomationScrollPattern> {
+        unsafe {
+            // Harmless when the thread is already initialized, and never undone: these are pool threads
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let automation: IUIAutomation = CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok()?;
+            let walker = automation.ControlViewWalker().ok()?;
+            let mut element = automation.ElementFromPoint(target.point).ok()?;
+            // Our own windows are never what the user wants to scroll
+            if element.CurrentProcessId().ok()? as u32 == std::process::id() {
+                return None;
+            }
+            for _ in 0..MAX_DEPTH {
+                if let Ok(pattern) = element.GetCurrentPatternAs::<IUIAutomationScrollPattern>(UIA_ScrollPatternId) {
+                    if pattern.CurrentVerticallyScrollable().is_ok_and(|scrollable| scrollable.as_bool()) {
+                        return Some(pattern);
+                    }
+                }
+                element = walker.GetParentElement(&element).ok()?;
+            }
+            None
+        }
+    }
+}
+
+/// AT-SPI2 over D-Bus, through the vertical scroll bar of the scroll pane around the deepest
+/// accessible under the point. Needs screen coordinates, so X11 only: Wayland doesn't tell
+/// apps where their windows are.
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Type, Value};
+    use super::{pane_gone, ScrollStep};
+    use crate::error::CaptureError;
+
+    const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+    const COMPONENT: &str = "org.a11y.atspi.Component";
+    const VALUE: &str = "org.a11y.atspi.Value";
+    const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+    const REGISTRY: &str = "org.a11y.atspi.Registry";
+    const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+    const NULL_PATH: &str = "/org/a11y/atspi/null";
+
+    const ROLE_SCROLL_BAR: u32 = 48;
+    const ROLE_SCROLL_PANE: u32 = 49;
+    const STATE_ACTIVE: u32 = 1;
+    const STATE_VERTICAL: u32 = 29;
+    /// ATSPI_COORD_TYPE_SCREEN
+    const COORD_SCREEN: u32 = 0;
+    /// Levels searched down from a window and back up from the accessible under the point
+    const MAX_DEPTH: usize = 32;
+    /// Scroll bar units per line step. GTK and Qt scroll bars count in pixels.
+    const LINE_UNITS: f64 = 40.0;
+
+    /// An accessible: the bus name of its app and its object path
+    type Object = (String, OwnedObjectPath);
+
+    /// Unlike the other platforms the pane is found once, a lookup is dozens of D-Bus calls
+    #[derive(Clone)]
+    pub struct Target {
+        connection: Connection,
+        pane: Object,
+        scroll_bar: Object,
+    }
+
+    pub fn find(x: i32, y: i32) -> Option<Target> {
+        let connection = connect()?;
+        let registry = (REGISTRY.to_string(), OwnedObjectPath::try_from(ROOT_PATH).ok()?);
+        let apps: Vec<Object> = call(&connection, &registry, ACCESSIBLE, "GetChildren", &())?;
+
+        // No stacking order in AT-SPI, the active window wins over others under the point
+        let mut hits = Vec::new();
+        for app in &apps {
+            let windows: Vec<Object> = call(&connection, app, ACCESSIBLE, "GetChildren", &()).unwrap_or_default();
+            for window in windows {
+                if call::<_, bool>(&connection, &window, COMPONENT, "Contains", &(x, y, COORD_SCREEN)) == Some(true) {
+                    let active = has_state(&connection, &window, STATE_ACTIVE);
+                    hits.push((active, window));
+                }
+            }
+        }
+        hits.sort_by_key(|(active, _)| !active);
+
+        hits.into_iter().find_map(|(_, window)| {
+            let deepest = descend(&connection, window, x, y);
+            let (pane, scroll_bar) = scroll_pane_around(&connection, deepest)?;
+            Some(Target { connection: connection.clone(), pane, scroll_bar })
+        })
+    }
+
+    pub fn vertical_percent(target: &Target) -> Option<f64> {
+        let (current, min, max) = range(target)?;
+        (max > min).then(|| ((current - min) / (max - min) * 100.0).clamp(0.0, 100.0))
+    }
+
+    pub fn scroll_down(target: &Target, step: ScrollStep, count: u32) -> Result<(), CaptureError> {
+        let (current, _, max) = range(target).ok_or_else(pane_gone)?;
+        let distance = match step {
+            // A page is the height of the pane, in the same pixels as the scroll bar
+            ScrollStep::Page => extents(&target.connection, &target.pane).map(|(_, _, _, height)| height as f64).ok_or_else(pane_gone)?,
+            ScrollStep::Line => LINE_UNITS,
+        };
+        let value = (current + distance * count as f64).min(max);
+        call::<_, ()>(&target.connection, &target.scroll_bar, PROPERTIES, "Set", &(VALUE, "CurrentValue", Value::from(value)))
+            .ok_or_else(|| CaptureError::Internal("AT-SPI refused to set the scroll position".to_string()))
+    }
+
+    /// The accessibility bus is separate from the session bus, which only tells us its address
+    fn connect() -> Option<Connection> {
+        let session = Connection::session().ok()?;
+        let bus = ("org.a11y.Bus".to_string(), OwnedObjectPath::try_from("/org/a11y/bus").ok()?);
+        let address: String = call(&session, &bus, "org.a11y.Bus", "GetAddress", &())?;
+        zbus::blocking::connection::Builder::address(address.as_str()).ok()?.build().ok()
+    }
+
+    /// Follow the children under the point down as far as they go
+    fn descend(connection: &Connection, mut object: Object, x: i32, y: i32) -> Object {
+        for _ in 0..MAX_DEPTH {
+            match call::<_, Object>(connection, &object, COMPONENT, "GetAccessibleAtPoint", &(x, y, COORD_SCREEN)) {
+                Some(child) if child.1.as_str() != NULL_PATH && child != object => object = child,
+                _ => break,
+            }
+        }
+        object
+    }
+
+    /// The nearest scroll pane at or above `object` with a vertical scroll bar, and that scroll bar
+    fn scroll_pane_around(connection: &Connection, mut object: Object) -> Option<(Object, Object)> {
+        for _ in 0..MAX_DEPTH {
+            if call::<_, u32>(connection, &object, ACCESSIBLE, "GetRole", &()) == Some(ROLE_SCROLL_PANE) {
+                let children: Vec<Object> = call(connection, &object, ACCESSIBLE, "GetChildren", &()).unwrap_or_default();
+                let scroll_bar = children.into_iter().find(|child| {
+                    call::<_, u32>(connection, child, ACCESSIBLE, "GetRole", &()) == Some(ROLE_SCROLL_BAR)
+                        && has_state(connection, child, STATE_VERTICAL)
+                });
+                if let Some(scroll_bar) = scroll_bar {
+                    return Some((object, scroll_bar));
+                }
+            }
+            let parent = Object::try_from(property(connection, &object, ACCESSIBLE, "Parent")?).ok()?;
+            if parent.1.as_str() == NULL_PATH {
+                return None;
+            }
+            object = parent;
+        }
+        None
+    }
+
+    /// Current, minimum and maximum value of the scroll bar
+    fn range(target: &Target) -> Option<(f64, f64, f64)> {
+        let value = |name| f64::try_from(property(&target.connection, &target.scroll_bar, VALUE, name)?).ok();
+        Some((value("CurrentValue")?, value("MinimumValue")?, value("MaximumValue")?))
+    }
+
+    fn extents(connection: &Connection, object: &Object) -> Option<(i32, i32, i32, i32)> {
+        call(connection, object, COMPONENT, "GetExtents", &(COORD_SCREEN,))
+    }
+
+    fn has_state(connection: &Connection, object: &Object, state: u32) -> bool {
+        call::<_, Vec<u32>>(connection, object, ACCESSIBLE, "GetState", &())
+            .and_then(|words| words.get((state / 32) as usize).copied())
+            .is_some_and(|word| word & (1 << (state % 32)) != 0)
+    }
+
+    fn property(connection: &Connection, object: &Object, interface: &str, name: &str) -> Option<OwnedValue> {
+        call(connection, object, PROPERTIES, "Get", &(interface, name))
+    }
+
+    fn call<B, R>(connection: &Connection, object: &Object, interface: &str, method: &str, body: &B) -> Option<R>
+    where
+        B: serde::Serialize + DynamicType,
+        R: for<'de> serde::Deserialize<'de> + Type,
+    {
+        let reply = connection
+            .call_method(Some(object.0.as_str()), object.1.as_str(), Some(interface), method, body)
+            .ok()?;
+        let body = reply.body();
+        body.deserialize::<R>().ok()
+    }
+}
+
+/// The Accessibility API, through the vertical scroll bar of the nearest AXScrollArea around
+/// the element under the point. Needs the app to be allowed under Privacy & Security > Accessibility.
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::c_void;
+    use std::ptr;
+    use core_foundation::array::{CFArray, CFArrayRef};
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::{CFString, CFStringRef};
+    use super::{pane_gone, ScrollStep};
+    use crate::display;
+    use crate::error::CaptureError;
+
+    type AXUIElementRef = CFTypeRef;
+
+    /// kAXValueCGSizeType
+    const AX_VALUE_CG_SIZE: u32 = 2;
+    /// Ancestors searched for a scroll area, the element under the point is usually text or a cell
+    const MAX_DEPTH: usize = 16;
+    /// Points per line step
+    const LINE_POINTS: f64 = 40.0;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> bool;
+        fn AXUIElementCreateSystemWide() -> AXUIElementRef;
+        fn AXUIElementCopyElementAtPosition(element: AXUIElementRef, x: f32, y: f32, found: *mut AXUIElementRef) -> i32;
+        fn AXUIElementCopyAttributeValue(element: AXUIElementRef, attribute: CFStringRef, value: *mut CFTypeRef) -> i32;
+        fn AXUIElementSetAttributeValue(element: AXUIElementRef, attribute: CFStringRef, value: CFTypeRef) -> i32;
+        fn AXUIElementGetPid(element: AXUIElementRef, pid: *mut i32) -> i32;
+        fn AXValueGetValue(value: CFTypeRef, value_type: u32, out: *mut c_void) -> bool;
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)] // Filled in by AXValueGetValue, only the height is read
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    /// Only the point is kept (in points, not pixels) and the element looked up again on every
+    /// call, AX elements aren't `Send`
+    #[derive(Clone)]
+    pub struct Target {
+        x: f32,
+        y: f32,
+    }
+
+    pub fn find(x: i32, y: i32) -> Option<Target> {
+        if !unsafe { AXIsProcessTrusted() } {
+            tracing::debug!("No accessibility access, can't read scroll positions");
+            return None;
+        }
+        // Global AX coordinates are points, our physical pixels divided by the monitor's scale
+        let scale = display::list_monitors().ok()?
+            .into_iter()
+            .map(|(_, info)| info)
+            .find(|info| x >= info.x && x < info.x + info.width as i32 && y >= info.y && y < info.y + info.height as i32)
+            .map(|info| info.scale_factor)
+            .unwrap_or(1.0);
+        let target = Target { x: x as f32 / scale, y: y as f32 / scale };
+        scroll_area(&target).map(|_| target)
+    }
+
+    pub fn vertical_percent(target: &Target) -> Option<f64> {
+        let (_, scroll_bar) = scroll_area(target)?;
+        Some(number(&attribute(&scroll_bar, "AXValue")?)? * 100.0)
+    }
+
+    pub fn scroll_down(target: &Target, step: ScrollStep, count: u32) -> Result<(), CaptureError> {
+        let (area, scroll_bar) = scroll_area(target).ok_or_else(pane_gone)?;
+        let current = attribute(&scroll_bar, "AXValue").and_then(|value| number(&value)).ok_or_else(pane_gone)?;
+
+        // The scroll bar goes from 0 to 1 over the part of the content that is out of view
+        let visible = height(&area).ok_or_else(pane_gone)?;
+        let content = attribute(&area, "AXContents")
+            .and_then(|contents| first(&contents))
+            .and_then(|document| height(&document))
+            .ok_or_else(pane_gone)?;
+        let hidden = content - visible;
+        if hidden <= 0.0 {
+            return Ok(());
+        }
+        let distance = match step {
+            ScrollStep::Page => visible,
+            ScrollStep::Line => LINE_POINTS,
+        };
+        let value = CFNumber::from((current + distance * count as f64 / hidden).min(1.0));
+        let name = CFString::new("AXValue");
+        let error = unsafe { AXUIElementSetAttributeValue(scroll_bar.as_CFTypeRef(), name.as_concrete_TypeRef(), value.as_CFTypeRef()) };
+        if error != 0 {
+            return Err(CaptureError::Internal(format!("setting the scroll position failed (AXError {})", error)));
+        }
+        Ok(())
+    }
+
+    /// The nearest scroll area around the point that has a vertical scroll bar, and that scroll bar
+    fn scroll_area(target: &Target) -> Option<(CFType, CFType)> {
+        let mut element = unsafe {
+            let system = CFType::wrap_under_create_rule(AXUIElementCreateSystemWide());
+            let mut found = ptr::null();
+            if AXUIElementCopyElementAtPosition(system.as_CFTypeRef(), target.x, target.y, &mut found) != 0 || found.is_null() {
+                return None;
+            }
+            CFType::wrap_under_create_rule(found)
+        };
+
+        // Our own windows are never what the user wants to scroll
+        let mut pid = 0;
+        if unsafe { AXUIElementGetPid(element.as_CFTypeRef(), &mut pid) } != 0 || pid as u32 == std::process::id() {
+            return None;
+        }
+
+        for _ in 0..MAX_DEPTH {
+            let role = attribute(&element, "AXRole").and_then(|role| role.downcast::<CFString>()).map(|role| role.to_string());
+            if role.as_deref() == Some("AXScrollArea") {
+                if let Some(scroll_bar) = attribute(&element, "AXVerticalScrollBar") {
+                    return Some((element, scroll_bar));
+                }
+            }
+            element = attribute(&element, "AXParent")?;
+        }
+        None
+    }
+
+    fn attribute(element: &CFType, name: &str) -> Option<CFType> {
+        let name = CFString::new(name);
+        let mut value = ptr::null();
+        unsafe {
+            if AXUIElementCopyAttributeValue(element.as_CFTypeRef(), name.as_concrete_TypeRef(), &mut value) != 0 || value.is_null() {
+                return None;
+            }
+            Some(CFType::wrap_under_create_rule(value))
+        }
+    }
+
+    fn number(value: &CFType) -> Option<f64> {
+        value.downcast::<CFNumber>()?.to_f64()
+    }
+
+    fn first(array: &CFType) -> Option<CFType> {
+        if !array.instance_of::<CFArray>() {
+            return None;
+        }
+        let array: CFArray = unsafe { CFArray::wrap_under_get_rule(array.as_CFTypeRef() as CFArrayRef) };
+        let item = array.get(0)?;
+        Some(unsafe { CFType::wrap_under_get_rule(*item) })
+    }
+
+    fn height(element: &CFType) -> Option<f64> {
+        let value = attribute(element, "AXSize")?;
+        let mut size = CGSize::default();
+        unsafe { AXValueGetValue(value.as_CFTypeRef(), AX_VALUE_CG_SIZE, &mut size as *mut CGSize as *mut c_void) }
+            .then_some(size.height)
+    }
+}
@@ -0,0 +1,267 @@
+use chrono::Local;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use tar::{Archive, Builder, EntryType, Header};
+use tauri::AppHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::encryption;
+use crate::error::CaptureError;
+use crate::history::{self, HistoryEntry};
+use crate::search;
+
+/// Bumped when the manifest changes in a way older versions can't read
+const ARCHIVE_VERSION: u32 = 1;
+/// First file of every archive
+const MANIFEST_NAME: &str = "manifest.json";
+const IMAGES_DIR: &str = "images";
+/// Largest file taken from an archive, the size comes from the archive itself
+const MAX_ENTRY_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// `manifest.json` of a history archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    version: u32,
+    /// RFC 3339 local time
+    exported_at: String,
+    entries: Vec<ArchivedEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedEntry {
+    /// `path` is the image's name in the archive, "images/<file>"
+    #[serde(flatten)]
+    entry: HistoryEntry,
+    /// Recognized text, if the entry was indexed for `search_history`
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Result of `export_history` and `import_history`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSummary {
+    /// Entries written to, or added from, the archive
+    pub entries: usize,
+    /// Entries left out: missing images on export, ones already in the history on import
+    pub skipped: usize,
+}
+
+/// Write the whole history (images, notes, tags and recognized text) to `path` as a gzipped tar
+/// archive, for `import_history` on another machine. Images are stored decrypted, the capture
+/// key stays in this machine's keychain.
+#[tauri::command]
+pub async fn export_history(app: AppHandle, path: String) -> Result<ArchiveSummary, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || export(&app, Path::new(&path)))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("export task failed: {}", e)))?
+}
+
+/// Add the entries of an archive made by `export_history` to the history. Entries that are
+/// already there (same id) are skipped, so importing the same archive twice is harmless.
+#[tauri::command]
+pub async fn import_history(app: AppHandle, path: String) -> Result<ArchiveSummary, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || import(&app, Path::new(&path)))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("import task failed: {}", e)))?
+}
+
+fn export(app: &AppHandle, path: &Path) -> Result<ArchiveSummary, CaptureError> {
+    let mut texts = search::indexed_texts(app)?;
+    let mut skipped = 0;
+    let mut entries: Vec<ArchivedEntry> = Vec::new();
+    for entry in history::list_history(app.clone(), None, None)? {
+        let Some(name) = Path::new(&entry.path).file_name().map(|name| name.to_string_lossy().to_string()) else {
+            continue;
+        };
+        if !Path::new(&entry.path).exists() {
+            warn!("Leaving history entry {} out of the archive, {} is missing", entry.id, entry.path);
+            skipped += 1;
+            continue;
+        }
+        let text = texts.remove(&entry.id);
+        let path = format!("{}/{}", IMAGES_DIR, name);
+        entries.push(ArchivedEntry { entry: HistoryEntry { path, ..entry }, text });
+    }
+
+    let manifest = Manifest { version: ARCHIVE_VERSION, exported_at: Local::now().to_rfc3339(), entries };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    let history_dir = history::history_dir(app)?;
+
+    let mut tar = Builder::new(GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default()));
+    write_file(&mut tar, MANIFEST_NAME, &json)?;
+    for archived in &manifest.entries {
+        let file_name = archived.entry.path.trim_start_matches(&format!("{}/", IMAGES_DIR));
+        let png = encryption::read(&history_dir.join(file_name))?;
+        write_file(&mut tar, &archived.entry.path, &png)?;
+    }
+    tar.into_inner()?.finish()?.flush()?;
+
+    info!("Exported {} history entries to {}", manifest.entries.len(), path.display());
+    Ok(ArchiveSummary { entries: manifest.entries.len(), skipped })
+}
+
+fn import(app: &AppHandle, path: &Path) -> Result<ArchiveSummary, CaptureError> {
+    let mut archive = Archive::new(GzDecoder::new(BufReader::new(File::open(path)?)));
+    let mut tar = archive.entries()?;
+    let manifest = match read_file(&mut tar)? {
+        Some((name, json)) if name == MANIFEST_NAME => parse_manifest(&json)?,
+        _ => return Err(CaptureError::InvalidState(format!("{} is not a history archive", path.display()))),
+    };
+
+    let existing: Vec<String> = history::list_history(app.clone(), None, None)?.into_iter().map(|entry| entry.id).collect();
+    let (known, new): (Vec<ArchivedEntry>, Vec<ArchivedEntry>) = manifest.entries
+        .into_iter()
+        .partition(|archived| existing.contains(&archived.entry.id));
+    let mut pending: HashMap<String, ArchivedEntry> = new.into_iter().map(|archived| (archived.entry.path.clone(), archived)).collect();
+
+    let history_dir = history::history_dir(app)?;
+    fs::create_dir_all(&history_dir)?;
+    let mut imported: Vec<HistoryEntry> = Vec::new();
+    let mut texts: HashMap<String, String> = HashMap::new();
+    while let Some((name, png)) = read_file(&mut tar)? {
+        let Some(archived) = pending.remove(&name) else {
+            continue;
+        };
+        let file_name = Path::new(&name).file_name().map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| CaptureError::InvalidState(format!("invalid file name in the archive: {}", name)))?;
+        // Names carry the capture time and part of the id, a clash means a different capture
+        let mut target = history_dir.join(&file_name);
+        if target.exists() {
+            target = history_dir.join(format!("{}.png", archived.entry.id));
+        }
+        fs::write(&target, encryption::protect(png)?)?;
+        if let Some(text) = archived.text {
+            texts.insert(archived.entry.id.clone(), text);
+        }
+        imported.push(HistoryEntry { path: target.display().to_string(), ..archived.entry });
+    }
+    for missing in pending.values() {
+        warn!("History archive lists {} but does not contain it", missing.entry.path);
+    }
+
+    let count = imported.len();
+    history::add_entries(app, imported)?;
+    search::import_texts(app, texts)?;
+    info!("Imported {} history entries from {}", count, path.display());
+    Ok(ArchiveSummary { entries: count, skipped: known.len() })
+}
+
+/// Read and check `manifest.json`
+fn parse_manifest(json: &[u8]) -> Result<Manifest, CaptureError> {
+    let manifest: Manifest = serde_json::from_slice(json)
+        .map_err(|e| CaptureError::InvalidState(format!("the archive's manifest is invalid: {}", e)))?;
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(CaptureError::InvalidState(format!(
+            "the archive is from a newer version of ScrollSnap (format {}), update to import it",
+            manifest.version
+        )));
+    }
+
+    // Ids end up in file names, an archive could otherwise write outside the history folder
+    if let Some(archived) = manifest.entries.iter().find(|archived| Uuid::parse_str(&archived.entry.id).is_err()) {
+        return Err(CaptureError::InvalidState(format!("the archive's manifest has an invalid id: {}", archived.entry.id)));
+    }
+    Ok(manifest)
+}
+
+/// Append a regular file to a tar archive
+fn write_file(tar: &mut Builder<impl Write>, name: &str, content: &[u8]) -> Result<(), CaptureError> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Local::now().timestamp().max(0) as u64);
+    tar.append_data(&mut header, name, content)?;
+    Ok(())
+}
+
+/// The next regular file of a tar archive as name and content, `None` at the end.
+/// Other entries (folders, links) are skipped.
+fn read_file(tar: &mut tar::Entries<impl Read>) -> Result<Option<(String, Vec<u8>)>, CaptureError> {
+    for entry in tar.by_ref() {
+        let entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let size = entry.size();
+        if size > MAX_ENTRY_SIZE {
+            return Err(CaptureError::InvalidState(format!("archive entry {} is too large ({} bytes)", name, size)));
+        }
+        // Grows with what is actually there, a truncated archive doesn't get the full size allocated
+        let mut content = Vec::new();
+        entry.take(size).read_to_end(&mut content)?;
+        if (content.len() as u64) < size {
+            return Err(CaptureError::InvalidState(format!("archive entry {} is cut off", name)));
+        }
+        return Ok(Some((name, content)));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest_with_id(id: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "version": ARCHIVE_VERSION,
+            "exportedAt": "2024-01-31T23:59:59+01:00",
+            "entries": [{
+                "id": id,
+                "path": "images/capture.png",
+                "width": 10,
+                "height": 10,
+                "createdAt": "2024-01-31T23:59:59+01:00",
+                "source": "capture",
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_files() {
+        let mut tar = Builder::new(Vec::new());
+        write_file(&mut tar, MANIFEST_NAME, b"{}").unwrap();
+        write_file(&mut tar, "images/capture.png", &[7; 1000]).unwrap();
+        let bytes = tar.into_inner().unwrap();
+
+        let mut archive = Archive::new(&bytes[..]);
+        let mut entries = archive.entries().unwrap();
+        assert_eq!(read_file(&mut entries).unwrap(), Some((MANIFEST_NAME.to_string(), b"{}".to_vec())));
+        assert_eq!(read_file(&mut entries).unwrap(), Some(("images/capture.png".to_string(), vec![7; 1000])));
+        assert_eq!(read_file(&mut entries).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_entries() {
+        // Only the header, the size it claims is what has to be refused
+        let mut header = Header::new_gnu();
+        header.set_path("images/huge.png").unwrap();
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(MAX_ENTRY_SIZE + 1);
+        header.set_cksum();
+        let bytes = header.as_bytes().to_vec();
+
+        let mut archive = Archive::new(&bytes[..]);
+        let mut entries = archive.entries().unwrap();
+        assert!(matches!(read_file(&mut entries), Err(CaptureError::InvalidState(_))));
+    }
+
+    #[test]
+    fn rejects_ids_that_are_not_uuids() {
+        assert!(parse_manifest(&manifest_with_id("0f8fad5b-d9cb-469f-a165-70867728950e")).is_ok());
+        for id in ["../../evil", "", "0f8fad5b-d9cb-469f-a165-70867728950e/.."] {
+            assert!(matches!(parse_manifest(&manifest_with_id(id)), Err(CaptureError::InvalidState(_))), "{}", id);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use tracing::info;
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::store::{self, ImageSize};
+
+/// Max per-channel difference for a pixel to still count as margin color (anti-aliasing, dithering)
+const TOLERANCE: i32 = 8;
+
+/// Finds the content of an image by trimming rows and columns that only contain the margin color.
+/// The margin color is taken from the top-left pixel. Rows are fed one at a time, so a canvas
+/// that was spilled to disk can be scanned without loading it whole.
+pub struct BorderScanner {
+    width: u32,
+    background: Option<[u8; 4]>,
+    rows: u32,
+    top: Option<u32>,
+    bottom: u32,
+    left: u32,
+    right: u32,
+}
+
+impl BorderScanner {
+    pub fn new(width: u32) -> Self {
+        Self { width, background: None, rows: 0, top: None, bottom: 0, left: width, right: 0 }
+    }
+
+    /// Feed raw RGBA pixels, any number of whole rows
+    pub fn push_rows(&mut self, rows: &[u8]) {
+        let row_bytes = self.width as usize * 4;
+        if row_bytes == 0 {
+            return;
+        }
+        for row in rows.chunks_exact(row_bytes) {
+            self.push_row(row);
+        }
+    }
+
+    fn push_row(&mut self, row: &[u8]) {
+        let y = self.rows;
+        self.rows += 1;
+
+        let background = *self.background.get_or_insert([row[0], row[1], row[2], row[3]]);
+        let is_content = |px: &[u8]| px.iter().zip(background).any(|(a, b)| (*a as i32 - b as i32).abs() > TOLERANCE);
+
+        let Some(first) = row.chunks_exact(4).position(is_content) else {
+            // Margin row
+            return;
+        };
+        let last = row.chunks_exact(4).rposition(is_content).unwrap_or(first);
+
+        self.top.get_or_insert(y);
+        self.bottom = y;
+        self.left = self.left.min(first as u32);
+        self.right = self.right.max(last as u32);
+    }
+
+    /// Bounds of the content, `None` if the whole image is margin color
+    pub fn finish(&self) -> Option<Rect> {
+        let top = self.top?;
+        Some(Rect {
+            x: self.left as i32,
+            y: top as i32,
+            width: self.right - self.left + 1,
+            height: self.bottom - top + 1,
+        })
+    }
+}
+
+/// Trim uniform-color margins (window chrome, empty page gutters) from the capture `id`
+#[tauri::command]
+pub async fn autocrop(id: String) -> Result<ImageSize, CaptureError> {
+    store::edit(id, |canvas| {
+        match canvas.content_bounds()? {
+            Some(bounds) => {
+                info!("Auto-crop: {}x{} -> {:?}", canvas.width(), canvas.height(), bounds);
+                *canvas = canvas.cropped(bounds)?;
+            }
+            None => info!("Auto-crop: no uniform margins found"),
+        }
+        Ok(ImageSize::of(canvas))
+    })
+    .await
+}
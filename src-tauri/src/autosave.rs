@@ -0,0 +1,300 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::display::Rect;
+use crate::encryption::FileCipher;
+use crate::error::CaptureError;
+use crate::history;
+use crate::store::{self, CaptureResult};
+use crate::utils;
+
+const AUTOSAVE_DIR: &str = "autosave";
+const MANIFEST_FILE: &str = "session.json";
+const ROWS_FILE: &str = "canvas.rgba";
+
+/// Rows read back at a time when recovering, so huge captures go through the canvas' own spilling
+const RECOVER_CHUNK_ROWS: usize = 2048;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    /// Checkpoint running captures so they can be recovered after a crash
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval_secs: 10 }
+    }
+}
+
+/// What the last checkpoint of an interrupted capture holds, returned by `get_recoverable_session`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverableSession {
+    pub session_id: String,
+    /// Physical desktop pixels
+    pub region: Rect,
+    pub width: u32,
+    /// Rows in the rows file, the canvas height at the last checkpoint
+    pub height: u32,
+    pub stitch_count: u32,
+    /// RFC 3339
+    pub started_at: String,
+    /// RFC 3339, time of the last checkpoint
+    pub saved_at: String,
+    /// Hex id of the rows file when it is encrypted, see `FileCipher`
+    #[serde(default)]
+    pub rows_cipher: Option<String>,
+}
+
+/// End of an encrypted strip of rows in the rows file
+struct Strip {
+    end_row: u32,
+    end_offset: u64,
+}
+
+/// Checkpoints of the running capture in `<app data>/autosave/`: the canvas as raw RGBA rows,
+/// only ever appended to (or cut back after an undo), and a manifest written after the rows.
+/// With encryption on, the rows file is a series of strips instead, one per checkpoint, each a
+/// length and the rows sealed with a `FileCipher` whose id is in the manifest.
+/// A clean finish or cancel removes the folder, so one that is still there at launch
+/// belongs to a capture that never finished.
+pub struct Autosave {
+    dir: PathBuf,
+    rows: File,
+    cipher: Option<FileCipher>,
+    strips: Vec<Strip>,
+    manifest: RecoverableSession,
+    interval: Duration,
+    last_checkpoint: Instant,
+}
+
+impl Autosave {
+    /// Start checkpointing a capture, replacing whatever an earlier one left behind.
+    /// Failing is not fatal, the capture just runs without it.
+    pub fn create(app: &AppHandle, session_id: &str, region: Rect, width: u32) -> Option<Self> {
+        let settings = crate::settings::current().autosave;
+        if !settings.enabled {
+            return None;
+        }
+        let result = autosave_dir(app).and_then(|dir| {
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir)?;
+            let cipher = FileCipher::create()?;
+            let rows = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(dir.join(ROWS_FILE))?;
+            let now = Local::now().to_rfc3339();
+            let manifest = RecoverableSession {
+                session_id: session_id.to_string(),
+                region,
+                width,
+                height: 0,
+                stitch_count: 0,
+                started_at: now.clone(),
+                saved_at: now,
+                rows_cipher: cipher.as_ref().map(FileCipher::id_hex),
+            };
+            Ok(Self {
+                dir,
+                rows,
+                cipher,
+                strips: Vec::new(),
+                manifest,
+                interval: Duration::from_secs(settings.interval_secs.max(1)),
+                // The first checkpoint is due right away
+                last_checkpoint: Instant::now() - Duration::from_secs(settings.interval_secs.max(1)),
+            })
+        });
+
+        match result {
+            Ok(autosave) => Some(autosave),
+            Err(e) => {
+                warn!("Could not create autosave directory: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Write the rows added (or drop the rows removed) since the last checkpoint,
+    /// if `interval_secs` passed since then
+    pub fn checkpoint(&mut self, canvas: &mut Canvas, stitch_count: u32) {
+        if self.last_checkpoint.elapsed() < self.interval {
+            return;
+        }
+        self.last_checkpoint = Instant::now();
+        if let Err(e) = self.write(canvas, stitch_count) {
+            warn!("Autosave checkpoint failed: {}", e);
+        }
+    }
+
+    /// Forget rows an undo took off the canvas. Runs right away: if the canvas grew back
+    /// before the next checkpoint, that one couldn't tell the old rows from the new ones.
+    pub fn truncate(&mut self, height: u32) {
+        if height >= self.manifest.height {
+            return;
+        }
+        let (height, len) = match self.cipher {
+            None => (height, height as u64 * self.manifest.width as u64 * 4),
+            // Strips are sealed whole, one that the cut goes through is written again from the canvas
+            Some(_) => {
+                let kept = self.strips.iter().take_while(|strip| strip.end_row <= height).count();
+                self.strips.truncate(kept);
+                self.strips.last().map_or((0, 0), |strip| (strip.end_row, strip.end_offset))
+            }
+        };
+        if let Err(e) = self.rows.set_len(len) {
+            warn!("Autosave truncate failed: {}", e);
+        }
+        self.manifest.height = height;
+    }
+
+    fn write(&mut self, canvas: &mut Canvas, stitch_count: u32) -> Result<(), CaptureError> {
+        self.truncate(canvas.height());
+        let height = canvas.height();
+        if height > self.manifest.height {
+            let rows = canvas.rows_since(self.manifest.height)?;
+            match &self.cipher {
+                None => {
+                    self.rows.seek(SeekFrom::Start(self.manifest.height as u64 * canvas.width() as u64 * 4))?;
+                    self.rows.write_all(&rows)?;
+                }
+                Some(cipher) => {
+                    let offset = self.strips.last().map_or(0, |strip| strip.end_offset);
+                    let sealed = cipher.seal(self.manifest.height as u64, &rows)?;
+                    self.rows.seek(SeekFrom::Start(offset))?;
+                    self.rows.write_all(&(sealed.len() as u64).to_le_bytes())?;
+                    self.rows.write_all(&sealed)?;
+                    self.strips.push(Strip { end_row: height, end_offset: offset + 8 + sealed.len() as u64 });
+                }
+            }
+        }
+        self.rows.sync_data()?;
+
+        self.manifest.height = height;
+        self.manifest.stitch_count = stitch_count;
+        self.manifest.saved_at = Local::now().to_rfc3339();
+        let json = serde_json::to_vec_pretty(&self.manifest).map_err(|e| CaptureError::Internal(e.to_string()))?;
+        utils::write_atomic(&self.dir.join(MANIFEST_FILE), &json)
+    }
+
+    /// The capture ended normally, nothing to recover
+    pub fn discard(self) {
+        let Self { dir, rows, .. } = self;
+        drop(rows);
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("Could not remove autosave {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// The capture that was still running when the app last exited, if it got to a checkpoint
+#[tauri::command]
+pub fn get_recoverable_session(app: AppHandle) -> Option<RecoverableSession> {
+    let dir = autosave_dir(&app).ok()?;
+    read_manifest(&dir).filter(|session| session.height > 0)
+}
+
+/// Store the interrupted capture, as far as it got, to open it in the editor
+#[tauri::command]
+pub async fn recover_session(app: AppHandle) -> Result<CaptureResult, CaptureError> {
+    let dir = autosave_dir(&app)?;
+    let session = read_manifest(&dir)
+        .ok_or_else(|| CaptureError::InvalidState("there is no interrupted capture to recover".to_string()))?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut canvas = Canvas::empty(session.width);
+        let mut file = File::open(dir.join(ROWS_FILE))?;
+        match session.rows_cipher.as_deref() {
+            Some(id) => recover_strips(&mut file, &FileCipher::resume(id)?, &session, &mut canvas)?,
+            // Rows put there in place of the encrypted ones would otherwise be taken as they are
+            None if crate::settings::current().encryption.enabled => {
+                return Err(CaptureError::EncryptionFailed("the interrupted capture was saved unencrypted".to_string()));
+            }
+            None => recover_rows(&mut file, &session, &mut canvas)?,
+        }
+
+        info!("Recovered interrupted capture {} ({}x{})", session.session_id, canvas.width(), canvas.height());
+        let result = store::insert(canvas, None)?;
+        history::save_capture_in_background(app, result.id.clone(), "recovered");
+        let _ = fs::remove_dir_all(&dir);
+        Ok(result)
+    })
+    .await
+    .map_err(|e| CaptureError::Internal(format!("recover task failed: {}", e)))?
+}
+
+/// Throw the interrupted capture away
+#[tauri::command]
+pub fn discard_recovered_session(app: AppHandle) -> Result<(), CaptureError> {
+    let dir = autosave_dir(&app)?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+fn recover_rows(file: &mut File, session: &RecoverableSession, canvas: &mut Canvas) -> Result<(), CaptureError> {
+    let row_bytes = session.width as usize * 4;
+    // An undo after the last checkpoint cuts the file shorter than the manifest says
+    let rows_on_disk = (file.metadata()?.len() / row_bytes.max(1) as u64) as usize;
+    let mut chunk = vec![0u8; RECOVER_CHUNK_ROWS * row_bytes];
+    let mut remaining = (session.height as usize).min(rows_on_disk);
+    while remaining > 0 {
+        let rows = remaining.min(RECOVER_CHUNK_ROWS);
+        file.read_exact(&mut chunk[..rows * row_bytes])?;
+        canvas.push_rows(&chunk[..rows * row_bytes])?;
+        remaining -= rows;
+    }
+    Ok(())
+}
+
+/// Read the strips of an encrypted rows file in order, each must start where the last one ended
+fn recover_strips(file: &mut File, cipher: &FileCipher, session: &RecoverableSession, canvas: &mut Canvas) -> Result<(), CaptureError> {
+    let row_bytes = session.width as u64 * 4;
+    let mut left = file.metadata()?.len();
+    // Like above, an undo may have cut strips the manifest still counts
+    while canvas.height() < session.height && left >= 8 {
+        let mut len = [0u8; 8];
+        file.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        left -= 8;
+        if len > left || len < FileCipher::OVERHEAD as u64 {
+            return Err(CaptureError::EncryptionFailed("an encrypted strip of the interrupted capture is truncated".to_string()));
+        }
+        let mut sealed = vec![0u8; len as usize];
+        file.read_exact(&mut sealed)?;
+        left -= len;
+        let rows = cipher.open(canvas.height() as u64, &sealed)?;
+        if row_bytes == 0 || !(rows.len() as u64).is_multiple_of(row_bytes) {
+            return Err(CaptureError::EncryptionFailed("an encrypted strip does not hold whole rows".to_string()));
+        }
+        let keep = ((session.height - canvas.height()) as u64 * row_bytes).min(rows.len() as u64) as usize;
+        canvas.push_rows(&rows[..keep])?;
+    }
+    Ok(())
+}
+
+fn read_manifest(dir: &Path) -> Option<RecoverableSession> {
+    let content = fs::read(dir.join(MANIFEST_FILE)).ok()?;
+    match serde_json::from_slice(&content) {
+        Ok(session) => Some(session),
+        Err(e) => {
+            warn!("Ignoring invalid autosave manifest: {}", e);
+            None
+        }
+    }
+}
+
+fn autosave_dir(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(AUTOSAVE_DIR))
+        .map_err(|e| CaptureError::Internal(e.to_string()))
+}
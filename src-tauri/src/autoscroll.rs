@@ -0,0 +1,88 @@
+use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+use serde::{Deserialize, Serialize};
+use crate::accessibility::{ScrollPane, ScrollStep};
+use crate::error::CaptureError;
+
+/// Scroll by sending key presses to the focused window between captures, instead of waiting
+/// for the user. Meant for PDF viewers and other apps that page with the keyboard but
+/// ignore (or smooth-scroll) wheel events. The window being captured needs keyboard focus.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoScroll {
+    pub key: ScrollKey,
+    /// Presses per step. More than one page per step only works while pages are shorter
+    /// than half the capture region, otherwise fragments stop overlapping.
+    pub repeat: u32,
+    /// Time for the app to finish scrolling and repaint before the next capture
+    pub settle_delay_ms: u64,
+}
+
+impl Default for AutoScroll {
+    fn default() -> Self {
+        Self { key: ScrollKey::PageDown, repeat: 1, settle_delay_ms: 400 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollKey {
+    #[default]
+    PageDown,
+    Space,
+    DownArrow,
+}
+
+impl ScrollKey {
+    fn key(self) -> Key {
+        match self {
+            ScrollKey::PageDown => Key::PageDown,
+            ScrollKey::Space => Key::Space,
+            ScrollKey::DownArrow => Key::DownArrow,
+        }
+    }
+
+    /// The same distance for scrolling through the accessibility API
+    fn step(self) -> ScrollStep {
+        match self {
+            ScrollKey::PageDown | ScrollKey::Space => ScrollStep::Page,
+            ScrollKey::DownArrow => ScrollStep::Line,
+        }
+    }
+}
+
+/// One scroll step: through the pane's scroll position when the app exposes one, which also
+/// works without keyboard focus, otherwise with key presses to the focused window
+pub fn step(options: &AutoScroll, pane: Option<&ScrollPane>) -> Result<(), CaptureError> {
+    match pane {
+        Some(pane) => pane.scroll_down(options.key.step(), options.repeat.max(1)),
+        None => press(options),
+    }
+}
+
+/// Send one scroll step to the focused window
+fn press(options: &AutoScroll) -> Result<(), CaptureError> {
+    let mut enigo = Enigo::new(&Settings::default())
+        .map_err(|e| CaptureError::Internal(format!("keyboard input is unavailable: {}", e)))?;
+    for _ in 0..options.repeat.max(1) {
+        enigo
+            .key(options.key.key(), Direction::Click)
+            .map_err(|e| CaptureError::Internal(format!("failed to send {:?}: {}", options.key, e)))?;
+    }
+    Ok(())
+}
+
+impl std::str::FromStr for ScrollKey {
+    type Err = CaptureError;
+
+    /// Same names as in the settings, for the command line
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "page-down" => Ok(ScrollKey::PageDown),
+            "space" => Ok(ScrollKey::Space),
+            "down-arrow" => Ok(ScrollKey::DownArrow),
+            other => Err(CaptureError::InvalidState(format!(
+                "unknown scroll key '{}', use page-down, space or down-arrow", other
+            ))),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::error::CaptureError;
+use crate::settings;
+
+/// Command line flag for starting with only the tray icon, see `instance::starts_hidden`
+pub const HIDDEN_FLAG: &str = "--hidden";
+
+/// Starting the app when the user logs in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutostartSettings {
+    pub enabled: bool,
+    /// Start to the tray without showing the main window
+    pub hidden: bool,
+}
+
+impl Default for AutostartSettings {
+    fn default() -> Self {
+        Self { enabled: false, hidden: true }
+    }
+}
+
+/// Register or unregister the login item to match the settings. Called from `setup` too, so
+/// the entry follows the executable when the app was moved or updated.
+pub fn apply() {
+    let autostart = settings::current().autostart;
+    let result = if autostart.enabled {
+        std::env::current_exe()
+            .map_err(CaptureError::from)
+            .and_then(|exe| {
+                let args: &[&str] = if autostart.hidden { &[HIDDEN_FLAG] } else { &[] };
+                platform::enable(&exe.display().to_string(), args)
+            })
+    } else {
+        platform::disable()
+    };
+    match result {
+        Ok(()) => info!("Start at login {}", if autostart.enabled { "enabled" } else { "disabled" }),
+        Err(e) => warn!("Could not update the start at login entry: {}", e),
+    }
+}
+
+/// A value under the user's "Run" key
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::System::Registry::{RegDeleteKeyValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ};
+    use crate::error::CaptureError;
+
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+    const VALUE_NAME: &str = "ScrollSnap";
+
+    pub fn enable(exe: &str, args: &[&str]) -> Result<(), CaptureError> {
+        let command = std::iter::once(format!("\"{}\"", exe))
+            .chain(args.iter().map(|arg| arg.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let data: Vec<u16> = command.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                &HSTRING::from(RUN_KEY),
+                &HSTRING::from(VALUE_NAME),
+                REG_SZ.0,
+                Some(data.as_ptr() as *const _),
+                (data.len() * 2) as u32,
+            )
+        }
+        .ok()
+        .map_err(|e| CaptureError::Internal(format!("could not write the Run key: {}", e)))
+    }
+
+    pub fn disable() -> Result<(), CaptureError> {
+        let result = unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, &HSTRING::from(RUN_KEY), &HSTRING::from(VALUE_NAME)) };
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(());
+        }
+        result.ok().map_err(|e| CaptureError::Internal(format!("could not remove the Run key value: {}", e)))
+    }
+}
+
+/// A LaunchAgent in "~/Library/LaunchAgents"
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::error::CaptureError;
+
+    const LABEL: &str = "com.goblin.scroll-snap";
+
+    fn plist_path() -> Result<PathBuf, CaptureError> {
+        let home = std::env::var_os("HOME").ok_or_else(|| CaptureError::Internal("HOME is not set".to_string()))?;
+        Ok(PathBuf::from(home).join("Library/LaunchAgents").join(format!("{}.plist", LABEL)))
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    pub fn enable(exe: &str, args: &[&str]) -> Result<(), CaptureError> {
+        let arguments: String = std::iter::once(exe)
+            .chain(args.iter().copied())
+            .map(|arg| format!("        <string>{}</string>\n", escape(arg)))
+            .collect();
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+            LABEL, arguments
+        );
+        let path = plist_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, plist)?;
+        Ok(())
+    }
+
+    pub fn disable() -> Result<(), CaptureError> {
+        match fs::remove_file(plist_path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An XDG autostart entry in "~/.config/autostart"
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::error::CaptureError;
+
+    fn desktop_path() -> Result<PathBuf, CaptureError> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .ok_or_else(|| CaptureError::Internal("neither XDG_CONFIG_HOME nor HOME is set".to_string()))?;
+        Ok(config.join("autostart").join("scroll-snap.desktop"))
+    }
+
+    /// Quoted for the Exec key, see the Desktop Entry spec
+    fn quote(arg: &str) -> String {
+        let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"").replace('`', "\\`").replace('$', "\\$");
+        format!("\"{}\"", escaped)
+    }
+
+    pub fn enable(exe: &str, args: &[&str]) -> Result<(), CaptureError> {
+        let exec = std::iter::once(quote(exe)).chain(args.iter().map(|arg| arg.to_string())).collect::<Vec<_>>().join(" ");
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=ScrollSnap\nExec={}\nX-GNOME-Autostart-enabled=true\n",
+            exec
+        );
+        let path = desktop_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, entry)?;
+        Ok(())
+    }
+
+    pub fn disable() -> Result<(), CaptureError> {
+        match fs::remove_file(desktop_path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::frames;
+
+/// Which backend a capture goes through, see `for_region`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    /// Whatever is on screen in the region
+    #[default]
+    Screen,
+    /// The window under the region, also while other windows cover it. Windows only.
+    Window,
+}
+
+/// Where the pixels of a capture come from. Everything above this (stitching, overlays,
+/// exports) only deals in images of physical desktop regions, so platform-specific ways
+/// of grabbing them can be added next to the xcap one.
+pub trait CaptureBackend: Send + Sync {
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+
+    /// Capture a physical region of the virtual desktop
+    fn capture_region(&self, region: &Rect) -> Result<DynamicImage, CaptureError>;
+}
+
+/// Screen capture through xcap, available on every platform
+pub struct XcapBackend;
+
+impl CaptureBackend for XcapBackend {
+    fn name(&self) -> &'static str {
+        "xcap"
+    }
+
+    /// Regions spanning several monitors are assembled from each monitor's part;
+    /// areas not covered by any monitor (e.g. gaps in an uneven layout) stay transparent.
+    fn capture_region(&self, region: &Rect) -> Result<DynamicImage, CaptureError> {
+        let mut parts = Vec::new();
+        for (monitor, info) in display::list_monitors()? {
+            let Some(part) = region.intersect(&info.rect()) else {
+                continue;
+            };
+
+            let image = monitor
+                .capture_region((part.x - info.x) as u32, (part.y - info.y) as u32, part.width, part.height)
+                .map_err(|e| CaptureError::CaptureFailed(format!("monitor '{}': {}", info.name, e)))?;
+            parts.push((part, image));
+        }
+
+        if parts.is_empty() {
+            return Err(CaptureError::RegionOutOfBounds(format!(
+                "{}x{} at ({}, {}) does not intersect any screen", region.width, region.height, region.x, region.y
+            )));
+        }
+
+        // Common case: the whole region is on a single monitor, no compositing needed
+        if parts.len() == 1 && parts[0].0 == *region {
+            let (_, image) = parts.pop().unwrap();
+            return Ok(DynamicImage::ImageRgba8(image));
+        }
+
+        let mut canvas = frames::take_blank(region.width, region.height);
+        for (part, image) in &parts {
+            imageops::replace(&mut canvas, image, (part.x - region.x) as i64, (part.y - region.y) as i64);
+        }
+
+        Ok(DynamicImage::ImageRgba8(canvas))
+    }
+}
+
+/// The backend of captures that don't choose one, e.g. single shots and schedules
+pub fn current() -> &'static dyn CaptureBackend {
+    &XcapBackend
+}
+
+/// Backend for a capture of `region`. A window backend picks its window here, once, so the
+/// capture sticks to it even if something else moves under the region later.
+pub fn for_region(kind: BackendKind, region: &Rect) -> Result<Arc<dyn CaptureBackend>, CaptureError> {
+    match kind {
+        BackendKind::Screen => Ok(Arc::new(XcapBackend)),
+        #[cfg(target_os = "windows")]
+        BackendKind::Window => Ok(Arc::new(WindowBackend::at(region)?)),
+        #[cfg(not(target_os = "windows"))]
+        BackendKind::Window => {
+            let _ = region;
+            Err(CaptureError::InvalidState("window capture is only available on Windows".to_string()))
+        }
+    }
+}
+
+/// One window, drawn through PrintWindow into our own bitmap instead of read off the screen.
+/// That works while other windows cover it, but not while it is minimized: minimized windows
+/// don't paint. The region keeps its position relative to the window, so moving the window
+/// during a capture doesn't matter either.
+#[cfg(target_os = "windows")]
+pub struct WindowBackend {
+    /// Window handles aren't `Send`, this is the raw value
+    hwnd: isize,
+    /// Top-left of the region relative to the window's top-left
+    offset: (i32, i32),
+}
+
+#[cfg(target_os = "windows")]
+impl WindowBackend {
+    /// The top-level window under the center of `region`, skipping our own windows
+    pub fn at(region: &Rect) -> Result<Self, CaptureError> {
+        use windows::Win32::Foundation::POINT;
+        use windows::Win32::UI::WindowsAndMessaging::{
+            GetAncestor, GetWindow, GetWindowThreadProcessId, IsWindowVisible, WindowFromPoint, GA_ROOT, GW_HWNDNEXT,
+        };
+
+        let center = POINT { x: region.x + region.width as i32 / 2, y: region.y + region.height as i32 / 2 };
+        let contains = |rect: &Rect| center.x >= rect.x && center.x < rect.right() && center.y >= rect.y && center.y < rect.bottom();
+        unsafe {
+            let mut hwnd = GetAncestor(WindowFromPoint(center), GA_ROOT);
+            // The capture overlays sit on top of the region, look at what's below them
+            loop {
+                if hwnd.is_invalid() {
+                    return Err(CaptureError::CaptureFailed("no window under the capture region".to_string()));
+                }
+                let mut process = 0;
+                GetWindowThreadProcessId(hwnd, Some(&mut process));
+                let rect = window_rect(hwnd.0 as isize)?;
+                if process != std::process::id() && IsWindowVisible(hwnd).as_bool() && contains(&rect) {
+                    return Ok(Self { hwnd: hwnd.0 as isize, offset: (region.x - rect.x, region.y - rect.y) });
+                }
+                hwnd = GetWindow(hwnd, GW_HWNDNEXT).unwrap_or_default();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl CaptureBackend for WindowBackend {
+    fn name(&self) -> &'static str {
+        "window"
+    }
+
+    /// Only the size of `region` is used, its position follows the window.
+    /// Parts of the region outside the window stay transparent.
+    fn capture_region(&self, region: &Rect) -> Result<DynamicImage, CaptureError> {
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::Graphics::Gdi::{
+            CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits, ReleaseDC, SelectObject,
+            BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+        };
+        use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS};
+        use windows::Win32::UI::WindowsAndMessaging::{IsIconic, IsWindow, PW_RENDERFULLCONTENT};
+
+        let hwnd = HWND(self.hwnd as _);
+        unsafe {
+            if !IsWindow(Some(hwnd)).as_bool() {
+                return Err(CaptureError::CaptureFailed("the captured window was closed".to_string()));
+            }
+            if IsIconic(hwnd).as_bool() {
+                return Err(CaptureError::CaptureFailed("the captured window is minimized".to_string()));
+            }
+        }
+        let window = window_rect(self.hwnd)?;
+        if window.width == 0 || window.height == 0 {
+            return Err(CaptureError::CaptureFailed("the captured window has no size".to_string()));
+        }
+
+        let (width, height) = (window.width as i32, window.height as i32);
+        // GetDIBits writes every pixel, a recycled buffer doesn't need clearing
+        let mut bgra = frames::take(window.width, window.height).into_raw();
+        let printed = unsafe {
+            let screen = GetDC(None);
+            let dc = CreateCompatibleDC(Some(screen));
+            let bitmap = CreateCompatibleBitmap(screen, width, height);
+            let previous = SelectObject(dc, bitmap.into());
+            // PW_RENDERFULLCONTENT also gets DirectComposition content, e.g. browsers
+            let printed = PrintWindow(hwnd, dc, PRINT_WINDOW_FLAGS(PW_RENDERFULLCONTENT)).as_bool();
+            let mut info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER {
+                    biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                    biWidth: width,
+                    // Negative for top-down rows
+                    biHeight: -height,
+                    biPlanes: 1,
+                    biBitCount: 32,
+                    biCompression: BI_RGB.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let rows = GetDIBits(dc, bitmap, 0, height as u32, Some(bgra.as_mut_ptr().cast()), &mut info, DIB_RGB_COLORS);
+            SelectObject(dc, previous);
+            let _ = DeleteObject(bitmap.into());
+            let _ = DeleteDC(dc);
+            ReleaseDC(None, screen);
+            printed && rows == height
+        };
+        if !printed {
+            return Err(CaptureError::CaptureFailed("the window could not be drawn (PrintWindow failed)".to_string()));
+        }
+
+        // GDI leaves alpha undefined, everything the window drew is opaque
+        for pixel in bgra.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+            pixel[3] = 255;
+        }
+        let image = RgbaImage::from_raw(window.width, window.height, bgra)
+            .ok_or_else(|| CaptureError::Internal("window bitmap has the wrong size".to_string()))?;
+        let mut canvas = frames::take_blank(region.width, region.height);
+        imageops::replace(&mut canvas, &image, -self.offset.0 as i64, -self.offset.1 as i64);
+        frames::recycle(DynamicImage::ImageRgba8(image));
+        Ok(DynamicImage::ImageRgba8(canvas))
+    }
+}
+
+/// Outer bounds of a window in physical desktop pixels, as PrintWindow draws it
+#[cfg(target_os = "windows")]
+fn window_rect(hwnd: isize) -> Result<Rect, CaptureError> {
+    use windows::Win32::Foundation::{HWND, RECT};
+    use windows::Win32::UI::WindowsAndMessaging::GetWindowRect;
+
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(HWND(hwnd as _), &mut rect) }
+        .map_err(|e| CaptureError::CaptureFailed(format!("failed to get the window position: {}", e)))?;
+    Ok(Rect {
+        x: rect.left,
+        y: rect.top,
+        width: (rect.right - rect.left).max(0) as u32,
+        height: (rect.bottom - rect.top).max(0) as u32,
+    })
+}
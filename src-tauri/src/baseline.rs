@@ -0,0 +1,177 @@
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tracing::info;
+use crate::error::CaptureError;
+use crate::settings;
+use crate::store::{self, ImageSize};
+use crate::utils;
+
+const BASELINE_DIR: &str = "baselines";
+
+/// Color of changed pixels in the diff image
+const DIFF_HIGHLIGHT: Rgba<u8> = Rgba([255, 0, 80, 255]);
+
+/// How strict `compare_to_baseline` is
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BaselineSettings {
+    /// Max per-channel difference for a pixel to still count as unchanged (font smoothing, gradients)
+    pub pixel_tolerance: u8,
+    /// Share of changed pixels, in percent, up to which a comparison still passes
+    pub max_diff_percent: f32,
+}
+
+impl Default for BaselineSettings {
+    fn default() -> Self {
+        Self { pixel_tolerance: 16, max_diff_percent: 0.1 }
+    }
+}
+
+/// Result of comparing a capture against a baseline
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaselineComparison {
+    pub passed: bool,
+    pub size_matches: bool,
+    pub changed_pixels: u64,
+    pub diff_percent: f32,
+    /// PNG data URL of the capture, dimmed, with changed pixels highlighted
+    pub diff_image: String,
+}
+
+/// Save the capture `id` as the baseline `name`, replacing an existing one
+#[tauri::command]
+pub async fn save_baseline(app: AppHandle, id: String, name: String) -> Result<ImageSize, CaptureError> {
+    let path = baseline_path(&app, &name)?;
+    store::edit(id, move |canvas| {
+        let png = canvas.encode_png(|_| {})?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, png)?;
+        info!("Saved baseline to {}", path.display());
+        Ok(ImageSize::of(canvas))
+    })
+    .await
+}
+
+/// Compare the capture `id` against the baseline `name`.
+/// `max_diff_percent` overrides the threshold from the settings for this comparison.
+#[tauri::command]
+pub async fn compare_to_baseline(
+    app: AppHandle,
+    id: String,
+    name: String,
+    max_diff_percent: Option<f32>,
+) -> Result<BaselineComparison, CaptureError> {
+    let path = baseline_path(&app, &name)?;
+    if !path.exists() {
+        return Err(CaptureError::InvalidState(format!("there is no baseline named '{}'", name)));
+    }
+
+    let mut options = settings::current().baseline;
+    if let Some(threshold) = max_diff_percent {
+        options.max_diff_percent = threshold;
+    }
+
+    store::edit(id, move |canvas| {
+        let baseline = image::open(&path)
+            .map_err(|e| CaptureError::DecodeFailed(format!("baseline {}: {}", path.display(), e)))?
+            .to_rgba8();
+        let current = canvas.load_image()?;
+        let (diff, changed_pixels) = diff_images(&baseline, &current, options.pixel_tolerance);
+
+        let size_matches = baseline.dimensions() == current.dimensions();
+        let total = diff.width() as u64 * diff.height() as u64;
+        let diff_percent = if total == 0 { 0.0 } else { changed_pixels as f32 * 100.0 / total as f32 };
+        let passed = size_matches && diff_percent <= options.max_diff_percent;
+        info!("Baseline '{}': {:.3}% changed, {}", name, diff_percent, if passed { "pass" } else { "fail" });
+
+        Ok(BaselineComparison {
+            passed,
+            size_matches,
+            changed_pixels,
+            diff_percent,
+            diff_image: utils::png_data_url(&utils::encode_png(&diff, |_| {})?),
+        })
+    })
+    .await
+}
+
+/// Names of all saved baselines
+#[tauri::command]
+pub fn list_baselines(app: AppHandle) -> Result<Vec<String>, CaptureError> {
+    let dir = baseline_dir(&app)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let is_png = path.extension().is_some_and(|ext| ext == "png");
+            if is_png { path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) } else { None }
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+#[tauri::command]
+pub fn delete_baseline(app: AppHandle, name: String) -> Result<(), CaptureError> {
+    fs::remove_file(baseline_path(&app, &name)?)?;
+    Ok(())
+}
+
+fn baseline_dir(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(BASELINE_DIR))
+        .map_err(|e| CaptureError::Internal(format!("Could not resolve app data directory: {}", e)))
+}
+
+/// Names become file names, so only allow characters that are safe everywhere
+fn baseline_path(app: &AppHandle, name: &str) -> Result<PathBuf, CaptureError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.');
+    if !valid {
+        return Err(CaptureError::InvalidState(format!(
+            "invalid baseline name '{}', use letters, digits, '-', '_' and '.'", name
+        )));
+    }
+    Ok(baseline_dir(app)?.join(format!("{}.png", name)))
+}
+
+/// Diff image covering both images and the number of changed pixels.
+/// Pixels only present in one of the images (different heights after a page change) count as changed.
+fn diff_images(baseline: &RgbaImage, current: &RgbaImage, tolerance: u8) -> (RgbaImage, u64) {
+    let width = baseline.width().max(current.width());
+    let height = baseline.height().max(current.height());
+    let mut changed = 0u64;
+
+    let diff = RgbaImage::from_fn(width, height, |x, y| {
+        let old = baseline.get_pixel_checked(x, y);
+        let new = current.get_pixel_checked(x, y);
+        let same = match (old, new) {
+            (Some(a), Some(b)) => a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= tolerance),
+            _ => false,
+        };
+        if !same {
+            changed += 1;
+            return DIFF_HIGHLIGHT;
+        }
+
+        // Unchanged: faded gray version of the capture so the highlights stand out
+        let px = new.unwrap();
+        let luma = (px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000;
+        let faded = (luma / 3 + 170) as u8;
+        Rgba([faded, faded, faded, 255])
+    });
+
+    (diff, changed)
+}
@@ -0,0 +1,589 @@
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+use tracing::{info, warn};
+use crate::autocrop::BorderScanner;
+use crate::display::Rect;
+use crate::encryption::FileCipher;
+use crate::error::CaptureError;
+use crate::frames;
+use crate::utils;
+
+/// Rows per strip moved to the tile file
+const STRIP_ROWS: u32 = 2048;
+
+/// Downscaling stops at a quarter of the captured resolution, past that the canvas spills
+const MAX_DOWNSCALE: u32 = 4;
+
+/// What a canvas does once its in-memory rows outgrow `MemorySettings::cap_mb`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PressureAction {
+    /// Move strips from the top to a temp file, full resolution is kept
+    #[default]
+    Spill,
+    /// Halve the resolution of the whole canvas, and of everything appended afterwards.
+    /// For machines short on disk space too. Spills once at `MAX_DOWNSCALE`.
+    Downscale,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemorySettings {
+    /// In-memory size of a canvas before `on_pressure` kicks in.
+    /// A 4K-wide RGBA row is 15 KB, so the default keeps ~17,000 rows in memory.
+    pub cap_mb: u32,
+    pub on_pressure: PressureAction,
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        Self { cap_mb: 256, on_pressure: PressureAction::Spill }
+    }
+}
+
+impl MemorySettings {
+    fn cap_bytes(&self) -> usize {
+        self.cap_mb.max(1) as usize * 1024 * 1024
+    }
+}
+
+/// Payload of the `memory-pressure` event, sent when a capture's canvas reaches the memory cap
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryPressure {
+    pub action: PressureAction,
+    /// Captured pixels per canvas pixel along each side, 1 unless downscaled
+    pub scale: u32,
+    pub cap_mb: u32,
+}
+
+/// The stitched image of a capture, while it runs and afterwards.
+///
+/// Starts out as a plain RGBA buffer. Once it grows past the memory cap, strips of
+/// `STRIP_ROWS` rows are moved from the top into a temp file. Stitching only ever touches the
+/// bottom, so the spilled rows are not needed again until export, which streams them back
+/// from disk straight into the encoder. With `PressureAction::Downscale` the canvas is
+/// shrunk instead, fragments are still matched at full resolution before they're appended.
+pub struct Canvas {
+    width: u32,
+    /// Raw RGBA rows below the spilled strips (all rows while nothing was spilled)
+    tail: Vec<u8>,
+    spill: Option<Spill>,
+    /// One entry per appended fragment, so the latest ones can be taken back off
+    segments: Vec<Segment>,
+    memory: MemorySettings,
+    /// Captured pixels per canvas pixel along each side, a power of two
+    scale: u32,
+    /// Width of the fragments, `width` times `scale` before rounding down
+    source_width: u32,
+    /// Fragment rows of a downscaled canvas that don't make up a whole canvas row yet
+    carry: Vec<u8>,
+    /// Reached the memory cap since the last `take_pressure`
+    pressure: Option<MemoryPressure>,
+}
+
+/// How a fragment was matched against the canvas
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stitch {
+    /// Index of the fragment among all captured frames, the first frame is 0
+    pub frame_index: u32,
+    /// Rows at the top of the fragment that were already on the canvas
+    pub overlap: u32,
+    /// Share of the overlapping pixels that matched, 1.0 for the first fragment
+    pub confidence: f32,
+}
+
+/// Where a fragment's new content starts on the canvas, reported by `get_capture_report`
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    pub row: u32,
+    #[serde(flatten)]
+    pub stitch: Stitch,
+}
+
+struct Spill {
+    path: PathBuf,
+    file: File,
+    rows: u32,
+    /// Seals every strip while encryption is on, see `write_strip`
+    cipher: Option<FileCipher>,
+}
+
+impl Canvas {
+    /// Start a canvas with the first captured fragment
+    pub fn new(first: &DynamicImage) -> Self {
+        let mut canvas = Self::empty(first.width());
+        canvas.tail = rgba_bytes(first).into_owned();
+        canvas.segments.push(Segment { row: 0, stitch: Stitch { frame_index: 0, overlap: 0, confidence: 1.0 } });
+        canvas
+    }
+
+    /// Take over a finished image, e.g. the result of an edit
+    pub fn from_rgba(img: RgbaImage) -> Result<Self, CaptureError> {
+        let mut canvas = Self::empty(img.width());
+        canvas.tail = img.into_raw();
+        canvas.spill_strips()?;
+        Ok(canvas)
+    }
+
+    /// A canvas with no rows yet, fragments are appended with an overlap of 0 first
+    pub fn empty(width: u32) -> Self {
+        Self {
+            width,
+            tail: Vec::new(),
+            spill: None,
+            segments: Vec::new(),
+            memory: MemorySettings::default(),
+            scale: 1,
+            source_width: width,
+            carry: Vec::new(),
+            pressure: None,
+        }
+    }
+
+    /// Apply a memory cap other than the default, checked from the next append on
+    pub fn set_memory_limit(&mut self, memory: MemorySettings) {
+        self.memory = memory;
+    }
+
+    /// Whether the canvas reached its memory cap (again) since the last call, and what it did
+    pub fn take_pressure(&mut self) -> Option<MemoryPressure> {
+        self.pressure.take()
+    }
+
+    /// Captured pixels per canvas pixel along each side, 1 unless the canvas was downscaled
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn row_bytes(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    pub fn height(&self) -> u32 {
+        let tail_rows = if self.width == 0 { 0 } else { (self.tail.len() / self.row_bytes()) as u32 };
+        tail_rows + self.spill.as_ref().map_or(0, |spill| spill.rows)
+    }
+
+    /// Seams between the stitched fragments, top to bottom
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Append `fragment` below the canvas, skipping its first `stitch.overlap` rows (already on the canvas)
+    pub fn append(&mut self, fragment: &DynamicImage, stitch: Stitch) -> Result<(), CaptureError> {
+        let overlap = stitch.overlap;
+        if fragment.width() != self.source_width {
+            return Err(CaptureError::Internal(format!(
+                "fragment width {} does not match canvas width {}", fragment.width(), self.source_width
+            )));
+        }
+        if overlap >= fragment.height() {
+            // Nothing new in this fragment
+            return Ok(());
+        }
+
+        let bytes = rgba_bytes(fragment);
+        let new_rows = &bytes[overlap as usize * self.source_width as usize * 4..];
+        self.segments.push(Segment { row: self.height(), stitch });
+        if self.scale == 1 {
+            self.tail.extend_from_slice(new_rows);
+        } else {
+            // Rows that don't fill a whole canvas row wait for the next fragment
+            self.carry.extend_from_slice(new_rows);
+            let source_row_bytes = self.source_width as usize * 4;
+            let whole = self.carry.len() / (source_row_bytes * self.scale as usize) * source_row_bytes * self.scale as usize;
+            let shrunk = shrink(&self.carry[..whole], self.source_width, self.scale);
+            self.tail.extend_from_slice(&shrunk);
+            self.carry.drain(..whole);
+        }
+        self.relieve_memory()
+    }
+
+    /// Remove the most recently appended fragment, e.g. one that caught a popup.
+    /// The first fragment stays, returns `false` when there is nothing left to undo.
+    pub fn undo_last_segment(&mut self) -> Result<bool, CaptureError> {
+        if self.segments.len() < 2 {
+            return Ok(false);
+        }
+        let last = self.segments.pop().unwrap();
+        self.truncate(last.row)?;
+        self.carry.clear();
+        Ok(true)
+    }
+
+    /// The bottom `rows` rows as an image, i.e. the last frame as far as the canvas is concerned.
+    /// `rows` and the image are in fragment pixels: a downscaled canvas is scaled back up, which
+    /// only gives a blurry version of the last frame.
+    pub fn bottom_rows(&mut self, rows: u32) -> Result<DynamicImage, CaptureError> {
+        let scale = self.scale;
+        let canvas_rows = rows.div_ceil(scale).min(self.height());
+        let bytes = canvas_rows as usize * self.row_bytes();
+        while self.tail.len() < bytes {
+            self.unspill_strip()?;
+        }
+        let bottom = &self.tail[self.tail.len() - bytes..];
+
+        if scale == 1 {
+            let mut frame = frames::take(self.width, canvas_rows);
+            frame.copy_from_slice(bottom);
+            return Ok(DynamicImage::ImageRgba8(frame));
+        }
+        let (width, row_bytes) = (self.width.max(1), self.row_bytes());
+        let rows = rows.min(canvas_rows * scale);
+        let mut frame = frames::take(self.source_width, rows);
+        for (y, row) in frame.rows_mut().enumerate() {
+            let source = &bottom[y / scale as usize * row_bytes..];
+            for (x, pixel) in row.enumerate() {
+                let i = (x as u32 / scale).min(width - 1) as usize * 4;
+                pixel.0.copy_from_slice(&source[i..i + 4]);
+            }
+        }
+        Ok(DynamicImage::ImageRgba8(frame))
+    }
+
+    /// Cut the canvas down to its first `height` rows
+    fn truncate(&mut self, height: u32) -> Result<(), CaptureError> {
+        while self.spill.as_ref().is_some_and(|spill| spill.rows > height) {
+            self.unspill_strip()?;
+        }
+        let spilled = self.spill.as_ref().map_or(0, |spill| spill.rows);
+        self.tail.truncate((height - spilled) as usize * self.row_bytes());
+        Ok(())
+    }
+
+    /// Move the last strip on disk back in front of the in-memory rows
+    fn unspill_strip(&mut self) -> Result<(), CaptureError> {
+        let row_bytes = self.row_bytes();
+        let spill = self.spill.as_mut()
+            .filter(|spill| spill.rows > 0)
+            .ok_or_else(|| CaptureError::Internal("no spilled rows left to read back".to_string()))?;
+
+        spill.rows -= STRIP_ROWS;
+        let index = (spill.rows / STRIP_ROWS) as u64;
+        let strip_bytes = STRIP_ROWS as usize * row_bytes;
+        let offset = index * stored_len(strip_bytes, spill.cipher.as_ref()) as u64;
+        spill.file.seek(SeekFrom::Start(offset))?;
+        let mut strip = read_strip(&mut spill.file, spill.cipher.as_ref(), index, strip_bytes)?;
+        spill.file.set_len(offset)?;
+
+        strip.extend_from_slice(&self.tail);
+        self.tail = strip;
+        Ok(())
+    }
+
+    /// Append raw RGBA rows at the bottom, in canvas pixels
+    pub fn push_rows(&mut self, rows: &[u8]) -> Result<(), CaptureError> {
+        self.tail.extend_from_slice(rows);
+        self.relieve_memory()
+    }
+
+    /// Get the in-memory part back under the cap, the way `MemorySettings::on_pressure` says
+    fn relieve_memory(&mut self) -> Result<(), CaptureError> {
+        let cap = self.memory.cap_bytes();
+        while self.memory.on_pressure == PressureAction::Downscale
+            && self.spill.is_none()
+            && self.scale < MAX_DOWNSCALE
+            && self.width >= 2
+            && self.tail.len() > cap
+        {
+            self.halve();
+        }
+        self.spill_strips()
+    }
+
+    /// Halve the resolution of every row, fragments appended afterwards are shrunk to match
+    fn halve(&mut self) {
+        self.tail = shrink(&self.tail, self.width, 2);
+        self.width /= 2;
+        self.scale *= 2;
+        for segment in &mut self.segments {
+            segment.row /= 2;
+        }
+        info!("Canvas exceeds {} MB, downscaled to 1/{} of the captured resolution", self.memory.cap_mb, self.scale);
+        self.pressure = Some(MemoryPressure { action: PressureAction::Downscale, scale: self.scale, cap_mb: self.memory.cap_mb });
+    }
+
+    /// Move whole strips from the top of the in-memory part to disk until it's below the cap
+    fn spill_strips(&mut self) -> Result<(), CaptureError> {
+        let strip_bytes = STRIP_ROWS as usize * self.row_bytes();
+        if strip_bytes == 0 {
+            return Ok(());
+        }
+
+        while self.tail.len() > self.memory.cap_bytes() && self.tail.len() >= strip_bytes {
+            if self.spill.is_none() {
+                // Unique per canvas, a crop builds a new canvas while the old one is still being read
+                let path = std::env::temp_dir().join(format!("scroll-snap-{}.rgba", Uuid::new_v4()));
+                let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+                info!("Canvas exceeds {} MB, spilling strips to {}", self.memory.cap_mb, path.display());
+                self.spill = Some(Spill { path, file, rows: 0, cipher: FileCipher::create()? });
+                self.pressure = Some(MemoryPressure { action: PressureAction::Spill, scale: self.scale, cap_mb: self.memory.cap_mb });
+            }
+
+            let spill = self.spill.as_mut().unwrap();
+            // Reading for export moves the file position, strips always go at the end
+            spill.file.seek(SeekFrom::End(0))?;
+            write_strip(&mut spill.file, spill.cipher.as_ref(), (spill.rows / STRIP_ROWS) as u64, &self.tail[..strip_bytes])?;
+            spill.rows += STRIP_ROWS;
+            self.tail.drain(..strip_bytes);
+        }
+        Ok(())
+    }
+
+    /// All rows from top to bottom in blocks, reading spilled strips back one at a time
+    fn blocks(&mut self) -> Result<impl Iterator<Item = Result<Cow<'_, [u8]>, CaptureError>> + '_, CaptureError> {
+        let strip_bytes = STRIP_ROWS as usize * self.row_bytes();
+
+        let mut spilled = Vec::new();
+        if let Some(spill) = self.spill.as_mut() {
+            spill.file.flush()?;
+            spill.file.seek(SeekFrom::Start(0))?;
+            spilled.push((&mut spill.file, spill.cipher.as_ref(), spill.rows));
+        }
+
+        let from_disk = spilled.into_iter().flat_map(move |(file, cipher, rows)| {
+            (0..(rows / STRIP_ROWS) as u64).map(move |index| read_strip(file, cipher, index, strip_bytes).map(Cow::Owned))
+        });
+        let from_memory = self.tail.chunks(strip_bytes.max(1)).map(|block| Ok(Cow::Borrowed(block)));
+        Ok(from_disk.chain(from_memory))
+    }
+
+    /// Bounds of the content inside uniform margins, `None` if there is nothing to trim
+    pub fn content_bounds(&mut self) -> Result<Option<Rect>, CaptureError> {
+        let (width, height) = (self.width, self.height());
+        let mut scanner = BorderScanner::new(width);
+        for block in self.blocks()? {
+            scanner.push_rows(&block?);
+        }
+        Ok(scanner.finish().filter(|bounds| bounds.width != width || bounds.height != height))
+    }
+
+    /// A new canvas holding only the part inside `rect`, streamed strip by strip
+    pub fn cropped(&mut self, rect: Rect) -> Result<Canvas, CaptureError> {
+        let (width, height) = (self.width, self.height());
+        if rect.width == 0 || rect.height == 0 || rect.x < 0 || rect.y < 0
+            || rect.right() > width as i32 || rect.bottom() > height as i32
+        {
+            return Err(CaptureError::InvalidRegion(format!(
+                "{}x{} at ({}, {}) is not inside the {}x{} image", rect.width, rect.height, rect.x, rect.y, width, height
+            )));
+        }
+
+        let row_bytes = self.row_bytes();
+        let (x0, x1) = (rect.x as usize * 4, rect.right() as usize * 4);
+        let mut out = Canvas::empty(rect.width);
+        // Seams inside the cropped rows stay, so the capture report still lines up
+        out.segments = self.segments.iter()
+            .filter(|segment| segment.row as i32 >= rect.y && (segment.row as i32) < rect.bottom())
+            .map(|segment| Segment { row: segment.row - rect.y as u32, ..*segment })
+            .collect();
+        let mut y = 0;
+        for block in self.blocks()? {
+            for row in block?.chunks_exact(row_bytes) {
+                if y >= rect.y && y < rect.bottom() {
+                    out.push_rows(&row[x0..x1])?;
+                }
+                y += 1;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Raw RGBA rows from `start` to the bottom. Cheap while they are still in memory,
+    /// which is where new rows always are.
+    pub fn rows_since(&mut self, start: u32) -> Result<Vec<u8>, CaptureError> {
+        let height = self.height();
+        self.rows(start, height.saturating_sub(start))
+    }
+
+    /// Raw RGBA rows `start..start + count`, cut off at the bottom. Only the spilled
+    /// rows in that range are read back from disk.
+    pub fn rows(&mut self, start: u32, count: u32) -> Result<Vec<u8>, CaptureError> {
+        let row_bytes = self.row_bytes();
+        let end = start.saturating_add(count).min(self.height());
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut rows = Vec::with_capacity((end - start) as usize * row_bytes);
+        let spilled = self.spill.as_ref().map_or(0, |spill| spill.rows);
+        if let Some(spill) = self.spill.as_mut().filter(|_| start < spilled) {
+            spill.file.flush()?;
+            spill.file.seek(SeekFrom::Start(start as u64 * row_bytes as u64))?;
+            rows.resize((end.min(spilled) - start) as usize * row_bytes, 0);
+            spill.file.read_exact(&mut rows)?;
+        }
+        if end > spilled {
+            let from = start.max(spilled) - spilled;
+            rows.extend_from_slice(&self.tail[from as usize * row_bytes..(end - spilled) as usize * row_bytes]);
+        }
+        Ok(rows)
+    }
+
+    /// Up to about `max` evenly spread pixels as RGB, streamed so spilled strips stay on disk
+    pub fn sample_pixels(&mut self, max: usize) -> Result<Vec<[u8; 3]>, CaptureError> {
+        let total = self.width as usize * self.height() as usize;
+        let step = total.div_ceil(max.max(1)).max(1);
+        let mut samples = Vec::with_capacity(total / step + 1);
+        let mut index = 0;
+        for block in self.blocks()? {
+            for px in block?.chunks_exact(4) {
+                if index % step == 0 {
+                    samples.push([px[0], px[1], px[2]]);
+                }
+                index += 1;
+            }
+        }
+        Ok(samples)
+    }
+
+    /// The whole canvas as one image, for edits that need random access to all pixels
+    pub fn load_image(&mut self) -> Result<RgbaImage, CaptureError> {
+        let (width, height) = (self.width, self.height());
+        let mut raw = Vec::with_capacity(width as usize * height as usize * 4);
+        for block in self.blocks()? {
+            raw.extend_from_slice(&block?);
+        }
+        RgbaImage::from_raw(width, height, raw)
+            .ok_or_else(|| CaptureError::Internal("canvas size does not match its pixel data".to_string()))
+    }
+
+    /// Encode the canvas as PNG
+    pub fn encode_png(&mut self, on_progress: impl FnMut(u32)) -> Result<Vec<u8>, CaptureError> {
+        let (width, height) = (self.width, self.height());
+        utils::encode_png_blocks(width, height, self.blocks()?, on_progress)
+    }
+
+    /// Write all rows to `path` so the canvas can be dropped while nothing uses it,
+    /// `ParkedCanvas::restore` brings it back
+    pub fn park(&mut self, path: PathBuf) -> Result<ParkedCanvas, CaptureError> {
+        let (width, height, segments) = (self.width, self.height(), self.segments.clone());
+        let cipher = FileCipher::create()?;
+        let mut file = BufWriter::new(File::create(&path)?);
+        for (index, block) in self.blocks()?.enumerate() {
+            write_strip(&mut file, cipher.as_ref(), index as u64, &block?)?;
+        }
+        file.flush()?;
+        Ok(ParkedCanvas { path, width, height, segments, cipher })
+    }
+}
+
+/// A canvas written out to a file by `Canvas::park`, the file is removed when this is dropped
+pub struct ParkedCanvas {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    segments: Vec<Segment>,
+    cipher: Option<FileCipher>,
+}
+
+impl ParkedCanvas {
+    /// Read the rows back into a canvas, which spills again if it outgrows the memory cap
+    pub fn restore(&self) -> Result<Canvas, CaptureError> {
+        let mut canvas = Canvas::empty(self.width);
+        let row_bytes = canvas.row_bytes();
+        let mut file = File::open(&self.path)?;
+        let mut remaining = self.height;
+        let mut index = 0;
+        while remaining > 0 {
+            let rows = remaining.min(STRIP_ROWS);
+            canvas.push_rows(&read_strip(&mut file, self.cipher.as_ref(), index, rows as usize * row_bytes)?)?;
+            remaining -= rows;
+            index += 1;
+        }
+        canvas.segments = self.segments.clone();
+        Ok(canvas)
+    }
+}
+
+impl Drop for ParkedCanvas {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove parked capture {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl Drop for Canvas {
+    fn drop(&mut self) {
+        if let Some(spill) = self.spill.take() {
+            drop(spill.file);
+            if let Err(e) = fs::remove_file(&spill.path) {
+                warn!("Failed to remove canvas tile file {}: {}", spill.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Write `strip` to a spill or park file as the strip at `index`, sealed while encryption is on:
+/// the rows are as confidential as the capture
+fn write_strip(file: &mut impl Write, cipher: Option<&FileCipher>, index: u64, strip: &[u8]) -> Result<(), CaptureError> {
+    match cipher {
+        Some(cipher) => file.write_all(&cipher.seal(index, strip)?)?,
+        None => file.write_all(strip)?,
+    }
+    Ok(())
+}
+
+/// Read back the strip at `index`, `len` bytes before sealing
+fn read_strip(file: &mut impl Read, cipher: Option<&FileCipher>, index: u64, len: usize) -> Result<Vec<u8>, CaptureError> {
+    let mut strip = vec![0u8; stored_len(len, cipher)];
+    file.read_exact(&mut strip)?;
+    match cipher {
+        Some(cipher) => cipher.open(index, &strip),
+        None => Ok(strip),
+    }
+}
+
+/// Bytes a strip of `len` takes in the file
+fn stored_len(len: usize, cipher: Option<&FileCipher>) -> usize {
+    len + cipher.map_or(0, |_| FileCipher::OVERHEAD)
+}
+
+/// Raw RGBA bytes of an image, without copying when it already is RGBA8 (captures always are)
+/// RGBA rows of `width` pixels shrunk by `factor` along each side, averaging `factor` x `factor`
+/// pixels. Columns left over on the right are dropped, a last group of fewer rows is averaged
+/// over the rows it has.
+fn shrink(rows: &[u8], width: u32, factor: u32) -> Vec<u8> {
+    let (width, factor) = (width as usize, factor as usize);
+    let row_bytes = width * 4;
+    let out_width = width / factor;
+    let mut out = Vec::with_capacity(rows.len() / factor / factor + out_width * 4);
+    if out_width == 0 {
+        return out;
+    }
+    let mut sums = vec![0u32; out_width * 4];
+    for group in rows.chunks(row_bytes * factor) {
+        sums.fill(0);
+        for row in group.chunks_exact(row_bytes) {
+            for (x, pixel) in row[..out_width * factor * 4].chunks_exact(4).enumerate() {
+                let sum = &mut sums[x / factor * 4..][..4];
+                for c in 0..4 {
+                    sum[c] += pixel[c] as u32;
+                }
+            }
+        }
+        let count = (group.len() / row_bytes * factor) as u32;
+        out.extend(sums.iter().map(|&sum| ((sum + count / 2) / count) as u8));
+    }
+    out
+}
+
+fn rgba_bytes(img: &DynamicImage) -> Cow<'_, [u8]> {
+    match img {
+        DynamicImage::ImageRgba8(rgba) => Cow::Borrowed(rgba.as_raw().as_slice()),
+        other => Cow::Owned(other.to_rgba8().into_raw()),
+    }
+}
@@ -1,155 +1,844 @@
-use xcap::Monitor;
-use image::{DynamicImage, ImageFormat};
-use std::thread;
-use std::time::Duration;
-use std::io::Cursor;
-use base64::{Engine as _, engine::general_purpose};
-use crate::stitch;
+use image::{imageops, DynamicImage, GenericImageView, Rgba};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use crate::accessibility::ScrollPane;
+use crate::autoscroll::{self, AutoScroll};
+use crate::autosave::Autosave;
+use crate::backend::{self, BackendKind, CaptureBackend};
+use crate::canvas::{Canvas, PressureAction, Stitch};
+use crate::composite::{self, CompositeOptions};
+use crate::debug::{DebugDump, FrameOutcome};
+use crate::display::{self, DisplayInfo, Rect};
+use crate::error::CaptureError;
+use crate::frames;
+use crate::history;
+use crate::hotkeys;
+use crate::i18n;
+use crate::metadata::CaptureMetadata;
+use crate::metrics::MetricsRecorder;
+use crate::notify;
+use crate::overlay;
+use crate::pacing::Pacer;
+use crate::permission;
+use crate::pipeline;
+use crate::privacy;
+use crate::session::{self, CaptureState, SessionHandle};
+use crate::sound::{self, Cue};
+use crate::stats::{self, SessionRecord};
+use crate::stitch::{self, OverlapSearch};
+use crate::store::{self, CaptureResult};
 use tauri::{AppHandle, Emitter, Manager};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::settings;
 use lazy_static::lazy_static;
-use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::sync::{Arc, Mutex};
 
 lazy_static! {
-    static ref CAPTURE_STATES: Mutex<HashMap<String, Arc<Mutex<bool>>>> = Mutex::new(HashMap::new());
+    // Physical region of the most recent capture, for `capture_last_region`
+    static ref LAST_REGION: Mutex<Option<Rect>> = Mutex::new(None);
+}
+
+/// Static frames with the scrollbar at the bottom before we call it the end of the page
+const END_OF_PAGE_STATIC_COUNT: u32 = 5;
+
+/// Static frames before we tell the user we're waiting for them to scroll (~1s at the default interval)
+const WAITING_FOR_SCROLL_COUNT: u32 = 10;
+
+/// Unconnected fragments kept around for recovery. Every one of them is matched against
+/// each new fragment, so this also bounds the extra work per frame.
+const MAX_PENDING_FRAGMENTS: usize = 8;
+
+/// Torn frames discarded in a row before we stitch anyway. Content that animates inside the
+/// overlap (videos, spinners) looks torn on every frame and would otherwise stall the capture.
+pub const MAX_TORN_RETRIES: u32 = 3;
+
+/// Static frames in a row, with auto-scroll on, before we call it the end of the page.
+/// A key press that moves nothing means the end (or that the key went to the wrong window).
+pub const AUTO_SCROLL_STATIC_COUNT: u32 = 2;
+
+/// Frames between checks of the monitor layout (~1s at the default interval).
+/// Capture errors and size changes trigger a check right away.
+const DISPLAY_CHECK_FRAMES: u32 = 10;
+
+/// Relative difference of aspect ratios below which a fragment of the wrong size is taken to be
+/// the same content at another scale (DPI change) and rescaled, rather than letterboxed
+const RESCALE_ASPECT_TOLERANCE: f64 = 0.01;
+
+/// Tunables of the capture loop. Persisted in settings and overridable per capture.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureOptions {
+    /// Delay between two fragments. With `adaptive_interval` this is the longest it waits
+    /// while the content moves, shorter when the machine keeps up.
+    pub poll_interval_ms: u64,
+    /// Capture more often while scrolling and less while nothing moves, instead of at a fixed
+    /// interval. Idle limits still count in `poll_interval_ms` frames.
+    pub adaptive_interval: bool,
+    /// Static frames after which we assume the user is done even without a visible scrollbar
+    pub max_static_count: u32,
+    pub max_stitches: u32,
+    /// Scroll with key presses instead of waiting for the user
+    pub auto_scroll: Option<AutoScroll>,
+    /// How much of each fragment is searched for the previous one
+    pub overlap_search: OverlapSearch,
+    /// Capture the screen, or the window under the region even while it's covered
+    pub backend: BackendKind,
+    /// Read the scroll position of the pane under the region from the accessibility API, where
+    /// the app exposes it (UI Automation, AT-SPI2 on X11, macOS Accessibility). Auto-scroll then
+    /// scrolls through it as well and stops right at the bottom instead of waiting for the content
+    /// to stop moving.
+    pub accessibility: bool,
 }
 
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 100,
+            adaptive_interval: true,
+            max_static_count: 30,
+            // Allow up to 500 stitches (very long image)
+            max_stitches: 500,
+            auto_scroll: None,
+            overlap_search: OverlapSearch::default(),
+            backend: BackendKind::Screen,
+            accessibility: true,
+        }
+    }
+}
+
+/// Why a capture loop ended, sent with the `capture-stopped` event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    /// Stop command or Escape key
+    User,
+    /// Content stopped moving and the scrollbar thumb is at the bottom
+    ReachedEnd,
+    /// Content stopped moving for `max_static_count` frames
+    Idle,
+    MaxStitches,
+    CaptureFailed,
+    /// A monitor was added/removed or changed resolution or scaling
+    DisplayChanged,
+    /// Cancel command, the result is discarded
+    Cancelled,
+}
+
+/// Payload of the `capture-interrupted` event. The capture still completes with
+/// everything stitched up to that point.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureInterrupted {
+    pub reason: StopReason,
+    pub message: String,
+}
+
+/// How a fragment of the wrong size was made to fit the capture, see `fit_fragment`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FragmentFit {
+    /// Same aspect ratio, resized to the region size
+    Rescaled,
+    /// Placed on the composite background, cropped where it is larger than the region
+    Letterboxed,
+}
+
+/// Payload of the `fragment-resized` event, sent when fragments stop matching the region size
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FragmentResized {
+    pub width: u32,
+    pub height: u32,
+    pub expected_width: u32,
+    pub expected_height: u32,
+    pub fit: FragmentFit,
+}
+
+/// Payload of the `capture-progress` event, shown in the HUD
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureProgress {
+    pub height: u32,
+    pub stitch_count: u32,
+    /// Vertical scroll position of the pane being captured in percent, where the app exposes it
+    pub scroll_percent: Option<f64>,
+}
+
+/// Start a manual scroll capture of a region given in logical (CSS) pixels.
+/// `scale_factor` is the device pixel ratio of the window the region was selected in;
+/// when omitted the factor of the monitor containing the region is used.
+/// `options` falls back to the persisted settings. `allow_sensitive` confirms capturing over
+/// windows the privacy guard asked about (a `SENSITIVE_WINDOW` error).
+/// Returns the session id that `stop_scroll_capture`/`cancel_scroll_capture` expect.
 #[tauri::command]
-pub async fn start_scroll_capture(app: AppHandle, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
-    println!("Starting manual scroll capture task at ({}, {}) {}x{}", x, y, width, height);
+#[allow(clippy::too_many_arguments)]
+pub async fn start_scroll_capture(
+    app: AppHandle,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    scale_factor: Option<f32>,
+    options: Option<CaptureOptions>,
+    allow_sensitive: Option<bool>,
+) -> Result<String, CaptureError> {
+    info!("Starting manual scroll capture task at ({}, {}) {}x{} (scale {:?})", x, y, width, height, scale_factor);
+    
+    // Convert once up front, every fragment is captured from the same physical region
+    let region = display::logical_to_physical(x, y, width, height, scale_factor)?;
+    info!("Physical capture region: {:?}", region);
+    
+    let options = options.unwrap_or_else(|| settings::current().capture);
+    start_capture(app, region, options, allow_sensitive.unwrap_or(false))
+}
+
+/// Start a new scroll capture of the region used last time, without reselecting it.
+/// `allow_sensitive` as for `start_scroll_capture`.
+#[tauri::command]
+pub async fn capture_last_region(app: AppHandle, options: Option<CaptureOptions>, allow_sensitive: Option<bool>) -> Result<String, CaptureError> {
+    let region = last_region().ok_or(CaptureError::NoPreviousRegion)?;
+    info!("Repeating capture of region {:?}", region);
+    
+    let options = options.unwrap_or_else(|| settings::current().capture);
+    start_capture(app, region, options, allow_sensitive.unwrap_or(false))
+}
+
+/// Physical region of the most recent scroll capture
+pub fn last_region() -> Option<Rect> {
+    *LAST_REGION.lock().unwrap()
+}
+
+/// Take a single screenshot of a region given in logical pixels, without the stitching loop.
+/// `allow_sensitive` as for `start_scroll_capture`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn capture_region_once(
+    app: AppHandle,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    scale_factor: Option<f32>,
+    allow_sensitive: Option<bool>,
+) -> Result<CaptureResult, CaptureError> {
+    permission::ensure_capture_permission()?;
+    
+    let region = display::validate_region(display::logical_to_physical(x, y, width, height, scale_factor)?)?;
+    info!("Single-shot capture of region {:?}", region);
+    
+    let image = capture_checked(&region, allow_sensitive.unwrap_or(false))?;
+    pipeline::run(&app, Canvas::new(&image), None, "region")
+}
+
+/// Take a screenshot of a whole display. Defaults to the primary display.
+/// `allow_sensitive` as for `start_scroll_capture`.
+#[tauri::command]
+pub async fn capture_fullscreen(app: AppHandle, display_id: Option<u32>, allow_sensitive: Option<bool>) -> Result<CaptureResult, CaptureError> {
+    permission::ensure_capture_permission()?;
+    
+    let (_, info) = display::list_monitors()?
+        .into_iter()
+        .find(|(_, info)| match display_id {
+            Some(id) => info.id == id,
+            None => info.is_primary,
+        })
+        .ok_or(CaptureError::ScreenNotFound)?;
+    info!("Fullscreen capture of display '{}'", info.name);
+    
+    let image = capture_checked(&info.rect(), allow_sensitive.unwrap_or(false))?;
+    pipeline::run(&app, Canvas::new(&image), None, "fullscreen")
+}
+
+/// Stitch screenshots that are already on disk (e.g. taken by hand) into one long image.
+/// The order is worked out from the content. Images of different widths are aligned as set in
+/// the composite options, matching only works between images that show the page at the same width.
+#[tauri::command]
+pub async fn stitch_files(app: AppHandle, paths: Vec<String>) -> Result<CaptureResult, CaptureError> {
+    if paths.is_empty() {
+        return Err(CaptureError::InvalidInput("no images to stitch".to_string()));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let images = paths
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map(|img| DynamicImage::ImageRgba8(img.to_rgba8()))
+                    .map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", path, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Narrower images are padded to the widest one, as set in the composite options
+        let width = images.iter().map(|img| img.width()).max().unwrap_or(0);
+        let composite = settings::current().composite;
+        if images.iter().any(|img| img.width() != width) {
+            info!("Aligning images of different widths to {}px ({:?})", width, composite.anchor);
+        }
+        let images = images
+            .iter()
+            .map(|img| composite::align_width(img, width, &composite))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let order = stitch::order_fragments(&images);
+        info!("Stitching {} files in order {:?}", images.len(), order.iter().map(|(i, _)| i).collect::<Vec<_>>());
+
+        let mut canvas = Canvas::empty(width);
+        let mut previous: Option<usize> = None;
+        for (index, overlap) in order {
+            let confidence = match previous {
+                Some(prev) => stitch::overlap_confidence(&images[prev], &images[index], overlap),
+                None => 1.0,
+            };
+            canvas.append(&images[index], Stitch { frame_index: index as u32, overlap, confidence })?;
+            previous = Some(index);
+        }
+
+        let result = store::insert(canvas, None)?;
+        history::save_capture_in_background(app, result.id.clone(), "files");
+        Ok(result)
+    })
+    .await
+    .map_err(|e| CaptureError::Internal(format!("stitch task failed: {}", e)))?
+}
+
+/// Start a scroll capture of a physical region, returns the session id.
+/// `allow_sensitive` skips the confirmation of the privacy guard, see `privacy::check`.
+pub fn start_capture(app: AppHandle, region: Rect, options: CaptureOptions, allow_sensitive: bool) -> Result<String, CaptureError> {
+    // Fail before touching any windows, otherwise macOS happily gives us black frames to stitch
+    permission::ensure_capture_permission()?;
+
+    // Same for bad regions. Screens may also have changed since the last region was stored.
+    let region = display::validate_region(region)?;
+    let blanked = privacy::check(&region, allow_sensitive)?;
+    
+    // Claim the session first so a second start request is rejected instead of racing this one
+    let handle = session::begin_capture(&app)?;
+    let session_id = handle.id.clone();
+    *LAST_REGION.lock().unwrap() = Some(region);
     
     // Instead of hiding, we set ignore cursor events to true
     // This allows the window to remain visible (showing the green border) but let clicks pass through
     let windows = app.webview_windows();
     for (label, window) in windows {
-        println!("Setting ignore cursor events for window: {}", label);
+        debug!("Setting ignore cursor events for window: {}", label);
         let _ = window.set_ignore_cursor_events(true);
     }
     
-    // Give the window manager some time to update
-    thread::sleep(Duration::from_millis(200));
-
-    // Create a stop flag for this capture session
-    let stop_flag = Arc::new(Mutex::new(false));
-    let stop_flag_clone = stop_flag.clone();
-    
-    // Store it so we can access it from stop command
-    // We use a simple key "current" since we only allow one capture at a time
-    CAPTURE_STATES.lock().unwrap().insert("current".to_string(), stop_flag);
+    // Frame the region so the user can see what's being captured, and show progress next to it
+    if let Err(e) = overlay::show_border(&app, &region) {
+        warn!("Could not show capture border: {}", e);
+    }
+    if let Err(e) = overlay::show_hud(&app, &region) {
+        warn!("Could not show capture HUD: {}", e);
+    }
     
-    // Spawn a thread to handle the long-running capture process
-    std::thread::spawn(move || {
-        let result = run_capture_loop(&app, x, y, width, height, stop_flag_clone);
+    // Run the long-running capture as a task, the command returns right away
+    tauri::async_runtime::spawn(async move {
+        // Held for the whole loop, dropping it (even while unwinding) releases the keys
+        let _shortcuts = hotkeys::register_session_shortcuts(&app, handle.clone());
+        let _panic_guard = PanicGuard { app: app.clone(), session_id: handle.id.clone() };
+
+        // Give the window manager some time to update
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        sound::play(Cue::Shutter);
+        let result = run_capture_loop(&app, &handle, region, options, &blanked).await;
+        frames::release();
+        overlay::close_overlays(&app);
         if let Err(e) = result {
-            println!("Capture loop error: {}", e);
-            let _ = app.emit("capture-error", e);
+            error!("Capture loop error: {}", e);
+            fail_capture(&app, &handle.id, e);
         }
     });
 
+    Ok(session_id)
+}
+
+/// Fails the capture if the capture task panics, which would otherwise leave the session
+/// stuck in Capturing and the windows ignoring the cursor
+struct PanicGuard {
+    app: AppHandle,
+    session_id: String,
+}
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            error!("Capture task panicked");
+            frames::release();
+            fail_capture(&self.app, &self.session_id, CaptureError::Internal("capture task panicked".to_string()));
+        }
+    }
+}
+
+/// Record, report and show a capture that ended in `e`, and give the windows back
+fn fail_capture(app: &AppHandle, session_id: &str, e: CaptureError) {
+    restore_windows(app);
+    stats::record(app, SessionRecord { error: Some(e.code().to_string()), ..SessionRecord::new(session_id) });
+    session::fail(app, e.localized());
+    notify::capture_failed(app, &e.localized());
+    let _ = app.emit("capture-error", &e);
+}
+
+/// Finish the capture with `session_id` and produce the image
+#[tauri::command]
+pub async fn stop_scroll_capture(session_id: String) -> Result<(), CaptureError> {
+    info!("Stopping capture {}...", session_id);
+    session::running_session(&session_id)?.request_stop();
     Ok(())
 }
 
+/// Take back the most recent stitch of the capture with `session_id`, e.g. one that caught a popup
 #[tauri::command]
-pub async fn stop_scroll_capture() -> Result<(), String> {
-    println!("Stopping capture...");
-    if let Some(flag) = CAPTURE_STATES.lock().unwrap().get("current") {
-        let mut stop = flag.lock().unwrap();
-        *stop = true;
-    }
+pub async fn undo_last_stitch(session_id: String) -> Result<(), CaptureError> {
+    info!("Undoing last stitch of capture {}...", session_id);
+    session::running_session(&session_id)?.request_undo();
+    Ok(())
+}
+
+/// Abort the capture with `session_id` without producing an image
+#[tauri::command]
+pub async fn cancel_scroll_capture(session_id: String) -> Result<(), CaptureError> {
+    info!("Cancelling capture {}...", session_id);
+    session::running_session(&session_id)?.request_cancel();
     Ok(())
 }
 
-fn run_capture_loop(app: &AppHandle, x: i32, y: i32, width: u32, height: u32, stop_flag: Arc<Mutex<bool>>) -> Result<(), String> {
+/// `blanked` are parts of the region grayed out in every fragment, see `privacy::check`
+async fn run_capture_loop(
+    app: &AppHandle,
+    handle: &SessionHandle,
+    region: Rect,
+    options: CaptureOptions,
+    blanked: &[Rect],
+) -> Result<(), CaptureError> {
     // 1. Initial Capture
-    // With WDA_EXCLUDEFROMCAPTURE and correct DPI scaling, we don't need to hide the window
-    let mut full_image = capture_rect(x, y, width, height).map_err(|e| e.to_string())?;
+    // No need to hide our windows: they are excluded from capture or sit outside the region,
+    // see `overlay::exclude_from_capture`
+    let backend = backend::for_region(options.backend, &region)?;
+    let pane = if options.accessibility { blocking(|| ScrollPane::at(&region)) } else { None };
+    let mut first_frame = capture_region_async(&backend, region).await?;
+    privacy::blank(&mut first_frame, blanked);
+    let started = Instant::now();
+    let mut canvas = Canvas::new(&first_frame);
+    canvas.set_memory_limit(settings::current().memory);
+
+    // Raw fragments for stitch bug reports, see `replay_session`
+    let mut dump = settings::current().debug_dump.then(|| DebugDump::create(app, &handle.id)).flatten();
+    if let Some(dump) = dump.as_mut() {
+        dump.frame(0, &first_frame);
+        dump.record(0, 0, FrameOutcome::Stitched);
+    }
     
-    // Allow up to 500 stitches (very long image)
-    let max_stitches = 500; 
     let mut stitch_count = 0;
-
-    println!("Entering capture loop. Please scroll manually.");
+    let mut autosave = Autosave::create(app, &handle.id, region, canvas.width());
     
-    // Initialize device query state
-    let device_state = DeviceState::new();
+    // The most recently stitched fragment. Its bottom is the bottom of the canvas,
+    // so matching against it is equivalent to matching the canvas but doesn't grow with it.
+    let mut last_frame = first_frame;
+    let mut static_count = 0;
+    // Consecutive changed frames that didn't overlap the last one
+    let mut missed_count = 0;
+    // Fragments that didn't overlap the canvas, chained to each other in scroll order.
+    // Each entry holds the overlap with the entry before it (unused for the first one) and the frame index.
+    // Once a new fragment bridges the gap, the whole chain is stitched back on.
+    let mut pending: Vec<(DynamicImage, u32, u32)> = Vec::new();
+    let mut torn_count = 0;
+
+    // Monitor layout at the start. The region is in physical desktop pixels, so any change
+    // to it means we'd be capturing something else from here on.
+    let layout = display::get_displays()?;
+    let mut frame_count = 0;
+    let mut interrupt_message = None;
+    // Size of the fragments while the backend returns something other than the region size
+    let mut resized_to: Option<(u32, u32)> = None;
+    let composite = settings::current().composite;
+    let mut metrics = MetricsRecorder::default();
+    let mut record = SessionRecord::new(&handle.id);
+
+    info!("Entering capture loop ({} backend). Please scroll manually.", backend.name());
+    let _ = app.emit("capture-progress", progress(&canvas, stitch_count, pane.as_ref()));
 
-    loop {
-        // Check stop flag from shortcut polling
-        let keys: Vec<Keycode> = device_state.get_keys();
-        if keys.contains(&Keycode::Escape) {
-             println!("Escape key detected via polling. Stopping capture.");
-             break;
+    let mut pacer = Pacer::new(Duration::from_millis(options.poll_interval_ms.max(1)), options.adaptive_interval);
+    let mut frame_started = Instant::now();
+    // The HUD is told once per pause that we're waiting for the user
+    let mut waiting_announced = false;
+
+    let stop_reason = loop {
+        metrics.finish_frame(app);
+
+        // Check cancel/stop flags, set by the stop shortcut and the commands
+        if handle.cancel_requested() {
+            warn!("Cancel flag detected. Discarding capture.");
+            break StopReason::Cancelled;
+        }
+        if handle.stop_requested() {
+            info!("Stop flag detected. Finishing capture.");
+            break StopReason::User;
         }
 
-        // Check stop flag from command
-        {
-            let stop = stop_flag.lock().unwrap();
-            if *stop {
-                println!("Stop flag detected. Finishing capture.");
-                break;
+        let undo_requests = handle.take_undo_requests();
+        if undo_requests > 0 {
+            for _ in 0..undo_requests {
+                if !blocking(|| canvas.undo_last_segment())? {
+                    info!("Nothing left to undo.");
+                    break;
+                }
+                stitch_count -= 1;
             }
+            // Continue matching from what is now the bottom of the canvas. Pending fragments
+            // were below the removed part, the user has to scroll back over them anyway.
+            frames::recycle(std::mem::replace(&mut last_frame, blocking(|| canvas.bottom_rows(region.height))?));
+            recycle_pending(&mut pending);
+            if let Some(autosave) = autosave.as_mut() {
+                autosave.truncate(canvas.height());
+            }
+            info!("Undid stitches, canvas is now {}px tall.", canvas.height());
+            let _ = app.emit("capture-progress", progress(&canvas, stitch_count, pane.as_ref()));
         }
 
-        if stitch_count >= max_stitches {
-            println!("Reached max stitches limit.");
-            break;
+        if stitch_count >= options.max_stitches {
+            info!("Reached max stitches limit.");
+            break StopReason::MaxStitches;
         }
         
-        // 2. Wait a bit for user to scroll
-        thread::sleep(Duration::from_millis(100));
+        // 2. Wait a bit for user to scroll (or scroll ourselves), a stop or cancel request cuts the wait short
+        match &options.auto_scroll {
+            Some(auto_scroll) => {
+                if let Err(e) = blocking(|| autoscroll::step(auto_scroll, pane.as_ref())) {
+                    warn!("Auto-scroll failed: {}", e);
+                    interrupt_message = Some(e.localized());
+                    record.error = Some(e.code().to_string());
+                    break StopReason::CaptureFailed;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(auto_scroll.settle_delay_ms)) => {}
+                    _ = handle.requested() => continue,
+                }
+            }
+            None => tokio::select! {
+                _ = tokio::time::sleep_until((frame_started + pacer.interval()).into()) => {}
+                _ = handle.requested() => continue,
+            },
+        }
+        frame_started = Instant::now();
         
         // 3. Capture new fragment
         // No need to hide window
-        let new_fragment = match capture_rect(x, y, width, height) {
-            Ok(img) => img,
+        frame_count += 1;
+        let capture_started = Instant::now();
+        let captured = capture_region_async(&backend, region).await;
+        metrics.start_frame(frame_count, capture_started.elapsed());
+        let new_fragment = match captured {
+            Ok(img) => {
+                let size = img.dimensions();
+                let (mut img, fit) = fit_fragment(img, region.width, region.height, &composite);
+                privacy::blank(&mut img, blanked);
+                match fit {
+                    // Reported once per size, not on every frame
+                    Some(fit) if resized_to != Some(size) => {
+                        warn!("Fragment size changed to {}x{}, {:?} to {}x{}.", size.0, size.1, fit, region.width, region.height);
+                        let _ = app.emit("fragment-resized", FragmentResized {
+                            width: size.0,
+                            height: size.1,
+                            expected_width: region.width,
+                            expected_height: region.height,
+                            fit,
+                        });
+                        resized_to = Some(size);
+                    }
+                    None if resized_to.is_some() => {
+                        info!("Fragments are back to {}x{}.", region.width, region.height);
+                        resized_to = None;
+                    }
+                    _ => {}
+                }
+                img
+            }
             Err(e) => {
-                println!("Capture failed: {}", e);
-                break;
+                error!("Capture failed: {}", e);
+                interrupt_message = Some(e.localized());
+                record.error = Some(e.code().to_string());
+                if layout_changed(&layout) {
+                    break StopReason::DisplayChanged;
+                }
+                break StopReason::CaptureFailed;
             }
         };
+        if let Some(dump) = &dump {
+            dump.frame(frame_count, &new_fragment);
+        }
+
+        // A removed monitor doesn't make captures fail, its part of the region just comes back empty
+        if frame_count % DISPLAY_CHECK_FRAMES == 0 && layout_changed(&layout) {
+            info!("Display configuration changed. Stopping capture.");
+            interrupt_message = Some(i18n::text("capture.display_changed", &[]));
+            break StopReason::DisplayChanged;
+        }
         
-        // 4. Calculate overlap
-        let overlap_index = stitch::calculate_overlap(&full_image, &new_fragment);
-        
-        // Check for static content (identical image)
-        if overlap_index == new_fragment.height() - 1 {
+        // Check for static content (identical image).
+        // While fragments are pending the screen shows the end of that chain, not `last_frame`.
+        let current_frame = pending.last().map(|(frame, _, _)| frame).unwrap_or(&last_frame);
+        let compare_started = Instant::now();
+        let is_same = blocking(|| stitch::is_same_frame(current_frame, &new_fragment));
+        metrics.add_compare(compare_started.elapsed());
+        if is_same {
+            static_count += 1;
+            pacer.on_static();
+            if let Some(dump) = dump.as_mut() {
+                dump.record(frame_count, 0, FrameOutcome::Static);
+            }
+
+            // Tell the HUD once per pause, not on every frame
+            let idle_frames = pacer.idle_frames(static_count);
+            if idle_frames >= WAITING_FOR_SCROLL_COUNT && !waiting_announced {
+                waiting_announced = true;
+                let _ = app.emit("waiting-for-scroll", ());
+            }
+            
+            if options.auto_scroll.is_some() && static_count >= AUTO_SCROLL_STATIC_COUNT {
+                info!("Auto-scroll key press didn't move the content. Stopping capture.");
+                break if stitch_count > 0 { StopReason::ReachedEnd } else { StopReason::Idle };
+            }
+
+            // Only auto-stop once something was captured, before that the user may still be getting ready
+            if stitch_count > 0 {
+                let at_bottom = match &pane {
+                    Some(pane) => blocking(|| pane.at_bottom()),
+                    None => stitch::scrollbar_at_bottom(&new_fragment) == Some(true),
+                };
+                if static_count >= END_OF_PAGE_STATIC_COUNT && at_bottom {
+                    info!("Scrollbar reached the bottom. Stopping capture.");
+                    break StopReason::ReachedEnd;
+                }
+                if idle_frames >= options.max_static_count {
+                    info!("No scrolling for {} frames. Stopping capture.", idle_frames);
+                    break StopReason::Idle;
+                }
+            }
+            
             // Just continue loop, waiting for user to scroll or stop
+            frames::recycle(new_fragment);
             continue;
         }
+        static_count = 0;
+        waiting_announced = false;
+        pacer.on_moving(frame_started.elapsed());
+        
+        // 4. Calculate overlap
+        let compare_started = Instant::now();
+        let overlap_index = blocking(|| stitch::calculate_overlap_with(&last_frame, &new_fragment, options.overlap_search));
+        metrics.add_compare(compare_started.elapsed());
         
         // Check for no overlap (too fast or error)
         if overlap_index == 0 {
-             continue;
+            if let Some(dump) = dump.as_mut() {
+                dump.record(frame_count, 0, FrameOutcome::NoOverlap);
+            }
+            // The page moved further than one fragment. Warn once per streak so the user
+            // can scroll less between pauses (or back a bit to fill the gap).
+            missed_count += 1;
+            record.unmatched += 1;
+            if missed_count == 1 {
+                info!("No overlap with previous frame, user scrolled too fast.");
+                let _ = app.emit("scroll-too-fast", ());
+            }
+
+            // Keep the fragment, it may still connect once the gap is filled
+            let compare_started = Instant::now();
+            let chain_overlap = pending.last()
+                .map(|(frame, _, _)| blocking(|| stitch::calculate_overlap_with(frame, &new_fragment, options.overlap_search)))
+                .unwrap_or(0);
+            metrics.add_compare(compare_started.elapsed());
+            if chain_overlap == 0 {
+                // Doesn't continue the current chain either, start a new one
+                recycle_pending(&mut pending);
+            }
+            pending.push((new_fragment, chain_overlap, frame_count));
+            if pending.len() > MAX_PENDING_FRAGMENTS {
+                frames::recycle(pending.remove(0).0);
+            }
+            continue;
         }
+        missed_count = 0;
+
+        // Fast scrolling on slow GPUs can catch the window mid-repaint, stitching that would
+        // bake garbage rows into the result. Drop it and look again on the next frame.
+        let compare_started = Instant::now();
+        let is_torn = blocking(|| stitch::is_torn_frame(&last_frame, &new_fragment, overlap_index));
+        metrics.add_compare(compare_started.elapsed());
+        if is_torn {
+            torn_count += 1;
+            record.torn += 1;
+            if let Some(dump) = dump.as_mut() {
+                dump.record(frame_count, overlap_index, FrameOutcome::Torn);
+            }
+            if torn_count <= MAX_TORN_RETRIES {
+                debug!("Frame looks torn (mid-repaint), recapturing.");
+                frames::recycle(new_fragment);
+                continue;
+            }
+            warn!("Frame still looks torn after {} retries, stitching anyway.", MAX_TORN_RETRIES);
+        }
+        torn_count = 0;
         
-        println!("Stitching: overlap index {}", overlap_index);
+        debug!("Stitching: overlap index {}", overlap_index);
 
         // 5. Stitch
-        full_image = stitch::append_image(&full_image, &new_fragment, overlap_index);
+        session::transition(app, CaptureState::Stitching)?;
+        let stitch_started = Instant::now();
+        blocking(|| -> Result<(), CaptureError> {
+            let confidence = stitch::overlap_confidence(&last_frame, &new_fragment, overlap_index);
+            canvas.append(&new_fragment, Stitch { frame_index: frame_count, overlap: overlap_index, confidence })
+        })?;
         stitch_count += 1;
+        sound::play(Cue::Tick);
+        frames::recycle(std::mem::replace(&mut last_frame, new_fragment));
+        if let Some(dump) = dump.as_mut() {
+            dump.record(frame_count, overlap_index, FrameOutcome::Stitched);
+        }
+
+        // 6. See if the new fragment bridges the gap to the pending chain.
+        // Either way the chain is done afterwards: it's stitched, or it was content we already have.
+        if !pending.is_empty() {
+            blocking(|| -> Result<(), CaptureError> {
+                let bridge = pending.iter().enumerate().find_map(|(i, (frame, _, _))| {
+                    let overlap = stitch::calculate_overlap_with(&last_frame, frame, options.overlap_search);
+                    (overlap > 0).then_some((i, overlap))
+                });
+                if let Some((start, bridge_overlap)) = bridge {
+                    let recovered = pending.len() - start;
+                    info!("Recovered {} pending fragments", recovered);
+                    for (i, (frame, overlap, frame_index)) in pending.drain(start..).enumerate() {
+                        let overlap = if i == 0 { bridge_overlap } else { overlap };
+                        let confidence = stitch::overlap_confidence(&last_frame, &frame, overlap);
+                        canvas.append(&frame, Stitch { frame_index, overlap, confidence })?;
+                        if let Some(dump) = dump.as_mut() {
+                            dump.record(frame_index, overlap, FrameOutcome::Stitched);
+                        }
+                        frames::recycle(std::mem::replace(&mut last_frame, frame));
+                    }
+                    stitch_count += recovered as u32;
+                }
+                recycle_pending(&mut pending);
+                Ok(())
+            })?;
+        }
+        metrics.add_stitch(stitch_started.elapsed());
+
+        if let Some(pressure) = canvas.take_pressure() {
+            warn!("Canvas reached the {} MB memory cap ({:?}, 1/{} scale).", pressure.cap_mb, pressure.action, pressure.scale);
+            let _ = app.emit("memory-pressure", pressure);
+            // Checkpoints so far have the old width, start over at the new resolution
+            if pressure.action == PressureAction::Downscale {
+                if let Some(previous) = autosave.take() {
+                    previous.discard();
+                    autosave = Autosave::create(app, &handle.id, region, canvas.width());
+                }
+            }
+        }
+
+        if let Some(autosave) = autosave.as_mut() {
+            blocking(|| autosave.checkpoint(&mut canvas, stitch_count));
+        }
+        let _ = app.emit("capture-progress", progress(&canvas, stitch_count, pane.as_ref()));
+        session::transition(app, CaptureState::Capturing)?;
+
+        // The pane tells us where the end is, no need to press once more and see nothing move
+        if options.auto_scroll.is_some() && pane.as_ref().is_some_and(|pane| blocking(|| pane.at_bottom())) {
+            info!("Auto-scroll reached the bottom of the pane. Stopping capture.");
+            break StopReason::ReachedEnd;
+        }
+    };
+
+    metrics.finish_frame(app);
+    if !pending.is_empty() {
+        warn!("Discarding {} fragments that never connected to the capture", pending.len());
+    }
+    
+    info!("Capture finished ({:?}). Total height: {}", stop_reason, canvas.height());
+    record.stop_reason = Some(stop_reason);
+    record.frames = frame_count;
+    record.stitches = stitch_count;
+    record.discarded = pending.len() as u32;
+    let _ = app.emit("capture-stopped", stop_reason);
+    if let Some(message) = interrupt_message {
+        let _ = app.emit("capture-interrupted", CaptureInterrupted { reason: stop_reason, message });
     }
     
-    println!("Capture finished. Total height: {}", full_image.height());
+    if stop_reason == StopReason::Cancelled {
+        if let Some(autosave) = autosave {
+            autosave.discard();
+        }
+        restore_windows(app);
+        stats::record(app, record);
+        session::finish_cancel(app)?;
+        let _ = app.emit("capture-cancelled", ());
+        return Ok(());
+    }
     
-    // Convert to Base64
-    let base64_img = image_to_base64(&full_image).map_err(|e| e.to_string())?;
+    session::transition(app, CaptureState::Encoding)?;
+    
+    let autocrop = settings::current().autocrop;
+    let mut capture_metadata = CaptureMetadata::new(app.package_info().version.to_string(), Some(region), &layout, stitch_count);
+    capture_metadata.capture_ms = Some(started.elapsed().as_millis() as u64);
+    capture_metadata.options = Some(options);
+    capture_metadata.downscale = (canvas.scale() > 1).then_some(canvas.scale());
+    let summary = metrics.summary();
+    info!(
+        "Frame timings over {} frames: capture {:.1}ms, compare {:.1}ms, stitch {:.1}ms on average (worst frame {:.1}ms)",
+        summary.frames, summary.capture.avg_ms, summary.compare.avg_ms, summary.stitch.avg_ms, summary.total.max_ms
+    );
+    capture_metadata.metrics = Some(summary);
+    // Only a preview is encoded here, the full image once it's saved or copied
+    let pipeline_app = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let encode_started = Instant::now();
+        if autocrop {
+            if let Some(bounds) = canvas.content_bounds()? {
+                info!("Auto-cropping capture to {:?}", bounds);
+                canvas = canvas.cropped(bounds)?;
+            }
+        }
+        capture_metadata.encode_ms = Some(encode_started.elapsed().as_millis() as u64);
+        pipeline::run(&pipeline_app, canvas, Some(capture_metadata), "capture")
+    })
+    .await
+    .map_err(|e| CaptureError::Internal(format!("encoding task failed: {}", e)))??;
+    (record.width, record.height) = (result.width, result.height);
+    stats::record(app, record);
+    // The result is in the store now, a crash from here on loses nothing the autosave has
+    if let Some(autosave) = autosave {
+        autosave.discard();
+    }
+    
+    restore_windows(app);
+
+    // Emit event with result
+    let (width, height) = (result.width, result.height);
+    app.emit("capture-complete", result).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    session::transition(app, CaptureState::Done)?;
+    sound::play(Cue::Chime);
+    notify::capture_complete(app, width, height);
+    
+    Ok(())
+}
+
+/// Re-enable cursor events for ALL windows and bring them back
+fn restore_windows(app: &AppHandle) {
+    // Overlays must go first, otherwise they would be shown and focused below
+    overlay::close_overlays(app);
     
-    // Re-enable cursor events for ALL windows before showing them
     let windows = app.webview_windows();
     for (label, window) in windows {
-        println!("Restoring cursor events for window: {}", label);
+        debug!("Restoring cursor events for window: {}", label);
         let _ = window.set_ignore_cursor_events(false);
         let _ = window.show();
         let _ = window.set_focus();
     }
-
-    // Emit event with result
-    app.emit("capture-complete", base64_img).map_err(|e| e.to_string())?;
-    
-    Ok(())
 }
 
+#[allow(dead_code)] // Capture keeps the windows shown with cursor events off, see `start_capture`
 fn toggle_window_visibility(app: &AppHandle, visible: bool) {
     let windows = app.webview_windows();
     for (_label, window) in windows {
@@ -161,76 +850,74 @@ fn toggle_window_visibility(app: &AppHandle, visible: bool) {
     }
 }
 
-fn capture_rect(x: i32, y: i32, width: u32, height: u32) -> Result<DynamicImage, String> {
-    let monitors = Monitor::all().map_err(|e| format!("Failed to get monitors: {}", e))?;
-    
-    // Find the monitor that contains the point (x, y)
-    let monitor = monitors.iter().find(|m| {
-        let mx = m.x().unwrap_or(0);
-        let my = m.y().unwrap_or(0);
-        let mw = m.width().unwrap_or(0);
-        let mh = m.height().unwrap_or(0);
-        
-        // Check if the center of the rect is within this monitor
-        let cx = x + (width as i32 / 2);
-        let cy = y + (height as i32 / 2);
-        
-        cx >= mx && cx < mx + mw as i32 && cy >= my && cy < my + mh as i32
-    }).or(monitors.first()).ok_or("No monitor found")?;
-
-    // No shrinking needed anymore
-    
-    // Calculate relative coordinates within the monitor
-    // IMPORTANT: xcap uses physical pixels, but Tauri often provides logical pixels.
-    // However, the `x` and `y` we get from Tauri's `start_scroll_capture` command 
-    // are usually physical coordinates if they come from `window.innerPosition` + `window.innerSize` 
-    // or if the frontend logic handles it.
-    // BUT, let's verify if `scale_factor` is needed.
-    // If the user's input `x, y, width, height` are Logical, we must multiply by `scale_factor`.
-    // Assuming they are Logical (CSS pixels) from the frontend overlay.
-    
-    let scale_factor = monitor.scale_factor().unwrap_or(1.0);
-    
-    // Convert input (logical) to physical
-    // Note: We need to be careful. If the input IS physical, this double-scales.
-    // Let's assume input is Logical because it comes from a web frontend.
-    let phys_x = (x as f32 * scale_factor) as i32;
-    let phys_y = (y as f32 * scale_factor) as i32;
-    let phys_w = (width as f32 * scale_factor) as u32;
-    let phys_h = (height as f32 * scale_factor) as u32;
-    
-    // Now calculate relative to monitor
-    let mx = monitor.x().unwrap_or(0);
-    let my = monitor.y().unwrap_or(0);
-    
-    let rx = phys_x - mx;
-    let ry = phys_y - my;
-    
-    // Use xcap's capture_area if available, or capture and crop
-    // xcap returns an image::RgbaImage directly
-    let image = monitor.capture_image()
-        .map_err(|e| format!("Failed to capture monitor: {}", e))?;
-    
-    // Crop the image
-    let img_width = image.width();
-    let img_height = image.height();
-    
-    let crop_x = if rx < 0 { 0 } else { rx as u32 };
-    let crop_y = if ry < 0 { 0 } else { ry as u32 };
-    let crop_w = if crop_x + phys_w > img_width { img_width - crop_x } else { phys_w };
-    let crop_h = if crop_y + phys_h > img_height { img_height - crop_y } else { phys_h };
-    
-    let mut dynamic_image = DynamicImage::ImageRgba8(image);
-    let cropped_image = dynamic_image.crop(crop_x, crop_y, crop_w, crop_h);
-        
-    Ok(cropped_image)
+/// Capture a physical region of the virtual desktop with the current backend, see `backend`
+pub fn capture_region(region: &Rect) -> Result<DynamicImage, CaptureError> {
+    backend::current().capture_region(region)
 }
 
-fn image_to_base64(img: &DynamicImage) -> Result<String, String> {
-    let mut buf = Cursor::new(Vec::new());
-    img.write_to(&mut buf, ImageFormat::Png)
-        .map_err(|e| format!("Failed to encode image: {}", e))?;
-        
-    let res_base64 = general_purpose::STANDARD.encode(buf.into_inner());
-    Ok(format!("data:image/png;base64,{}", res_base64))
+/// `capture_region` behind the privacy guard, see `privacy::check`
+pub fn capture_checked(region: &Rect, allow_sensitive: bool) -> Result<DynamicImage, CaptureError> {
+    let blanked = privacy::check(region, allow_sensitive)?;
+    let mut image = capture_region(region)?;
+    privacy::blank(&mut image, &blanked);
+    Ok(image)
+}
+
+/// Bring a fragment to the region size when the capture backend returns something else, e.g.
+/// after a scaling change. Stitching compares fragments pixel by pixel and the canvas has a
+/// fixed width, so they all have to be the same size. Returns how it was fitted, `None` if
+/// it already had the right size.
+pub fn fit_fragment(img: DynamicImage, width: u32, height: u32, composite: &CompositeOptions) -> (DynamicImage, Option<FragmentFit>) {
+    if img.dimensions() == (width, height) {
+        return (img, None);
+    }
+
+    let aspect = |w: u32, h: u32| w as f64 / h.max(1) as f64;
+    let expected = aspect(width, height);
+    if img.width() > 0 && img.height() > 0 && (aspect(img.width(), img.height()) - expected).abs() / expected < RESCALE_ASPECT_TOLERANCE {
+        let resized = img.resize_exact(width, height, imageops::FilterType::Triangle);
+        return (DynamicImage::ImageRgba8(resized.to_rgba8()), Some(FragmentFit::Rescaled));
+    }
+
+    let background = composite.background().unwrap_or_else(|e| {
+        warn!("{}, letterboxing on transparent", e);
+        Rgba([0, 0, 0, 0])
+    });
+    (composite::place(&img, width, height, composite.anchor, background), Some(FragmentFit::Letterboxed))
+}
+
+/// Whether monitors were added, removed, moved, resized or rescaled since `layout` was taken.
+/// Failing to list them counts as a change too.
+fn layout_changed(layout: &[DisplayInfo]) -> bool {
+    display::get_displays().map_or(true, |now| now != layout)
+}
+
+/// Empty the pending fragments, handing their buffers back for reuse
+fn recycle_pending(pending: &mut Vec<(DynamicImage, u32, u32)>) {
+    for (frame, _, _) in pending.drain(..) {
+        frames::recycle(frame);
+    }
+}
+
+/// Progress of the capture so far, with the scroll position when the pane has one
+fn progress(canvas: &Canvas, stitch_count: u32, pane: Option<&ScrollPane>) -> CaptureProgress {
+    CaptureProgress {
+        height: canvas.height(),
+        stitch_count,
+        scroll_percent: pane.and_then(|pane| blocking(|| pane.vertical_percent())),
+    }
+}
+
+/// Run CPU-heavy pixel work inside the capture task without stalling the other tasks
+/// scheduled on the same runtime worker
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    tokio::task::block_in_place(f)
+}
+
+/// Grab a fragment on the blocking pool, screen capture APIs are synchronous
+async fn capture_region_async(backend: &Arc<dyn CaptureBackend>, region: Rect) -> Result<DynamicImage, CaptureError> {
+    let backend = backend.clone();
+    tauri::async_runtime::spawn_blocking(move || backend.capture_region(&region))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("capture task failed: {}", e)))?
 }
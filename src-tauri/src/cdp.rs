@@ -0,0 +1,174 @@
+use futures_util::{SinkExt, StreamExt};
+use image::DynamicImage;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tauri::AppHandle;
+use tracing::info;
+use crate::canvas::{Canvas, Stitch};
+use crate::error::CaptureError;
+use crate::pipeline;
+use crate::store::CaptureResult;
+use crate::utils;
+
+/// Port Chrome and Edge use with a bare `--remote-debugging-port`
+const DEFAULT_PORT: u16 = 9222;
+
+/// Page height (CSS pixels) per screenshot. Chrome can't render arbitrarily tall surfaces
+/// in one go, long pages come back blank or cut off past ~16k device pixels.
+const CHUNK_HEIGHT: f64 = 4096.0;
+
+/// Max wait for one DevTools call, screenshots of long pages take a while
+const CALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Entry of the browser's `/json` target list
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Target {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    web_socket_debugger_url: Option<String>,
+}
+
+/// Minimal Chrome DevTools Protocol client: request/response over the target's WebSocket
+struct Cdp {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl Cdp {
+    async fn connect(url: &str) -> Result<Self, CaptureError> {
+        let (ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| CaptureError::BrowserUnavailable(format!("could not connect to {}: {}", url, e)))?;
+        Ok(Self { ws, next_id: 1 })
+    }
+
+    /// Send a command and wait for its result, skipping events that arrive in between
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value, CaptureError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "id": id, "method": method, "params": params });
+        let failed = |e: String| CaptureError::CaptureFailed(format!("{}: {}", method, e));
+        self.ws.send(Message::Text(request.to_string())).await.map_err(|e| failed(e.to_string()))?;
+
+        tokio::time::timeout(CALL_TIMEOUT, async {
+            while let Some(message) = self.ws.next().await {
+                let Message::Text(text) = message.map_err(|e| failed(e.to_string()))? else {
+                    continue;
+                };
+                let mut response: Value = serde_json::from_str(&text).map_err(|e| failed(e.to_string()))?;
+                if response["id"].as_u64() != Some(id) {
+                    continue;
+                }
+                if let Some(error) = response.get("error") {
+                    return Err(failed(error["message"].as_str().unwrap_or("unknown error").to_string()));
+                }
+                return Ok(response["result"].take());
+            }
+            Err(failed("browser closed the connection".to_string()))
+        })
+        .await
+        .map_err(|_| failed("timed out".to_string()))?
+    }
+}
+
+/// Full-page capture of a browser tab through the Chrome DevTools Protocol.
+/// The browser renders the page itself, so there are no stitching heuristics involved and
+/// sticky headers, lazy images etc. come out right. Needs Chrome/Edge started with
+/// `--remote-debugging-port` (default 9222). Picks the first tab whose URL contains
+/// `url_contains`, or the first tab. A `BROWSER_UNAVAILABLE` error means the UI should
+/// fall back to a normal scroll capture.
+#[tauri::command]
+pub async fn capture_browser_page(app: AppHandle, port: Option<u16>, url_contains: Option<String>) -> Result<CaptureResult, CaptureError> {
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let (url, ws_url) = find_target(port, url_contains.as_deref()).await?;
+    info!("Capturing browser page {} over DevTools", url);
+
+    let mut cdp = Cdp::connect(&ws_url).await?;
+    let metrics = cdp.call("Page.getLayoutMetrics", json!({})).await?;
+    // `cssContentSize` is in CSS pixels, older browsers only have `contentSize`
+    let size = metrics.get("cssContentSize").or_else(|| metrics.get("contentSize"))
+        .ok_or_else(|| CaptureError::CaptureFailed("browser did not report the page size".to_string()))?;
+    let width = size["width"].as_f64().unwrap_or(0.0).ceil();
+    let height = size["height"].as_f64().unwrap_or(0.0).ceil();
+    if width < 1.0 || height < 1.0 {
+        return Err(CaptureError::CaptureFailed(format!("page has no content ({}x{})", width, height)));
+    }
+
+    // Capture at the device pixel ratio so the result matches what is on screen
+    let ratio = cdp
+        .call("Runtime.evaluate", json!({ "expression": "window.devicePixelRatio", "returnByValue": true }))
+        .await?["result"]["value"]
+        .as_f64()
+        .unwrap_or(1.0);
+
+    let mut canvas: Option<Canvas> = None;
+    let mut y = 0.0;
+    let mut index = 0;
+    while y < height {
+        let chunk_height = (height - y).min(CHUNK_HEIGHT);
+        let shot = cdp
+            .call("Page.captureScreenshot", json!({
+                "format": "png",
+                "captureBeyondViewport": true,
+                "clip": { "x": 0, "y": y, "width": width, "height": chunk_height, "scale": ratio },
+            }))
+            .await?;
+        let data = shot["data"].as_str()
+            .ok_or_else(|| CaptureError::CaptureFailed("screenshot without image data".to_string()))?
+            .to_string();
+
+        let chunk = tauri::async_runtime::spawn_blocking(move || -> Result<DynamicImage, CaptureError> {
+            let bytes = utils::decode_base64_image(&data)?;
+            let img = image::load_from_memory(&bytes).map_err(|e| CaptureError::DecodeFailed(e.to_string()))?;
+            Ok(DynamicImage::ImageRgba8(img.to_rgba8()))
+        })
+        .await
+        .map_err(|e| CaptureError::Internal(format!("decode task failed: {}", e)))??;
+
+        // Chunks line up exactly, nothing to match
+        match canvas.as_mut() {
+            None => canvas = Some(Canvas::new(&chunk)),
+            Some(canvas) => canvas.append(&chunk, Stitch { frame_index: index, overlap: 0, confidence: 1.0 })?,
+        }
+        y += chunk_height;
+        index += 1;
+    }
+
+    let canvas = canvas.unwrap();
+    info!("Browser capture finished, {}x{} in {} screenshots", canvas.width(), canvas.height(), index);
+    tauri::async_runtime::spawn_blocking(move || pipeline::run(&app, canvas, None, "browser"))
+    .await
+    .map_err(|e| CaptureError::Internal(format!("encode task failed: {}", e)))?
+}
+
+/// URL and DevTools WebSocket of the tab to capture
+async fn find_target(port: u16, url_contains: Option<&str>) -> Result<(String, String), CaptureError> {
+    let list_url = format!("http://127.0.0.1:{}/json", port);
+    let unavailable = |e: reqwest::Error| CaptureError::BrowserUnavailable(format!("{}: {}", list_url, e));
+    let targets: Vec<Target> = reqwest::get(&list_url)
+        .await
+        .map_err(unavailable)?
+        .json()
+        .await
+        .map_err(unavailable)?;
+
+    targets
+        .into_iter()
+        .filter(|target| target.kind == "page")
+        .filter(|target| match url_contains {
+            Some(part) => target.url.contains(part),
+            None => true,
+        })
+        .find_map(|target| target.web_socket_debugger_url.map(|ws_url| (target.url, ws_url)))
+        .ok_or_else(|| CaptureError::BrowserUnavailable(match url_contains {
+            Some(part) => format!("no tab with '{}' in its URL on port {}", part, port),
+            None => format!("no open tab on port {}", port),
+        }))
+}
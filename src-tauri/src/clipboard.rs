@@ -0,0 +1,117 @@
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use crate::decorate;
+use crate::error::CaptureError;
+use crate::settings;
+
+/// Pause between attempts while another app holds the clipboard
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardSettings {
+    /// How long to keep retrying while the clipboard is locked. On Windows clipboard
+    /// managers and remote desktop clients open it all the time, the first try often fails.
+    pub timeout_ms: u64,
+    /// Linux only: keep serving the copied image from a background thread until something else
+    /// is copied. X11/Wayland clipboards are owned by the copying app, without this the image
+    /// is gone as soon as our handle is dropped unless a clipboard manager took it over.
+    pub keep_alive: bool,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        Self { timeout_ms: 2000, keep_alive: true }
+    }
+}
+
+/// Put the image on the clipboard, with the export watermark/caption if enabled.
+/// arboard writes it as PNG (lossless, with alpha) next to the bitmap formats:
+/// a registered "PNG" format ahead of CF_DIBV5 on Windows and `image/png` on Linux,
+/// macOS gets an NSImage. Apps pick the first format they understand.
+pub fn set_image(mut rgba: RgbaImage) -> Result<(), CaptureError> {
+    let export = settings::current().export;
+    if export.is_enabled() {
+        rgba = decorate::apply(rgba, &export)?;
+    }
+    let (w, h) = rgba.dimensions();
+    let image_data = ImageData {
+        width: w as usize,
+        height: h as usize,
+        bytes: Cow::from(rgba.into_raw()),
+    };
+
+    let options = settings::current().clipboard;
+    #[cfg(target_os = "linux")]
+    if options.keep_alive {
+        return set_and_serve(image_data, options.timeout_ms);
+    }
+
+    retry(options.timeout_ms, || {
+        let mut clipboard = Clipboard::new()?;
+        clipboard.set_image(image_data.clone())
+    })
+}
+
+/// Put text on the clipboard, retrying like `set_image`
+pub fn set_text(text: String) -> Result<(), CaptureError> {
+    retry(settings::current().clipboard.timeout_ms, || {
+        let mut clipboard = Clipboard::new()?;
+        clipboard.set_text(text.clone())
+    })
+}
+
+/// Run `f` until it succeeds or `timeout_ms` passed, the last error is returned
+fn retry<T>(timeout_ms: u64, mut f: impl FnMut() -> Result<T, arboard::Error>) -> Result<T, CaptureError> {
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if Instant::now() < deadline => {
+                if attempts == 1 {
+                    debug!("Clipboard busy ({}), retrying", e);
+                }
+                thread::sleep(RETRY_DELAY);
+            }
+            Err(e) => {
+                warn!("Giving up on the clipboard after {} attempts", attempts);
+                return Err(CaptureError::ClipboardBusy(e.to_string()));
+            }
+        }
+    }
+}
+
+/// Set the image from a thread that stays around, serving paste requests until the clipboard
+/// is taken over by another copy (ours included, the previous thread then ends)
+#[cfg(target_os = "linux")]
+fn set_and_serve(image_data: ImageData<'static>, timeout_ms: u64) -> Result<(), CaptureError> {
+    use arboard::SetExtLinux;
+    use std::sync::mpsc;
+
+    let (started, result) = mpsc::channel::<Result<(), CaptureError>>();
+    thread::spawn(move || {
+        let mut clipboard = match retry(timeout_ms, Clipboard::new) {
+            Ok(clipboard) => clipboard,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return;
+            }
+        };
+        // Confirm before blocking, `wait` only returns once someone else owns the clipboard
+        let _ = started.send(Ok(()));
+        if let Err(e) = clipboard.set().wait().image(image_data) {
+            warn!("Clipboard keep-alive ended with error: {}", e);
+        }
+    });
+    result
+        .recv()
+        .map_err(|_| CaptureError::ClipboardBusy("clipboard thread exited".to_string()))?
+}
+
@@ -0,0 +1,131 @@
+use serde::Serialize;
+use crate::canvas::Canvas;
+use crate::capture;
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::permission;
+use crate::store;
+
+/// Pixels looked at for a palette. Plenty for the dominant colors, and keeps huge captures fast.
+const PALETTE_SAMPLES: usize = 200_000;
+
+/// Most colors a palette can have
+const MAX_PALETTE_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Color {
+    /// "#rrggbb"
+    pub hex: String,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Share of the image close to this color, in percent. 100 for a picked pixel.
+    pub percent: f32,
+}
+
+impl Color {
+    fn new([r, g, b]: [u8; 3], percent: f32) -> Self {
+        Self { hex: format!("#{:02x}{:02x}{:02x}", r, g, b), r, g, b, percent }
+    }
+}
+
+/// Color of one pixel. `x`/`y` are capture pixels of the capture `id`, or physical
+/// desktop pixels without one, which samples the screen as it is right now.
+#[tauri::command]
+pub async fn pick_color(id: Option<String>, x: i32, y: i32) -> Result<Color, CaptureError> {
+    let pixel = Rect { x, y, width: 1, height: 1 };
+    let samples = match id {
+        Some(id) => store::edit(id, move |canvas| canvas.cropped(pixel)?.sample_pixels(1)).await?,
+        None => capture_samples(live_region(pixel)?, 1).await?,
+    };
+    let rgb = samples.first().copied()
+        .ok_or_else(|| CaptureError::Internal("no pixel was sampled".to_string()))?;
+    Ok(Color::new(rgb, 100.0))
+}
+
+/// The `n` (default 6) dominant colors of the capture `id`, or of `region` (physical desktop
+/// pixels) on the live screen, most common first
+#[tauri::command]
+pub async fn get_palette(id: Option<String>, n: Option<usize>, region: Option<Rect>) -> Result<Vec<Color>, CaptureError> {
+    let n = n.unwrap_or(6).clamp(1, MAX_PALETTE_SIZE);
+    let samples = match (region, id) {
+        (Some(region), _) => capture_samples(live_region(region)?, PALETTE_SAMPLES).await?,
+        (None, Some(id)) => store::edit(id, |canvas| canvas.sample_pixels(PALETTE_SAMPLES)).await?,
+        (None, None) => return Err(CaptureError::InvalidState("a capture id or a region is needed".to_string())),
+    };
+    Ok(palette(samples, n))
+}
+
+fn live_region(region: Rect) -> Result<Rect, CaptureError> {
+    permission::ensure_capture_permission()?;
+    display::validate_region(region)
+}
+
+async fn capture_samples(region: Rect, max: usize) -> Result<Vec<[u8; 3]>, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let image = capture::capture_region(&region)?;
+        Canvas::new(&image).sample_pixels(max)
+    })
+    .await
+    .map_err(|e| CaptureError::Internal(format!("color task failed: {}", e)))?
+}
+
+/// Median cut: keep splitting the box of colors with the widest channel range at its median
+/// until there are `n` boxes, each box's average is one palette color
+fn palette(samples: Vec<[u8; 3]>, n: usize) -> Vec<Color> {
+    let total = samples.len().max(1) as f32;
+    let mut boxes = vec![samples];
+    while boxes.len() < n {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by_key(|(_, (_, range))| *range);
+        let Some((index, (channel, range))) = widest else {
+            break;
+        };
+        if range == 0 {
+            // Every box is a single color already
+            break;
+        }
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|color| color[channel]);
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut colors: Vec<Color> = boxes
+        .iter()
+        .filter(|colors| !colors.is_empty())
+        .map(|colors| Color::new(average(colors), colors.len() as f32 * 100.0 / total))
+        .collect();
+    colors.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+    colors
+}
+
+/// Channel with the largest spread and that spread
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = colors.iter().fold((u8::MAX, 0), |(min, max), color| {
+                (min.min(color[channel]), max.max(color[channel]))
+            });
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+fn average(colors: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    for color in colors {
+        for channel in 0..3 {
+            sum[channel] += color[channel] as u64;
+        }
+    }
+    let count = colors.len().max(1) as u64;
+    sum.map(|channel| (channel / count) as u8)
+}
@@ -0,0 +1,55 @@
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use crate::decorate;
+use crate::error::CaptureError;
+
+/// Where fragments narrower or wider than the canvas are placed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    #[default]
+    Left,
+    Center,
+}
+
+/// How fragments of different sizes are put together, e.g. screenshots of different widths
+/// in `stitch_files` or frames the capture backend returned at another size
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompositeOptions {
+    pub anchor: Anchor,
+    /// Padding color as "#rgb", "#rrggbb" or "#rrggbbaa", transparent when unset.
+    /// Transparent padding stays transparent in every export format that has alpha.
+    pub background: Option<String>,
+}
+
+impl CompositeOptions {
+    pub fn background(&self) -> Result<Rgba<u8>, CaptureError> {
+        match &self.background {
+            Some(color) => decorate::parse_color(color).map(Rgba),
+            None => Ok(Rgba([0, 0, 0, 0])),
+        }
+    }
+}
+
+/// Bring `img` to `width`, padding with the background or cropping at the anchor.
+/// The fragment's own pixels are copied as they are, alpha included.
+pub fn align_width(img: &DynamicImage, width: u32, options: &CompositeOptions) -> Result<DynamicImage, CaptureError> {
+    if img.width() == width {
+        return Ok(img.clone());
+    }
+    Ok(place(img, width, img.height(), options.anchor, options.background()?))
+}
+
+/// Put `img` on a `width` x `height` background, at the anchor horizontally and centered
+/// vertically. Parts outside the new size are cut off.
+pub fn place(img: &DynamicImage, width: u32, height: u32, anchor: Anchor, background: Rgba<u8>) -> DynamicImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, background);
+    let x = match anchor {
+        Anchor::Left => 0,
+        Anchor::Center => (width as i64 - img.width() as i64) / 2,
+    };
+    let y = (height as i64 - img.height() as i64) / 2;
+    imageops::replace(&mut canvas, &img.to_rgba8(), x, y);
+    DynamicImage::ImageRgba8(canvas)
+}
@@ -0,0 +1,184 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use crate::canvas::{Canvas, Stitch};
+use crate::composite;
+use crate::encryption;
+use crate::error::CaptureError;
+use crate::settings;
+use crate::stitch;
+use crate::store::{self, CaptureResult};
+use crate::utils;
+
+const DEBUG_DIR: &str = "debug";
+const LOG_FILE: &str = "stitches.jsonl";
+
+/// What the capture loop did with a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrameOutcome {
+    /// Same content as the previous frame
+    Static,
+    /// Didn't overlap the canvas, kept as pending
+    NoOverlap,
+    /// Looked mid-repaint and was dropped
+    Torn,
+    Stitched,
+}
+
+/// One line of the stitch log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameRecord {
+    pub frame_index: u32,
+    pub overlap: u32,
+    pub outcome: FrameOutcome,
+}
+
+/// Raw fragments and stitch decisions of one capture, written when `debug_dump` is enabled.
+/// The folder is self-contained, so it can be zipped and attached to a bug report
+/// and replayed with `replay_session` on another machine. Fragments dumped while encryption
+/// is on are encrypted like the history, those only replay where the key is.
+pub struct DebugDump {
+    dir: PathBuf,
+    log: File,
+}
+
+impl DebugDump {
+    /// Create `<app data>/debug/<session id>/`. Failing is not fatal, the capture just runs without it.
+    pub fn create(app: &AppHandle, session_id: &str) -> Option<Self> {
+        let result = app
+            .path()
+            .app_data_dir()
+            .map_err(|e| CaptureError::Internal(e.to_string()))
+            .and_then(|dir| {
+                let dir = dir.join(DEBUG_DIR).join(session_id);
+                fs::create_dir_all(&dir)?;
+                let log = File::create(dir.join(LOG_FILE))?;
+                Ok(Self { dir, log })
+            });
+
+        match result {
+            Ok(dump) => {
+                info!("Dumping capture fragments to {}", dump.dir.display());
+                Some(dump)
+            }
+            Err(e) => {
+                warn!("Could not create debug dump directory: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Save a captured fragment. Encoding runs in the background so the capture keeps its pace.
+    pub fn frame(&self, frame_index: u32, frame: &DynamicImage) {
+        let path = frame_path(&self.dir, frame_index);
+        let frame = frame.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            let saved = utils::encode_png(&frame.to_rgba8(), |_| {})
+                .and_then(encryption::protect)
+                .and_then(|png| fs::write(&path, png).map_err(CaptureError::from));
+            if let Err(e) = saved {
+                warn!("Failed to dump fragment {}: {}", path.display(), e);
+            }
+        });
+    }
+
+    pub fn record(&mut self, frame_index: u32, overlap: u32, outcome: FrameOutcome) {
+        let record = FrameRecord { frame_index, overlap, outcome };
+        let written = serde_json::to_string(&record)
+            .map_err(std::io::Error::other)
+            .and_then(|line| writeln!(self.log, "{}", line));
+        if let Err(e) = written {
+            warn!("Failed to write stitch log: {}", e);
+        }
+    }
+}
+
+fn frame_path(dir: &Path, frame_index: u32) -> PathBuf {
+    dir.join(format!("frame-{:05}.png", frame_index))
+}
+
+/// Run the stitching again on the fragments of a debug dump, without a screen.
+/// Overlaps that differ from what the capture chose are logged. The result is stored like a new capture.
+#[tauri::command]
+pub async fn replay_session(dir: String) -> Result<CaptureResult, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || replay(Path::new(&dir)))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("replay task failed: {}", e)))?
+}
+
+fn replay(dir: &Path) -> Result<CaptureResult, CaptureError> {
+    // Overlaps the capture chose, to compare against
+    let mut recorded = HashMap::new();
+    if let Ok(log) = File::open(dir.join(LOG_FILE)) {
+        for line in BufReader::new(log).lines() {
+            if let Ok(record) = serde_json::from_str::<FrameRecord>(&line?) {
+                if record.outcome == FrameOutcome::Stitched {
+                    recorded.insert(record.frame_index, record.overlap);
+                }
+            }
+        }
+    }
+
+    let mut frames: Vec<(u32, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = path.file_name()?.to_str()?;
+            let index = name.strip_prefix("frame-")?.strip_suffix(".png")?.parse().ok()?;
+            Some((index, path))
+        })
+        .collect();
+    frames.sort();
+
+    let load = |path: &Path| {
+        image::load_from_memory(&encryption::read(path)?)
+            .map(|img| DynamicImage::ImageRgba8(img.to_rgba8()))
+            .map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", path.display(), e)))
+    };
+
+    let Some((_, first_path)) = frames.first() else {
+        return Err(CaptureError::InvalidState(format!("no fragments found in {}", dir.display())));
+    };
+    let mut last_frame = load(first_path)?;
+    let mut canvas = Canvas::new(&last_frame);
+    info!("Replaying {} fragments from {}", frames.len(), dir.display());
+    let search = settings::current().capture.overlap_search;
+    let composite = settings::current().composite;
+
+    for (frame_index, path) in frames.iter().skip(1) {
+        let mut frame = load(path)?;
+        if frame.width() != canvas.width() {
+            warn!("Frame {}: width {} does not match the capture, aligning it", frame_index, frame.width());
+            frame = composite::align_width(&frame, canvas.width(), &composite)?;
+        }
+        if stitch::is_same_frame(&last_frame, &frame) {
+            continue;
+        }
+
+        let overlap = stitch::calculate_overlap_with(&last_frame, &frame, search);
+        match recorded.get(frame_index) {
+            Some(&chosen) if chosen != overlap => {
+                info!("Frame {}: capture stitched at {}, replay finds {}", frame_index, chosen, overlap)
+            }
+            None if overlap > 0 => info!("Frame {}: not stitched during capture, replay finds {}", frame_index, overlap),
+            _ => {}
+        }
+        if overlap == 0 {
+            continue;
+        }
+
+        let confidence = stitch::overlap_confidence(&last_frame, &frame, overlap);
+        canvas.append(&frame, Stitch { frame_index: *frame_index, overlap, confidence })?;
+        last_frame = frame;
+    }
+
+    info!("Replay finished, {}x{}", canvas.width(), canvas.height());
+    store::insert(canvas, None)
+}
@@ -0,0 +1,476 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use chrono::Local;
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::error::CaptureError;
+use crate::store::{self, ImageSize};
+
+/// Distance of the watermark from the image edges
+const WATERMARK_MARGIN: i64 = 16;
+
+/// Caption strip text size and padding in pixels
+const CAPTION_FONT_SIZE: f32 = 18.0;
+const CAPTION_PADDING: u32 = 12;
+const CAPTION_BACKGROUND: Rgba<u8> = Rgba([24, 24, 27, 255]);
+const CAPTION_TEXT: Rgba<u8> = Rgba([228, 228, 231, 255]);
+
+/// Fonts tried in order when `font_path` isn't set
+#[cfg(target_os = "windows")]
+const SYSTEM_FONTS: &[&str] = &["C:\\Windows\\Fonts\\segoeui.ttf", "C:\\Windows\\Fonts\\arial.ttf"];
+#[cfg(target_os = "macos")]
+const SYSTEM_FONTS: &[&str] = &["/System/Library/Fonts/Helvetica.ttc", "/Library/Fonts/Arial.ttf"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+];
+
+/// Branding applied to captures when they are saved or copied. Everything is off by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Smaller copy for the web, applied before the watermark and caption so text stays sharp
+    pub resize: Option<Resize>,
+    pub watermark: Option<Watermark>,
+    pub caption: Option<Caption>,
+    /// TTF/OTF file used for all text, falls back to a common system font
+    pub font_path: Option<String>,
+    /// Write capture time, region, monitor and stitch count into saved PNGs, see `read_metadata`
+    pub embed_metadata: bool,
+    /// Also write `<name>.json` next to saved images, with the seams and timings of the capture
+    pub sidecar: bool,
+    /// Losslessly recompress saved PNGs in the background, see the `png-optimize` event
+    pub optimize_png: bool,
+    /// oxipng preset used by `optimize_png`, 0 (fastest) to 6 (smallest), 2 when unset
+    pub optimize_level: Option<u8>,
+    /// Used when saving to a `.avif` file
+    pub avif: AvifOptions,
+}
+
+/// AVIF encoder settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AvifOptions {
+    /// 1 to 100, around 80 text still looks identical to the PNG
+    pub quality: u8,
+    /// 1 (slowest, smallest) to 10 (fastest)
+    pub speed: u8,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        Self { quality: 80, speed: 6 }
+    }
+}
+
+/// Downscaling on export. With both limits set the smaller result wins, images are never enlarged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resize {
+    /// Scale to this percentage of the original size
+    pub percent: Option<f32>,
+    /// Scale down to at most this width, the height follows
+    pub max_width: Option<u32>,
+    #[serde(default)]
+    pub filter: ResizeFilter,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    /// Sharpest, best for text
+    #[default]
+    Lanczos3,
+    /// Softer and faster
+    Triangle,
+    /// Keeps hard pixel edges, for pixel art or when speed matters most
+    Nearest,
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> imageops::FilterType {
+        match self {
+            ResizeFilter::Lanczos3 => imageops::FilterType::Lanczos3,
+            ResizeFilter::Triangle => imageops::FilterType::Triangle,
+            ResizeFilter::Nearest => imageops::FilterType::Nearest,
+        }
+    }
+}
+
+impl Resize {
+    /// Target size for an image of `width` x `height`, `None` if it stays as is
+    fn target_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut scale = 1.0f64;
+        if let Some(percent) = self.percent {
+            scale = scale.min(percent as f64 / 100.0);
+        }
+        if let Some(max_width) = self.max_width {
+            scale = scale.min(max_width as f64 / width.max(1) as f64);
+        }
+        if scale >= 1.0 || scale <= 0.0 {
+            return None;
+        }
+        let scaled = |value: u32| ((value as f64 * scale).round() as u32).max(1);
+        Some((scaled(width), scaled(height)))
+    }
+}
+
+/// Text or PNG logo placed in a corner of the image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watermark {
+    #[serde(flatten)]
+    pub content: WatermarkContent,
+    #[serde(default)]
+    pub corner: Corner,
+    /// 0.0 (invisible) to 1.0 (opaque)
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Text height, or the maximum logo height, in pixels
+    #[serde(default = "default_watermark_size")]
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum WatermarkContent {
+    Text { text: String },
+    Image { path: String },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// Strip appended below the image with a note on the left and the capture time on the right
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Caption {
+    /// Page URL, window title or any other note
+    pub text: Option<String>,
+    pub timestamp: bool,
+}
+
+impl Default for Caption {
+    fn default() -> Self {
+        Self { text: None, timestamp: true }
+    }
+}
+
+/// Social-media style framing: the capture with rounded corners and a drop shadow on a padded background
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameOptions {
+    pub padding: u32,
+    pub corner_radius: u32,
+    /// Background color as "#rgb", "#rrggbb" or "#rrggbbaa"
+    pub background: String,
+    /// Second color for a diagonal gradient, solid background when unset
+    pub background_to: Option<String>,
+    /// Width of the shadow falloff in pixels, 0 disables the shadow
+    pub shadow_blur: u32,
+    pub shadow_offset_y: i32,
+    pub shadow_opacity: f32,
+}
+
+impl Default for FrameOptions {
+    fn default() -> Self {
+        Self {
+            padding: 64,
+            corner_radius: 12,
+            background: "#6366f1".to_string(),
+            background_to: Some("#a855f7".to_string()),
+            shadow_blur: 32,
+            shadow_offset_y: 12,
+            shadow_opacity: 0.4,
+        }
+    }
+}
+
+fn default_opacity() -> f32 {
+    0.5
+}
+
+fn default_watermark_size() -> u32 {
+    32
+}
+
+impl ExportOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.resize.is_some() || self.watermark.is_some() || self.caption.is_some()
+    }
+}
+
+/// Apply the configured resize, watermark and caption. The watermark goes on before the caption so it stays on the capture itself.
+pub fn apply(mut img: RgbaImage, options: &ExportOptions) -> Result<RgbaImage, CaptureError> {
+    // Only load a font if some text is actually drawn
+    let needs_font = options.caption.is_some()
+        || matches!(options.watermark, Some(Watermark { content: WatermarkContent::Text { .. }, .. }));
+    let font = if needs_font { Some(load_font(options.font_path.as_deref())?) } else { None };
+
+    if let Some(resize) = &options.resize {
+        if let Some((width, height)) = resize.target_size(img.width(), img.height()) {
+            info!("Scaling export from {}x{} to {}x{}", img.width(), img.height(), width, height);
+            img = imageops::resize(&img, width, height, resize.filter.filter_type());
+        }
+    }
+
+    if let Some(watermark) = &options.watermark {
+        let mark = match &watermark.content {
+            WatermarkContent::Text { text } => {
+                render_text(font.as_ref().unwrap(), text, watermark.size as f32, Rgba([255, 255, 255, 255]))
+            }
+            WatermarkContent::Image { path } => {
+                let logo = image::open(path)
+                    .map_err(|e| CaptureError::DecodeFailed(format!("watermark image {}: {}", path, e)))?
+                    .to_rgba8();
+                if logo.height() > watermark.size {
+                    let width = (logo.width() as u64 * watermark.size as u64 / logo.height() as u64).max(1) as u32;
+                    imageops::resize(&logo, width, watermark.size, imageops::FilterType::Lanczos3)
+                } else {
+                    logo
+                }
+            }
+        };
+        draw_watermark(&mut img, mark, watermark.corner, watermark.opacity);
+    }
+
+    if let Some(caption) = &options.caption {
+        img = append_caption(img, font.as_ref().unwrap(), caption);
+    }
+
+    Ok(img)
+}
+
+fn draw_watermark(img: &mut RgbaImage, mut mark: RgbaImage, corner: Corner, opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    for pixel in mark.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+
+    let right = img.width() as i64 - mark.width() as i64 - WATERMARK_MARGIN;
+    let bottom = img.height() as i64 - mark.height() as i64 - WATERMARK_MARGIN;
+    let (x, y) = match corner {
+        Corner::TopLeft => (WATERMARK_MARGIN, WATERMARK_MARGIN),
+        Corner::TopRight => (right, WATERMARK_MARGIN),
+        Corner::BottomLeft => (WATERMARK_MARGIN, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+    imageops::overlay(img, &mark, x, y);
+}
+
+fn append_caption(img: RgbaImage, font: &FontVec, caption: &Caption) -> RgbaImage {
+    let left = caption.text.as_deref().map(|text| render_text(font, text, CAPTION_FONT_SIZE, CAPTION_TEXT));
+    let right = caption.timestamp.then(|| {
+        let now = Local::now().format("%Y-%m-%d %H:%M").to_string();
+        render_text(font, &now, CAPTION_FONT_SIZE, CAPTION_TEXT)
+    });
+
+    let text_height = left.iter().chain(right.iter()).map(|t| t.height()).max().unwrap_or(0);
+    let strip_height = text_height + 2 * CAPTION_PADDING;
+
+    let mut out = RgbaImage::from_pixel(img.width(), img.height() + strip_height, CAPTION_BACKGROUND);
+    imageops::replace(&mut out, &img, 0, 0);
+
+    let top = img.height() as i64 + CAPTION_PADDING as i64;
+    let padding = CAPTION_PADDING as i64;
+    // Timestamp first, a long note is drawn over it rather than pushed out of the image
+    if let Some(text) = right {
+        imageops::overlay(&mut out, &text, img.width() as i64 - text.width() as i64 - padding, top);
+    }
+    if let Some(text) = left {
+        imageops::overlay(&mut out, &text, padding, top);
+    }
+    out
+}
+
+/// Render a single line of text into a tight transparent image
+fn render_text(font: &FontVec, text: &str, size: f32, color: Rgba<u8>) -> RgbaImage {
+    let scaled = font.as_scaled(PxScale::from(size));
+
+    // Lay out glyphs along the baseline
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = previous {
+            caret += scaled.kern(prev, id);
+        }
+        glyphs.push(id.with_scale_and_position(scaled.scale(), point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    let width = caret.ceil().max(1.0) as u32;
+    let height = scaled.height().ceil().max(1.0) as u32;
+    let mut out = RgbaImage::new(width, height);
+    for glyph in glyphs {
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, coverage| {
+            let px = bounds.min.x as i64 + x as i64;
+            let py = bounds.min.y as i64 + y as i64;
+            if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                let alpha = (coverage * color[3] as f32).round() as u8;
+                let pixel = out.get_pixel_mut(px as u32, py as u32);
+                if alpha > pixel[3] {
+                    *pixel = Rgba([color[0], color[1], color[2], alpha]);
+                }
+            }
+        });
+    }
+    out
+}
+
+fn load_font(path: Option<&str>) -> Result<FontVec, CaptureError> {
+    let candidates: Vec<&str> = match path {
+        Some(path) => vec![path],
+        None => SYSTEM_FONTS.to_vec(),
+    };
+
+    for candidate in candidates {
+        if let Ok(data) = fs::read(candidate) {
+            if let Ok(font) = FontVec::try_from_vec_and_index(data, 0) {
+                return Ok(font);
+            }
+            warn!("Could not parse font {}", candidate);
+        }
+    }
+
+    Err(CaptureError::EncodeFailed("no usable font found for captions/watermarks, set a font path in the export settings".to_string()))
+}
+
+/// Put the capture `id` into a frame (padding, background, rounded corners, shadow)
+#[tauri::command]
+pub async fn frame_image(id: String, options: Option<FrameOptions>) -> Result<ImageSize, CaptureError> {
+    let options = options.unwrap_or_default();
+    store::edit(id, move |canvas| {
+        let framed = frame(&canvas.load_image()?, &options)?;
+        *canvas = Canvas::from_rgba(framed)?;
+        info!("Framed capture, now {}x{}", canvas.width(), canvas.height());
+        Ok(ImageSize::of(canvas))
+    })
+    .await
+}
+
+fn frame(img: &RgbaImage, options: &FrameOptions) -> Result<RgbaImage, CaptureError> {
+    let from = parse_color(&options.background)?;
+    let to = match &options.background_to {
+        Some(color) => parse_color(color)?,
+        None => from,
+    };
+
+    let (w, h) = img.dimensions();
+    let padding = options.padding;
+    let (out_w, out_h) = (w + 2 * padding, h + 2 * padding);
+    let radius = (options.corner_radius as f32).min(w as f32 / 2.0).min(h as f32 / 2.0);
+    let blur = options.shadow_blur as f32;
+    let shadow_opacity = options.shadow_opacity.clamp(0.0, 1.0);
+
+    // Rounded rectangle of the capture in output coordinates, the shadow is the same shape moved down
+    let (cx, cy) = (padding as f32 + w as f32 / 2.0, padding as f32 + h as f32 / 2.0);
+    let (hw, hh) = (w as f32 / 2.0, h as f32 / 2.0);
+    let span = (out_w + out_h).max(1) as f32;
+
+    let mut out = RgbaImage::new(out_w, out_h);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+
+        // Diagonal gradient from top-left to bottom-right, premultiplied so that
+        // translucent backgrounds blend like the capture and shadow on top of them
+        let t = (x + y) as f32 / span;
+        let mut color = [0.0f32; 4];
+        for c in 0..4 {
+            color[c] = from[c] as f32 + (to[c] as f32 - from[c] as f32) * t;
+        }
+        for c in 0..3 {
+            color[c] *= color[3] / 255.0;
+        }
+
+        // Shadow with a linear falloff from the edge of the shape, computed directly instead of
+        // blurring, which would be far too slow on captures tens of thousands of pixels tall
+        if blur > 0.0 && shadow_opacity > 0.0 {
+            let d = rounded_rect_distance(px - cx, py - cy - options.shadow_offset_y as f32, hw, hh, radius);
+            let alpha = shadow_opacity * (1.0 - (d / blur).clamp(0.0, 1.0));
+            for value in color.iter_mut() {
+                *value *= 1.0 - alpha;
+            }
+            color[3] += 255.0 * alpha;
+        }
+
+        // The capture itself, with anti-aliased rounded corners
+        let d = rounded_rect_distance(px - cx, py - cy, hw, hh, radius);
+        let coverage = (0.5 - d).clamp(0.0, 1.0);
+        if coverage > 0.0 {
+            let src = img.get_pixel((x - padding).min(w - 1), (y - padding).min(h - 1));
+            let alpha = coverage * src[3] as f32 / 255.0;
+            for c in 0..3 {
+                color[c] = color[c] * (1.0 - alpha) + src[c] as f32 * alpha;
+            }
+            color[3] = color[3] * (1.0 - alpha) + 255.0 * alpha;
+        }
+
+        let unpremultiply = |c: f32| if color[3] > 0.0 { (c * 255.0 / color[3]).round().min(255.0) as u8 } else { 0 };
+        *pixel = Rgba([unpremultiply(color[0]), unpremultiply(color[1]), unpremultiply(color[2]), color[3].round() as u8]);
+    }
+
+    Ok(out)
+}
+
+/// Signed distance from a point (relative to the center) to a rounded rectangle, negative inside
+fn rounded_rect_distance(x: f32, y: f32, half_width: f32, half_height: f32, radius: f32) -> f32 {
+    let qx = x.abs() - (half_width - radius);
+    let qy = y.abs() - (half_height - radius);
+    let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+    outside + qx.max(qy).min(0.0) - radius
+}
+
+/// Parse "#rgb", "#rrggbb" or "#rrggbbaa" as RGBA, opaque unless alpha is given
+/// (the leading '#' is optional)
+pub fn parse_color(value: &str) -> Result<[u8; 4], CaptureError> {
+    let hex = value.trim_start_matches('#');
+    let invalid = || CaptureError::InvalidInput(format!("invalid color '{}', expected #rgb, #rrggbb or #rrggbbaa", value));
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).map_err(|_| invalid());
+    match hex.len() {
+        // Each digit doubled, "#abc" is "#aabbcc"
+        3 => Ok([channel(0, 1)? * 17, channel(1, 1)? * 17, channel(2, 1)? * 17, 255]),
+        6 => Ok([channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, 255]),
+        8 => Ok([channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, channel(3, 2)?]),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#abc").unwrap(), [0xaa, 0xbb, 0xcc, 255]);
+        assert_eq!(parse_color("#aabbcc").unwrap(), [0xaa, 0xbb, 0xcc, 255]);
+        assert_eq!(parse_color("#aabbcc80").unwrap(), [0xaa, 0xbb, 0xcc, 0x80]);
+        assert_eq!(parse_color("6366F1").unwrap(), [0x63, 0x66, 0xf1, 255]);
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for value in ["", "#", "#ab", "#abcd", "#aabbc", "#aabbccd", "#aabbccddee", "#gggggg", "#+1+1+1", "#aabbcé", "red"] {
+            assert!(matches!(parse_color(value), Err(CaptureError::InvalidInput(_))), "{}", value);
+        }
+    }
+}
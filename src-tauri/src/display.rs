@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use xcap::Monitor;
+use tracing::info;
+use crate::error::CaptureError;
+
+/// Rectangle in physical virtual-desktop pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    /// Overlapping part of two rectangles, `None` if they don't touch
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(Rect {
+            x: left,
+            y: top,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+}
+
+/// A connected screen as reported to the frontend.
+/// Position and size are in physical pixels of the virtual desktop.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayInfo {
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl DisplayInfo {
+    fn from_monitor(monitor: &Monitor) -> Result<Self, CaptureError> {
+        let err = |e: xcap::XCapError| CaptureError::CaptureFailed(format!("failed to query monitor: {}", e));
+        Ok(Self {
+            id: monitor.id().map_err(err)?,
+            name: monitor.name().unwrap_or_default(),
+            x: monitor.x().map_err(err)?,
+            y: monitor.y().map_err(err)?,
+            width: monitor.width().map_err(err)?,
+            height: monitor.height().map_err(err)?,
+            scale_factor: monitor.scale_factor().unwrap_or(1.0),
+            is_primary: monitor.is_primary().unwrap_or(false),
+        })
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect { x: self.x, y: self.y, width: self.width, height: self.height }
+    }
+}
+
+/// All monitors together with their info, in the order the OS reports them
+pub fn list_monitors() -> Result<Vec<(Monitor, DisplayInfo)>, CaptureError> {
+    let monitors = Monitor::all()
+        .map_err(|e| CaptureError::CaptureFailed(format!("failed to get monitors: {}", e)))?;
+
+    monitors.into_iter()
+        .map(|monitor| {
+            let info = DisplayInfo::from_monitor(&monitor)?;
+            Ok((monitor, info))
+        })
+        .collect()
+}
+
+/// Convert a region in logical (CSS) pixels into physical desktop pixels.
+///
+/// With mixed DPI there is no single factor for the whole desktop, so the conversion uses either
+/// the scale factor of the window the selection was drawn in (`scale_factor`), or the factor of the
+/// monitor whose logical bounds contain the region center. Logical bounds follow Tauri's convention:
+/// physical position and size divided by the monitor's own scale factor.
+pub fn logical_to_physical(x: i32, y: i32, width: u32, height: u32, scale_factor: Option<f32>) -> Result<Rect, CaptureError> {
+    let scale = match scale_factor {
+        Some(scale) if scale > 0.0 => scale,
+        _ => {
+            let cx = x as f32 + width as f32 / 2.0;
+            let cy = y as f32 + height as f32 / 2.0;
+            list_monitors()?
+                .into_iter()
+                .map(|(_, info)| info)
+                .find(|info| {
+                    let s = info.scale_factor;
+                    let (lx, ly) = (info.x as f32 / s, info.y as f32 / s);
+                    let (lw, lh) = (info.width as f32 / s, info.height as f32 / s);
+                    cx >= lx && cx < lx + lw && cy >= ly && cy < ly + lh
+                })
+                .ok_or(CaptureError::ScreenNotFound)?
+                .scale_factor
+        }
+    };
+
+    // Round the edges rather than the size so adjacent regions never gain or lose a pixel
+    let left = (x as f32 * scale).round() as i32;
+    let top = (y as f32 * scale).round() as i32;
+    let right = ((x as f32 + width as f32) * scale).round() as i32;
+    let bottom = ((y as f32 + height as f32) * scale).round() as i32;
+
+    Ok(Rect {
+        x: left,
+        y: top,
+        width: (right - left).max(0) as u32,
+        height: (bottom - top).max(0) as u32,
+    })
+}
+
+/// Check a physical capture region against the current screens before anything is captured.
+/// Empty regions are rejected, regions sticking out of the virtual desktop are clamped to it,
+/// and regions that don't touch any screen are rejected.
+pub fn validate_region(region: Rect) -> Result<Rect, CaptureError> {
+    if region.width == 0 || region.height == 0 {
+        return Err(CaptureError::InvalidRegion(format!("{}x{} has no area", region.width, region.height)));
+    }
+
+    let screens: Vec<Rect> = list_monitors()?.into_iter().map(|(_, info)| info.rect()).collect();
+    if !screens.iter().any(|screen| screen.intersect(&region).is_some()) {
+        return Err(CaptureError::RegionOutOfBounds(format!(
+            "{}x{} at ({}, {}) does not intersect any screen", region.width, region.height, region.x, region.y
+        )));
+    }
+
+    // Bounding box of all screens. Gaps between differently sized monitors stay inside it,
+    // `capture_region` leaves those transparent.
+    let desktop = screens.iter().skip(1).fold(screens[0], |acc, screen| {
+        let left = acc.x.min(screen.x);
+        let top = acc.y.min(screen.y);
+        Rect {
+            x: left,
+            y: top,
+            width: (acc.right().max(screen.right()) - left) as u32,
+            height: (acc.bottom().max(screen.bottom()) - top) as u32,
+        }
+    });
+
+    // Can't fail, the region intersects at least one screen and so the desktop
+    let clamped = region.intersect(&desktop).unwrap_or(region);
+    if clamped != region {
+        info!("Clamped capture region {:?} to the screen bounds: {:?}", region, clamped);
+    }
+    Ok(clamped)
+}
+
+#[tauri::command]
+pub fn get_displays() -> Result<Vec<DisplayInfo>, CaptureError> {
+    Ok(list_monitors()?.into_iter().map(|(_, info)| info).collect())
+}
@@ -0,0 +1,94 @@
+use image::imageops;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Emitter, WebviewWindow};
+use tracing::{info, warn};
+use crate::decorate;
+use crate::error::CaptureError;
+use crate::i18n;
+use crate::settings;
+use crate::store;
+use crate::utils;
+
+/// Longest side of the image shown under the cursor while dragging
+const PREVIEW_SIZE: u32 = 256;
+
+/// Outcome of a drag, sent with the `drag-finished` event
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DragOutcome {
+    Dropped,
+    Cancelled,
+}
+
+/// Start dragging the capture `id` out of `window` as a PNG file, so it can be dropped
+/// into chat apps, browsers or file managers. Call it from a mousedown/dragstart handler,
+/// the platforms only start a drag while the mouse button is held.
+///
+/// The file goes to a temp folder and is left there, receivers may read it after the drop.
+#[tauri::command]
+pub async fn start_drag(app: AppHandle, window: WebviewWindow, id: String) -> Result<String, CaptureError> {
+    let export = settings::current().export;
+    let (path, preview) = store::edit(id, move |canvas| {
+        // Same output as saving, watermark and caption included
+        let mut img = canvas.load_image()?;
+        if export.is_enabled() {
+            img = decorate::apply(img, &export)?;
+        }
+
+        let dir = std::env::temp_dir().join("scrollsnap-drag");
+        fs::create_dir_all(&dir)?;
+        // The file keeps its name where it's dropped
+        let time = chrono::Local::now().format("%Y%m%d-%H%M%S%3f").to_string();
+        let path = dir.join(format!("{}.png", i18n::text("file.capture", &[("time", &time)])));
+        fs::write(&path, utils::encode_png(&img, |_| {})?)?;
+
+        let scale = PREVIEW_SIZE as f32 / img.width().max(img.height()).max(1) as f32;
+        let preview = if scale < 1.0 {
+            let width = ((img.width() as f32 * scale) as u32).max(1);
+            let height = ((img.height() as f32 * scale) as u32).max(1);
+            imageops::thumbnail(&img, width, height)
+        } else {
+            img
+        };
+        Ok((path, utils::encode_png(&preview, |_| {})?))
+    })
+    .await?;
+
+    info!("Dragging capture from {}", path.display());
+    let dragged = path.clone();
+    let target = window.clone();
+    // The drag APIs are tied to the UI thread
+    window
+        .run_on_main_thread(move || {
+            if let Err(e) = begin(&app, &target, dragged, preview) {
+                warn!("Could not start drag: {}", e);
+                let _ = app.emit("capture-error", &e);
+            }
+        })
+        .map_err(|e| CaptureError::Internal(e.to_string()))?;
+
+    Ok(path.display().to_string())
+}
+
+fn begin(app: &AppHandle, window: &WebviewWindow, path: PathBuf, preview: Vec<u8>) -> Result<(), CaptureError> {
+    let app = app.clone();
+    let on_drop = move |result: drag::DragResult, _cursor: drag::CursorPosition| {
+        let outcome = match result {
+            drag::DragResult::Dropped => DragOutcome::Dropped,
+            drag::DragResult::Cancel => DragOutcome::Cancelled,
+        };
+        let _ = app.emit("drag-finished", outcome);
+    };
+
+    #[cfg(target_os = "linux")]
+    let handle = window.gtk_window().map_err(|e| CaptureError::Internal(e.to_string()))?;
+    #[cfg(target_os = "linux")]
+    let handle = &handle;
+    #[cfg(not(target_os = "linux"))]
+    let handle = window;
+
+    drag::start_drag(handle, drag::DragItem::Files(vec![path]), drag::Image::Raw(preview), on_drop, drag::Options::default())
+        .map_err(|e| CaptureError::Internal(format!("drag failed: {}", e)))
+}
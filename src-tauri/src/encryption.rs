@@ -0,0 +1,216 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tracing::info;
+use crate::error::CaptureError;
+use crate::keychain;
+use crate::settings;
+use crate::utils;
+
+/// Start of every encrypted file
+const MAGIC: &[u8; 8] = b"SSNAPENC";
+/// AES-GCM's 96-bit nonce, random for every message
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// Random id of a file sealed strip by strip, see `FileCipher`
+const FILE_ID_LEN: usize = 16;
+
+/// Keychain entry of the key
+const KEY_ACCOUNT: &str = "capture-key";
+
+/// Encryption of captures on disk, for confidential content
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionSettings {
+    /// Encrypt the history (index, entries, thumbnails and text index), autosave checkpoints,
+    /// canvas rows spilled or parked in the temp folder, and debug dump fragments, with
+    /// AES-256-GCM. The key is made on first use and kept in the OS keychain. Turning this on
+    /// encrypts the existing history, and while it is on unencrypted files are refused.
+    /// Encrypted files stay readable after turning it off. Saved and exported images are not
+    /// encrypted, they are meant to be opened elsewhere.
+    pub enabled: bool,
+}
+
+lazy_static! {
+    static ref CIPHER: Mutex<Option<Aes256Gcm>> = Mutex::new(None);
+}
+
+fn enabled() -> bool {
+    settings::current().encryption.enabled
+}
+
+/// `bytes` to write to disk: sealed with the capture key when encryption is on, unchanged otherwise
+pub fn protect(bytes: Vec<u8>) -> Result<Vec<u8>, CaptureError> {
+    if enabled() {
+        seal(&bytes)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Read a file written through `protect`, decrypting it if it was encrypted. With encryption
+/// on, a file without the header is refused: it may have been put there in place of ours.
+pub fn read(path: &Path) -> Result<Vec<u8>, CaptureError> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(MAGIC) {
+        if enabled() {
+            return Err(CaptureError::EncryptionFailed(format!("{} is not encrypted", path.display())));
+        }
+        return Ok(bytes);
+    }
+    open(&bytes)
+}
+
+/// Encrypt every file in `dir` that isn't yet. Run when encryption is turned on, so the
+/// files from before don't get refused by `read`.
+pub fn encrypt_existing(dir: &Path) -> Result<usize, CaptureError> {
+    let mut encrypted = 0;
+    let items = match fs::read_dir(dir) {
+        Ok(items) => items,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    for item in items {
+        let path = item?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        // Leftovers of `write_atomic` start with a dot
+        if path.is_file() && !name.starts_with('.') && encrypt_file(&path)? {
+            encrypted += 1;
+        }
+    }
+    Ok(encrypted)
+}
+
+/// Encrypt the file at `path` if it isn't yet, whether it had to be. A missing file is left alone.
+pub fn encrypt_file(path: &Path) -> Result<bool, CaptureError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    if bytes.starts_with(MAGIC) {
+        return Ok(false);
+    }
+    utils::write_atomic(path, &seal(&bytes)?)?;
+    Ok(true)
+}
+
+/// AES-256-GCM: `MAGIC | nonce | ciphertext | tag`, with the header as associated data
+fn seal(plain: &[u8]) -> Result<Vec<u8>, CaptureError> {
+    let nonce = random::<NONCE_LEN>()?;
+    let sealed = cipher()?
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plain, aad: MAGIC })
+        .map_err(|_| CaptureError::EncryptionFailed("could not encrypt".to_string()))?;
+    let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + sealed.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Undo `seal`
+fn open(bytes: &[u8]) -> Result<Vec<u8>, CaptureError> {
+    let body = MAGIC.len() + NONCE_LEN;
+    if bytes.len() < body + TAG_LEN {
+        return Err(CaptureError::EncryptionFailed("the encrypted file is truncated".to_string()));
+    }
+    cipher()?
+        .decrypt(Nonce::from_slice(&bytes[MAGIC.len()..body]), Payload { msg: &bytes[body..], aad: MAGIC })
+        .map_err(|_| CaptureError::EncryptionFailed("the file was changed or belongs to another key".to_string()))
+}
+
+/// Encryption of a file written piece by piece, like the autosave rows. Every strip is sealed
+/// on its own and bound to the file and its position in it, so strips can't be changed,
+/// moved or taken from another file without `open` failing.
+pub struct FileCipher {
+    cipher: Aes256Gcm,
+    file_id: [u8; FILE_ID_LEN],
+}
+
+impl FileCipher {
+    /// Bytes a strip grows by when sealed
+    pub const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+    /// A cipher for a new file when encryption is on, `None` otherwise
+    pub fn create() -> Result<Option<Self>, CaptureError> {
+        if !enabled() {
+            return Ok(None);
+        }
+        Ok(Some(Self { cipher: cipher()?, file_id: random()? }))
+    }
+
+    /// The cipher of a file written before, from `id_hex`
+    pub fn resume(id_hex: &str) -> Result<Self, CaptureError> {
+        let file_id = hex::decode(id_hex)
+            .ok()
+            .and_then(|id| <[u8; FILE_ID_LEN]>::try_from(id).ok())
+            .ok_or_else(|| CaptureError::EncryptionFailed(format!("invalid file id '{}'", id_hex)))?;
+        Ok(Self { cipher: cipher()?, file_id })
+    }
+
+    /// Stored next to the file, it is not secret
+    pub fn id_hex(&self) -> String {
+        hex::encode(self.file_id)
+    }
+
+    /// Seal `data`, the strip starting at `position` (in whatever unit the file is counted in)
+    pub fn seal(&self, position: u64, data: &[u8]) -> Result<Vec<u8>, CaptureError> {
+        let nonce = random::<NONCE_LEN>()?;
+        let aad = self.aad(position);
+        let sealed = self.cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad: &aad })
+            .map_err(|_| CaptureError::EncryptionFailed("could not encrypt".to_string()))?;
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    /// Undo `seal` for the strip at `position`
+    pub fn open(&self, position: u64, sealed: &[u8]) -> Result<Vec<u8>, CaptureError> {
+        if sealed.len() < Self::OVERHEAD {
+            return Err(CaptureError::EncryptionFailed("an encrypted strip is truncated".to_string()));
+        }
+        let aad = self.aad(position);
+        self.cipher
+            .decrypt(Nonce::from_slice(&sealed[..NONCE_LEN]), Payload { msg: &sealed[NONCE_LEN..], aad: &aad })
+            .map_err(|_| CaptureError::EncryptionFailed(format!("the strip at {} was changed or belongs to another file", position)))
+    }
+
+    fn aad(&self, position: u64) -> Vec<u8> {
+        [self.file_id.as_slice(), &position.to_le_bytes()].concat()
+    }
+}
+
+fn random<const N: usize>() -> Result<[u8; N], CaptureError> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| CaptureError::EncryptionFailed(format!("no randomness: {}", e)))?;
+    Ok(bytes)
+}
+
+/// The cipher with the capture key, from the keychain or a new key stored there on first use
+fn cipher() -> Result<Aes256Gcm, CaptureError> {
+    let mut cached = CIPHER.lock().unwrap();
+    if let Some(cipher) = cached.as_ref() {
+        return Ok(cipher.clone());
+    }
+
+    let key = match keychain::load(KEY_ACCOUNT)? {
+        Some(key) if key.len() == KEY_LEN => key,
+        Some(key) => {
+            return Err(CaptureError::EncryptionFailed(format!("the stored key has {} bytes instead of {}", key.len(), KEY_LEN)));
+        }
+        None => {
+            let key = random::<KEY_LEN>()?.to_vec();
+            keychain::store(KEY_ACCOUNT, &key)?;
+            info!("Created a capture encryption key in the keychain");
+            key
+        }
+    };
+
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| CaptureError::EncryptionFailed(e.to_string()))?;
+    *cached = Some(cipher.clone());
+    Ok(cipher)
+}
@@ -0,0 +1,116 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+use crate::i18n;
+
+/// Every error that reaches the frontend.
+/// Serialized as `{ code, message }` so the UI can switch on the stable `code`. `message` is
+/// in the locale picked with `set_locale`, `Display` stays English for the logs. Details
+/// (paths, OS and library errors) are passed through as they are.
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("No screen found for the selected region")]
+    ScreenNotFound,
+    #[error("Screen Recording permission is required. Enable ScrollSnap in System Settings > Privacy & Security > Screen Recording and restart the app.")]
+    PermissionDenied,
+    #[error("Selected region is outside the screen: {0}")]
+    RegionOutOfBounds(String),
+    #[error("Invalid capture region: {0}")]
+    InvalidRegion(String),
+    #[error("Failed to capture screen: {0}")]
+    CaptureFailed(String),
+    #[error("A capture is already in progress")]
+    AlreadyRunning,
+    #[error("There is no previous capture region to repeat")]
+    NoPreviousRegion,
+    #[error("Invalid capture state: {0}")]
+    InvalidState(String),
+    #[error("Failed to encode image: {0}")]
+    EncodeFailed(String),
+    #[error("Failed to decode image: {0}")]
+    DecodeFailed(String),
+    #[error("Clipboard is unavailable: {0}")]
+    ClipboardBusy(String),
+    #[error("Upload failed: {0}")]
+    UploadFailed(String),
+    #[error("Post-capture hook failed: {0}")]
+    HookFailed(String),
+    #[error("No browser with remote debugging found: {0}")]
+    BrowserUnavailable(String),
+    #[error("The capture region overlaps sensitive windows: {0}")]
+    SensitiveWindow(String),
+    #[error("Encryption failed: {0}")]
+    EncryptionFailed(String),
+    #[error("Keychain is unavailable: {0}")]
+    KeychainFailed(String),
+    #[error("Script failed: {0}")]
+    ScriptFailed(String),
+    /// A value passed in by the user or the frontend is malformed, e.g. a color or an option
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("File error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CaptureError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CaptureError::ScreenNotFound => "SCREEN_NOT_FOUND",
+            CaptureError::PermissionDenied => "PERMISSION_DENIED",
+            CaptureError::RegionOutOfBounds(_) => "REGION_OUT_OF_BOUNDS",
+            CaptureError::InvalidRegion(_) => "INVALID_REGION",
+            CaptureError::CaptureFailed(_) => "CAPTURE_FAILED",
+            CaptureError::AlreadyRunning => "ALREADY_RUNNING",
+            CaptureError::NoPreviousRegion => "NO_PREVIOUS_REGION",
+            CaptureError::InvalidState(_) => "INVALID_STATE",
+            CaptureError::EncodeFailed(_) => "ENCODE_FAILED",
+            CaptureError::DecodeFailed(_) => "DECODE_FAILED",
+            CaptureError::ClipboardBusy(_) => "CLIPBOARD_BUSY",
+            CaptureError::UploadFailed(_) => "UPLOAD_FAILED",
+            CaptureError::HookFailed(_) => "HOOK_FAILED",
+            CaptureError::BrowserUnavailable(_) => "BROWSER_UNAVAILABLE",
+            CaptureError::SensitiveWindow(_) => "SENSITIVE_WINDOW",
+            CaptureError::EncryptionFailed(_) => "ENCRYPTION_FAILED",
+            CaptureError::KeychainFailed(_) => "KEYCHAIN_FAILED",
+            CaptureError::ScriptFailed(_) => "SCRIPT_FAILED",
+            CaptureError::InvalidInput(_) => "INVALID_INPUT",
+            CaptureError::Io(_) => "IO_ERROR",
+            CaptureError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    /// The message in the current locale, see `i18n::text`
+    pub fn localized(&self) -> String {
+        let detail = match self {
+            CaptureError::RegionOutOfBounds(detail)
+            | CaptureError::InvalidRegion(detail)
+            | CaptureError::CaptureFailed(detail)
+            | CaptureError::InvalidState(detail)
+            | CaptureError::EncodeFailed(detail)
+            | CaptureError::DecodeFailed(detail)
+            | CaptureError::ClipboardBusy(detail)
+            | CaptureError::UploadFailed(detail)
+            | CaptureError::HookFailed(detail)
+            | CaptureError::BrowserUnavailable(detail)
+            | CaptureError::SensitiveWindow(detail)
+            | CaptureError::EncryptionFailed(detail)
+            | CaptureError::KeychainFailed(detail)
+            | CaptureError::ScriptFailed(detail)
+            | CaptureError::InvalidInput(detail)
+            | CaptureError::Internal(detail) => detail.clone(),
+            CaptureError::Io(e) => e.to_string(),
+            CaptureError::ScreenNotFound | CaptureError::PermissionDenied | CaptureError::AlreadyRunning | CaptureError::NoPreviousRegion => String::new(),
+        };
+        i18n::text(&format!("error.{}", self.code()), &[("detail", &detail)])
+    }
+}
+
+impl Serialize for CaptureError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CaptureError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.localized())?;
+        state.end()
+    }
+}
@@ -0,0 +1,75 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::AppHandle;
+use tracing::info;
+use crate::error::CaptureError;
+use crate::issue::IssueExporter;
+use crate::settings;
+use crate::store::ClipboardExporter;
+use crate::upload::UploadExporter;
+use crate::utils::FileExporter;
+
+/// A destination captures can be sent to. Each one lives in its own module with its own options
+/// type, and is made available by adding it to `EXPORTERS`.
+pub trait Exporter: Send + Sync {
+    /// Identifier used by `export_capture` and in `Settings::exporters`, e.g. "file"
+    fn name(&self) -> &'static str;
+
+    /// MIME type of what the destination receives
+    fn mime(&self) -> &'static str;
+
+    /// Send the capture `id` and return where it went, e.g. a path or URL. `options` are the
+    /// exporter's own, see `parse_options`. Blocking, called off the async runtime.
+    fn export(&self, app: &AppHandle, id: &str, options: &Value) -> Result<String, CaptureError>;
+}
+
+/// Every destination, in the order they are listed in the UI
+static EXPORTERS: &[&dyn Exporter] = &[&FileExporter, &ClipboardExporter, &UploadExporter, &IssueExporter];
+
+/// An exporter as listed by `list_exporters`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExporterInfo {
+    pub name: String,
+    pub mime: String,
+}
+
+/// The exporter called `name`
+pub fn find(name: &str) -> Result<&'static dyn Exporter, CaptureError> {
+    EXPORTERS.iter()
+        .copied()
+        .find(|exporter| exporter.name() == name)
+        .ok_or_else(|| CaptureError::InvalidInput(format!("no exporter named '{}'", name)))
+}
+
+/// Options of an exporter as its own type. Missing options (`null`) are read as `{}`.
+pub fn parse_options<T: DeserializeOwned>(exporter: &str, options: &Value) -> Result<T, CaptureError> {
+    let options = if options.is_null() { Value::Object(Default::default()) } else { options.clone() };
+    serde_json::from_value(options)
+        .map_err(|e| CaptureError::InvalidState(format!("invalid {} exporter options: {}", exporter, e)))
+}
+
+/// All destinations captures can be exported to
+#[tauri::command]
+pub fn list_exporters() -> Vec<ExporterInfo> {
+    EXPORTERS.iter()
+        .map(|exporter| ExporterInfo { name: exporter.name().to_string(), mime: exporter.mime().to_string() })
+        .collect()
+}
+
+/// Send the capture `id` to the exporter `exporter` and return where it went.
+/// Without `options` the ones saved for that exporter in the settings are used.
+#[tauri::command]
+pub async fn export_capture(app: AppHandle, id: String, exporter: String, options: Option<Value>) -> Result<String, CaptureError> {
+    let exporter = find(&exporter)?;
+    let options = options
+        .or_else(|| settings::current().exporters.remove(exporter.name()))
+        .unwrap_or(Value::Null);
+
+    let location = tauri::async_runtime::spawn_blocking(move || exporter.export(&app, &id, &options))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("export task failed: {}", e)))??;
+    info!("Exported capture through '{}' to {}", exporter.name(), location);
+    Ok(location)
+}
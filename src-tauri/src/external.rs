@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_opener::OpenerExt;
+use uuid::Uuid;
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::error::CaptureError;
+use crate::history;
+use crate::settings;
+use crate::store;
+
+/// How long to watch for the edited file when the default app was used.
+/// There is no process to wait for then, the opener returns right away.
+const EDIT_WATCH_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const EDIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Save the capture `id` to a temp file and open it in `program`, the `external_editor`
+/// from the settings, or the system's default app for PNGs. Returns the temp file path.
+///
+/// With `reimport`, the file is added to the history once it was saved in the editor:
+/// after the program exits when one was given, otherwise on the first change to the file.
+/// Emits `external-edit-imported` with the history entry.
+#[tauri::command]
+pub async fn open_with(app: AppHandle, id: String, program: Option<String>, reimport: Option<bool>) -> Result<String, CaptureError> {
+    let path = std::env::temp_dir().join(format!("scrollsnap-edit-{}.png", &Uuid::new_v4().to_string()[..8]));
+    let target = path.clone();
+    store::edit(id, move |canvas| {
+        fs::write(&target, canvas.encode_png(|_| {})?)?;
+        Ok(())
+    })
+    .await?;
+    let saved_at = modified(&path)?;
+
+    let program = program.or_else(|| settings::current().external_editor);
+    let child = match &program {
+        Some(program) => {
+            info!("Opening {} with {}", path.display(), program);
+            Some(Command::new(program).arg(&path).spawn()
+                .map_err(|e| CaptureError::Internal(format!("could not start {}: {}", program, e)))?)
+        }
+        None => {
+            info!("Opening {} with the default app", path.display());
+            app.opener().open_path(path.display().to_string(), None::<&str>)
+                .map_err(|e| CaptureError::Internal(format!("could not open {}: {}", path.display(), e)))?;
+            None
+        }
+    };
+
+    if reimport.unwrap_or(false) {
+        let watched = path.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            let changed = match child {
+                Some(mut child) => {
+                    let _ = child.wait();
+                    modified(&watched).map(|time| time > saved_at).unwrap_or(false)
+                }
+                None => wait_for_change(&watched, saved_at),
+            };
+            if !changed {
+                info!("{} was not changed, nothing to import", watched.display());
+                return;
+            }
+            match import(&app, &watched) {
+                Ok(entry) => {
+                    let _ = app.emit("external-edit-imported", entry);
+                }
+                Err(e) => {
+                    warn!("Could not import edited file {}: {}", watched.display(), e);
+                    let _ = app.emit("capture-error", &e);
+                }
+            }
+        });
+    }
+
+    Ok(path.display().to_string())
+}
+
+/// Open the system file manager with `path` selected: Explorer on Windows, Finder on macOS,
+/// the desktop's file manager on Linux (falls back to opening the folder).
+/// For saved captures and history entries.
+#[tauri::command]
+pub fn reveal_in_folder(app: AppHandle, path: String) -> Result<(), CaptureError> {
+    if !Path::new(&path).exists() {
+        return Err(CaptureError::InvalidState(format!("{} does not exist", path)));
+    }
+    app.opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| CaptureError::Internal(format!("could not reveal {}: {}", path, e)))
+}
+
+/// Poll until the file was written and then left alone for one interval,
+/// so a save in progress isn't read half-way
+fn wait_for_change(path: &Path, saved_at: SystemTime) -> bool {
+    let started = Instant::now();
+    let mut last_seen = saved_at;
+    while started.elapsed() < EDIT_WATCH_TIMEOUT {
+        std::thread::sleep(EDIT_POLL_INTERVAL);
+        let Ok(time) = modified(path) else {
+            continue;
+        };
+        if time > saved_at && time == last_seen {
+            return true;
+        }
+        last_seen = time;
+    }
+    false
+}
+
+fn import(app: &AppHandle, path: &Path) -> Result<history::HistoryEntry, CaptureError> {
+    let image = image::open(path)
+        .map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", path.display(), e)))?
+        .to_rgba8();
+    history::save(app, &mut Canvas::from_rgba(image)?, "external-edit")
+}
+
+fn modified(path: &Path) -> Result<SystemTime, CaptureError> {
+    Ok(fs::metadata(path)?.modified()?)
+}
@@ -0,0 +1,67 @@
+use image::{DynamicImage, RgbaImage};
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Spare frame buffers kept around. A capture needs one or two at a time, a few more
+/// cover frames that are still held while the next ones come in.
+const MAX_SPARE_FRAMES: usize = 4;
+
+/// Frame buffers handed back by the capture loop, reused for the next frames instead of
+/// allocating (and page-faulting in) tens of megabytes every poll interval.
+/// Only buffers of sizes that were asked for are kept: fragments that come straight from
+/// xcap are allocated inside it, keeping those around would only hold on to memory.
+struct FramePool {
+    spare: Vec<RgbaImage>,
+    /// Byte lengths of the buffers `take` was called for
+    wanted: HashSet<usize>,
+}
+
+lazy_static! {
+    static ref POOL: Mutex<FramePool> = Mutex::new(FramePool { spare: Vec::new(), wanted: HashSet::new() });
+}
+
+/// A `width` x `height` frame with whatever a previous frame left in it, for callers that
+/// overwrite every pixel
+pub fn take(width: u32, height: u32) -> RgbaImage {
+    let len = width as usize * height as usize * 4;
+    let mut pool = POOL.lock().unwrap();
+    pool.wanted.insert(len);
+    let reused = pool
+        .spare
+        .iter()
+        .position(|frame| frame.as_raw().len() == len)
+        .map(|i| pool.spare.swap_remove(i));
+    drop(pool);
+    reused
+        .and_then(|frame| RgbaImage::from_raw(width, height, frame.into_raw()))
+        .unwrap_or_else(|| RgbaImage::new(width, height))
+}
+
+/// A fully transparent `width` x `height` frame
+pub fn take_blank(width: u32, height: u32) -> RgbaImage {
+    let mut frame = take(width, height);
+    frame.fill(0);
+    frame
+}
+
+/// Hand a frame that's no longer needed back for reuse
+pub fn recycle(frame: DynamicImage) {
+    let DynamicImage::ImageRgba8(frame) = frame else {
+        return;
+    };
+    let mut pool = POOL.lock().unwrap();
+    if pool.wanted.contains(&frame.as_raw().len()) {
+        if pool.spare.len() == MAX_SPARE_FRAMES {
+            pool.spare.remove(0);
+        }
+        pool.spare.push(frame);
+    }
+}
+
+/// Free the spare frames, once a capture is over
+pub fn release() {
+    let mut pool = POOL.lock().unwrap();
+    pool.spare.clear();
+    pool.wanted.clear();
+}
@@ -0,0 +1,285 @@
+use image::RgbaImage;
+use lazy_static::lazy_static;
+use tracing::{info, warn};
+use wgpu::util::DeviceExt;
+
+/// Frames below this many pixels stay on the CPU, uploading them costs more than it saves
+const GPU_MIN_PIXELS: u64 = 2_000_000;
+
+/// Box size the scores are computed at, in pixels. Same as the finest CPU pyramid level.
+const BOX: u32 = 4;
+
+/// Threads per workgroup, all shader entry points use the same
+const WORKGROUP: u32 = 64;
+
+const SHADER: &str = r#"
+struct Params {
+    width: u32,
+    columns: u32,
+    prev_rows: u32,
+    curr_rows: u32,
+    offsets: u32,
+    box_rows: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> prev_pixels: array<u32>;
+@group(0) @binding(2) var<storage, read> curr_pixels: array<u32>;
+@group(0) @binding(3) var<storage, read_write> prev_boxes: array<u32>;
+@group(0) @binding(4) var<storage, read_write> curr_boxes: array<u32>;
+@group(0) @binding(5) var<storage, read_write> scores: array<f32>;
+
+var<workgroup> partial: array<f32, 64>;
+
+// r + 2g + b of a little-endian RGBA pixel, like the CPU pyramid
+fn brightness(pixel: u32) -> u32 {
+    return (pixel & 0xffu) + 2u * ((pixel >> 8u) & 0xffu) + ((pixel >> 16u) & 0xffu);
+}
+
+// 4x4 boxes of the signature, stacked without gaps
+@compute @workgroup_size(64)
+fn prev_box_sums(@builtin(global_invocation_id) id: vec3<u32>) {
+    let column = id.x;
+    let box_row = id.y;
+    if (column >= params.columns || box_row >= params.box_rows) {
+        return;
+    }
+    var sum = 0u;
+    for (var dy = 0u; dy < 4u; dy++) {
+        let base = (box_row * 4u + dy) * params.width + column * 4u;
+        for (var dx = 0u; dx < 4u; dx++) {
+            sum += brightness(prev_pixels[base + dx]);
+        }
+    }
+    prev_boxes[box_row * params.columns + column] = sum;
+}
+
+// 4x4 boxes of the new frame, starting at every row
+@compute @workgroup_size(64)
+fn curr_box_sums(@builtin(global_invocation_id) id: vec3<u32>) {
+    let column = id.x;
+    let row = id.y;
+    if (column >= params.columns || row + 4u > params.curr_rows) {
+        return;
+    }
+    var sum = 0u;
+    for (var dy = 0u; dy < 4u; dy++) {
+        let base = (row + dy) * params.width + column * 4u;
+        for (var dx = 0u; dx < 4u; dx++) {
+            sum += brightness(curr_pixels[base + dx]);
+        }
+    }
+    curr_boxes[row * params.columns + column] = sum;
+}
+
+// One workgroup per offset: sum of absolute differences between the signature boxes and the
+// boxes of the new frame starting at that offset
+@compute @workgroup_size(64)
+fn offset_scores(@builtin(workgroup_id) group: vec3<u32>, @builtin(local_invocation_index) lane: u32) {
+    let offset = group.x + group.y * 65535u;
+    let count = params.box_rows * params.columns;
+    var sum = 0.0;
+    if (offset < params.offsets) {
+        for (var k = lane; k < count; k += 64u) {
+            let box_row = k / params.columns;
+            let column = k % params.columns;
+            let a = prev_boxes[k];
+            let b = curr_boxes[(offset + box_row * 4u) * params.columns + column];
+            sum += f32(max(a, b) - min(a, b));
+        }
+    }
+    partial[lane] = sum;
+    workgroupBarrier();
+    for (var stride = 32u; stride > 0u; stride >>= 1u) {
+        if (lane < stride) {
+            partial[lane] += partial[lane + stride];
+        }
+        workgroupBarrier();
+    }
+    if (lane == 0u && offset < params.offsets) {
+        scores[offset] = partial[0];
+    }
+}
+"#;
+
+/// Matching scores of offsets on the GPU, see `offset_scores`
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    prev_box_sums: wgpu::ComputePipeline,
+    curr_box_sums: wgpu::ComputePipeline,
+    offset_scores: wgpu::ComputePipeline,
+}
+
+lazy_static! {
+    // Set up on first use. `None` without a usable hardware adapter, matching then stays on the CPU.
+    static ref GPU: Option<Gpu> = Gpu::new();
+}
+
+impl Gpu {
+    fn new() -> Option<Self> {
+        let mut descriptor = wgpu::InstanceDescriptor::new_without_display_handle();
+        descriptor.backends = wgpu::Backends::PRIMARY;
+        let instance = wgpu::Instance::new(descriptor);
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }));
+        let Some(adapter) = adapter.ok().filter(|adapter| adapter.get_info().device_type != wgpu::DeviceType::Cpu) else {
+            // A software rasterizer would be slower than the CPU pyramid
+            info!("No GPU available for overlap matching, using the CPU");
+            return None;
+        };
+        let adapter_info = adapter.get_info();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("overlap matching"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .map_err(|e| warn!("Failed to open GPU '{}': {}", adapter_info.name, e))
+        .ok()?;
+
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+                storage(5, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("overlap matching"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        info!("Matching overlaps on GPU '{}' ({:?})", adapter_info.name, adapter_info.backend);
+        Some(Self {
+            prev_box_sums: pipeline("prev_box_sums"),
+            curr_box_sums: pipeline("curr_box_sums"),
+            offset_scores: pipeline("offset_scores"),
+            device,
+            queue,
+            layout,
+        })
+    }
+
+    fn scores(&self, prev: &RgbaImage, curr: &RgbaImage, signature_start_y: u32, signature_height: u32) -> Option<Vec<f32>> {
+        let width = prev.width();
+        let columns = width / BOX;
+        let box_rows = signature_height / BOX;
+        let offsets = curr.height() - signature_height + 1;
+        let limits = self.device.limits();
+        let curr_bytes = curr.as_raw().len() as u64;
+        let curr_boxes_bytes = curr.height() as u64 * columns as u64 * 4;
+        if curr_bytes.max(curr_boxes_bytes) > limits.max_storage_buffer_binding_size
+            || curr.height() > limits.max_compute_workgroups_per_dimension
+        {
+            return None;
+        }
+
+        let params: [u32; 6] = [width, columns, signature_height, curr.height(), offsets, box_rows];
+        let row_bytes = width as usize * 4;
+        let signature = &prev.as_raw()[signature_start_y as usize * row_bytes..][..signature_height as usize * row_bytes];
+        let init = |label: &str, contents: &[u8], usage: wgpu::BufferUsages| {
+            self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+        };
+        let empty = |label: &str, size: u64, usage: wgpu::BufferUsages| {
+            self.device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size, usage, mapped_at_creation: false })
+        };
+        let params = init("params", &params.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>(), wgpu::BufferUsages::UNIFORM);
+        let prev_pixels = init("prev pixels", signature, wgpu::BufferUsages::STORAGE);
+        let curr_pixels = init("curr pixels", curr.as_raw(), wgpu::BufferUsages::STORAGE);
+        let prev_boxes = empty("prev boxes", (box_rows as u64 * columns as u64 * 4).max(4), wgpu::BufferUsages::STORAGE);
+        let curr_boxes = empty("curr boxes", curr_boxes_bytes.max(4), wgpu::BufferUsages::STORAGE);
+        let scores_bytes = offsets as u64 * 4;
+        let scores = empty("scores", scores_bytes, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+        let readback = empty("readback", scores_bytes, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[&params, &prev_pixels, &curr_pixels, &prev_boxes, &curr_boxes, &scores]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry { binding: binding as u32, resource: buffer.as_entire_binding() })
+                .collect::<Vec<_>>(),
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_pipeline(&self.prev_box_sums);
+            pass.dispatch_workgroups(columns.div_ceil(WORKGROUP), box_rows, 1);
+            pass.set_pipeline(&self.curr_box_sums);
+            pass.dispatch_workgroups(columns.div_ceil(WORKGROUP), curr.height() - BOX + 1, 1);
+            pass.set_pipeline(&self.offset_scores);
+            pass.dispatch_workgroups(offsets.min(65535), offsets.div_ceil(65535), 1);
+        }
+        encoder.copy_buffer_to_buffer(&scores, 0, &readback, 0, scores_bytes);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        if let Err(e) = self.device.poll(wgpu::PollType::wait_indefinitely()) {
+            warn!("GPU overlap matching failed: {}", e);
+            return None;
+        }
+        let data = slice.get_mapped_range().ok()?;
+        Some(data.chunks_exact(4).map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect())
+    }
+}
+
+/// Sum of absolute brightness differences between the signature block of `prev` and the
+/// rows of `curr` at every offset, compared in 4x4 boxes. `scores[y]` is for the signature's
+/// top at row `y` of `curr`. `None` without a GPU, or for frames too small to be worth it.
+pub fn offset_scores(prev: &RgbaImage, curr: &RgbaImage, signature_start_y: u32, signature_height: u32) -> Option<Vec<f32>> {
+    let pixels = curr.width() as u64 * curr.height() as u64;
+    if pixels < GPU_MIN_PIXELS
+        || prev.width() != curr.width()
+        || signature_height < BOX
+        || signature_height > curr.height()
+        || signature_start_y + signature_height > prev.height()
+    {
+        return None;
+    }
+    GPU.as_ref()?.scores(prev, curr, signature_start_y, signature_height)
+}
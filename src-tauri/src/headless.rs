@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use crate::accessibility::ScrollPane;
+use crate::autoscroll;
+use crate::backend;
+use crate::canvas::{Canvas, Stitch};
+use crate::composite::CompositeOptions;
+use crate::capture::{self, CaptureOptions, AUTO_SCROLL_STATIC_COUNT, MAX_TORN_RETRIES};
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::frames;
+use crate::logging;
+use crate::permission;
+use crate::stitch;
+
+/// A capture requested on the command line, see `main.rs` for the flags
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// x, y, width, height in physical desktop pixels
+    pub region: (i32, i32, u32, u32),
+    /// Defaults to `scrollsnap-<timestamp>.png` in the working directory
+    pub out: Option<PathBuf>,
+    /// Scrolls itself when `auto_scroll` is set, otherwise waits for the user to scroll
+    pub capture: CaptureOptions,
+}
+
+/// Run one capture without any window and write it to a PNG. Returns the process exit code.
+pub fn run(options: HeadlessOptions) -> i32 {
+    attach_console();
+    logging::init_console();
+    match capture(&options) {
+        Ok((path, width, height)) => {
+            println!("Saved {}x{} capture to {}", width, height, path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Capture failed: {}", e);
+            1
+        }
+    }
+}
+
+/// The same stitching as the capture loop, minus everything that needs the app:
+/// no overlays, events or pending-fragment recovery. Stops when nothing moves any more,
+/// or when auto-scroll reaches the bottom of a pane with a readable scroll position.
+fn capture(options: &HeadlessOptions) -> Result<(PathBuf, u32, u32), CaptureError> {
+    permission::ensure_capture_permission()?;
+    let (x, y, width, height) = options.region;
+    let region = display::validate_region(Rect { x, y, width, height })?;
+    let out = options.out.clone().unwrap_or_else(|| {
+        PathBuf::from(format!("scrollsnap-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+    });
+
+    let backend = backend::for_region(options.capture.backend, &region)?;
+    let pane = if options.capture.accessibility { ScrollPane::at(&region) } else { None };
+    let mut last_frame = backend.capture_region(&region)?;
+    let mut canvas = Canvas::new(&last_frame);
+    if options.capture.auto_scroll.is_none() {
+        println!("Capturing {:?}, scroll the content now. Stops once it stays still.", region);
+    }
+
+    let (mut frame_index, mut stitch_count, mut static_count, mut torn_count) = (0, 0, 0, 0);
+    let mut resized_to = None;
+    while stitch_count < options.capture.max_stitches {
+        match &options.capture.auto_scroll {
+            Some(auto_scroll) => {
+                autoscroll::step(auto_scroll, pane.as_ref())?;
+                thread::sleep(Duration::from_millis(auto_scroll.settle_delay_ms));
+            }
+            None => thread::sleep(Duration::from_millis(options.capture.poll_interval_ms)),
+        }
+
+        let frame = backend.capture_region(&region)?;
+        frame_index += 1;
+        let size = (frame.width(), frame.height());
+        let (frame, fit) = capture::fit_fragment(frame, region.width, region.height, &CompositeOptions::default());
+        if let Some(fit) = fit.filter(|_| resized_to != Some(size)) {
+            println!("Captured frame changed size to {}x{}, {:?} to {}x{}.", size.0, size.1, fit, region.width, region.height);
+        }
+        resized_to = fit.map(|_| size);
+
+        if stitch::is_same_frame(&last_frame, &frame) {
+            static_count += 1;
+            let limit = if options.capture.auto_scroll.is_some() { AUTO_SCROLL_STATIC_COUNT } else { options.capture.max_static_count };
+            // Before the first stitch the user may still be getting ready, unless we scroll ourselves
+            if static_count >= limit && (stitch_count > 0 || options.capture.auto_scroll.is_some()) {
+                break;
+            }
+            frames::recycle(frame);
+            continue;
+        }
+        static_count = 0;
+
+        let overlap = stitch::calculate_overlap_with(&last_frame, &frame, options.capture.overlap_search);
+        if overlap == 0 {
+            println!("Frame {} doesn't overlap the previous one, scroll less at a time.", frame_index);
+            frames::recycle(frame);
+            continue;
+        }
+        if stitch::is_torn_frame(&last_frame, &frame, overlap) {
+            torn_count += 1;
+            if torn_count <= MAX_TORN_RETRIES {
+                frames::recycle(frame);
+                continue;
+            }
+        }
+        torn_count = 0;
+
+        let confidence = stitch::overlap_confidence(&last_frame, &frame, overlap);
+        canvas.append(&frame, Stitch { frame_index, overlap, confidence })?;
+        stitch_count += 1;
+        frames::recycle(std::mem::replace(&mut last_frame, frame));
+        println!("Stitched frame {}, {}px so far", frame_index, canvas.height());
+        if options.capture.auto_scroll.is_some() && pane.as_ref().is_some_and(|pane| pane.at_bottom()) {
+            break;
+        }
+    }
+
+    let png = canvas.encode_png(|_| {})?;
+    fs::write(&out, png)?;
+    Ok((out, canvas.width(), canvas.height()))
+}
+
+/// Release builds are GUI-subsystem executables on Windows, so output only shows up
+/// in the terminal that started us after attaching to its console
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_console() {}
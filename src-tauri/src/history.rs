@@ -0,0 +1,323 @@
+use chrono::{DateTime, Local};
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::encryption;
+use crate::error::CaptureError;
+use crate::search;
+use crate::settings;
+use crate::store;
+use crate::utils;
+
+const HISTORY_DIR: &str = "history";
+const INDEX_FILE: &str = "index.json";
+/// Largest thumbnail `get_thumbnail` makes, bigger requests get this size
+const MAX_THUMBNAIL_DIM: u32 = 1024;
+/// Tall captures are cut to their top part, at most this many times as tall as wide
+const MAX_THUMBNAIL_ASPECT: u32 = 3;
+const THUMBNAIL_QUALITY: u8 = 85;
+
+lazy_static! {
+    // Saves from the scheduler and the UI can overlap, the index is read-modify-write
+    static ref INDEX_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// A capture kept in the history folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: String,
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    /// RFC 3339 local time
+    pub created_at: String,
+    /// What produced it, e.g. "schedule:<name>"
+    pub source: String,
+    /// Set with `annotate_capture`
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl HistoryEntry {
+    /// Decode the entry's image, decrypting it if it was saved encrypted
+    pub fn load_image(&self) -> Result<DynamicImage, CaptureError> {
+        image::load_from_memory(&encryption::read(Path::new(&self.path))?)
+            .map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", self.path, e)))
+    }
+}
+
+/// Encode `canvas` into the history folder and add it to the index
+pub fn save(app: &AppHandle, canvas: &mut Canvas, source: &str) -> Result<HistoryEntry, CaptureError> {
+    let png = canvas.encode_png(|_| {})?;
+    let entry = write_entry(app, &Uuid::new_v4().to_string(), png, canvas.width(), canvas.height(), source, false)?;
+    info!("Saved capture to history: {}", entry.path);
+    Ok(entry)
+}
+
+/// Keep the capture `id` from the store in the history under the same id. A later call replaces
+/// the image of the entry with the capture as it is now (after edits), note and tags stay.
+/// A new entry starts with the note and tags of the capture's metadata. Blocking.
+pub fn save_capture(app: &AppHandle, id: &str, source: &str) -> Result<HistoryEntry, CaptureError> {
+    let (png, width, height) = store::with_capture(id, |canvas| Ok((canvas.encode_png(|_| {})?, canvas.width(), canvas.height())))?;
+    let entry = write_entry(app, id, png, width, height, source, true)?;
+    info!("Saved capture {} to history: {}", id, entry.path);
+    Ok(entry)
+}
+
+/// `save_capture` on a background thread, failing only costs the history entry so it's just logged
+pub fn save_capture_in_background(app: AppHandle, id: String, source: &str) {
+    let source = source.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = save_capture(&app, &id, &source) {
+            warn!("Could not save capture {} to history: {}", id, e);
+        }
+    });
+}
+
+/// Write the image of entry `id` and add it to the index, or update the entry if it is there.
+/// With `from_store` a new entry takes its note and tags from the metadata of the stored capture `id`.
+fn write_entry(
+    app: &AppHandle,
+    id: &str,
+    png: Vec<u8>,
+    width: u32,
+    height: u32,
+    source: &str,
+    from_store: bool,
+) -> Result<HistoryEntry, CaptureError> {
+    let dir = history_dir(app)?;
+    fs::create_dir_all(&dir)?;
+    let png = encryption::protect(png)?;
+
+    // The metadata is read under the lock, so an `annotate_capture` in between ends up in either
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut entries = read_index(&dir)?;
+    let entry = match entries.iter_mut().find(|entry| entry.id == id) {
+        Some(entry) => {
+            utils::write_atomic(Path::new(&entry.path), &png)?;
+            // Thumbnails of the old image
+            for file in entry_files(entry).into_iter().skip(1) {
+                let _ = fs::remove_file(file);
+            }
+            entry.width = width;
+            entry.height = height;
+            entry.clone()
+        }
+        None => {
+            let now = Local::now();
+            let path = dir.join(format!("{}-{}.png", now.format("%Y%m%d-%H%M%S"), &id[..8]));
+            fs::write(&path, png)?;
+            let metadata = if from_store { store::metadata(id)? } else { None };
+            let entry = HistoryEntry {
+                id: id.to_string(),
+                path: path.display().to_string(),
+                width,
+                height,
+                created_at: now.to_rfc3339(),
+                source: source.to_string(),
+                note: metadata.as_ref().and_then(|metadata| metadata.note.clone()),
+                tags: metadata.map(|metadata| metadata.tags).unwrap_or_default(),
+            };
+            entries.push(entry.clone());
+            entry
+        }
+    };
+    write_index(&dir, &entries)?;
+    search::index_in_background(app.clone(), entry.clone());
+    Ok(entry)
+}
+
+/// Add entries whose images are already in the history folder, e.g. imported from an archive.
+/// The index stays in capture order, ids it already has are skipped.
+pub fn add_entries(app: &AppHandle, new: Vec<HistoryEntry>) -> Result<(), CaptureError> {
+    let dir = history_dir(app)?;
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut entries = read_index(&dir)?;
+    for entry in new {
+        if !entries.iter().any(|existing| existing.id == entry.id) {
+            entries.push(entry);
+        }
+    }
+    // Mixed UTC offsets (the other machine's time zone) compare correctly once parsed
+    entries.sort_by_key(|entry| DateTime::parse_from_rfc3339(&entry.created_at).ok());
+    write_index(&dir, &entries)
+}
+
+/// History entries, oldest first. With `query` only those whose note, tags or source contain it,
+/// with `tag` only those tagged with it. Both ignore case.
+#[tauri::command]
+pub fn list_history(app: AppHandle, query: Option<String>, tag: Option<String>) -> Result<Vec<HistoryEntry>, CaptureError> {
+    let entries = {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        read_index(&history_dir(&app)?)?
+    };
+    let query = query.map(|query| query.trim().to_lowercase()).filter(|query| !query.is_empty());
+    let tag = tag.map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty());
+    Ok(entries
+        .into_iter()
+        .filter(|entry| tag.as_ref().is_none_or(|tag| entry.tags.iter().any(|t| t.to_lowercase() == *tag)))
+        .filter(|entry| query.as_ref().is_none_or(|query| {
+            entry.note.as_deref().unwrap_or_default().to_lowercase().contains(query)
+                || entry.tags.iter().any(|t| t.to_lowercase().contains(query))
+                || entry.source.to_lowercase().contains(query)
+        }))
+        .collect())
+}
+
+/// Set the note and tags of the entry `id`, `None` if there is no such entry
+pub fn annotate(app: &AppHandle, id: &str, note: Option<String>, tags: Vec<String>) -> Result<Option<HistoryEntry>, CaptureError> {
+    let dir = history_dir(app)?;
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut entries = read_index(&dir)?;
+    let Some(entry) = entries.iter_mut().find(|entry| entry.id == id) else {
+        return Ok(None);
+    };
+    entry.note = note;
+    entry.tags = tags;
+    let entry = entry.clone();
+    write_index(&dir, &entries)?;
+    Ok(Some(entry))
+}
+
+/// Delete the entries `ids` with their thumbnails and recognized text, and return how many
+/// bytes that freed. Ids that are not in the history are ignored.
+pub fn remove(app: &AppHandle, ids: &[String]) -> Result<u64, CaptureError> {
+    let dir = history_dir(app)?;
+    let removed = {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        let (removed, kept): (Vec<HistoryEntry>, Vec<HistoryEntry>) = read_index(&dir)?.into_iter().partition(|entry| ids.contains(&entry.id));
+        write_index(&dir, &kept)?;
+        removed
+    };
+
+    let mut reclaimed = 0;
+    for entry in &removed {
+        for file in entry_files(entry) {
+            let size = fs::metadata(&file).map(|meta| meta.len()).unwrap_or(0);
+            match fs::remove_file(&file) {
+                Ok(()) => reclaimed += size,
+                Err(e) => warn!("Could not delete {}: {}", file.display(), e),
+            }
+        }
+    }
+    search::remove_from_index(app, ids)?;
+    Ok(reclaimed)
+}
+
+/// Bytes the entry takes on disk, its image and cached thumbnails
+pub fn disk_usage(entry: &HistoryEntry) -> u64 {
+    entry_files(entry).iter().filter_map(|file| fs::metadata(file).ok()).map(|meta| meta.len()).sum()
+}
+
+/// The entry's image and its cached thumbnails, "<name>.thumb-<size>.jpg"
+fn entry_files(entry: &HistoryEntry) -> Vec<PathBuf> {
+    let path = PathBuf::from(&entry.path);
+    let mut files = vec![path.clone()];
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem().and_then(|stem| stem.to_str())) else {
+        return files;
+    };
+    let prefix = format!("{}.thumb-", stem);
+    if let Ok(read_dir) = fs::read_dir(dir) {
+        files.extend(read_dir
+            .filter_map(|item| item.ok())
+            .filter(|item| item.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|item| item.path()));
+    }
+    files
+}
+
+/// JPEG data URL of the history entry `id`, scaled with Lanczos to fit `max_dim` on its
+/// longer side, for history lists. Cached next to the entry, one file per size.
+#[tauri::command]
+pub async fn get_thumbnail(app: AppHandle, id: String, max_dim: u32) -> Result<String, CaptureError> {
+    let entry = find(&app, &id)?;
+    let max_dim = max_dim.clamp(1, MAX_THUMBNAIL_DIM);
+    tauri::async_runtime::spawn_blocking(move || {
+        let cached = Path::new(&entry.path).with_extension(format!("thumb-{}.jpg", max_dim));
+        if let Ok(jpeg) = encryption::read(&cached) {
+            return Ok(utils::jpeg_data_url(&jpeg));
+        }
+
+        let img = entry.load_image()?.to_rgba8();
+        let (width, height) = img.dimensions();
+        let top = imageops::crop_imm(&img, 0, 0, width, height.min(width.saturating_mul(MAX_THUMBNAIL_ASPECT))).to_image();
+        let scale = (max_dim as f32 / top.width().max(top.height()) as f32).min(1.0);
+        let thumbnail = imageops::resize(
+            &top,
+            ((top.width() as f32 * scale) as u32).max(1),
+            ((top.height() as f32 * scale) as u32).max(1),
+            FilterType::Lanczos3,
+        );
+        let jpeg = utils::encode_jpeg(&thumbnail, THUMBNAIL_QUALITY)?;
+        utils::write_atomic(&cached, &encryption::protect(jpeg.clone())?)?;
+        Ok(utils::jpeg_data_url(&jpeg))
+    })
+    .await
+    .map_err(|e| CaptureError::Internal(format!("thumbnail task failed: {}", e)))?
+}
+
+/// Encrypt an index left unencrypted by versions that kept it in the clear, when encryption
+/// is on. Called once from `setup`.
+pub fn init(app: &AppHandle) {
+    if !settings::current().encryption.enabled {
+        return;
+    }
+    let _guard = INDEX_LOCK.lock().unwrap();
+    match history_dir(app).and_then(|dir| encryption::encrypt_file(&dir.join(INDEX_FILE))) {
+        Ok(true) => info!("Encrypted the history index"),
+        Ok(false) => {}
+        Err(e) => warn!("Could not encrypt the history index: {}", e),
+    }
+}
+
+/// Encrypt the index, entries, thumbnails and recognized text saved while encryption was off,
+/// when it is turned on
+pub fn encrypt_all(app: &AppHandle) -> Result<(), CaptureError> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let encrypted = encryption::encrypt_existing(&history_dir(app)?)?;
+    info!("Encrypted {} history files", encrypted);
+    Ok(())
+}
+
+/// The entry with `id`
+pub fn find(app: &AppHandle, id: &str) -> Result<HistoryEntry, CaptureError> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    read_index(&history_dir(app)?)?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| CaptureError::InvalidState(format!("no history entry with id {}", id)))
+}
+
+pub fn history_dir(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(HISTORY_DIR))
+        .map_err(|e| CaptureError::Internal(format!("Could not resolve app data directory: {}", e)))
+}
+
+fn read_index(dir: &Path) -> Result<Vec<HistoryEntry>, CaptureError> {
+    match encryption::read(&dir.join(INDEX_FILE)) {
+        Ok(content) => serde_json::from_slice(&content)
+            .map_err(|e| CaptureError::Internal(format!("history index is corrupt: {}", e))),
+        Err(CaptureError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_index(dir: &Path, entries: &[HistoryEntry]) -> Result<(), CaptureError> {
+    let content = serde_json::to_vec_pretty(entries).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    // Notes and tags can say as much as the captures
+    fs::write(dir.join(INDEX_FILE), encryption::protect(content)?)?;
+    Ok(())
+}
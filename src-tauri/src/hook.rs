@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+use crate::error::CaptureError;
+
+/// Keep at most this much of the hook's stdout/stderr for the UI
+const MAX_OUTPUT_LEN: usize = 4096;
+
+/// External program to run after a capture has been saved.
+/// The program is spawned directly (never through a shell), so `args` are passed verbatim
+/// except for the `{path}` placeholder which is replaced with the saved file path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    pub enabled: bool,
+    pub program: String,
+    pub args: Vec<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            program: String::new(),
+            args: vec!["{path}".to_string()],
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Metadata handed to the hook as JSON on stdin and as `SCROLL_SNAP_*` env vars
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureInfo {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u64,
+}
+
+/// How a hook or script ended, stdout and stderr cut to `MAX_OUTPUT_LEN`
+#[derive(Debug, Clone, Serialize)]
+pub struct HookFinished {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run the hook on a background thread. Results are reported via
+/// `hook-finished` and `hook-error` events, so saving never waits on user scripts.
+pub fn spawn_post_capture_hook(app: AppHandle, config: HookConfig, info: CaptureInfo) {
+    if !config.enabled || config.program.trim().is_empty() {
+        return;
+    }
+
+    thread::spawn(move || {
+        match run_hook(&config, &info) {
+            Ok(finished) => {
+                info!("Post-capture hook exited with {:?}", finished.exit_code);
+                let _ = app.emit("hook-finished", finished);
+            }
+            Err(e) => {
+                warn!("Post-capture hook failed: {}", e);
+                let _ = app.emit("hook-error", &e);
+            }
+        }
+    });
+}
+
+fn run_hook(config: &HookConfig, info: &CaptureInfo) -> Result<HookFinished, CaptureError> {
+    let args: Vec<String> = config.args.iter()
+        .map(|arg| arg.replace("{path}", &info.path))
+        .collect();
+
+    info!("Running post-capture hook: {} {:?}", config.program, args);
+
+    let mut command = command(&config.program);
+    command
+        .args(&args)
+        .env("SCROLL_SNAP_PATH", &info.path)
+        .env("SCROLL_SNAP_WIDTH", info.width.to_string())
+        .env("SCROLL_SNAP_HEIGHT", info.height.to_string())
+        .env("SCROLL_SNAP_BYTES", info.bytes.to_string());
+
+    if let Some(dir) = Path::new(&info.path).parent() {
+        command.current_dir(dir);
+    }
+
+    let json = serde_json::to_vec(info).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    run(&mut command, &json, Duration::from_secs(config.timeout_secs.max(1))).map_err(CaptureError::HookFailed)
+}
+
+/// `program` with piped I/O, no console window, and none of our environment
+/// except what's needed to run programs
+pub fn command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // Don't leak our whole environment into user scripts, only what's needed to run programs
+    command.env_clear();
+    for key in ["PATH", "HOME", "USERPROFILE", "SystemRoot", "TEMP", "TMP", "TMPDIR", "LANG"] {
+        if let Ok(value) = std::env::var(key) {
+            command.env(key, value);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
+
+/// Run a `command` with `input` on stdin until it exits or `timeout` passes.
+/// Errors are messages for the caller to wrap, also when the program exits unsuccessfully.
+pub fn run(command: &mut Command, input: &[u8], timeout: Duration) -> Result<HookFinished, String> {
+    run_limited(command, input, timeout, MAX_OUTPUT_LEN)
+}
+
+/// `run`, keeping up to `max_output` bytes of stdout, for programs whose output is the result
+pub fn run_limited(command: &mut Command, input: &[u8], timeout: Duration, max_output: usize) -> Result<HookFinished, String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command.spawn()
+        .map_err(|e| format!("failed to start '{}': {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_vec();
+        // Written on its own thread, a large input could fill the pipe while the program is
+        // still writing output. The program may not read stdin at all, a broken pipe is fine.
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    // Drain output on separate threads so a chatty program can't block on a full pipe
+    let stdout = child.stdout.take().map(|stdout| read_limited(stdout, max_output));
+    let stderr = child.stderr.take().map(|stderr| read_limited(stderr, MAX_OUTPUT_LEN));
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {} seconds", timeout.as_secs()));
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+
+    let stdout = stdout.and_then(|h| h.join().ok()).unwrap_or_default();
+    let stderr = stderr.and_then(|h| h.join().ok()).unwrap_or_default();
+
+    if !status.success() {
+        return Err(format!("exited with {}: {}", status, stderr.trim()));
+    }
+
+    Ok(HookFinished {
+        exit_code: status.code(),
+        stdout,
+        stderr,
+    })
+}
+
+fn read_limited<R: Read + Send + 'static>(mut reader: R, limit: usize) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = reader.by_ref().take(limit as u64).read_to_end(&mut buf);
+        // The rest is thrown away unread into memory, but still drained so the program can finish
+        let _ = io::copy(&mut reader, &mut io::sink());
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
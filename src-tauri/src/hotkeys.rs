@@ -0,0 +1,161 @@
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::AppHandle;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+use tracing::{error, info, warn};
+use crate::capture;
+use crate::idle;
+use crate::preset;
+use crate::privacy::{self, PendingCapture};
+use crate::session::SessionHandle;
+use crate::settings;
+
+/// Key that finishes a running capture. Only registered while a capture is running,
+/// so Esc keeps working normally in every other app the rest of the time.
+pub const STOP_SHORTCUT: &str = "Escape";
+
+/// Keys that take back the last stitch of a running capture (a popup or tooltip got caught).
+/// Registered for the capture only, like the stop key. Not plain Backspace, the capture runs
+/// while the user works in other apps and would swallow every Backspace typed there.
+pub const UNDO_SHORTCUT: &str = "CommandOrControl+Backspace";
+
+lazy_static! {
+    // Accelerator currently registered for "repeat last region", so it can be swapped on settings change
+    static ref REPEAT_SHORTCUT: Mutex<Option<String>> = Mutex::new(None);
+    // Accelerators of the region presets, all swapped whenever a preset changes
+    static ref PRESET_SHORTCUTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// (Re-)register global hotkeys from the current settings.
+/// Called at startup and whenever settings are saved.
+pub fn apply(app: &AppHandle) {
+    let wanted = settings::current().repeat_capture_shortcut;
+    let mut registered = REPEAT_SHORTCUT.lock().unwrap();
+    if *registered == wanted {
+        return;
+    }
+
+    if let Some(old) = registered.take() {
+        if let Err(e) = app.global_shortcut().unregister(old.as_str()) {
+            warn!("Failed to unregister shortcut {}: {}", old, e);
+        }
+    }
+
+    if let Some(accelerator) = wanted {
+        let result = app.global_shortcut().on_shortcut(accelerator.as_str(), |app, _shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+            // Leave low-power mode before the capture starts, not after it
+            idle::touch();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = capture::capture_last_region(app.clone(), None, None).await {
+                    if !privacy::ask_to_confirm(&app, PendingCapture::LastRegion, &e) {
+                        error!("Repeat capture failed: {}", e);
+                    }
+                }
+            });
+        });
+
+        match result {
+            Ok(()) => {
+                info!("Registered repeat capture shortcut {}", accelerator);
+                *registered = Some(accelerator);
+            }
+            Err(e) => warn!("Failed to register shortcut {}: {}", accelerator, e),
+        }
+    }
+}
+
+/// (Re-)register the shortcuts of the region presets.
+/// Called at startup and whenever a preset is saved or deleted.
+pub fn apply_presets(app: &AppHandle) {
+    let mut registered = PRESET_SHORTCUTS.lock().unwrap();
+    for old in registered.drain(..) {
+        if let Err(e) = app.global_shortcut().unregister(old.as_str()) {
+            warn!("Failed to unregister shortcut {}: {}", old, e);
+        }
+    }
+
+    for preset in preset::list_presets() {
+        let Some(accelerator) = preset.shortcut else {
+            continue;
+        };
+        let name = preset.name;
+        let result = app.global_shortcut().on_shortcut(accelerator.as_str(), move |app, _shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+            idle::touch();
+            let (app, name) = (app.clone(), name.clone());
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = preset::capture_preset(app.clone(), name.clone(), None).await {
+                    if !privacy::ask_to_confirm(&app, PendingCapture::Preset { name: name.clone() }, &e) {
+                        error!("Capture of preset '{}' failed: {}", name, e);
+                    }
+                }
+            });
+        });
+
+        match result {
+            Ok(()) => {
+                info!("Registered preset shortcut {}", accelerator);
+                registered.push(accelerator);
+            }
+            Err(e) => warn!("Failed to register shortcut {}: {}", accelerator, e),
+        }
+    }
+}
+
+/// Keeps the capture shortcuts (stop, undo) registered for as long as it lives.
+/// Unregistering happens in `Drop`, so the keys are released on every way out of the capture
+/// loop: normal finish, cancel, error or a panic unwinding the capture task.
+pub struct SessionShortcutGuard {
+    app: AppHandle,
+    registered: Vec<&'static str>,
+}
+
+/// Register the capture shortcuts for one capture session.
+/// Pressing one only raises a flag on the session, same as the `stop_scroll_capture` and
+/// `undo_last_stitch` commands, so both ways end up in the same code path in the capture loop.
+pub fn register_session_shortcuts(app: &AppHandle, handle: SessionHandle) -> SessionShortcutGuard {
+    let mut guard = SessionShortcutGuard { app: app.clone(), registered: Vec::new() };
+
+    let stop_handle = handle.clone();
+    guard.register(STOP_SHORTCUT, move || {
+        info!("Stop shortcut pressed for session {}.", stop_handle.id);
+        stop_handle.request_stop();
+    });
+    guard.register(UNDO_SHORTCUT, move || {
+        info!("Undo shortcut pressed for session {}.", handle.id);
+        handle.request_undo();
+    });
+    guard
+}
+
+impl SessionShortcutGuard {
+    fn register(&mut self, accelerator: &'static str, on_press: impl Fn() + Send + Sync + 'static) {
+        let result = self.app.global_shortcut().on_shortcut(accelerator, move |_app, _shortcut, event| {
+            if event.state == ShortcutState::Pressed {
+                on_press();
+            }
+        });
+
+        match result {
+            Ok(()) => self.registered.push(accelerator),
+            // Not fatal, the capture can still be controlled from the UI
+            Err(e) => warn!("Failed to register capture shortcut {}: {}", accelerator, e),
+        }
+    }
+}
+
+impl Drop for SessionShortcutGuard {
+    fn drop(&mut self) {
+        for accelerator in &self.registered {
+            if let Err(e) = self.app.global_shortcut().unregister(*accelerator) {
+                warn!("Failed to unregister capture shortcut {}: {}", accelerator, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::AppHandle;
+use tracing::{info, warn};
+use crate::tray;
+
+/// Catalogs compiled into the binary, one JSON object of key to text per language.
+/// `{name}` in a text is filled in by `text`. Missing keys fall back to English.
+static CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("es", include_str!("../locales/es.json")),
+    ("fr", include_str!("../locales/fr.json")),
+];
+const FALLBACK: &str = "en";
+
+lazy_static! {
+    static ref MESSAGES: HashMap<&'static str, HashMap<String, String>> = CATALOGS
+        .iter()
+        .filter_map(|(locale, json)| match serde_json::from_str(json) {
+            Ok(messages) => Some((*locale, messages)),
+            Err(e) => {
+                warn!("Ignoring broken {} catalog: {}", locale, e);
+                None
+            }
+        })
+        .collect();
+    // Set by the frontend at startup, until then (and for headless runs) it's English
+    static ref LOCALE: Mutex<&'static str> = Mutex::new(FALLBACK);
+}
+
+/// The text for `key` in the current locale, with every `{name}` of `args` filled in
+pub fn text(key: &str, args: &[(&str, &str)]) -> String {
+    let locale = *LOCALE.lock().unwrap();
+    let template = [locale, FALLBACK]
+        .iter()
+        .find_map(|locale| MESSAGES.get(locale).and_then(|messages| messages.get(key)))
+        .cloned()
+        .unwrap_or_else(|| key.to_string());
+    args.iter().fold(template, |text, (name, value)| text.replace(&format!("{{{}}}", name), value))
+}
+
+/// Switch backend messages (errors, notifications, the tray menu, default file names) to the
+/// frontend's language, e.g. "de-AT". Returns the locale used: the closest one there is a
+/// catalog for, English if there is none.
+#[tauri::command]
+pub fn set_locale(app: AppHandle, locale: String) -> String {
+    let wanted = locale.trim().to_lowercase().replace('_', "-");
+    let language = wanted.split('-').next().unwrap_or_default();
+    let chosen = CATALOGS
+        .iter()
+        .map(|(locale, _)| *locale)
+        .find(|locale| *locale == wanted || *locale == language)
+        .unwrap_or(FALLBACK);
+
+    *LOCALE.lock().unwrap() = chosen;
+    info!("Backend locale set to {} (asked for {})", chosen, locale);
+    tray::relabel(&app);
+    chosen.to_string()
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use tokio::sync::Notify;
+use tracing::info;
+use crate::frames;
+use crate::session;
+use crate::settings;
+use crate::sound;
+
+/// How often the idle manager looks at the time of the last capture, while not idle
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref LAST_ACTIVITY: Mutex<Instant> = Mutex::new(Instant::now());
+    static ref IDLE: AtomicBool = AtomicBool::new(false);
+    // Background loops park on this while idle, so the app has no timers waking the CPU
+    static ref WAKE: Notify = Notify::new();
+}
+
+/// Low-power mode after a while without captures: the audio output and spare frame buffers
+/// are released and the background cleanup stops ticking. The capture hotkeys stay registered,
+/// pressing one leaves the mode right away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleSettings {
+    pub enabled: bool,
+    /// Minutes without a capture before going idle
+    pub after_minutes: u32,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self { enabled: true, after_minutes: 10 }
+    }
+}
+
+/// Start watching for idleness, called once from `setup`
+pub fn start() {
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            wait_until_active().await;
+            ticker.tick().await;
+            let idle = settings::current().idle;
+            let since = LAST_ACTIVITY.lock().unwrap().elapsed();
+            // A long scroll capture has no captures finishing, but it's anything but idle
+            if idle.enabled && since >= Duration::from_secs(idle.after_minutes as u64 * 60) && session::current_state().is_finished() {
+                enter(since);
+            }
+        }
+    });
+}
+
+/// Record a capture (or a hotkey about to start one), leaving low-power mode if it was on
+pub fn touch() {
+    *LAST_ACTIVITY.lock().unwrap() = Instant::now();
+    if IDLE.swap(false, Ordering::SeqCst) {
+        info!("Leaving low-power mode");
+        WAKE.notify_waiters();
+    }
+}
+
+/// Whether the app is in low-power mode
+#[tauri::command]
+pub fn is_idle() -> bool {
+    IDLE.load(Ordering::SeqCst)
+}
+
+/// Returns right away while active, otherwise once `touch` ends low-power mode
+pub async fn wait_until_active() {
+    loop {
+        // Registered before the check, a `touch` in between is not missed
+        let woken = WAKE.notified();
+        if !IDLE.load(Ordering::SeqCst) {
+            return;
+        }
+        woken.await;
+    }
+}
+
+fn enter(since: Duration) {
+    if IDLE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("No capture for {} minutes, entering low-power mode", since.as_secs() / 60);
+    frames::release();
+    sound::release();
+}
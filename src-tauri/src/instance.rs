@@ -0,0 +1,82 @@
+use tauri::{AppHandle, Manager};
+use tracing::{error, info};
+use crate::autostart::HIDDEN_FLAG;
+use crate::capture;
+use crate::idle;
+use crate::privacy::{self, PendingCapture};
+use crate::session;
+
+/// What a launch asked for on the command line. Headless captures (`--region`) never get here,
+/// they run without the app and don't need the single instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LaunchAction {
+    /// Bring the main window to the front
+    Focus,
+    /// Capture the last region again, same as the repeat shortcut.
+    /// Only useful when forwarded, the last region isn't kept across restarts.
+    CaptureLast,
+    /// Nothing, e.g. the login item started us while we were already running
+    Stay,
+}
+
+impl LaunchAction {
+    fn from_args(args: &[String]) -> Self {
+        // argv[0] is the executable
+        if args.iter().skip(1).any(|arg| arg == "--capture-last") {
+            LaunchAction::CaptureLast
+        } else if args.iter().skip(1).any(|arg| arg == HIDDEN_FLAG) {
+            LaunchAction::Stay
+        } else {
+            LaunchAction::Focus
+        }
+    }
+}
+
+/// Called in the running instance when the app is launched again. The second process exits
+/// right after forwarding, so there is only ever one owner of the hotkeys and the clipboard.
+pub fn on_second_instance(app: &AppHandle, args: Vec<String>, _cwd: String) {
+    info!("Second launch forwarded: {:?}", args);
+    run_action(app, LaunchAction::from_args(&args));
+}
+
+fn run_action(app: &AppHandle, action: LaunchAction) {
+    match action {
+        LaunchAction::Focus => {
+            // Windows are hidden on purpose while capturing, don't pop them up over the content
+            if !session::current_state().is_finished() {
+                info!("Capture in progress, not showing the window");
+                return;
+            }
+            focus_main_window(app);
+        }
+        LaunchAction::CaptureLast => capture_last(app),
+        LaunchAction::Stay => {}
+    }
+}
+
+/// Whether this launch asked for only the tray icon, without the main window
+pub fn starts_hidden() -> bool {
+    std::env::args().skip(1).any(|arg| arg == HIDDEN_FLAG)
+}
+
+/// Capture the last region again in the background, for launches and the tray menu
+pub fn capture_last(app: &AppHandle) {
+    idle::touch();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = capture::capture_last_region(app.clone(), None, None).await {
+            if !privacy::ask_to_confirm(&app, PendingCapture::LastRegion, &e) {
+                error!("Capture of the last region failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Show the main window (and the capture in it) in front of everything else
+pub fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
@@ -0,0 +1,272 @@
+use base64::{Engine as _, engine::general_purpose};
+use chrono::Local;
+use reqwest::multipart::{Form, Part};
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+use tracing::info;
+use crate::error::CaptureError;
+use crate::exporter::{self, Exporter};
+use crate::i18n;
+use crate::keychain;
+use crate::store;
+use crate::upload::json_path;
+
+const GITHUB_API: &str = "https://api.github.com";
+
+/// Options of the "issue" exporter
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssueExportOptions {
+    #[serde(flatten)]
+    pub tracker: IssueTracker,
+    /// Title of a new issue, "Capture <time>" (localized) when unset
+    pub title: Option<String>,
+    /// Text above the capture in a new issue, or of the comment on an existing one
+    pub description: Option<String>,
+    /// Attach to this issue (GitHub number or Jira key) instead of creating one
+    pub issue: Option<String>,
+}
+
+/// Where issues are filed. The token is kept in the OS keychain, see `set_issue_token`.
+/// The frontend sends this as `{ "tracker": "github", ... }` etc.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "tracker", rename_all = "lowercase", rename_all_fields = "camelCase")]
+pub enum IssueTracker {
+    /// GitHub has no API for issue attachments, so the capture is committed to the repo
+    /// and linked from the issue
+    Github {
+        /// "owner/name"
+        repo: String,
+        /// Folder in the repo the captures are committed to
+        #[serde(default = "default_github_folder")]
+        folder: String,
+        /// Branch to commit to, the default branch when unset
+        branch: Option<String>,
+        /// API of a GitHub Enterprise server, e.g. "https://github.example.com/api/v3"
+        api_url: Option<String>,
+    },
+    Jira {
+        /// e.g. "https://example.atlassian.net"
+        base_url: String,
+        /// Project key, e.g. "QA"
+        project: String,
+        #[serde(default = "default_jira_issue_type")]
+        issue_type: String,
+        /// Account email for Jira Cloud API tokens. Without it the token is sent as a
+        /// personal access token (Jira Server and Data Center).
+        email: Option<String>,
+    },
+}
+
+/// Tracker a token is stored for
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackerKind {
+    Github,
+    Jira,
+}
+
+impl TrackerKind {
+    fn keychain_account(self) -> &'static str {
+        match self {
+            TrackerKind::Github => "github-token",
+            TrackerKind::Jira => "jira-token",
+        }
+    }
+}
+
+fn default_github_folder() -> String {
+    "captures".to_string()
+}
+
+fn default_jira_issue_type() -> String {
+    "Bug".to_string()
+}
+
+/// Store the API token for `tracker` in the OS keychain, replacing the one before
+#[tauri::command]
+pub fn set_issue_token(tracker: TrackerKind, token: String) -> Result<(), CaptureError> {
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(CaptureError::InvalidState("the token is empty".to_string()));
+    }
+    keychain::store(tracker.keychain_account(), token.as_bytes())?;
+    info!("Stored the {:?} token in the keychain", tracker);
+    Ok(())
+}
+
+/// The "issue" exporter: files the capture as a new issue, or adds it to an existing one,
+/// and returns the issue URL
+pub struct IssueExporter;
+
+impl Exporter for IssueExporter {
+    fn name(&self) -> &'static str {
+        "issue"
+    }
+
+    fn mime(&self) -> &'static str {
+        "image/png"
+    }
+
+    fn export(&self, _app: &AppHandle, id: &str, options: &Value) -> Result<String, CaptureError> {
+        let options: IssueExportOptions = exporter::parse_options(self.name(), options)?;
+        let png = store::with_capture(id, |canvas| canvas.encode_png(|_| {}))?;
+        let now = Local::now();
+        let file_name = format!("{}.png", i18n::text("file.capture", &[("time", &now.format("%Y%m%d-%H%M%S").to_string())]));
+        let title = options.title.clone()
+            .unwrap_or_else(|| i18n::text("issue.title", &[("time", &now.format("%Y-%m-%d %H:%M").to_string())]));
+        let description = options.description.clone().unwrap_or_default();
+
+        let url = tauri::async_runtime::block_on(async {
+            match &options.tracker {
+                IssueTracker::Github { repo, folder, branch, api_url } => {
+                    let github = GitHub {
+                        api: api_url.as_deref().unwrap_or(GITHUB_API).trim_end_matches('/').to_string(),
+                        repo: repo.clone(),
+                        token: token(TrackerKind::Github)?,
+                    };
+                    let path = format!("{}/{}", folder.trim_matches('/'), file_name);
+                    let image_url = github.commit_file(&path, branch.as_deref(), png).await?;
+                    let body = format!("{}\n\n![{}]({})", description, file_name, image_url).trim_start().to_string();
+                    match &options.issue {
+                        Some(number) => github.comment(number, &body).await,
+                        None => github.create_issue(&title, &body).await,
+                    }
+                }
+                IssueTracker::Jira { base_url, project, issue_type, email } => {
+                    let jira = Jira {
+                        base: base_url.trim_end_matches('/').to_string(),
+                        email: email.clone(),
+                        token: token(TrackerKind::Jira)?,
+                    };
+                    let key = match &options.issue {
+                        Some(key) => {
+                            if !description.is_empty() {
+                                jira.comment(key, &description).await?;
+                            }
+                            key.clone()
+                        }
+                        None => jira.create_issue(project, issue_type, &title, &description).await?,
+                    };
+                    jira.attach(&key, &file_name, png).await?;
+                    Ok(format!("{}/browse/{}", jira.base, key))
+                }
+            }
+        })?;
+        info!("Filed capture to issue {}", url);
+        Ok(url)
+    }
+}
+
+fn token(tracker: TrackerKind) -> Result<String, CaptureError> {
+    let token = keychain::load(tracker.keychain_account())?
+        .ok_or_else(|| CaptureError::InvalidState(format!("no {:?} token, set one first", tracker)))?;
+    String::from_utf8(token).map_err(|_| CaptureError::InvalidState(format!("the stored {:?} token is not text", tracker)))
+}
+
+/// Send `request` and return the JSON response, failing on error statuses
+async fn send(request: RequestBuilder, service: &str) -> Result<Value, CaptureError> {
+    let response = request.send().await.map_err(|e| CaptureError::UploadFailed(format!("{}: {}", service, e)))?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| CaptureError::UploadFailed(format!("{}: {}", service, e)))?;
+    if !status.is_success() {
+        return Err(CaptureError::UploadFailed(format!("{} returned {}: {}", service, status, text)));
+    }
+    Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+}
+
+struct GitHub {
+    api: String,
+    repo: String,
+    token: String,
+}
+
+impl GitHub {
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        reqwest::Client::new()
+            .request(method, format!("{}/repos/{}/{}", self.api, self.repo, path))
+            .bearer_auth(&self.token)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "scroll-snap")
+    }
+
+    /// Commit `bytes` to `path` and return a link that shows the image to everyone with access
+    async fn commit_file(&self, path: &str, branch: Option<&str>, bytes: Vec<u8>) -> Result<String, CaptureError> {
+        let mut body = json!({
+            "message": format!("Add {}", path),
+            "content": general_purpose::STANDARD.encode(bytes),
+        });
+        if let Some(branch) = branch {
+            body["branch"] = json!(branch);
+        }
+        let response = send(self.request(reqwest::Method::PUT, &format!("contents/{}", path)).json(&body), "GitHub").await?;
+        // `download_url` of private repos carries a short-lived token, the blob link works for everyone with access
+        json_path(&response, "content.html_url")
+            .map(|url| format!("{}?raw=true", url))
+            .ok_or_else(|| CaptureError::UploadFailed("GitHub response did not contain the file link".to_string()))
+    }
+
+    async fn create_issue(&self, title: &str, body: &str) -> Result<String, CaptureError> {
+        let response = send(self.request(reqwest::Method::POST, "issues").json(&json!({ "title": title, "body": body })), "GitHub").await?;
+        json_path(&response, "html_url")
+            .ok_or_else(|| CaptureError::UploadFailed("GitHub response did not contain the issue link".to_string()))
+    }
+
+    async fn comment(&self, number: &str, body: &str) -> Result<String, CaptureError> {
+        let path = format!("issues/{}/comments", number.trim_start_matches('#'));
+        let response = send(self.request(reqwest::Method::POST, &path).json(&json!({ "body": body })), "GitHub").await?;
+        json_path(&response, "html_url")
+            .ok_or_else(|| CaptureError::UploadFailed("GitHub response did not contain the comment link".to_string()))
+    }
+}
+
+struct Jira {
+    base: String,
+    email: Option<String>,
+    token: String,
+}
+
+impl Jira {
+    fn request(&self, path: &str) -> RequestBuilder {
+        let request = reqwest::Client::new().post(format!("{}/rest/api/2/{}", self.base, path));
+        match &self.email {
+            Some(email) => request.basic_auth(email, Some(&self.token)),
+            None => request.bearer_auth(&self.token),
+        }
+    }
+
+    /// Key of the new issue, e.g. "QA-123"
+    async fn create_issue(&self, project: &str, issue_type: &str, title: &str, description: &str) -> Result<String, CaptureError> {
+        let body = json!({
+            "fields": {
+                "project": { "key": project },
+                "issuetype": { "name": issue_type },
+                "summary": title,
+                "description": description,
+            }
+        });
+        let response = send(self.request("issue").json(&body), "Jira").await?;
+        json_path(&response, "key")
+            .ok_or_else(|| CaptureError::UploadFailed("Jira response did not contain the issue key".to_string()))
+    }
+
+    async fn comment(&self, key: &str, body: &str) -> Result<(), CaptureError> {
+        send(self.request(&format!("issue/{}/comment", key)).json(&json!({ "body": body })), "Jira").await?;
+        Ok(())
+    }
+
+    async fn attach(&self, key: &str, file_name: &str, bytes: Vec<u8>) -> Result<(), CaptureError> {
+        let part = Part::bytes(bytes)
+            .file_name(file_name.to_string())
+            .mime_str("image/png")
+            .map_err(|e| CaptureError::Internal(e.to_string()))?;
+        let request = self.request(&format!("issue/{}/attachments", key))
+            // Jira rejects attachment uploads without it as a possible CSRF
+            .header("X-Atlassian-Token", "no-check")
+            .multipart(Form::new().part("file", part));
+        send(request, "Jira").await?;
+        Ok(())
+    }
+}
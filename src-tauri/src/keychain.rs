@@ -0,0 +1,165 @@
+use crate::error::CaptureError;
+
+/// Service all our entries are stored under, the entry itself is picked by account
+const SERVICE: &str = "scroll-snap";
+
+/// The secret stored as `account`, `None` if there is none yet
+pub fn load(account: &str) -> Result<Option<Vec<u8>>, CaptureError> {
+    platform::load(account)
+}
+
+/// Store `secret` as `account`, replacing what was there
+pub fn store(account: &str, secret: &[u8]) -> Result<(), CaptureError> {
+    platform::store(account, secret)
+}
+
+/// Credential Manager, as generic credentials named "scroll-snap/<account>"
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::core::{HSTRING, PWSTR};
+    use windows::Win32::Foundation::ERROR_NOT_FOUND;
+    use windows::Win32::Security::Credentials::{
+        CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC,
+    };
+    use super::SERVICE;
+    use crate::error::CaptureError;
+
+    fn target(account: &str) -> String {
+        format!("{}/{}", SERVICE, account)
+    }
+
+    pub fn load(account: &str) -> Result<Option<Vec<u8>>, CaptureError> {
+        let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
+        unsafe {
+            match CredReadW(&HSTRING::from(target(account)), CRED_TYPE_GENERIC, None, &mut credential) {
+                Ok(()) => {}
+                Err(e) if e.code() == ERROR_NOT_FOUND.to_hresult() => return Ok(None),
+                Err(e) => return Err(CaptureError::KeychainFailed(format!("could not read '{}' from Credential Manager: {}", account, e))),
+            }
+            let blob = std::slice::from_raw_parts((*credential).CredentialBlob, (*credential).CredentialBlobSize as usize).to_vec();
+            CredFree(credential as *const _);
+            Ok(Some(blob))
+        }
+    }
+
+    pub fn store(account: &str, secret: &[u8]) -> Result<(), CaptureError> {
+        let mut target: Vec<u16> = target(account).encode_utf16().chain(std::iter::once(0)).collect();
+        let mut blob = secret.to_vec();
+        let credential = CREDENTIALW {
+            Type: CRED_TYPE_GENERIC,
+            TargetName: PWSTR(target.as_mut_ptr()),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            ..Default::default()
+        };
+        unsafe { CredWriteW(&credential, 0) }
+            .map_err(|e| CaptureError::KeychainFailed(format!("could not store '{}' in Credential Manager: {}", account, e)))
+    }
+}
+
+/// The login keychain, as generic passwords
+#[cfg(target_os = "macos")]
+mod platform {
+    use security_framework::passwords::{get_generic_password, set_generic_password};
+    use super::SERVICE;
+    use crate::error::CaptureError;
+
+    /// errSecItemNotFound
+    const ITEM_NOT_FOUND: i32 = -25300;
+
+    pub fn load(account: &str) -> Result<Option<Vec<u8>>, CaptureError> {
+        match get_generic_password(SERVICE, account) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(e) if e.code() == ITEM_NOT_FOUND => Ok(None),
+            Err(e) => Err(CaptureError::KeychainFailed(format!("could not read '{}' from the keychain: {}", account, e))),
+        }
+    }
+
+    pub fn store(account: &str, secret: &[u8]) -> Result<(), CaptureError> {
+        set_generic_password(SERVICE, account, secret)
+            .map_err(|e| CaptureError::KeychainFailed(format!("could not store '{}' in the keychain: {}", account, e)))
+    }
+}
+
+/// The Secret Service (GNOME Keyring, KWallet) over D-Bus, in the default collection.
+/// A locked keyring has to be unlocked by the user first, we don't show the unlock prompt.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::collections::HashMap;
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+    use super::SERVICE;
+    use crate::error::CaptureError;
+
+    const BUS_NAME: &str = "org.freedesktop.secrets";
+    const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+    /// Object path the Secret Service returns for "nothing", e.g. no prompt needed
+    const NO_OBJECT: &str = "/";
+
+    /// Session, parameters, value and content type
+    type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+    pub fn load(account: &str) -> Result<Option<Vec<u8>>, CaptureError> {
+        let connection = Connection::session().map_err(error)?;
+        let session = open_session(&connection)?;
+        let reply = connection
+            .call_method(Some(BUS_NAME), SERVICE_PATH, Some("org.freedesktop.Secret.Service"), "SearchItems", &(attributes(account),))
+            .map_err(error)?;
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = reply.body().deserialize().map_err(error)?;
+        let Some(item) = unlocked.first() else {
+            if locked.is_empty() {
+                return Ok(None);
+            }
+            return Err(CaptureError::KeychainFailed("the keyring is locked, unlock it and try again".to_string()));
+        };
+
+        let reply = connection
+            .call_method(Some(BUS_NAME), item.as_str(), Some("org.freedesktop.Secret.Item"), "GetSecret", &(&session,))
+            .map_err(error)?;
+        let (_, _, value, _): Secret = reply.body().deserialize().map_err(error)?;
+        Ok(Some(value))
+    }
+
+    pub fn store(account: &str, secret: &[u8]) -> Result<(), CaptureError> {
+        let connection = Connection::session().map_err(error)?;
+        let session = open_session(&connection)?;
+        let properties = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(format!("ScrollSnap {}", account))),
+            ("org.freedesktop.Secret.Item.Attributes", Value::from(attributes(account))),
+        ]);
+        let secret: Secret = (session, Vec::new(), secret.to_vec(), "application/octet-stream".to_string());
+        let reply = connection
+            .call_method(
+                Some(BUS_NAME),
+                DEFAULT_COLLECTION,
+                Some("org.freedesktop.Secret.Collection"),
+                "CreateItem",
+                &(properties, secret, true),
+            )
+            .map_err(error)?;
+        let (item, _prompt): (OwnedObjectPath, OwnedObjectPath) = reply.body().deserialize().map_err(error)?;
+        if item.as_str() == NO_OBJECT {
+            return Err(CaptureError::KeychainFailed("the keyring is locked, unlock it and try again".to_string()));
+        }
+        Ok(())
+    }
+
+    /// A "plain" session: the secret only travels over the local session bus
+    fn open_session(connection: &Connection) -> Result<OwnedObjectPath, CaptureError> {
+        let reply = connection
+            .call_method(Some(BUS_NAME), SERVICE_PATH, Some("org.freedesktop.Secret.Service"), "OpenSession", &("plain", Value::from("")))
+            .map_err(error)?;
+        let (_, session): (OwnedValue, OwnedObjectPath) = reply.body().deserialize().map_err(error)?;
+        Ok(session)
+    }
+
+    fn attributes(account: &str) -> HashMap<&'static str, String> {
+        HashMap::from([("application", SERVICE.to_string()), ("account", account.to_string())])
+    }
+
+    fn error(e: zbus::Error) -> CaptureError {
+        CaptureError::KeychainFailed(format!("Secret Service: {}", e))
+    }
+}
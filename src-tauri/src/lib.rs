@@ -1,12 +1,82 @@
 use tauri::Manager;
-#[cfg(target_os = "windows")]
-use windows::Win32::Foundation::HWND;
-#[cfg(target_os = "windows")]
-use windows::Win32::UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE};
+use tracing::warn;
 
+mod accessibility;
+mod archive;
+mod autocrop;
+mod autoscroll;
+mod autosave;
+mod autostart;
+mod backend;
+mod baseline;
+mod canvas;
 mod capture;
+mod cdp;
+mod clipboard;
+mod color;
+mod composite;
+mod debug;
+mod decorate;
+mod display;
+mod dnd;
+mod encryption;
+mod error;
+mod exporter;
+mod external;
+mod frames;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod headless;
+mod history;
+mod hook;
+mod hotkeys;
+mod i18n;
+mod idle;
+mod instance;
+mod issue;
+mod keychain;
+mod logging;
+mod metadata;
+mod metrics;
+mod native_host;
+mod notify;
+mod optimize;
+mod overlay;
+mod pacing;
+mod pdf;
+mod permission;
+mod pipeline;
+mod preset;
+mod privacy;
+mod redact;
+mod retention;
+mod schedule;
+mod search;
+mod script;
+mod scroll_area;
+mod session;
+mod settings;
+mod snippet;
+mod sound;
+mod stats;
 mod stitch;
+mod store;
+mod tray;
+mod updater;
+mod upload;
 mod utils;
+mod watch;
+mod window_list;
+
+pub use autoscroll::{AutoScroll, ScrollKey};
+pub use backend::BackendKind;
+pub use capture::CaptureOptions;
+pub use headless::HeadlessOptions;
+
+/// Capture from the command line without starting the app, returns the process exit code
+pub fn run_headless(options: HeadlessOptions) -> i32 {
+    headless::run(options)
+}
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -16,26 +86,125 @@ fn greet(name: &str) -> String {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Started by the browser for the companion extension, talk over stdin/stdout instead of opening the app
+    if native_host::is_native_messaging_launch() {
+        logging::init_console();
+        native_host::run();
+        return;
+    }
+
     tauri::Builder::default()
+        // Has to be the first plugin, so a second launch exits before anything else is set up
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            instance::on_second_instance(app, args, cwd);
+        }))
         .setup(|app| {
-            #[cfg(target_os = "windows")]
-            {
-                let window = app.get_webview_window("main").unwrap();
-                let hwnd = window.hwnd().unwrap().0;
-                unsafe {
-                    let _ = SetWindowDisplayAffinity(HWND(hwnd as _), WDA_EXCLUDEFROMCAPTURE);
+            logging::init(app.handle());
+            if let Some(window) = app.get_webview_window("main") {
+                overlay::exclude_from_capture(&window);
+                // Created hidden, `--hidden` (start at login) keeps it that way
+                if !instance::starts_hidden() {
+                    let _ = window.show();
                 }
             }
+            settings::load(app.handle());
+            store::init();
+            history::init(app.handle());
+            preset::load(app.handle());
+            hotkeys::apply(app.handle());
+            hotkeys::apply_presets(app.handle());
+            schedule::start(app.handle());
+            retention::start(app.handle());
+            idle::start();
+            updater::start(app.handle());
+            notify::init(app.handle());
+            autostart::apply();
+            if let Err(e) = tray::create(app.handle()) {
+                warn!("Could not create the tray icon: {}", e);
+            }
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
         .invoke_handler(tauri::generate_handler![
             greet, 
             capture::start_scroll_capture,
             capture::stop_scroll_capture,
-            utils::copy_to_clipboard,
-            utils::save_image
+            capture::cancel_scroll_capture,
+            capture::undo_last_stitch,
+            capture::capture_last_region,
+            autosave::get_recoverable_session,
+            autosave::recover_session,
+            autosave::discard_recovered_session,
+            capture::capture_region_once,
+            capture::capture_fullscreen,
+            capture::stitch_files,
+            cdp::capture_browser_page,
+            debug::replay_session,
+            display::get_displays,
+            window_list::get_window_rects,
+            scroll_area::detect_scroll_area,
+            session::get_capture_state,
+            session::set_region_selection,
+            utils::save_image,
+            upload::upload_image,
+            snippet::copy_as_markdown,
+            snippet::copy_as_html,
+            autocrop::autocrop,
+            store::crop_image,
+            store::get_captured_image,
+            store::get_capture_tile,
+            store::get_capture_report,
+            store::copy_capture_to_clipboard,
+            exporter::list_exporters,
+            exporter::export_capture,
+            issue::set_issue_token,
+            external::open_with,
+            external::reveal_in_folder,
+            metadata::read_metadata,
+            metadata::annotate_capture,
+            pdf::export_pdf,
+            logging::get_recent_logs,
+            logging::set_log_level,
+            color::pick_color,
+            color::get_palette,
+            dnd::start_drag,
+            decorate::frame_image,
+            redact::redact_regions,
+            redact::auto_redact,
+            baseline::save_baseline,
+            baseline::compare_to_baseline,
+            baseline::list_baselines,
+            baseline::delete_baseline,
+            history::list_history,
+            history::get_thumbnail,
+            search::search_history,
+            archive::export_history,
+            archive::import_history,
+            retention::purge_history,
+            idle::is_idle,
+            i18n::set_locale,
+            stats::get_stats,
+            updater::check_for_update,
+            updater::download_update,
+            updater::install_update,
+            schedule::create_schedule,
+            schedule::list_schedules,
+            schedule::delete_schedule,
+            preset::list_presets,
+            preset::save_preset,
+            preset::delete_preset,
+            preset::capture_preset,
+            privacy::confirm_capture,
+            watch::watch_region,
+            watch::unwatch_region,
+            settings::get_settings,
+            settings::set_settings,
+            permission::check_capture_permission,
+            permission::open_capture_permission_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Manager};
+use tracing::info;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+use crate::error::CaptureError;
+use crate::settings::{self, Settings};
+
+const LOG_PREFIX: &str = "scroll-snap";
+const LOG_SUFFIX: &str = "log";
+
+/// Daily log files kept, older ones are deleted on rotation
+const MAX_LOG_FILES: usize = 7;
+
+/// Lines `get_recent_logs` returns by default
+const DEFAULT_RECENT_LINES: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    /// Adds per-frame stitch decisions and state changes
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+lazy_static! {
+    // Changes the level of the installed subscriber at runtime
+    static ref LEVEL: Mutex<Option<reload::Handle<LevelFilter, Registry>>> = Mutex::new(None);
+    // Flushes the background file writer when dropped, kept for as long as the app runs
+    static ref FILE_GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+}
+
+/// Log to stdout and to daily rotated files in the app log directory, called first thing in `setup`.
+/// Starts at `info`, the configured level is applied once settings are loaded.
+pub fn init(app: &AppHandle) {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+    let file = log_dir(app).and_then(|dir| {
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_PREFIX)
+            .filename_suffix(LOG_SUFFIX)
+            .max_log_files(MAX_LOG_FILES)
+            .build(&dir);
+        match appender {
+            Ok(appender) => {
+                let (writer, guard) = tracing_appender::non_blocking(appender);
+                *FILE_GUARD.lock().unwrap() = Some(guard);
+                Some(fmt::layer().with_ansi(false).with_writer(writer))
+            }
+            Err(e) => {
+                eprintln!("Could not open log file in {}: {}", dir.display(), e);
+                None
+            }
+        }
+    });
+
+    let installed = tracing_subscriber::registry().with(filter).with(fmt::layer()).with(file).try_init();
+    if let Err(e) = installed {
+        eprintln!("Could not set up logging: {}", e);
+        return;
+    }
+    *LEVEL.lock().unwrap() = Some(handle);
+    if let Some(dir) = log_dir(app) {
+        info!("Logging to {}", dir.display());
+    }
+}
+
+/// Stdout only, for the native messaging host and the command line which run without the app
+pub fn init_console() {
+    let _ = tracing_subscriber::registry().with(LevelFilter::INFO).with(fmt::layer()).try_init();
+}
+
+/// Change the level of the running logger
+pub fn apply_level(level: LogLevel) {
+    if let Some(handle) = LEVEL.lock().unwrap().as_ref() {
+        if let Err(e) = handle.modify(|filter| *filter = level.filter()) {
+            eprintln!("Could not change log level: {}", e);
+        }
+    }
+}
+
+/// Change the log level and keep it in the settings
+#[tauri::command]
+pub fn set_log_level(app: AppHandle, level: LogLevel) -> Result<(), CaptureError> {
+    settings::save(&app, Settings { log_level: level, ..settings::current() })?;
+    info!("Log level set to {:?}", level);
+    Ok(())
+}
+
+/// The last `lines` (default 500) log lines, oldest first, reaching back into
+/// older files if the current one is shorter. For attaching to bug reports.
+#[tauri::command]
+pub fn get_recent_logs(app: AppHandle, lines: Option<usize>) -> Result<Vec<String>, CaptureError> {
+    let wanted = lines.unwrap_or(DEFAULT_RECENT_LINES);
+    let dir = log_dir(&app)
+        .ok_or_else(|| CaptureError::Internal("Could not resolve app log directory".to_string()))?;
+    let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy())
+                    .is_some_and(|name| name.starts_with(LOG_PREFIX) && name.ends_with(LOG_SUFFIX))
+            })
+            .collect(),
+        Err(_) => return Ok(Vec::new()),
+    };
+    // The date in the name sorts them oldest first
+    files.sort();
+
+    let mut recent: Vec<String> = Vec::new();
+    for path in files.iter().rev() {
+        if recent.len() >= wanted {
+            break;
+        }
+        let content = fs::read_to_string(path)?;
+        let mut older: Vec<String> = content.lines().map(str::to_string).collect();
+        let keep = older.len().saturating_sub(wanted - recent.len());
+        older.drain(..keep);
+        older.append(&mut recent);
+        recent = older;
+    }
+    Ok(recent)
+}
+
+fn log_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_log_dir().ok()
+}
@@ -1,8 +1,91 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::PathBuf;
+use scroll_snap_lib::{AutoScroll, BackendKind, CaptureOptions, HeadlessOptions};
+
+const USAGE: &str = "Usage: scroll-snap --region X,Y,WIDTH,HEIGHT [options]
+
+Captures the region (physical pixels) without opening the app and saves it as PNG.
+
+Options:
+  --out PATH            Output file (default: scrollsnap-<timestamp>.png)
+  --auto-scroll         Scroll with key presses instead of waiting for you to scroll
+  --scroll-key KEY      page-down, space or down-arrow (default: page-down)
+  --settle-ms MS        Wait after each key press (default: 400)
+  --interval-ms MS      Delay between captures when scrolling by hand (default: 100)
+  --max-stitches N      Stop after N fragments (default: 500)
+  --idle-frames N       Stop after N frames without scrolling (default: 30)
+  --window              Capture the window under the region even while it's covered (Windows only)";
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // A region on the command line means a headless capture, anything else starts the app
+    if args.iter().any(|arg| arg == "--region") {
+        match parse_headless(&args) {
+            Ok(options) => std::process::exit(scroll_snap_lib::run_headless(options)),
+            Err(e) => {
+                eprintln!("{}\n\n{}", e, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
     // Fix for Linux WebKit rendering issues (inverted/mirrored UI) in VMs or specific drivers
     std::env::set_var("WEBKIT_DISABLE_COMPOSITING_MODE", "1");
     scroll_snap_lib::run()
 }
+
+fn parse_headless(args: &[String]) -> Result<HeadlessOptions, String> {
+    let mut region = None;
+    let mut out = None;
+    let mut capture = CaptureOptions::default();
+    let mut auto_scroll = None::<AutoScroll>;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--region" => region = Some(parse_region(value()?)?),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--auto-scroll" => {
+                auto_scroll.get_or_insert_with(AutoScroll::default);
+            }
+            "--scroll-key" => {
+                let key = value()?.parse().map_err(|e| format!("{}", e))?;
+                auto_scroll.get_or_insert_with(AutoScroll::default).key = key;
+            }
+            "--settle-ms" => auto_scroll.get_or_insert_with(AutoScroll::default).settle_delay_ms = parse_number(arg, value()?)?,
+            "--interval-ms" => capture.poll_interval_ms = parse_number(arg, value()?)?,
+            "--max-stitches" => capture.max_stitches = parse_number(arg, value()?)?,
+            "--idle-frames" => capture.max_static_count = parse_number(arg, value()?)?,
+            "--window" => capture.backend = BackendKind::Window,
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+
+    capture.auto_scroll = auto_scroll;
+    Ok(HeadlessOptions {
+        region: region.ok_or("--region is required")?,
+        out,
+        capture,
+    })
+}
+
+fn parse_region(value: &str) -> Result<(i32, i32, u32, u32), String> {
+    let invalid = || format!("Invalid region '{}', expected X,Y,WIDTH,HEIGHT", value);
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    if parts.len() != 4 {
+        return Err(invalid());
+    }
+    Ok((
+        parts[0].parse().map_err(|_| invalid())?,
+        parts[1].parse().map_err(|_| invalid())?,
+        parts[2].parse().map_err(|_| invalid())?,
+        parts[3].parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
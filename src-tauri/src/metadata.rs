@@ -0,0 +1,165 @@
+use png::text_metadata::{EncodableTextChunk, ITXtChunk, TEXtChunk};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use tauri::AppHandle;
+use tracing::info;
+use crate::canvas::Segment;
+use crate::capture::CaptureOptions;
+use crate::decorate::ExportOptions;
+use crate::display::{DisplayInfo, Rect};
+use crate::error::CaptureError;
+use crate::history;
+use crate::metrics::MetricsSummary;
+use crate::store;
+
+/// iTXt keyword holding the metadata as JSON
+const METADATA_KEYWORD: &str = "ScrollSnap";
+
+/// Where a capture came from, embedded in exported PNGs when enabled in the export options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureMetadata {
+    /// RFC 3339 local time
+    pub captured_at: String,
+    pub app_version: String,
+    /// Physical desktop pixels, unset for captures not taken from the screen
+    pub region: Option<Rect>,
+    /// Name of the monitor the region was on
+    pub monitor: Option<String>,
+    pub stitch_count: u32,
+    /// Free text, from `annotate_capture` or passed when saving
+    pub note: Option<String>,
+    /// Set with `annotate_capture`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// From the first frame until scrolling stopped
+    pub capture_ms: Option<u64>,
+    /// From scrolling stopped until the result was stored, auto-crop included
+    pub encode_ms: Option<u64>,
+    /// Capture loop settings the capture was taken with
+    pub options: Option<CaptureOptions>,
+    /// Time spent per frame in each stage of the capture loop
+    pub metrics: Option<MetricsSummary>,
+    /// Captured pixels per image pixel along each side, when the canvas was downscaled
+    /// to stay under the memory cap
+    pub downscale: Option<u32>,
+}
+
+impl CaptureMetadata {
+    pub fn new(app_version: String, region: Option<Rect>, layout: &[DisplayInfo], stitch_count: u32) -> Self {
+        // The monitor under the middle of the region, regions may span several
+        let monitor = region.and_then(|region| {
+            let (x, y) = (region.x + region.width as i32 / 2, region.y + region.height as i32 / 2);
+            layout
+                .iter()
+                .find(|display| x >= display.x && x < display.x + display.width as i32 && y >= display.y && y < display.y + display.height as i32)
+                .map(|display| display.name.clone())
+        });
+        Self {
+            captured_at: chrono::Local::now().to_rfc3339(),
+            app_version,
+            region,
+            monitor,
+            stitch_count,
+            note: None,
+            tags: Vec::new(),
+            capture_ms: None,
+            encode_ms: None,
+            options: None,
+            metrics: None,
+            downscale: None,
+        }
+    }
+}
+
+/// Written next to a saved image as `<name>.json` when enabled in the export options,
+/// for tools that process captures and want to know how they were put together
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sidecar {
+    /// File name of the image this belongs to
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub metadata: Option<CaptureMetadata>,
+    /// Rows are in capture pixels, before any export scaling or caption
+    pub segments: Vec<Segment>,
+    pub export: ExportOptions,
+}
+
+/// Insert the metadata into an encoded PNG as text chunks right after the header, without
+/// re-encoding the pixels. Besides our JSON, the standard "Software" and "Creation Time"
+/// keywords are set so image viewers can show them.
+pub fn embed(png: &[u8], metadata: &CaptureMetadata) -> Result<Vec<u8>, CaptureError> {
+    // 8 byte signature, then IHDR: length (4), type (4), 13 bytes of data, CRC (4)
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    if png.len() < IHDR_END || &png[12..16] != b"IHDR" {
+        return Err(CaptureError::DecodeFailed("not a PNG file".to_string()));
+    }
+
+    let encode_err = |e: png::EncodingError| CaptureError::Internal(format!("failed to encode metadata: {}", e));
+    let json = serde_json::to_string(metadata).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    let mut chunks = Vec::new();
+    TEXtChunk::new("Software", format!("ScrollSnap {}", metadata.app_version)).encode(&mut chunks).map_err(encode_err)?;
+    TEXtChunk::new("Creation Time", metadata.captured_at.clone()).encode(&mut chunks).map_err(encode_err)?;
+    // iTXt because the note can be any language
+    ITXtChunk::new(METADATA_KEYWORD, json).encode(&mut chunks).map_err(encode_err)?;
+
+    let mut out = Vec::with_capacity(png.len() + chunks.len());
+    out.extend_from_slice(&png[..IHDR_END]);
+    out.extend_from_slice(&chunks);
+    out.extend_from_slice(&png[IHDR_END..]);
+    Ok(out)
+}
+
+/// Attach a note and tags to the capture `id`, so it can be found again later. Both the capture
+/// that was just taken (its metadata is embedded and written to the sidecar on save) and its
+/// history entry are updated, so they are searchable with `list_history` and `search_history`
+/// after the capture left the store. Ids of older history entries work too.
+/// Replaces what was set before, an empty note removes it.
+#[tauri::command]
+pub fn annotate_capture(app: AppHandle, id: String, note: Option<String>, tags: Vec<String>) -> Result<(), CaptureError> {
+    let note = note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    let tags = tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()).fold(Vec::<String>::new(), |mut tags, tag| {
+        if !tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.to_string());
+        }
+        tags
+    });
+
+    // The store first: a history entry written in between takes the note from there
+    let stored = store::metadata(&id).is_ok();
+    if stored {
+        let version = app.package_info().version.to_string();
+        let (note, tags) = (note.clone(), tags.clone());
+        store::update_metadata(&id, |metadata| {
+            let metadata = metadata.get_or_insert_with(|| CaptureMetadata::new(version, None, &[], 0));
+            metadata.note = note;
+            metadata.tags = tags;
+        })?;
+    }
+    let in_history = history::annotate(&app, &id, note, tags)?.is_some();
+    if !stored && !in_history {
+        return Err(CaptureError::InvalidState(format!("no capture or history entry with id {}", id)));
+    }
+    info!("Annotated capture {}", id);
+    Ok(())
+}
+
+/// Metadata embedded in the PNG at `path`, `None` for images saved without it.
+/// Only the chunks before the pixel data are read.
+#[tauri::command]
+pub fn read_metadata(path: String) -> Result<Option<CaptureMetadata>, CaptureError> {
+    let file = File::open(&path)?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", path, e)))?;
+    let Some(chunk) = reader.info().utf8_text.iter().find(|chunk| chunk.keyword == METADATA_KEYWORD) else {
+        return Ok(None);
+    };
+    let text = chunk.get_text().map_err(|e| CaptureError::DecodeFailed(format!("{}: {}", path, e)))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| CaptureError::DecodeFailed(format!("invalid metadata in {}: {}", path, e)))
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// Payload of the `capture-metrics` event, sent once per captured frame.
+/// Times are in milliseconds.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameMetrics {
+    pub frame_index: u32,
+    /// Grabbing the frame from the screen
+    pub capture_ms: f32,
+    /// Comparing against the previous frame: static check, overlap search and tear check
+    pub compare_ms: f32,
+    /// Appending to the canvas, including pending fragments that were recovered
+    pub stitch_ms: f32,
+    /// All of the above, the time the loop spent on this frame besides waiting
+    pub total_ms: f32,
+}
+
+/// Average and worst time of one stage over a capture, in milliseconds
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageStats {
+    pub avg_ms: f32,
+    pub max_ms: f32,
+}
+
+/// Per-stage timings over a whole capture, kept with the capture metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSummary {
+    pub frames: u32,
+    pub capture: StageStats,
+    pub compare: StageStats,
+    pub stitch: StageStats,
+    pub total: StageStats,
+}
+
+/// Collects the timings of the frame the capture loop is working on, and the totals.
+/// A frame is reported when the next one starts, since the loop leaves a frame at many points.
+#[derive(Default)]
+pub struct MetricsRecorder {
+    current: Option<FrameMetrics>,
+    frames: u32,
+    sums: [f32; 4],
+    maxes: [f32; 4],
+}
+
+impl MetricsRecorder {
+    pub fn start_frame(&mut self, frame_index: u32, capture: Duration) {
+        self.current = Some(FrameMetrics { frame_index, capture_ms: millis(capture), ..Default::default() });
+    }
+
+    pub fn add_compare(&mut self, elapsed: Duration) {
+        if let Some(frame) = self.current.as_mut() {
+            frame.compare_ms += millis(elapsed);
+        }
+    }
+
+    pub fn add_stitch(&mut self, elapsed: Duration) {
+        if let Some(frame) = self.current.as_mut() {
+            frame.stitch_ms += millis(elapsed);
+        }
+    }
+
+    /// Report the current frame, if there is one
+    pub fn finish_frame(&mut self, app: &AppHandle) {
+        let Some(mut frame) = self.current.take() else {
+            return;
+        };
+        frame.total_ms = frame.capture_ms + frame.compare_ms + frame.stitch_ms;
+        let stages = [frame.capture_ms, frame.compare_ms, frame.stitch_ms, frame.total_ms];
+        for (i, ms) in stages.into_iter().enumerate() {
+            self.sums[i] += ms;
+            self.maxes[i] = self.maxes[i].max(ms);
+        }
+        self.frames += 1;
+        let _ = app.emit("capture-metrics", frame);
+    }
+
+    pub fn summary(&self) -> MetricsSummary {
+        let stats = |i: usize| StageStats {
+            avg_ms: self.sums[i] / self.frames.max(1) as f32,
+            max_ms: self.maxes[i],
+        };
+        MetricsSummary { frames: self.frames, capture: stats(0), compare: stats(1), stitch: stats(2), total: stats(3) }
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use image::DynamicImage;
+use tracing::{debug, info, warn};
+use crate::canvas::{Canvas, Stitch};
+use crate::composite::CompositeOptions;
+use crate::capture;
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::permission;
+use crate::privacy;
+use crate::stitch;
+
+/// Flag to start in host mode by hand (e.g. from a wrapper script for Firefox)
+const HOST_FLAG: &str = "--native-messaging";
+
+/// Browsers refuse bigger messages from the host, and a malformed length would make us allocate gigabytes
+const MAX_MESSAGE_BYTES: u32 = 1024 * 1024;
+
+/// Chrome starts the host with the calling extension's origin as the first argument
+pub fn is_native_messaging_launch() -> bool {
+    std::env::args().skip(1).any(|arg| arg == HOST_FLAG || arg.starts_with("chrome-extension://"))
+}
+
+/// What the companion extension can ask for. The extension scrolls the page and hides
+/// sticky elements itself, and tells us when the next part is on screen.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Request {
+    /// Begin a capture of the page viewport, in physical desktop pixels. `allowSensitive`
+    /// confirms capturing over the windows a `SENSITIVE_WINDOW` error named.
+    Start {
+        region: Rect,
+        #[serde(default, rename = "allowSensitive")]
+        allow_sensitive: bool,
+    },
+    /// The page was scrolled, capture the next part. `scrolledBy` (physical pixels) is
+    /// used as the overlap directly when the extension knows it, otherwise it's matched.
+    Frame {
+        #[serde(rename = "scrolledBy")]
+        scrolled_by: Option<u32>,
+    },
+    /// Encode and save the capture. Defaults to a file in the temp directory.
+    Finish { path: Option<String> },
+    Cancel,
+}
+
+/// Request with the id the extension uses to match our reply
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    id: Option<Value>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Response {
+    Started { width: u32, height: u32 },
+    Stitched { overlap: u32, height: u32 },
+    /// Nothing moved since the last frame
+    Static,
+    /// Scrolled too far, the frame was dropped. Scroll back a bit and send `frame` again.
+    NoOverlap,
+    /// The image itself is too big for a message, the extension gets the file instead
+    Done { path: String, width: u32, height: u32 },
+    Cancelled,
+    Error { code: &'static str, message: String },
+}
+
+#[derive(Debug, Serialize)]
+struct Reply {
+    id: Option<Value>,
+    #[serde(flatten)]
+    response: Response,
+}
+
+/// A capture driven by the extension
+struct HostCapture {
+    region: Rect,
+    /// Parts of the region grayed out in every frame, see `privacy::check`
+    blanked: Vec<Rect>,
+    canvas: Canvas,
+    last_frame: DynamicImage,
+    frame_index: u32,
+}
+
+/// Serve native messaging requests on stdin/stdout until the browser closes the pipe.
+/// Runs instead of the normal app, no windows are opened.
+pub fn run() {
+    let mut output = match claim_stdout() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Native messaging host could not take over stdout: {}", e);
+            return;
+        }
+    };
+    info!("Native messaging host started");
+
+    let mut input = io::stdin().lock();
+    let mut capture: Option<HostCapture> = None;
+    loop {
+        let envelope = match read_message(&mut input) {
+            Ok(Some(message)) => serde_json::from_slice::<Envelope>(&message)
+                .map_err(|e| CaptureError::InvalidState(format!("invalid request: {}", e))),
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Native messaging input failed: {}", e);
+                break;
+            }
+        };
+
+        let (id, response) = match envelope {
+            Ok(Envelope { id, request }) => {
+                debug!("Native messaging request: {:?}", request);
+                (id, handle(&mut capture, request))
+            }
+            Err(e) => (None, Err(e)),
+        };
+        let response = response.unwrap_or_else(|e| Response::Error { code: e.code(), message: e.localized() });
+
+        if let Err(e) = write_message(&mut output, &Reply { id, response }) {
+            warn!("Native messaging output failed: {}", e);
+            break;
+        }
+    }
+    info!("Native messaging host finished");
+}
+
+fn handle(capture: &mut Option<HostCapture>, request: Request) -> Result<Response, CaptureError> {
+    match request {
+        Request::Start { region, allow_sensitive } => {
+            permission::ensure_capture_permission()?;
+            let region = display::validate_region(region)?;
+            let blanked = privacy::check(&region, allow_sensitive)?;
+            let mut first_frame = capture::capture_region(&region)?;
+            privacy::blank(&mut first_frame, &blanked);
+            let canvas = Canvas::new(&first_frame);
+            let response = Response::Started { width: canvas.width(), height: canvas.height() };
+            *capture = Some(HostCapture { region, blanked, canvas, last_frame: first_frame, frame_index: 0 });
+            Ok(response)
+        }
+        Request::Frame { scrolled_by } => {
+            let current = capture.as_mut().ok_or_else(no_capture)?;
+            let mut frame = capture::capture_region(&current.region)?;
+            privacy::blank(&mut frame, &current.blanked);
+            current.frame_index += 1;
+            let size = (frame.width(), frame.height());
+            let (frame, fit) = capture::fit_fragment(frame, current.region.width, current.region.height, &CompositeOptions::default());
+            if let Some(fit) = fit {
+                warn!("Captured frame changed size to {}x{}, {:?}", size.0, size.1, fit);
+            }
+
+            let overlap = match scrolled_by {
+                Some(0) => return Ok(Response::Static),
+                Some(delta) if delta >= frame.height() => return Ok(Response::NoOverlap),
+                Some(delta) => frame.height() - delta,
+                None if stitch::is_same_frame(&current.last_frame, &frame) => return Ok(Response::Static),
+                None => match stitch::calculate_overlap(&current.last_frame, &frame) {
+                    0 => return Ok(Response::NoOverlap),
+                    overlap => overlap,
+                },
+            };
+
+            let confidence = stitch::overlap_confidence(&current.last_frame, &frame, overlap);
+            current.canvas.append(&frame, Stitch { frame_index: current.frame_index, overlap, confidence })?;
+            current.last_frame = frame;
+            Ok(Response::Stitched { overlap, height: current.canvas.height() })
+        }
+        Request::Finish { path } => {
+            let mut finished = capture.take().ok_or_else(no_capture)?;
+            let path = path.map(PathBuf::from).unwrap_or_else(|| {
+                std::env::temp_dir().join(format!("scrollsnap-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+            });
+            let png = finished.canvas.encode_png(|_| {})?;
+            std::fs::write(&path, png)?;
+            info!("Native messaging capture saved to {}", path.display());
+            Ok(Response::Done {
+                path: path.display().to_string(),
+                width: finished.canvas.width(),
+                height: finished.canvas.height(),
+            })
+        }
+        Request::Cancel => {
+            *capture = None;
+            Ok(Response::Cancelled)
+        }
+    }
+}
+
+fn no_capture() -> CaptureError {
+    CaptureError::InvalidState("no capture started, send 'start' first".to_string())
+}
+
+/// One message: a 32-bit length in native byte order, then that much UTF-8 JSON.
+/// `None` once the browser closed stdin.
+fn read_message(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_ne_bytes(length);
+    if length > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("message of {} bytes is too big", length)));
+    }
+    let mut message = vec![0u8; length as usize];
+    input.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, reply: &Reply) -> io::Result<()> {
+    let json = serde_json::to_vec(reply).map_err(io::Error::other)?;
+    output.write_all(&(json.len() as u32).to_ne_bytes())?;
+    output.write_all(&json)?;
+    output.flush()
+}
+
+/// Take the real stdout for the protocol and point the process's stdout at stderr.
+/// The app's log output goes to stdout, which would otherwise corrupt the message stream.
+#[cfg(unix)]
+fn claim_stdout() -> io::Result<File> {
+    use std::os::fd::FromRawFd;
+
+    io::stdout().flush()?;
+    unsafe {
+        let protocol = libc::dup(libc::STDOUT_FILENO);
+        if protocol < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(protocol))
+    }
+}
+
+/// Take the real stdout for the protocol and point the process's stdout at stderr.
+/// The app's log output goes to stdout, which would otherwise corrupt the message stream.
+#[cfg(windows)]
+fn claim_stdout() -> io::Result<File> {
+    use std::os::windows::io::FromRawHandle;
+    use windows::Win32::System::Console::{GetStdHandle, SetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
+
+    io::stdout().flush()?;
+    unsafe {
+        let protocol = GetStdHandle(STD_OUTPUT_HANDLE).map_err(io::Error::other)?;
+        // Without a console stderr may not exist, then the log just goes nowhere
+        let log = GetStdHandle(STD_ERROR_HANDLE).unwrap_or_default();
+        SetStdHandle(STD_OUTPUT_HANDLE, log).map_err(io::Error::other)?;
+        Ok(File::from_raw_handle(protocol.0 as _))
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+use tracing::warn;
+use crate::i18n;
+use crate::instance;
+use crate::settings;
+
+/// Which OS notifications to show. The app windows are hidden while capturing,
+/// so without these a capture that failed in the background goes unnoticed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub on_complete: bool,
+    pub on_failure: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self { on_complete: true, on_failure: true }
+    }
+}
+
+/// Clicking a notification brings the main window (with the last capture) to the front.
+/// Called once from `setup`, notifications shown before that report no clicks.
+pub fn init(app: &AppHandle) {
+    let handle = app.clone();
+    let result = app.notification().on_action(move |action| {
+        if action.action_id() == "tap" {
+            instance::focus_main_window(&handle);
+        }
+    });
+    if let Err(e) = result {
+        warn!("Could not listen for notification clicks: {}", e);
+    }
+}
+
+pub fn capture_complete(app: &AppHandle, width: u32, height: u32) {
+    if !settings::current().notifications.on_complete {
+        return;
+    }
+    let body = i18n::text("notify.complete.body", &[("width", &width.to_string()), ("height", &height.to_string())]);
+    show(app, &i18n::text("notify.complete.title", &[]), &body);
+}
+
+pub fn capture_failed(app: &AppHandle, message: &str) {
+    if !settings::current().notifications.on_failure {
+        return;
+    }
+    show(app, &i18n::text("notify.failed.title", &[]), message);
+}
+
+fn show(app: &AppHandle, title: &str, body: &str) {
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        warn!("Could not show notification '{}': {}", title, e);
+    }
+}
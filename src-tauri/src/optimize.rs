@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+use crate::error::CaptureError;
+use crate::utils;
+
+/// oxipng's own default, a good trade of time for size on tall captures
+const DEFAULT_LEVEL: u8 = 2;
+
+/// Payload of the `png-optimize` event, sent when optimizing starts and when it ends
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimizeProgress {
+    pub path: String,
+    pub stage: OptimizeStage,
+    pub original_bytes: u64,
+    /// Size on disk afterwards, set once finished
+    pub optimized_bytes: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptimizeStage {
+    Started,
+    Finished,
+    Failed,
+}
+
+/// Losslessly recompress the PNG saved at `path` (`png` is its content) with oxipng at the preset
+/// `level` on a background thread, and replace the file if that made it smaller. Captures are
+/// encoded for speed first, this spends the time on the smallest output instead. Text chunks
+/// like the embedded metadata are kept. `on_done` gets the final file size, also after a failure.
+pub fn optimize_in_background(app: AppHandle, path: PathBuf, png: Vec<u8>, level: Option<u8>, on_done: impl FnOnce(u64) + Send + 'static) {
+    let display_path = path.display().to_string();
+    let original_bytes = png.len() as u64;
+    let progress = move |stage, optimized_bytes, error| OptimizeProgress {
+        path: display_path.clone(),
+        stage,
+        original_bytes,
+        optimized_bytes,
+        error,
+    };
+
+    thread::spawn(move || {
+        let _ = app.emit("png-optimize", progress(OptimizeStage::Started, None, None));
+        let started = Instant::now();
+        let result = recompress(&png, level.unwrap_or(DEFAULT_LEVEL))
+            .map_err(|e| e.to_string())
+            .and_then(|optimized| {
+                if optimized.len() < png.len() {
+                    utils::write_atomic(&path, &optimized).map_err(|e| e.to_string())?;
+                    Ok(optimized.len() as u64)
+                } else {
+                    Ok(original_bytes)
+                }
+            });
+
+        match result {
+            Ok(size) => {
+                info!("Optimized {} from {} to {} bytes in {:?}", path.display(), original_bytes, size, started.elapsed());
+                let _ = app.emit("png-optimize", progress(OptimizeStage::Finished, Some(size), None));
+                on_done(size);
+            }
+            Err(e) => {
+                warn!("Could not optimize {}: {}", path.display(), e);
+                let _ = app.emit("png-optimize", progress(OptimizeStage::Failed, None, Some(e)));
+                on_done(original_bytes);
+            }
+        }
+    });
+}
+
+/// Run oxipng on `png`. Its default keeps every chunk, so metadata survives; it still drops
+/// the alpha channel when every pixel is opaque (true for almost all screen captures).
+fn recompress(png: &[u8], level: u8) -> Result<Vec<u8>, CaptureError> {
+    oxipng::optimize_from_memory(png, &oxipng::Options::from_preset(level))
+        .map_err(|e| CaptureError::EncodeFailed(format!("oxipng: {}", e)))
+}
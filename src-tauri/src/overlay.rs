@@ -0,0 +1,134 @@
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WebviewUrl, WebviewWindow, WebviewWindowBuilder};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::HWND;
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE};
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+
+pub const BORDER_LABEL: &str = "capture-border";
+pub const HUD_LABEL: &str = "capture-hud";
+
+/// Border thickness in physical pixels. The window is grown by this much on every side
+/// so the border sits just outside the captured region.
+const BORDER_WIDTH: i32 = 4;
+
+/// HUD size in logical pixels and its distance from the region
+const HUD_WIDTH: f64 = 280.0;
+const HUD_HEIGHT: f64 = 64.0;
+const HUD_MARGIN: f64 = 12.0;
+
+/// Hide a window from screen capture, so the border and HUD never end up in a frame.
+/// Uses WDA_EXCLUDEFROMCAPTURE on Windows and NSWindowSharingNone on macOS. X11 and Wayland
+/// have no such flag, there our windows have to stay outside the captured region instead.
+pub fn exclude_from_capture(window: &WebviewWindow) {
+    #[cfg(target_os = "windows")]
+    if let Ok(hwnd) = window.hwnd() {
+        unsafe {
+            let _ = SetWindowDisplayAffinity(HWND(hwnd.0 as _), WDA_EXCLUDEFROMCAPTURE);
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let target = window.clone();
+        // AppKit windows may only be touched from the main thread
+        let _ = window.run_on_main_thread(move || {
+            if let Ok(ns_window) = target.ns_window() {
+                unsafe {
+                    let ns_window = &*(ns_window as *const objc2::runtime::AnyObject);
+                    // NSWindowSharingNone
+                    let _: () = objc2::msg_send![ns_window, setSharingType: 0usize];
+                }
+            }
+        });
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let _ = window;
+}
+
+/// Show a click-through, always-on-top frame around the region being captured
+pub fn show_border(app: &AppHandle, region: &Rect) -> Result<(), CaptureError> {
+    close_window(app, BORDER_LABEL);
+
+    // The frontend renders just the border for this route, everything else is transparent
+    let window = build_overlay_window(app, BORDER_LABEL, "index.html#/border")?;
+
+    // Position in physical pixels, the builder only takes logical ones
+    let _ = window.set_position(PhysicalPosition::new(region.x - BORDER_WIDTH, region.y - BORDER_WIDTH));
+    let _ = window.set_size(PhysicalSize::new(
+        region.width + 2 * BORDER_WIDTH as u32,
+        region.height + 2 * BORDER_WIDTH as u32,
+    ));
+    let _ = window.set_ignore_cursor_events(true);
+    exclude_from_capture(&window);
+    let _ = window.show();
+
+    Ok(())
+}
+
+/// Show the capture HUD (height, stitch count, how to stop) next to the region.
+/// It goes above the region, or below if there's no room, or inside the top edge as a last resort.
+pub fn show_hud(app: &AppHandle, region: &Rect) -> Result<(), CaptureError> {
+    close_window(app, HUD_LABEL);
+
+    let monitors = display::list_monitors()?;
+    let monitor = monitors.iter()
+        .map(|(_, info)| info)
+        .find(|info| info.rect().intersect(region).is_some())
+        .ok_or(CaptureError::ScreenNotFound)?;
+
+    let scale = monitor.scale_factor as f64;
+    let hud_w = (HUD_WIDTH * scale) as i32;
+    let hud_h = (HUD_HEIGHT * scale) as i32;
+    let margin = (HUD_MARGIN * scale) as i32 + BORDER_WIDTH;
+
+    let x = (region.x + (region.width as i32 - hud_w) / 2)
+        .clamp(monitor.x, (monitor.x + monitor.width as i32 - hud_w).max(monitor.x));
+    let above = region.y - margin - hud_h;
+    let below = region.bottom() + margin;
+    let y = if above >= monitor.y {
+        above
+    } else if below + hud_h <= monitor.y + monitor.height as i32 {
+        below
+    } else {
+        region.y + margin
+    };
+
+    let window = build_overlay_window(app, HUD_LABEL, "index.html#/hud")?;
+    let _ = window.set_position(PhysicalPosition::new(x, y));
+    let _ = window.set_size(PhysicalSize::new(hud_w as u32, hud_h as u32));
+    let _ = window.set_ignore_cursor_events(true);
+    exclude_from_capture(&window);
+    let _ = window.show();
+
+    Ok(())
+}
+
+/// Remove all capture overlays (border and HUD)
+pub fn close_overlays(app: &AppHandle) {
+    close_window(app, BORDER_LABEL);
+    close_window(app, HUD_LABEL);
+}
+
+fn build_overlay_window(app: &AppHandle, label: &str, url: &str) -> Result<WebviewWindow, CaptureError> {
+    WebviewWindowBuilder::new(app, label, WebviewUrl::App(url.into()))
+        .title("ScrollSnap")
+        .transparent(true)
+        .decorations(false)
+        .shadow(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .resizable(false)
+        .focused(false)
+        .visible(false)
+        .build()
+        .map_err(|e| CaptureError::Internal(format!("Failed to create {} window: {}", label, e)))
+}
+
+fn close_window(app: &AppHandle, label: &str) {
+    if let Some(window) = app.get_webview_window(label) {
+        let _ = window.destroy();
+    }
+}
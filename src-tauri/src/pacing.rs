@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/// Shortest delay between frames while the content moves
+const MIN_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Longest delay while nothing moves. Bounded so the first frame after scrolling resumes
+/// still overlaps the last one.
+const MAX_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Growth of the delay per static frame
+const BACKOFF_FACTOR: f32 = 1.5;
+
+/// Picks the delay between frames of a manual scroll capture. While the content moves, frames
+/// come as often as the machine keeps up with, capturing and matching at most about half the
+/// time. While it stands still the delay grows, up to `MAX_INTERVAL`.
+pub struct Pacer {
+    base: Duration,
+    current: Duration,
+    adaptive: bool,
+    static_since: Option<Instant>,
+}
+
+impl Pacer {
+    /// `base` is the configured poll interval, used as is when `adaptive` is off
+    pub fn new(base: Duration, adaptive: bool) -> Self {
+        Self { base, current: base, adaptive, static_since: None }
+    }
+
+    /// Delay from the start of one frame to the start of the next
+    pub fn interval(&self) -> Duration {
+        self.current
+    }
+
+    /// The frame showed the same content as the one before
+    pub fn on_static(&mut self) {
+        self.static_since.get_or_insert_with(Instant::now);
+        if self.adaptive {
+            self.current = self.current.mul_f32(BACKOFF_FACTOR).min(MAX_INTERVAL.max(self.base));
+        }
+    }
+
+    /// The content moved. `work` is how long capturing and comparing the frame took.
+    /// Never slower than the base interval, a slow machine then captures back to back.
+    pub fn on_moving(&mut self, work: Duration) {
+        self.static_since = None;
+        if self.adaptive {
+            self.current = (work * 2).clamp(MIN_INTERVAL.min(self.base), self.base);
+        }
+    }
+
+    /// Base intervals that fit into the current static streak, at least `static_count`.
+    /// Limits given in frames keep meaning the same time when the interval grows.
+    pub fn idle_frames(&self, static_count: u32) -> u32 {
+        let elapsed = self.static_since.map_or(0, |since| {
+            (since.elapsed().as_millis() / self.base.as_millis().max(1)) as u32
+        });
+        elapsed.max(static_count)
+    }
+}
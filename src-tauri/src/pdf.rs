@@ -0,0 +1,223 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::imageops;
+use image::RgbaImage;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::info;
+use crate::error::CaptureError;
+use crate::search::{self, RecognizedWord};
+use crate::settings;
+use crate::store;
+use crate::utils;
+
+/// Pixels per inch the capture is laid out at, so 100% zoom in a PDF reader matches the screen
+const PIXELS_PER_INCH: f32 = 96.0;
+/// Advance of every glyph of the text layer's font, in thousandths of the font size
+const GLYPH_WIDTH: f32 = 500.0;
+/// Maps every two-byte code to the same UTF-16 code unit, so readers copy and search the text
+const TO_UNICODE: &str = "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n/CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n1 beginbfrange\n<0000> <FFFF> <0000>\nendbfrange\n\
+endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend";
+
+/// Save the capture `id` as a PDF, cut into pages of `page_height` pixels (by default
+/// A4 proportions at the capture's width). Returns the page count.
+///
+/// Unless `searchable` is false, every page gets an invisible text layer from Tesseract (see
+/// `SearchSettings`) laid over the words of the image, so the PDF can be searched and copied from.
+#[tauri::command]
+pub async fn export_pdf(id: String, path: String, page_height: Option<u32>, searchable: Option<bool>) -> Result<usize, CaptureError> {
+    store::edit(id, move |canvas| {
+        let img = canvas.load_image()?;
+        let page_height = page_height
+            .unwrap_or((img.width() as u64 * 297 / 210) as u32)
+            .clamp(1, img.height().max(1));
+        let text = if searchable.unwrap_or(true) { Some(recognize_pages(&img, page_height)?) } else { None };
+        let pdf = render(&img, page_height, text.as_deref())?;
+        let pages = img.height().div_ceil(page_height) as usize;
+        utils::write_atomic(&PathBuf::from(&path), &pdf)?;
+        info!("Exported {} PDF pages to {}", pages, path);
+        Ok(pages)
+    })
+    .await
+}
+
+/// The words on each page, relative to the page
+fn recognize_pages(img: &RgbaImage, page_height: u32) -> Result<Vec<Vec<RecognizedWord>>, CaptureError> {
+    let search = settings::current().search;
+    (0..img.height().div_ceil(page_height))
+        .map(|index| {
+            let top = index * page_height;
+            let page = imageops::crop_imm(img, 0, top, img.width(), page_height.min(img.height() - top)).to_image();
+            search::recognize_words(&search, &page)
+        })
+        .collect()
+}
+
+/// `text` has the words of each page, for the text layer
+fn render(img: &RgbaImage, page_height: u32, text: Option<&[Vec<RecognizedWord>]>) -> Result<Vec<u8>, CaptureError> {
+    let mut pdf = PdfWriter::new();
+    let width = img.width();
+    let page_count = img.height().div_ceil(page_height);
+    // Objects 1 and 2 are the catalog and the page tree, then three objects per page
+    let page_ids: Vec<usize> = (0..page_count as usize).map(|i| 3 + i * 3).collect();
+
+    pdf.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+    pdf.object(2, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_count).as_bytes());
+
+    // The text layer's font comes after all the pages, then the soft masks of pages with
+    // transparent pixels
+    let font_id = 3 + page_count as usize * 3;
+    let mut next_id = font_id;
+    let mut fonts = String::new();
+    if text.is_some() {
+        write_font(&mut pdf, font_id);
+        fonts = format!(" /Font << /F0 {} 0 R >>", font_id);
+        next_id += 4;
+    }
+
+    for (index, &page_id) in page_ids.iter().enumerate() {
+        let top = index as u32 * page_height;
+        let rows = page_height.min(img.height() - top);
+        let (page_width, page_h) = (points(width), points(rows));
+
+        // Color and alpha go into separate images, alpha only where some pixel isn't opaque
+        let start = top as usize * width as usize * 4;
+        let end = start + rows as usize * width as usize * 4;
+        let pixels = &img.as_raw()[start..end];
+        let rgb: Vec<u8> = pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        let mut smask = String::new();
+        if pixels.chunks_exact(4).any(|pixel| pixel[3] != 255) {
+            let alpha: Vec<u8> = pixels.chunks_exact(4).map(|pixel| pixel[3]).collect();
+            pdf.stream(next_id, &format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /FlateDecode",
+                width, rows
+            ), &deflate(&alpha)?);
+            smask = format!(" /SMask {} 0 R", next_id);
+            next_id += 1;
+        }
+
+        let mut content = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q", page_width, page_h);
+        if let Some(words) = text.and_then(|pages| pages.get(index)) {
+            content.push_str(&text_layer(words, rows));
+        }
+        pdf.object(page_id, format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /XObject << /Im0 {} 0 R >>{} >> /Contents {} 0 R >>",
+            page_width, page_h, page_id + 2, fonts, page_id + 1
+        ).as_bytes());
+        pdf.stream(page_id + 1, "", content.as_bytes());
+        pdf.stream(page_id + 2, &format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode{}",
+            width, rows, smask
+        ), &deflate(&rgb)?);
+    }
+
+    Ok(pdf.finish(1))
+}
+
+/// Invisible text (render mode 3) over each word, stretched to the word's box. Each glyph
+/// is a UTF-16 code unit, see `write_font`.
+fn text_layer(words: &[RecognizedWord], page_rows: u32) -> String {
+    let mut layer = String::from(" BT 3 Tr");
+    for word in words {
+        let units: Vec<u16> = word.text.encode_utf16().collect();
+        if units.is_empty() || word.height == 0 {
+            continue;
+        }
+        let size = points(word.height);
+        let natural = units.len() as f32 * GLYPH_WIDTH / 1000.0 * size;
+        let scale = points(word.width) / natural * 100.0;
+        let code: String = units.iter().map(|unit| format!("{:04X}", unit)).collect();
+        // PDF's origin is the bottom left, words sit on their box's bottom edge
+        let baseline = points(page_rows.saturating_sub(word.top + word.height));
+        layer.push_str(&format!(
+            " /F0 {:.2} Tf {:.2} Tz 1 0 0 1 {:.2} {:.2} Tm <{}> Tj",
+            size, scale, points(word.left), baseline, code
+        ));
+    }
+    layer.push_str(" ET");
+    layer
+}
+
+/// A font for text that is never drawn: two-byte codes, each mapped to that UTF-16 code unit
+/// for copying, and all glyphs `GLYPH_WIDTH` wide. Takes the four objects from `id`.
+fn write_font(pdf: &mut PdfWriter, id: usize) {
+    pdf.object(id, format!(
+        "<< /Type /Font /Subtype /Type0 /BaseFont /GlyphLessFont /Encoding /Identity-H /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+        id + 1, id + 3
+    ).as_bytes());
+    pdf.object(id + 1, format!(
+        "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /GlyphLessFont /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+         /FontDescriptor {} 0 R /DW {} /CIDToGIDMap /Identity >>",
+        id + 2, GLYPH_WIDTH
+    ).as_bytes());
+    pdf.object(id + 2, format!(
+        "<< /Type /FontDescriptor /FontName /GlyphLessFont /Flags 5 /FontBBox [0 0 {} 1000] /ItalicAngle 0 /Ascent 1000 /Descent 0 /CapHeight 1000 /StemV 80 >>",
+        GLYPH_WIDTH
+    ).as_bytes());
+    pdf.stream(id + 3, "", TO_UNICODE.as_bytes());
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, CaptureError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn points(pixels: u32) -> f32 {
+    pixels as f32 * 72.0 / PIXELS_PER_INCH
+}
+
+/// Just enough of the PDF format for pages of images and text: numbered objects and the cross-reference table
+struct PdfWriter {
+    out: Vec<u8>,
+    /// Byte offset of each object, by object number - 1
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        // The binary comment tells tools the file isn't plain text
+        Self { out: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(), offsets: Vec::new() }
+    }
+
+    fn begin(&mut self, id: usize) {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.out.len();
+        self.out.extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+    }
+
+    fn object(&mut self, id: usize, body: &[u8]) {
+        self.begin(id);
+        self.out.extend_from_slice(body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) {
+        self.begin(id);
+        self.out.extend_from_slice(format!("<< {} /Length {} >>\nstream\n", dict, data.len()).as_bytes());
+        self.out.extend_from_slice(data);
+        self.out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let xref = self.out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1, root, xref
+        ));
+        self.out.extend_from_slice(table.as_bytes());
+        self.out
+    }
+}
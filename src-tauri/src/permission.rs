@@ -0,0 +1,78 @@
+use serde::Serialize;
+use crate::error::CaptureError;
+
+/// Whether we are allowed to read screen contents.
+/// Only macOS gates this (Screen Recording privacy setting); elsewhere it's always `NotRequired`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    NotRequired,
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+#[cfg(target_os = "macos")]
+pub fn capture_permission_state() -> PermissionState {
+    // Safe: plain query without arguments, available since macOS 10.15
+    if unsafe { CGPreflightScreenCaptureAccess() } {
+        PermissionState::Granted
+    } else {
+        PermissionState::Denied
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn capture_permission_state() -> PermissionState {
+    PermissionState::NotRequired
+}
+
+/// Pre-flight check before capturing.
+/// Without permission macOS hands us black frames instead of an error, so fail early with a clear message.
+pub fn ensure_capture_permission() -> Result<(), CaptureError> {
+    match capture_permission_state() {
+        PermissionState::Denied => Err(CaptureError::PermissionDenied),
+        _ => Ok(()),
+    }
+}
+
+/// Report the permission state. With `request = true` the system prompt is shown
+/// (macOS only shows it once per app, afterwards use `open_capture_permission_settings`).
+#[tauri::command]
+pub fn check_capture_permission(request: bool) -> PermissionState {
+    let state = capture_permission_state();
+
+    #[cfg(target_os = "macos")]
+    if request && state == PermissionState::Denied {
+        tracing::info!("Requesting screen capture permission");
+        // The result only changes after the app restarts, so we still report the current state
+        unsafe {
+            CGRequestScreenCaptureAccess();
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    let _ = request;
+
+    state
+}
+
+/// Open the Screen Recording pane of System Settings
+#[tauri::command]
+pub fn open_capture_permission_settings() -> Result<(), CaptureError> {
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("open")
+            .arg("x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture")
+            .spawn()
+            .map_err(|e| CaptureError::Internal(format!("Failed to open System Settings: {}", e)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::AppHandle;
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::decorate::{self, ExportOptions, Resize, Watermark};
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::history;
+use crate::i18n;
+use crate::idle;
+use crate::metadata::CaptureMetadata;
+use crate::redact;
+use crate::script::{self, HookPoint};
+use crate::settings;
+use crate::store::{self, CaptureResult};
+use crate::utils;
+
+/// One post-processing step, see `Settings::pipeline`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum PipelineStep {
+    /// Trim uniform-color margins, like `autocrop`
+    Autocrop,
+    /// Keep only `region` (image pixels), clipped to the image
+    Crop { region: Rect },
+    /// Pixelate the same areas (image pixels) in every capture, e.g. a sidebar with account details
+    Redact { regions: Vec<Rect> },
+    Watermark(Watermark),
+    Resize(Resize),
+    /// Save a copy to `folder` as "scrollsnap-<time>.<format>" (the name is localized), with the export options applied
+    Export {
+        folder: String,
+        #[serde(default)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Avif,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Avif => "avif",
+        }
+    }
+}
+
+/// Run the post-stitch scripts and the configured pipeline on a finished capture and put the
+/// result in the store, and in the history as coming from `source`. Steps run in order. One that
+/// fails is logged and skipped, the capture is never lost over it.
+/// Blocking, call it off the async runtime.
+pub fn run(app: &AppHandle, mut canvas: Canvas, metadata: Option<CaptureMetadata>, source: &str) -> Result<CaptureResult, CaptureError> {
+    // One-shot captures have no session, this is where they count as activity
+    idle::touch();
+    let steps = settings::current().pipeline;
+    let export = settings::current().export;
+
+    if script::has_scripts(HookPoint::PostStitch) {
+        if let Some(img) = script::run(app, HookPoint::PostStitch, &canvas.load_image()?, metadata.as_ref()) {
+            canvas = Canvas::from_rgba(img)?;
+        }
+    }
+
+    for step in &steps {
+        match apply(&mut canvas, step, &export) {
+            Ok(Some(edited)) => canvas = edited,
+            Ok(None) => {}
+            Err(e) => warn!("Pipeline step {:?} failed, skipping it: {}", step, e),
+        }
+    }
+    let result = store::insert(canvas, metadata)?;
+    history::save_capture_in_background(app.clone(), result.id.clone(), source);
+
+    for step in &steps {
+        let PipelineStep::Export { folder, format } = step else {
+            continue;
+        };
+        let time = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let name = format!("{}.{}", i18n::text("file.capture", &[("time", &time)]), format.extension());
+        let path = Path::new(folder).join(name).display().to_string();
+        match utils::write_image(app, &result.id, path, Some(true), None) {
+            Ok(path) => info!("Pipeline exported capture to {}", path),
+            Err(e) => warn!("Pipeline export to {} failed: {}", folder, e),
+        }
+    }
+    Ok(result)
+}
+
+/// The canvas after `step`, `None` if it stays as is (or the step only runs after storing)
+fn apply(canvas: &mut Canvas, step: &PipelineStep, export: &ExportOptions) -> Result<Option<Canvas>, CaptureError> {
+    let bounds = Rect { x: 0, y: 0, width: canvas.width(), height: canvas.height() };
+    match step {
+        PipelineStep::Autocrop => match canvas.content_bounds()? {
+            Some(content) => canvas.cropped(content).map(Some),
+            None => Ok(None),
+        },
+        PipelineStep::Crop { region } => {
+            let area = region.intersect(&bounds)
+                .ok_or_else(|| CaptureError::InvalidRegion(format!("{:?} is outside the {}x{} image", region, bounds.width, bounds.height)))?;
+            canvas.cropped(area).map(Some)
+        }
+        PipelineStep::Redact { regions } => {
+            let mut img = canvas.load_image()?;
+            for area in regions.iter().filter_map(|region| region.intersect(&bounds)) {
+                redact::pixelate(&mut img, area);
+            }
+            Canvas::from_rgba(img).map(Some)
+        }
+        PipelineStep::Watermark(watermark) => {
+            let options = ExportOptions { watermark: Some(watermark.clone()), font_path: export.font_path.clone(), ..Default::default() };
+            Canvas::from_rgba(decorate::apply(canvas.load_image()?, &options)?).map(Some)
+        }
+        PipelineStep::Resize(resize) => {
+            let options = ExportOptions { resize: Some(resize.clone()), ..Default::default() };
+            Canvas::from_rgba(decorate::apply(canvas.load_image()?, &options)?).map(Some)
+        }
+        PipelineStep::Export { .. } => Ok(None),
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use crate::capture::{self, CaptureOptions};
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::hotkeys;
+use crate::settings;
+
+const PRESETS_FILE: &str = "presets.json";
+
+/// A named region to capture again and again, e.g. "Docs window" or "Left monitor full"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub name: String,
+    /// Physical desktop pixels
+    pub region: Rect,
+    /// Capture settings for this preset, the ones from the settings when unset
+    pub options: Option<CaptureOptions>,
+    /// Global accelerator that captures the preset, e.g. "CommandOrControl+Shift+1"
+    pub shortcut: Option<String>,
+}
+
+lazy_static! {
+    static ref PRESETS: Mutex<Vec<Preset>> = Mutex::new(Vec::new());
+}
+
+/// Load saved presets, called once from `setup` before the hotkeys are registered
+pub fn load(app: &AppHandle) {
+    let Some(path) = presets_path(app) else {
+        return;
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<Vec<Preset>>(&content) {
+            Ok(presets) => {
+                info!("Loaded {} region presets", presets.len());
+                *PRESETS.lock().unwrap() = presets;
+            }
+            Err(e) => warn!("Ignoring invalid presets file {}: {}", path.display(), e),
+        },
+        Err(_) => info!("No region presets found"),
+    }
+}
+
+#[tauri::command]
+pub fn list_presets() -> Vec<Preset> {
+    PRESETS.lock().unwrap().clone()
+}
+
+/// Save a preset for `region` (physical pixels), or the region of the last capture.
+/// Replaces the preset with the same name.
+#[tauri::command]
+pub fn save_preset(
+    app: AppHandle,
+    name: String,
+    region: Option<Rect>,
+    options: Option<CaptureOptions>,
+    shortcut: Option<String>,
+) -> Result<Preset, CaptureError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(CaptureError::InvalidState("a preset needs a name".to_string()));
+    }
+    let region = match region {
+        Some(region) => region,
+        None => capture::last_region().ok_or(CaptureError::NoPreviousRegion)?,
+    };
+    if region.width == 0 || region.height == 0 {
+        return Err(CaptureError::InvalidRegion(format!("{}x{} is empty", region.width, region.height)));
+    }
+
+    let preset = Preset { name, region, options, shortcut: shortcut.filter(|shortcut| !shortcut.trim().is_empty()) };
+    let mut presets = PRESETS.lock().unwrap().clone();
+    match presets.iter_mut().find(|existing| existing.name == preset.name) {
+        Some(existing) => *existing = preset.clone(),
+        None => presets.push(preset.clone()),
+    }
+    save(&app, presets)?;
+    info!("Saved region preset '{}' {:?}", preset.name, preset.region);
+    Ok(preset)
+}
+
+#[tauri::command]
+pub fn delete_preset(app: AppHandle, name: String) -> Result<(), CaptureError> {
+    let mut presets = PRESETS.lock().unwrap().clone();
+    let before = presets.len();
+    presets.retain(|preset| preset.name != name);
+    if presets.len() == before {
+        return Err(CaptureError::InvalidInput(format!("no preset named '{}'", name)));
+    }
+    save(&app, presets)
+}
+
+/// Start a scroll capture of the preset `name`, with its own capture settings if it has any.
+/// Returns the session id, `allow_sensitive` is as for `start_scroll_capture`.
+#[tauri::command]
+pub async fn capture_preset(app: AppHandle, name: String, allow_sensitive: Option<bool>) -> Result<String, CaptureError> {
+    let preset = PRESETS.lock().unwrap()
+        .iter()
+        .find(|preset| preset.name == name)
+        .cloned()
+        .ok_or_else(|| CaptureError::InvalidInput(format!("no preset named '{}'", name)))?;
+    info!("Capturing preset '{}' {:?}", preset.name, preset.region);
+
+    let options = preset.options.unwrap_or_else(|| settings::current().capture);
+    capture::start_capture(app, preset.region, options, allow_sensitive.unwrap_or(false))
+}
+
+/// Persist and then apply, so the in-memory list never gets ahead of the file
+fn save(app: &AppHandle, presets: Vec<Preset>) -> Result<(), CaptureError> {
+    let path = presets_path(app)
+        .ok_or_else(|| CaptureError::Internal("Could not resolve app config directory".to_string()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string_pretty(&presets).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    fs::write(&path, content)?;
+    *PRESETS.lock().unwrap() = presets;
+    hotkeys::apply_presets(app);
+    Ok(())
+}
+
+fn presets_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_config_dir().ok().map(|dir| dir.join(PRESETS_FILE))
+}
@@ -0,0 +1,167 @@
+use image::{DynamicImage, Rgba};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+use crate::capture;
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::instance;
+use crate::preset;
+use crate::schedule;
+use crate::settings;
+use crate::window_list;
+
+/// Gray the blanked areas are filled with, so they read as removed rather than as content
+const BLANK_COLOR: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+/// Guard against capturing password managers, banking apps and the like by accident
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// Matched case-insensitively against the app name and title of every window over the region
+    pub sensitive_apps: Vec<String>,
+    pub action: SensitiveAction,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        Self {
+            sensitive_apps: ["1Password", "Bitwarden", "Dashlane", "Enpass", "KeePass", "Keychain Access", "LastPass"]
+                .map(String::from)
+                .to_vec(),
+            action: SensitiveAction::Confirm,
+        }
+    }
+}
+
+/// What happens when a sensitive window overlaps the capture region
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensitiveAction {
+    Ignore,
+    /// Refuse to start until the user confirms
+    #[default]
+    Confirm,
+    /// Capture anyway, with those windows grayed out in every fragment
+    Blank,
+}
+
+/// A capture started without a window to ask in (a hotkey, the tray, a schedule) that stopped
+/// for confirmation. The UI asks and hands it back to `confirm_capture`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum PendingCapture {
+    LastRegion,
+    Preset { name: String },
+    Schedule { id: String },
+}
+
+/// Payload of the `privacy-confirm` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfirmRequest {
+    capture: PendingCapture,
+    /// The localized `SENSITIVE_WINDOW` error, naming the windows
+    message: String,
+}
+
+/// Check `region` (physical pixels) before a capture starts. Returns the parts of the region,
+/// relative to it, to blank in every fragment. Fails with `SensitiveWindow` when confirmation is
+/// needed and `confirmed` isn't set, also when the windows over the region can't be listed.
+pub fn check(region: &Rect, confirmed: bool) -> Result<Vec<Rect>, CaptureError> {
+    let privacy = settings::current().privacy;
+    if privacy.action == SensitiveAction::Ignore || privacy.sensitive_apps.is_empty() {
+        return Ok(Vec::new());
+    }
+    let patterns: Vec<String> = privacy.sensitive_apps.iter()
+        .map(|app| app.trim().to_lowercase())
+        .filter(|app| !app.is_empty())
+        .collect();
+
+    let windows = match window_list::get_window_rects() {
+        Ok(windows) => windows,
+        // Unknown windows can't be blanked either, so ask instead of capturing whatever is there
+        Err(e) if !confirmed => {
+            warn!("Could not list windows for the privacy check: {}", e);
+            return Err(CaptureError::SensitiveWindow(format!("unknown, the window list could not be read ({})", e)));
+        }
+        Err(e) => {
+            warn!("Capturing without the privacy check as confirmed, windows could not be listed: {}", e);
+            return Ok(Vec::new());
+        }
+    };
+    let sensitive: Vec<_> = windows.into_iter()
+        .filter(|window| {
+            let (app_name, title) = (window.app_name.to_lowercase(), window.title.to_lowercase());
+            patterns.iter().any(|pattern| app_name.contains(pattern) || title.contains(pattern))
+        })
+        .filter_map(|window| {
+            let name = if window.app_name.is_empty() { window.title } else { window.app_name };
+            region.intersect(&window.rect).map(|overlap| (name, overlap))
+        })
+        .collect();
+    if sensitive.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let names = sensitive.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ");
+    match privacy.action {
+        SensitiveAction::Confirm if !confirmed => Err(CaptureError::SensitiveWindow(names)),
+        SensitiveAction::Blank => {
+            info!("Blanking sensitive windows in the capture: {}", names);
+            Ok(sensitive.into_iter()
+                .map(|(_, overlap)| Rect { x: overlap.x - region.x, y: overlap.y - region.y, ..overlap })
+                .collect())
+        }
+        _ => {
+            info!("Capturing over sensitive windows as confirmed: {}", names);
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// When `error` is the guard asking for confirmation, bring up the main window and send it
+/// `privacy-confirm` so the user can confirm `capture`. Whether it was.
+pub fn ask_to_confirm(app: &AppHandle, capture: PendingCapture, error: &CaptureError) -> bool {
+    if !matches!(error, CaptureError::SensitiveWindow(_)) {
+        return false;
+    }
+    info!("Asking to confirm {:?}: {}", capture, error);
+    instance::focus_main_window(app);
+    let _ = app.emit("privacy-confirm", ConfirmRequest { capture, message: error.localized() });
+    true
+}
+
+/// Run a capture from `privacy-confirm` again, now that the user confirmed it
+#[tauri::command]
+pub async fn confirm_capture(app: AppHandle, capture: PendingCapture) -> Result<(), CaptureError> {
+    match capture {
+        PendingCapture::LastRegion => capture::capture_last_region(app, None, Some(true)).await.map(drop),
+        PendingCapture::Preset { name } => preset::capture_preset(app, name, Some(true)).await.map(drop),
+        PendingCapture::Schedule { id } => schedule::run_confirmed(app, id).await,
+    }
+}
+
+/// Gray out `areas` of a fragment, in fragment coordinates
+pub fn blank(img: &mut DynamicImage, areas: &[Rect]) {
+    if areas.is_empty() {
+        return;
+    }
+    if img.as_rgba8().is_none() {
+        *img = DynamicImage::ImageRgba8(img.to_rgba8());
+    }
+    let Some(rgba) = img.as_mut_rgba8() else {
+        return;
+    };
+    let (width, height) = rgba.dimensions();
+    for area in areas {
+        let (left, top) = (area.x.max(0) as u32, area.y.max(0) as u32);
+        let right = (area.right().max(0) as u32).min(width);
+        let bottom = (area.bottom().max(0) as u32).min(height);
+        for y in top..bottom {
+            for x in left..right {
+                rgba.put_pixel(x, y, BLANK_COLOR);
+            }
+        }
+    }
+}
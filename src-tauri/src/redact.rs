@@ -0,0 +1,223 @@
+use image::RgbaImage;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use crate::canvas::Canvas;
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::search::{self, RecognizedWord};
+use crate::settings;
+use crate::store::{self, ImageSize};
+
+/// Size of the pixelation blocks. Large enough that text can't be read back from the averages.
+const BLOCK_SIZE: u32 = 12;
+/// Redacted boxes grow by this much on every side, OCR boxes hug the letters tightly
+const MARGIN: u32 = 2;
+
+lazy_static! {
+    static ref EMAIL: Regex = Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap();
+    // Well-known key formats (OpenAI, Stripe, GitHub, AWS, Slack, Google), JWTs and bearer tokens
+    static ref API_KEY: Regex = Regex::new(concat!(
+        r"\b(sk-[A-Za-z0-9_-]{20,}|[sr]k_(live|test)_[A-Za-z0-9]{16,}|gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{40,}",
+        r"|(AKIA|ASIA)[0-9A-Z]{16}|xox[abposr]-[A-Za-z0-9-]{10,}|AIza[0-9A-Za-z_-]{35}",
+        r"|eyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,})",
+        r"|(?i:bearer)\s+[A-Za-z0-9._~+/-]{20,}=*",
+    )).unwrap();
+    /// Long runs of letters and digits mixed, like most generated secrets
+    static ref TOKEN: Regex = Regex::new(r"\b[A-Za-z0-9_-]{32,}\b").unwrap();
+    static ref CARD_NUMBER: Regex = Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap();
+}
+
+/// What `auto_redact` looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensitiveKind {
+    Email,
+    /// Known API key formats, JWTs, bearer tokens and long random-looking strings
+    ApiKey,
+    /// 13 to 19 digits that pass the Luhn check
+    CardNumber,
+}
+
+/// A box `auto_redact` pixelated
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SensitiveMatch {
+    pub kind: SensitiveKind,
+    /// In image pixels
+    pub region: Rect,
+}
+
+/// Result of `auto_redact`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoRedaction {
+    pub size: ImageSize,
+    pub matches: Vec<SensitiveMatch>,
+}
+
+/// Pixelate rectangles (in image pixels) of the capture `id`, e.g. emails or tokens marked in the editor
+#[tauri::command]
+pub async fn redact_regions(id: String, regions: Vec<Rect>) -> Result<ImageSize, CaptureError> {
+    if regions.is_empty() {
+        return Err(CaptureError::InvalidRegion("no regions to redact".to_string()));
+    }
+
+    store::edit(id, move |canvas| {
+        let mut img = canvas.load_image()?;
+        let bounds = Rect { x: 0, y: 0, width: img.width(), height: img.height() };
+        for region in &regions {
+            // Boxes drawn past the edge are clipped rather than rejected
+            let Some(area) = region.intersect(&bounds) else {
+                return Err(CaptureError::InvalidRegion(format!(
+                    "{}x{} at ({}, {}) is outside the image", region.width, region.height, region.x, region.y
+                )));
+            };
+            pixelate(&mut img, area);
+        }
+        *canvas = Canvas::from_rgba(img)?;
+        info!("Redacted {} region(s)", regions.len());
+        Ok(ImageSize::of(canvas))
+    })
+    .await
+}
+
+/// Find emails, API keys and card numbers in the capture `id` with Tesseract (see
+/// `SearchSettings`) and pixelate the words they are in. `kinds` limits what is looked for,
+/// all of them by default. Returns what was redacted, so the UI can show it before sharing.
+#[tauri::command]
+pub async fn auto_redact(id: String, kinds: Option<Vec<SensitiveKind>>) -> Result<AutoRedaction, CaptureError> {
+    let kinds = kinds.unwrap_or_else(|| vec![SensitiveKind::Email, SensitiveKind::ApiKey, SensitiveKind::CardNumber]);
+    let search = settings::current().search;
+
+    store::edit(id, move |canvas| {
+        let mut img = canvas.load_image()?;
+        let words = search::recognize_words(&search, &img)?;
+        let bounds = Rect { x: 0, y: 0, width: img.width(), height: img.height() };
+        let matches: Vec<SensitiveMatch> = find_sensitive(&words, &kinds)
+            .into_iter()
+            .filter_map(|found| found.region.intersect(&bounds).map(|region| SensitiveMatch { region, ..found }))
+            .collect();
+        if matches.is_empty() {
+            info!("Auto-redaction found nothing to redact");
+            return Ok(AutoRedaction { size: ImageSize::of(canvas), matches });
+        }
+
+        for found in &matches {
+            pixelate(&mut img, found.region);
+        }
+        *canvas = Canvas::from_rgba(img)?;
+        info!("Auto-redacted {} region(s)", matches.len());
+        Ok(AutoRedaction { size: ImageSize::of(canvas), matches })
+    })
+    .await
+}
+
+/// Match the patterns of `kinds` against each line of `words`, joined with single spaces so
+/// numbers and tokens split by OCR still match. A match covers every word it touches.
+fn find_sensitive(words: &[RecognizedWord], kinds: &[SensitiveKind]) -> Vec<SensitiveMatch> {
+    let mut matches = Vec::new();
+    for line in words.chunk_by(|a, b| a.line == b.line) {
+        let mut text = String::new();
+        // Byte range of each word in `text`
+        let mut spans = Vec::with_capacity(line.len());
+        for word in line {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            spans.push(text.len()..text.len() + word.text.len());
+            text.push_str(&word.text);
+        }
+
+        let mut found: Vec<(SensitiveKind, std::ops::Range<usize>)> = Vec::new();
+        for &kind in kinds {
+            let ranges: Vec<std::ops::Range<usize>> = match kind {
+                SensitiveKind::Email => EMAIL.find_iter(&text).map(|m| m.range()).collect(),
+                SensitiveKind::ApiKey => API_KEY
+                    .find_iter(&text)
+                    .chain(TOKEN.find_iter(&text).filter(|m| looks_random(m.as_str())))
+                    .map(|m| m.range())
+                    .collect(),
+                SensitiveKind::CardNumber => CARD_NUMBER.find_iter(&text).filter(|m| luhn(m.as_str())).map(|m| m.range()).collect(),
+            };
+            found.extend(ranges.into_iter().map(|range| (kind, range)));
+        }
+
+        for (kind, range) in found {
+            let touched: Vec<&RecognizedWord> = line
+                .iter()
+                .zip(&spans)
+                .filter(|(_, span)| span.start < range.end && range.start < span.end)
+                .map(|(word, _)| word)
+                .collect();
+            let Some(region) = bounding_box(&touched) else {
+                continue;
+            };
+            // Several patterns can hit the same words, e.g. a token that is also a card number
+            if !matches.iter().any(|other: &SensitiveMatch| other.region == region) {
+                matches.push(SensitiveMatch { kind, region });
+            }
+        }
+    }
+    matches
+}
+
+fn bounding_box(words: &[&RecognizedWord]) -> Option<Rect> {
+    let left = words.iter().map(|word| word.left).min()?.saturating_sub(MARGIN);
+    let top = words.iter().map(|word| word.top).min()?.saturating_sub(MARGIN);
+    let right = words.iter().map(|word| word.left + word.width).max()? + MARGIN;
+    let bottom = words.iter().map(|word| word.top + word.height).max()? + MARGIN;
+    Some(Rect { x: left as i32, y: top as i32, width: right - left, height: bottom - top })
+}
+
+/// Both letters and digits, so long words and numbers aren't taken for secrets
+fn looks_random(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit()) && token.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// The Luhn checksum of card numbers, ignoring spaces and dashes
+fn luhn(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    (13..=19).contains(&digits.len()) && sum.is_multiple_of(10)
+}
+
+/// Replace each block inside `area` with its average color. `area` must lie inside the image.
+pub fn pixelate(img: &mut RgbaImage, area: Rect) {
+    let (left, top) = (area.x as u32, area.y as u32);
+    let (right, bottom) = (area.right() as u32, area.bottom() as u32);
+
+    for block_y in (top..bottom).step_by(BLOCK_SIZE as usize) {
+        for block_x in (left..right).step_by(BLOCK_SIZE as usize) {
+            let xs = block_x..(block_x + BLOCK_SIZE).min(right);
+            let ys = block_y..(block_y + BLOCK_SIZE).min(bottom);
+
+            let mut sum = [0u64; 4];
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    for (total, value) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+                        *total += value as u64;
+                    }
+                }
+            }
+            let count = (xs.len() * ys.len()) as u64;
+            let average = sum.map(|total| (total / count) as u8);
+
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    img.get_pixel_mut(x, y).0 = average;
+                }
+            }
+        }
+    }
+}
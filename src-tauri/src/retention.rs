@@ -0,0 +1,115 @@
+use chrono::{DateTime, Duration as ChronoDuration, Local};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+use crate::error::CaptureError;
+use crate::history::{self, HistoryEntry};
+use crate::idle;
+use crate::settings;
+
+/// How often the retention limits are enforced in the background, and once at startup
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Limits on how much the history keeps. The oldest entries go first once any limit is
+/// exceeded. Unset limits don't apply, by default everything is kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Keep at most this many entries
+    pub max_entries: Option<usize>,
+    /// Delete entries older than this many days
+    pub max_days: Option<u32>,
+    /// Keep images and thumbnails below this many megabytes in total
+    pub max_total_mb: Option<u64>,
+}
+
+/// Result of `purge_history`, also the payload of the `history-purged` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgeReport {
+    /// Ids of the deleted entries
+    pub removed: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+/// Enforce the retention limits now and then, called once from `setup`
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(CLEANUP_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            // Nothing new lands in the history while idle, except from schedules
+            idle::wait_until_active().await;
+            ticker.tick().await;
+            let app = app.clone();
+            let result = tauri::async_runtime::spawn_blocking(move || purge(&app)).await;
+            match result {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("History cleanup failed: {}", e),
+                Err(e) => warn!("History cleanup task failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Delete the history entries over the retention limits right away, instead of waiting for the
+/// background cleanup. Returns what was deleted, which is also sent as `history-purged`.
+#[tauri::command]
+pub async fn purge_history(app: AppHandle) -> Result<PurgeReport, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || purge(&app))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("purge task failed: {}", e)))?
+}
+
+fn purge(app: &AppHandle) -> Result<PurgeReport, CaptureError> {
+    let retention = settings::current().retention;
+    let entries = history::list_history(app.clone(), None, None)?;
+    let removed = over_limits(&retention, &entries, Local::now());
+    if removed.is_empty() {
+        return Ok(PurgeReport { removed, reclaimed_bytes: 0 });
+    }
+
+    let reclaimed_bytes = history::remove(app, &removed)?;
+    info!("Purged {} history entries, reclaimed {} KB", removed.len(), reclaimed_bytes / 1024);
+    let report = PurgeReport { removed, reclaimed_bytes };
+    let _ = app.emit("history-purged", &report);
+    Ok(report)
+}
+
+/// Ids of the entries (oldest first) that have to go to stay within `retention`
+fn over_limits(retention: &RetentionSettings, entries: &[HistoryEntry], now: DateTime<Local>) -> Vec<String> {
+    let mut kept: Vec<&HistoryEntry> = entries.iter().collect();
+    let mut removed: Vec<String> = Vec::new();
+
+    if let Some(days) = retention.max_days {
+        let cutoff = now - ChronoDuration::days(days as i64);
+        // Entries with an unreadable date are left alone rather than guessed at
+        kept.retain(|entry| match DateTime::parse_from_rfc3339(&entry.created_at) {
+            Ok(created) if created < cutoff => {
+                removed.push(entry.id.clone());
+                false
+            }
+            _ => true,
+        });
+    }
+
+    if let Some(max) = retention.max_entries {
+        let excess = kept.len().saturating_sub(max);
+        removed.extend(kept.drain(..excess).map(|entry| entry.id.clone()));
+    }
+
+    if let Some(max_mb) = retention.max_total_mb {
+        let max_bytes = max_mb.saturating_mul(1024 * 1024);
+        let sizes: Vec<u64> = kept.iter().map(|entry| history::disk_usage(entry)).collect();
+        let mut total: u64 = sizes.iter().sum();
+        let mut excess = 0;
+        while total > max_bytes && excess < kept.len() {
+            total -= sizes[excess];
+            excess += 1;
+        }
+        removed.extend(kept.drain(..excess).map(|entry| entry.id.clone()));
+    }
+    removed
+}
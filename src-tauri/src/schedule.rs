@@ -0,0 +1,243 @@
+use chrono::{DateTime, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+use tracing::{error, info, warn};
+use crate::canvas::Canvas;
+use crate::capture;
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::history::{self, HistoryEntry};
+use crate::i18n;
+use crate::idle;
+use crate::notify;
+use crate::permission;
+use crate::privacy::{self, PendingCapture};
+
+const SCHEDULES_FILE: &str = "schedules.json";
+
+/// How often schedules are checked. Below a minute so "HH:MM" times aren't missed.
+const CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Periodic screenshot of a fixed region, e.g. a dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    /// Physical desktop pixels
+    pub region: Rect,
+    /// Capture every N minutes
+    pub every_minutes: Option<u32>,
+    /// Capture daily at these local times, "HH:MM"
+    #[serde(default)]
+    pub at_times: Vec<String>,
+}
+
+/// Payload of the `scheduled-capture` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledCapture {
+    pub schedule_id: String,
+    pub entry: HistoryEntry,
+}
+
+lazy_static! {
+    static ref SCHEDULES: Mutex<Vec<Schedule>> = Mutex::new(Vec::new());
+    // Last capture per schedule id. Intervals count from here, or from when the schedule
+    // was created/loaded, so restarting the app doesn't fire every schedule at once.
+    static ref LAST_RUN: Mutex<HashMap<String, DateTime<Local>>> = Mutex::new(HashMap::new());
+}
+
+/// Load saved schedules and start checking them, called once from `setup`
+pub fn start(app: &AppHandle) {
+    if let Some(path) = schedules_path(app) {
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<Vec<Schedule>>(&content) {
+                Ok(schedules) => {
+                    info!("Loaded {} capture schedules", schedules.len());
+                    let now = Local::now();
+                    let mut last_run = LAST_RUN.lock().unwrap();
+                    for schedule in &schedules {
+                        last_run.insert(schedule.id.clone(), now);
+                    }
+                    *SCHEDULES.lock().unwrap() = schedules;
+                }
+                Err(e) => warn!("Ignoring invalid schedules file {}: {}", path.display(), e),
+            },
+            Err(_) => info!("No capture schedules found"),
+        }
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            // Without schedules there's nothing to check, don't wake up for it while idle
+            if SCHEDULES.lock().unwrap().is_empty() {
+                idle::wait_until_active().await;
+            }
+            ticker.tick().await;
+            run_due(&app);
+        }
+    });
+}
+
+fn run_due(app: &AppHandle) {
+    let now = Local::now();
+    let due: Vec<Schedule> = {
+        let schedules = SCHEDULES.lock().unwrap();
+        let mut last_run = LAST_RUN.lock().unwrap();
+        let due: Vec<Schedule> = schedules
+            .iter()
+            .filter(|schedule| is_due(schedule, last_run.get(&schedule.id).copied(), now))
+            .cloned()
+            .collect();
+        for schedule in &due {
+            last_run.insert(schedule.id.clone(), now);
+        }
+        due
+    };
+
+    for schedule in due {
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            info!("Running scheduled capture '{}'", schedule.name);
+            run_and_report(&app, &schedule, false);
+        });
+    }
+}
+
+/// Run the schedule `id` once more, after the user confirmed capturing over sensitive windows
+pub async fn run_confirmed(app: AppHandle, id: String) -> Result<(), CaptureError> {
+    let schedule = SCHEDULES.lock().unwrap()
+        .iter()
+        .find(|schedule| schedule.id == id)
+        .cloned()
+        .ok_or_else(|| CaptureError::InvalidState(format!("no schedule with id {}", id)))?;
+    info!("Running scheduled capture '{}' as confirmed", schedule.name);
+    tauri::async_runtime::spawn_blocking(move || run_and_report(&app, &schedule, true))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("schedule task failed: {}", e)))
+}
+
+/// Run a schedule, reporting the result with `scheduled-capture` or a failure notification.
+/// A capture over sensitive windows is held until the user confirms it.
+fn run_and_report(app: &AppHandle, schedule: &Schedule, allow_sensitive: bool) {
+    match run_schedule(app, schedule, allow_sensitive) {
+        Ok(entry) => {
+            let _ = app.emit("scheduled-capture", ScheduledCapture { schedule_id: schedule.id.clone(), entry });
+        }
+        Err(e) if privacy::ask_to_confirm(app, PendingCapture::Schedule { id: schedule.id.clone() }, &e) => {}
+        Err(e) => {
+            error!("Scheduled capture '{}' failed: {}", schedule.name, e);
+            let message = i18n::text("notify.scheduled_failed", &[("name", &schedule.name), ("error", &e.localized())]);
+            notify::capture_failed(app, &message);
+            let _ = app.emit("capture-error", &e);
+        }
+    }
+}
+
+fn is_due(schedule: &Schedule, last_run: Option<DateTime<Local>>, now: DateTime<Local>) -> bool {
+    if let (Some(minutes), Some(last)) = (schedule.every_minutes, last_run) {
+        if now - last >= chrono::Duration::minutes(minutes as i64) {
+            return true;
+        }
+    }
+
+    // Each time of day fires once, the check runs several times within that minute
+    let minute = now.format("%H:%M").to_string();
+    let ran_this_minute = last_run
+        .is_some_and(|last| now - last < chrono::Duration::minutes(1) && last.format("%H:%M").to_string() == minute);
+    schedule.at_times.contains(&minute) && !ran_this_minute
+}
+
+fn run_schedule(app: &AppHandle, schedule: &Schedule, allow_sensitive: bool) -> Result<HistoryEntry, CaptureError> {
+    permission::ensure_capture_permission()?;
+    let image = capture::capture_checked(&schedule.region, allow_sensitive)?;
+    history::save(app, &mut Canvas::new(&image), &format!("schedule:{}", schedule.name))
+}
+
+/// Add a schedule for `region` (physical pixels), or the region of the last capture.
+/// At least one of `every_minutes` and `at_times` has to be given.
+#[tauri::command]
+pub fn create_schedule(
+    app: AppHandle,
+    name: String,
+    region: Option<Rect>,
+    every_minutes: Option<u32>,
+    at_times: Option<Vec<String>>,
+) -> Result<Schedule, CaptureError> {
+    let region = match region {
+        Some(region) => region,
+        None => capture::last_region().ok_or(CaptureError::NoPreviousRegion)?,
+    };
+    let at_times = at_times.unwrap_or_default();
+    if every_minutes == Some(0) {
+        return Err(CaptureError::InvalidState("every_minutes must be at least 1".to_string()));
+    }
+    if every_minutes.is_none() && at_times.is_empty() {
+        return Err(CaptureError::InvalidState("a schedule needs every_minutes or at_times".to_string()));
+    }
+    // Normalized to "HH:MM" so "9:05" matches too
+    let at_times = at_times
+        .iter()
+        .map(|time| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map(|time| time.format("%H:%M").to_string())
+                .map_err(|_| CaptureError::InvalidState(format!("invalid time '{}', expected HH:MM", time)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let schedule = Schedule { id: Uuid::new_v4().to_string(), name, region, every_minutes, at_times };
+    let mut schedules = SCHEDULES.lock().unwrap().clone();
+    schedules.push(schedule.clone());
+    save(&app, schedules)?;
+    LAST_RUN.lock().unwrap().insert(schedule.id.clone(), Local::now());
+    // Wakes the check loop in case it was parked for having no schedules
+    idle::touch();
+    info!("Created capture schedule '{}'", schedule.name);
+    Ok(schedule)
+}
+
+#[tauri::command]
+pub fn list_schedules() -> Vec<Schedule> {
+    SCHEDULES.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn delete_schedule(app: AppHandle, id: String) -> Result<(), CaptureError> {
+    let mut schedules = SCHEDULES.lock().unwrap().clone();
+    let before = schedules.len();
+    schedules.retain(|schedule| schedule.id != id);
+    if schedules.len() == before {
+        return Err(CaptureError::InvalidState(format!("no schedule with id {}", id)));
+    }
+    save(&app, schedules)?;
+    LAST_RUN.lock().unwrap().remove(&id);
+    Ok(())
+}
+
+/// Persist and then apply, so the in-memory list never gets ahead of the file
+fn save(app: &AppHandle, schedules: Vec<Schedule>) -> Result<(), CaptureError> {
+    let path = schedules_path(app)
+        .ok_or_else(|| CaptureError::Internal("Could not resolve app config directory".to_string()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string_pretty(&schedules).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    fs::write(&path, content)?;
+    *SCHEDULES.lock().unwrap() = schedules;
+    Ok(())
+}
+
+fn schedules_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_config_dir().ok().map(|dir| dir.join(SCHEDULES_FILE))
+}
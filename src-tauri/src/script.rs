@@ -0,0 +1,270 @@
+use image::RgbaImage;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::metadata::CaptureMetadata;
+use crate::redact;
+use crate::settings;
+
+/// Largest strings, arrays and maps a script can build, so a runaway one can't take all memory
+const MAX_COLLECTION_SIZE: usize = 1 << 20;
+const MAX_CALL_LEVELS: usize = 64;
+
+/// A Rhai script (https://rhai.rs) that processes captures at a hook point. Scripts run embedded
+/// in the app and see the capture as the variable `capture`:
+///
+/// - `capture.width`, `capture.height`, `capture.hook` ("post-stitch" or "pre-export") and
+///   `capture.metadata` (a map, empty when the capture has none)
+/// - `capture.get_pixel(x, y)` → `[r, g, b, a]`, `capture.set_pixel(x, y, [r, g, b, a])`
+/// - `capture.fill(x, y, width, height, [r, g, b, a])`, `capture.pixelate(x, y, width, height)`
+///   and `capture.crop(x, y, width, height)`
+///
+/// Changes to the capture replace the image. Scripts can't touch files, the network or the
+/// app's own state, and `print` goes to the log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    pub enabled: bool,
+    pub hook: HookPoint,
+    /// The `.rhai` file
+    pub path: String,
+    /// The script is stopped after this long, pixel loops over a tall capture add up
+    pub timeout_secs: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hook: HookPoint::PostStitch,
+            path: String::new(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// When a script runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookPoint {
+    /// On every new capture, before the pipeline. Changes end up in the capture itself.
+    PostStitch,
+    /// On the image written by `save_image`. Changes only end up in the saved file.
+    PreExport,
+}
+
+impl HookPoint {
+    fn name(self) -> &'static str {
+        match self {
+            HookPoint::PostStitch => "post-stitch",
+            HookPoint::PreExport => "pre-export",
+        }
+    }
+}
+
+/// Failure of one script, emitted as `script-error`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScriptError {
+    hook: HookPoint,
+    path: String,
+    message: String,
+}
+
+/// The `capture` a script sees. Every access is bounds checked, a script can only fail, never
+/// write outside the image.
+#[derive(Debug, Clone)]
+struct Capture {
+    img: RgbaImage,
+    hook: HookPoint,
+    metadata: Map,
+    changed: bool,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl Capture {
+    fn width(&mut self) -> i64 {
+        self.img.width() as i64
+    }
+
+    fn height(&mut self) -> i64 {
+        self.img.height() as i64
+    }
+
+    fn hook(&mut self) -> String {
+        self.hook.name().to_string()
+    }
+
+    fn metadata(&mut self) -> Map {
+        self.metadata.clone()
+    }
+
+    fn get_pixel(&mut self, x: i64, y: i64) -> ScriptResult<Array> {
+        let (x, y) = self.point(x, y)?;
+        Ok(self.img.get_pixel(x, y).0.iter().map(|&channel| Dynamic::from_int(channel as i64)).collect())
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: Array) -> ScriptResult<()> {
+        let (x, y) = self.point(x, y)?;
+        self.img.get_pixel_mut(x, y).0 = rgba(color)?;
+        self.changed = true;
+        Ok(())
+    }
+
+    fn fill(&mut self, x: i64, y: i64, width: i64, height: i64, color: Array) -> ScriptResult<()> {
+        let area = self.area(x, y, width, height)?;
+        let color = rgba(color)?;
+        for y in area.y as u32..area.bottom() as u32 {
+            for x in area.x as u32..area.right() as u32 {
+                self.img.get_pixel_mut(x, y).0 = color;
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    fn pixelate(&mut self, x: i64, y: i64, width: i64, height: i64) -> ScriptResult<()> {
+        let area = self.area(x, y, width, height)?;
+        redact::pixelate(&mut self.img, area);
+        self.changed = true;
+        Ok(())
+    }
+
+    fn crop(&mut self, x: i64, y: i64, width: i64, height: i64) -> ScriptResult<()> {
+        let area = self.area(x, y, width, height)?;
+        self.img = image::imageops::crop_imm(&self.img, area.x as u32, area.y as u32, area.width, area.height).to_image();
+        self.changed = true;
+        Ok(())
+    }
+
+    fn point(&self, x: i64, y: i64) -> ScriptResult<(u32, u32)> {
+        if x < 0 || y < 0 || x >= self.img.width() as i64 || y >= self.img.height() as i64 {
+            return Err(format!("({}, {}) is outside the {}x{} capture", x, y, self.img.width(), self.img.height()).into());
+        }
+        Ok((x as u32, y as u32))
+    }
+
+    /// A non-empty rectangle inside the image
+    fn area(&self, x: i64, y: i64, width: i64, height: i64) -> ScriptResult<Rect> {
+        let inside = x >= 0
+            && y >= 0
+            && width > 0
+            && height > 0
+            && x.checked_add(width).is_some_and(|right| right <= self.img.width() as i64)
+            && y.checked_add(height).is_some_and(|bottom| bottom <= self.img.height() as i64);
+        if !inside {
+            return Err(format!(
+                "{}x{} at ({}, {}) is not inside the {}x{} capture",
+                width, height, x, y, self.img.width(), self.img.height()
+            ).into());
+        }
+        Ok(Rect { x: x as i32, y: y as i32, width: width as u32, height: height as u32 })
+    }
+}
+
+/// `[r, g, b, a]` with channels 0 to 255
+fn rgba(color: Array) -> ScriptResult<[u8; 4]> {
+    let channels: Vec<u8> = color
+        .iter()
+        .filter_map(|channel| channel.as_int().ok())
+        .filter_map(|channel| u8::try_from(channel).ok())
+        .collect();
+    <[u8; 4]>::try_from(channels).map_err(|_| "colors are [r, g, b, a] with channels from 0 to 255".into())
+}
+
+/// Whether any enabled script runs at `hook`, to skip loading the image when none does
+pub fn has_scripts(hook: HookPoint) -> bool {
+    settings::current().scripts.iter().any(|script| script.enabled && script.hook == hook)
+}
+
+/// Run the enabled scripts for `hook` on `img` in order, each on the result of the one before.
+/// `None` if no script changed it. A script that fails leaves the image as it was, reported
+/// through `script-error`. Blocking, call it off the async runtime.
+pub fn run(app: &AppHandle, hook: HookPoint, img: &RgbaImage, metadata: Option<&CaptureMetadata>) -> Option<RgbaImage> {
+    let scripts: Vec<ScriptConfig> = settings::current().scripts.into_iter()
+        .filter(|script| script.enabled && script.hook == hook && !script.path.trim().is_empty())
+        .collect();
+
+    let mut processed: Option<RgbaImage> = None;
+    for script in &scripts {
+        match run_script(script, processed.as_ref().unwrap_or(img), metadata) {
+            Ok(Some(img)) => processed = Some(img),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Script '{}' at {:?} failed: {}", script.path, hook, e);
+                let _ = app.emit("script-error", ScriptError { hook, path: script.path.clone(), message: e.localized() });
+            }
+        }
+    }
+    processed
+}
+
+/// The image the script left behind, `None` if it didn't touch it
+fn run_script(script: &ScriptConfig, img: &RgbaImage, metadata: Option<&CaptureMetadata>) -> Result<Option<RgbaImage>, CaptureError> {
+    let source = fs::read_to_string(&script.path)
+        .map_err(|e| CaptureError::ScriptFailed(format!("could not read {}: {}", script.path, e)))?;
+    let timeout = Duration::from_secs(script.timeout_secs.max(1));
+    let engine = engine(timeout);
+
+    let metadata = match metadata {
+        Some(metadata) => {
+            let json = serde_json::to_string(metadata).map_err(|e| CaptureError::Internal(e.to_string()))?;
+            engine.parse_json(json, true).map_err(|e| CaptureError::ScriptFailed(e.to_string()))?
+        }
+        None => Map::new(),
+    };
+    let mut scope = Scope::new();
+    scope.push("capture", Capture { img: img.clone(), hook: script.hook, metadata, changed: false });
+
+    info!("Running {:?} script {}", script.hook, script.path);
+    engine
+        .run_with_scope(&mut scope, &source)
+        .map_err(|e| match *e {
+            EvalAltResult::ErrorTerminated(..) => CaptureError::ScriptFailed(format!("timed out after {} seconds", timeout.as_secs())),
+            e => CaptureError::ScriptFailed(e.to_string()),
+        })?;
+
+    let capture = scope
+        .get_value::<Capture>("capture")
+        .ok_or_else(|| CaptureError::ScriptFailed("the script replaced `capture` with something else".to_string()))?;
+    if !capture.changed {
+        return Ok(None);
+    }
+    info!("Script changed the capture from {}x{} to {}x{}", img.width(), img.height(), capture.img.width(), capture.img.height());
+    Ok(Some(capture.img))
+}
+
+/// An engine with the capture API, no module imports and a time limit
+fn engine(timeout: Duration) -> Engine {
+    let mut engine = Engine::new();
+    // The default resolver loads modules from files
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_string_size(MAX_COLLECTION_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    let started = Instant::now();
+    engine.on_progress(move |_| (started.elapsed() > timeout).then_some(Dynamic::UNIT));
+    engine.on_print(|text| info!("Script: {}", text));
+    engine.on_debug(|text, _, position| info!("Script debug at {}: {}", position, text));
+
+    engine
+        .register_type_with_name::<Capture>("Capture")
+        .register_get("width", Capture::width)
+        .register_get("height", Capture::height)
+        .register_get("hook", Capture::hook)
+        .register_get("metadata", Capture::metadata)
+        .register_fn("get_pixel", Capture::get_pixel)
+        .register_fn("set_pixel", Capture::set_pixel)
+        .register_fn("fill", Capture::fill)
+        .register_fn("pixelate", Capture::pixelate)
+        .register_fn("crop", Capture::crop);
+    engine
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+use enigo::{Axis, Coordinate, Enigo, Mouse, Settings};
+use image::RgbaImage;
+use tauri::{AppHandle, Manager};
+use tracing::{debug, info};
+use crate::capture;
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::permission;
+
+/// Wheel notches per probe, enough to move text by a few lines in most apps
+const PROBE_NOTCHES: i32 = 3;
+/// Time for the app to scroll (smoothly, in some apps) and repaint
+const SETTLE_DELAY: Duration = Duration::from_millis(350);
+/// Summed RGB difference from which a pixel counts as changed, ignores dithering and subpixel noise
+const PIXEL_TOLERANCE: u32 = 48;
+/// Share of a row or column that has to change for it to count as scrolling content
+const MIN_CHANGED_SHARE: f32 = 0.01;
+/// Static gaps up to this many pixels inside the content are bridged, e.g. blank margins and gutters
+const MAX_GAP: usize = 48;
+
+/// Propose the part of `region` (physical pixels, usually a window from `get_window_rects`)
+/// that scrolls, without title bar, toolbars and side panels.
+///
+/// Scrolls the wheel over the middle of the region and compares before and after: rows and
+/// columns that moved are content, the static ones around them are chrome. The wheel is turned
+/// back afterwards. Tries upwards too for content that is already at the bottom.
+/// `None` if nothing moved, e.g. the app doesn't scroll by wheel or the content fits.
+#[tauri::command]
+pub async fn detect_scroll_area(app: AppHandle, region: Rect) -> Result<Option<Rect>, CaptureError> {
+    permission::ensure_capture_permission()?;
+    let region = display::validate_region(region)?;
+
+    // The wheel has to reach the window below the selection overlay
+    for window in app.webview_windows().values() {
+        let _ = window.set_ignore_cursor_events(true);
+    }
+    let result = tauri::async_runtime::spawn_blocking(move || probe(&region))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("scroll area detection failed: {}", e)));
+    for window in app.webview_windows().values() {
+        let _ = window.set_ignore_cursor_events(false);
+    }
+
+    let area = result??;
+    match &area {
+        Some(area) => info!("Detected scroll area {:?} in {:?}", area, region),
+        None => info!("Nothing scrolled in {:?}", region),
+    }
+    Ok(area)
+}
+
+fn probe(region: &Rect) -> Result<Option<Rect>, CaptureError> {
+    let mut enigo = Enigo::new(&Settings::default())
+        .map_err(|e| CaptureError::Internal(format!("mouse input is unavailable: {}", e)))?;
+    let input_err = |e| CaptureError::Internal(format!("failed to scroll: {}", e));
+
+    let cursor = enigo.location().ok();
+    enigo
+        .move_mouse(region.x + region.width as i32 / 2, region.y + region.height as i32 / 2, Coordinate::Abs)
+        .map_err(input_err)?;
+
+    let mut area = None;
+    for notches in [PROBE_NOTCHES, -PROBE_NOTCHES] {
+        let before = capture::capture_region(region)?.to_rgba8();
+        enigo.scroll(notches, Axis::Vertical).map_err(input_err)?;
+        std::thread::sleep(SETTLE_DELAY);
+        let after = capture::capture_region(region)?.to_rgba8();
+        enigo.scroll(-notches, Axis::Vertical).map_err(input_err)?;
+
+        area = moving_area(&before, &after);
+        debug!("Probed {} wheel notches: {:?}", notches, area);
+        if area.is_some() {
+            break;
+        }
+        std::thread::sleep(SETTLE_DELAY);
+    }
+
+    if let Some((x, y)) = cursor {
+        let _ = enigo.move_mouse(x, y, Coordinate::Abs);
+    }
+    Ok(area.map(|(x, y, width, height)| Rect {
+        x: region.x + x as i32,
+        y: region.y + y as i32,
+        width: width as u32,
+        height: height as u32,
+    }))
+}
+
+/// Bounds of the changed part between two frames as (x, y, width, height) within the frame.
+/// Columns are found first, so a changing side panel can't stretch the rows of the content.
+fn moving_area(before: &RgbaImage, after: &RgbaImage) -> Option<(usize, usize, usize, usize)> {
+    if before.dimensions() != after.dimensions() {
+        return None;
+    }
+    let (width, height) = (before.width() as usize, before.height() as usize);
+    let changed: Vec<bool> = before.pixels().zip(after.pixels())
+        .map(|(a, b)| {
+            let diff: u32 = (0..3).map(|c| (a.0[c] as i32 - b.0[c] as i32).unsigned_abs()).sum();
+            diff > PIXEL_TOLERANCE
+        })
+        .collect();
+
+    let mut columns = vec![0u32; width];
+    for row in changed.chunks(width) {
+        for (count, &changed) in columns.iter_mut().zip(row) {
+            *count += changed as u32;
+        }
+    }
+    let (left, right) = longest_span(&columns, min_count(height))?;
+
+    let rows: Vec<u32> = changed.chunks(width)
+        .map(|row| row[left..right].iter().filter(|&&changed| changed).count() as u32)
+        .collect();
+    let (top, bottom) = longest_span(&rows, min_count(right - left))?;
+
+    Some((left, top, right - left, bottom - top))
+}
+
+fn min_count(length: usize) -> u32 {
+    ((length as f32 * MIN_CHANGED_SHARE).ceil() as u32).max(1)
+}
+
+/// The longest run of entries with at least `min`, bridging gaps up to `MAX_GAP`, as start..end
+fn longest_span(counts: &[u32], min: u32) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut current: Option<(usize, usize)> = None;
+    for (i, &count) in counts.iter().enumerate() {
+        if count < min {
+            continue;
+        }
+        current = match current {
+            Some((start, end)) if i - end <= MAX_GAP => Some((start, i + 1)),
+            _ => Some((i, i + 1)),
+        };
+        let (start, end) = current.unwrap();
+        if best.is_none_or(|(best_start, best_end)| end - start > best_end - best_start) {
+            best = Some((start, end));
+        }
+    }
+    best
+}
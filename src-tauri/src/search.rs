@@ -0,0 +1,363 @@
+use image::imageops;
+use image::RgbaImage;
+use rusqlite::{params, Connection, OptionalExtension, MAIN_DB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use tauri::AppHandle;
+use tracing::{info, warn};
+use crate::encryption;
+use crate::error::CaptureError;
+use crate::history::{self, HistoryEntry};
+use crate::hook;
+use crate::settings;
+use crate::utils;
+
+/// Full-text index of the history (notes, tags and recognized text), next to the history index
+const TEXT_INDEX_FILE: &str = "text-index.sqlite";
+/// The recognized text of earlier versions, moved into the full-text index when it is created
+const LEGACY_TEXT_INDEX_FILE: &str = "text-index.json";
+/// One row per history entry. Diacritics are folded, so "cafe" finds "café".
+const TEXT_INDEX_SCHEMA: &str = "CREATE VIRTUAL TABLE IF NOT EXISTS texts \
+    USING fts5(id UNINDEXED, note, tags, text, tokenize = 'unicode61 remove_diacritics 2')";
+/// Ranking of the columns above, a word in the note or tags says more than one in a long page
+const RANK: &str = "bm25(texts, 0.0, 4.0, 4.0, 1.0)";
+/// Put around matches in snippets by SQLite, split off again into `SnippetPart`s
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_END: char = '\u{3}';
+/// Words in a snippet
+const SNIPPET_TOKENS: i64 = 24;
+/// Tall captures take a while, tesseract goes through them line by line
+const OCR_TIMEOUT: Duration = Duration::from_secs(300);
+/// Tesseract output read at most, far more text than any capture holds, the rest is drained unread
+const MAX_OCR_OUTPUT: usize = 32 * 1024 * 1024;
+/// Tall images are recognized in bands this high, tesseract refuses images past 32767 pixels
+const OCR_BAND_ROWS: u32 = 8192;
+/// Bands overlap by this much, so a line of text cut by one band is whole in the next
+const OCR_BAND_OVERLAP: u32 = 256;
+/// TSV `level` of a word, the others are pages, blocks, paragraphs and lines
+const TSV_WORD_LEVEL: &str = "5";
+
+lazy_static! {
+    // Recognition runs in the background, several entries can finish at the same time
+    static ref TEXT_INDEX_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Text recognition of history entries, for `search_history`. Off by default, it needs
+/// Tesseract installed (https://github.com/tesseract-ocr/tesseract).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Recognize the text of every entry saved to the history
+    pub ocr: bool,
+    /// The tesseract executable, found on PATH by default
+    pub tesseract: String,
+    /// Tesseract languages, e.g. "eng+deu"
+    pub languages: String,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self { ocr: false, tesseract: "tesseract".to_string(), languages: "eng".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct IndexedText {
+    id: String,
+    text: String,
+}
+
+/// A word tesseract recognized, with its box in image pixels
+#[derive(Debug, Clone)]
+pub struct RecognizedWord {
+    pub text: String,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Words with the same line number are one line of text, numbered down the image
+    pub line: u32,
+}
+
+/// A history entry whose text matches, see `search_history`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub entry: HistoryEntry,
+    /// The text around the first match, in parts so the UI can highlight without parsing markup
+    pub snippet: Vec<SnippetPart>,
+    /// BM25 relevance, higher is better, hits are sorted by it
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+/// Recognize the text of a newly saved entry on a background thread, if enabled. Notes and
+/// tags are indexed for every entry anyway, when searching.
+pub fn index_in_background(app: AppHandle, entry: HistoryEntry) {
+    let search = settings::current().search;
+    if !search.ocr {
+        return;
+    }
+    thread::spawn(move || {
+        let result = entry.load_image()
+            .and_then(|img| recognize_words(&search, &img.to_rgba8()))
+            .and_then(|words| add_to_index(&app, &entry.id, words.into_iter().map(|word| word.text).collect::<Vec<_>>().join(" ")));
+        match result {
+            Ok(()) => info!("Indexed the text of history entry {}", entry.id),
+            Err(e) => warn!("Text recognition of history entry {} failed: {}", entry.id, e),
+        }
+    });
+}
+
+/// History entries whose note, tags or recognized text contain every word of `query`, or a
+/// word starting with it, ignoring case and accents. Most relevant first, each with a snippet
+/// of the text around the matches.
+#[tauri::command]
+pub fn search_history(app: AppHandle, query: String) -> Result<Vec<SearchHit>, CaptureError> {
+    let Some(query) = match_query(&query) else {
+        return Ok(Vec::new());
+    };
+    let entries = history::list_history(app.clone(), None, None)?;
+
+    let ranked: Vec<(String, String, f64)> = {
+        let _guard = TEXT_INDEX_LOCK.lock().unwrap();
+        let path = text_index_path(&app)?;
+        let db = open_index(&path)?;
+        if sync_entries(&db, &entries).map_err(index_error)? {
+            save_index(&path, &db)?;
+        }
+        let sql = format!("SELECT id, snippet(texts, -1, ?2, ?3, '…', ?4), {RANK} FROM texts WHERE texts MATCH ?1 ORDER BY {RANK}");
+        let mut statement = db.prepare(&sql).map_err(index_error)?;
+        let rows = statement
+            .query_map(
+                params![query, HIGHLIGHT_START.to_string(), HIGHLIGHT_END.to_string(), SNIPPET_TOKENS],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(index_error)?;
+        rows.collect::<Result<_, _>>().map_err(index_error)?
+    };
+
+    let mut entries: HashMap<String, HistoryEntry> = entries.into_iter().map(|entry| (entry.id.clone(), entry)).collect();
+    Ok(ranked
+        .into_iter()
+        .filter_map(|(id, snippet, rank)| Some(SearchHit { entry: entries.remove(&id)?, snippet: snippet_parts(&snippet), score: -rank }))
+        .collect())
+}
+
+/// The words in `img` with their boxes, in reading order. The image goes to tesseract on
+/// stdin and the words come back on stdout, so neither is written to disk: the capture may
+/// be encrypted there.
+pub fn recognize_words(search: &SearchSettings, img: &RgbaImage) -> Result<Vec<RecognizedWord>, CaptureError> {
+    let mut words: Vec<RecognizedWord> = Vec::new();
+    if img.height() == 0 {
+        return Ok(words);
+    }
+    let mut start = 0;
+    let mut lines = 0;
+    loop {
+        let rows = (OCR_BAND_ROWS + OCR_BAND_OVERLAP).min(img.height() - start);
+        let last = start + rows >= img.height();
+        let band = imageops::crop_imm(img, 0, start, img.width(), rows).to_image();
+        let tsv = tesseract(search, &utils::encode_png(&band, |_| {})?, &["tsv"])?;
+        // A word belongs to the band its top is in, the overlap is only there to see it whole
+        let band_words: Vec<RecognizedWord> = parse_tsv(&tsv).filter(|word| last || word.top < OCR_BAND_ROWS).collect();
+        let band_lines = band_words.iter().map(|word| word.line + 1).max().unwrap_or(0);
+        words.extend(band_words.into_iter().map(|word| RecognizedWord { top: word.top + start, line: word.line + lines, ..word }));
+        lines += band_lines;
+        if last {
+            return Ok(words);
+        }
+        start += OCR_BAND_ROWS;
+    }
+}
+
+/// Words of tesseract's TSV output: level, page, block, paragraph, line, word, left, top,
+/// width, height, confidence and text. Lines are numbered from 0 in the order they come.
+fn parse_tsv(tsv: &str) -> impl Iterator<Item = RecognizedWord> + '_ {
+    let mut current: Option<(&str, &str, &str)> = None;
+    let mut line_number = 0;
+    tsv.lines().skip(1).filter_map(move |line| {
+        let fields: Vec<&str> = line.splitn(12, '\t').collect();
+        if fields.len() < 12 || fields[0] != TSV_WORD_LEVEL || fields[11].trim().is_empty() {
+            return None;
+        }
+        let number = |i: usize| fields[i].parse::<u32>().ok();
+        // Line numbers restart in every paragraph
+        let key = (fields[2], fields[3], fields[4]);
+        if current.is_some_and(|current| current != key) {
+            line_number += 1;
+        }
+        current = Some(key);
+        Some(RecognizedWord {
+            text: fields[11].trim().to_string(),
+            left: number(6)?,
+            top: number(7)?,
+            width: number(8)?,
+            height: number(9)?,
+            line: line_number,
+        })
+    })
+}
+
+/// Run tesseract with `png` on stdin and `config` (e.g. "tsv") after the usual arguments
+fn tesseract(search: &SearchSettings, png: &[u8], config: &[&str]) -> Result<String, CaptureError> {
+    let mut command = hook::command(&search.tesseract);
+    command.args(["stdin", "stdout", "-l", &search.languages]).args(config);
+    hook::run_limited(&mut command, png, OCR_TIMEOUT, MAX_OCR_OUTPUT)
+        .map(|finished| finished.stdout)
+        .map_err(|e| CaptureError::Internal(format!("tesseract: {}", e)))
+}
+
+fn add_to_index(app: &AppHandle, id: &str, text: String) -> Result<(), CaptureError> {
+    // Runs of blank lines and spaces only get in the way of snippets
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    import_texts(app, HashMap::from([(id.to_string(), text)]))
+}
+
+/// Recognized text of every indexed entry, by entry id
+pub fn indexed_texts(app: &AppHandle) -> Result<HashMap<String, String>, CaptureError> {
+    let _guard = TEXT_INDEX_LOCK.lock().unwrap();
+    let db = open_index(&text_index_path(app)?)?;
+    let mut statement = db.prepare("SELECT id, text FROM texts WHERE text != ''").map_err(index_error)?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(index_error)?;
+    rows.collect::<Result<_, _>>().map_err(index_error)
+}
+
+/// Add recognized text by entry id, e.g. from a history archive, replacing what was indexed for those ids
+pub fn import_texts(app: &AppHandle, texts: HashMap<String, String>) -> Result<(), CaptureError> {
+    if texts.is_empty() {
+        return Ok(());
+    }
+    let path = text_index_path(app)?;
+    let _guard = TEXT_INDEX_LOCK.lock().unwrap();
+    let db = open_index(&path)?;
+    for (id, text) in &texts {
+        set_text(&db, id, text).map_err(index_error)?;
+    }
+    save_index(&path, &db)
+}
+
+/// Forget the text of the entries `ids`, after they were removed from the history
+pub fn remove_from_index(app: &AppHandle, ids: &[String]) -> Result<(), CaptureError> {
+    let path = text_index_path(app)?;
+    let _guard = TEXT_INDEX_LOCK.lock().unwrap();
+    let db = open_index(&path)?;
+    let mut removed = 0;
+    for id in ids {
+        removed += db.execute("DELETE FROM texts WHERE id = ?1", [id]).map_err(index_error)?;
+    }
+    if removed == 0 {
+        return Ok(());
+    }
+    save_index(&path, &db)
+}
+
+/// The full-text index, in memory. It is saved whole through `encryption::protect`, so on
+/// disk it is as confidential as the captures it came from.
+fn open_index(path: &Path) -> Result<Connection, CaptureError> {
+    let mut db = Connection::open_in_memory().map_err(index_error)?;
+    match encryption::read(path) {
+        Ok(bytes) => db.deserialize_read_exact(MAIN_DB, bytes.as_slice(), bytes.len(), false).map_err(index_error)?,
+        Err(CaptureError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            db.execute_batch(TEXT_INDEX_SCHEMA).map_err(index_error)?;
+            import_legacy_index(path, &db)?;
+        }
+        Err(e) => return Err(e),
+    }
+    Ok(db)
+}
+
+fn save_index(path: &Path, db: &Connection) -> Result<(), CaptureError> {
+    let bytes = db.serialize(MAIN_DB).map_err(index_error)?.to_vec();
+    utils::write_atomic(path, &encryption::protect(bytes)?)
+}
+
+/// Move the recognized text of the JSON index earlier versions kept into the new `db` at `path`
+fn import_legacy_index(path: &Path, db: &Connection) -> Result<(), CaptureError> {
+    let legacy = path.with_file_name(LEGACY_TEXT_INDEX_FILE);
+    let texts: Vec<IndexedText> = match encryption::read(&legacy) {
+        Ok(json) => serde_json::from_slice(&json)
+            .map_err(|e| CaptureError::Internal(format!("text index is corrupt: {}", e)))?,
+        Err(CaptureError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for indexed in &texts {
+        set_text(db, &indexed.id, &indexed.text).map_err(index_error)?;
+    }
+    save_index(path, db)?;
+    fs::remove_file(&legacy)?;
+    info!("Moved the text of {} history entries into the full-text index", texts.len());
+    Ok(())
+}
+
+/// Bring the notes and tags in `db` up to date with `entries`, adding the entries it misses.
+/// Whether anything changed.
+fn sync_entries(db: &Connection, entries: &[HistoryEntry]) -> rusqlite::Result<bool> {
+    let mut statement = db.prepare("SELECT id, note, tags FROM texts")?;
+    let indexed: HashMap<String, (String, String)> = statement
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+    let mut changed = false;
+    for entry in entries {
+        let note = entry.note.clone().unwrap_or_default();
+        let tags = entry.tags.join(" ");
+        match indexed.get(&entry.id) {
+            Some(current) if *current == (note.clone(), tags.clone()) => continue,
+            Some(_) => db.execute("UPDATE texts SET note = ?2, tags = ?3 WHERE id = ?1", params![entry.id, note, tags])?,
+            None => db.execute("INSERT INTO texts (id, note, tags, text) VALUES (?1, ?2, ?3, '')", params![entry.id, note, tags])?,
+        };
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// Replace the recognized text of the entry `id`, adding its row if it has none yet
+fn set_text(db: &Connection, id: &str, text: &str) -> rusqlite::Result<()> {
+    let row: Option<i64> = db.query_row("SELECT rowid FROM texts WHERE id = ?1", [id], |row| row.get(0)).optional()?;
+    match row {
+        Some(rowid) => db.execute("UPDATE texts SET text = ?2 WHERE rowid = ?1", params![rowid, text])?,
+        None => db.execute("INSERT INTO texts (id, note, tags, text) VALUES (?1, '', '', ?2)", params![id, text])?,
+    };
+    Ok(())
+}
+
+fn index_error(e: rusqlite::Error) -> CaptureError {
+    CaptureError::Internal(format!("text index: {}", e))
+}
+
+fn text_index_path(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    Ok(history::history_dir(app)?.join(TEXT_INDEX_FILE))
+}
+
+/// FTS5 query for the words of `query`, each one quoted and matched as a prefix so "screen"
+/// also finds "screenshot". `None` if it has no words to look for.
+fn match_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// A snippet from SQLite, with the matches between `HIGHLIGHT_START` and `HIGHLIGHT_END`, in parts
+fn snippet_parts(marked: &str) -> Vec<SnippetPart> {
+    marked
+        .split([HIGHLIGHT_START, HIGHLIGHT_END])
+        .enumerate()
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(i, text)| SnippetPart { text: text.to_string(), highlight: i % 2 == 1 })
+        .collect()
+}
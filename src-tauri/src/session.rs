@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+use uuid::Uuid;
+use tracing::debug;
+use crate::error::CaptureError;
+use crate::idle;
+
+/// Lifecycle of a scroll capture.
+/// Idle → Selecting → Capturing ⇄ Stitching → Encoding → Done, with Failed reachable from anywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureState {
+    Idle,
+    Selecting,
+    Capturing,
+    Stitching,
+    Encoding,
+    Done,
+    Failed,
+}
+
+impl CaptureState {
+    /// A new capture may only start when nothing else is running
+    pub fn is_finished(self) -> bool {
+        matches!(self, CaptureState::Idle | CaptureState::Selecting | CaptureState::Done | CaptureState::Failed)
+    }
+
+    fn can_transition_to(self, to: CaptureState) -> bool {
+        use CaptureState::*;
+        match (self, to) {
+            (_, Failed) => true,
+            (from, Selecting) | (from, Capturing) if from.is_finished() => true,
+            (Selecting, Idle) | (Done, Idle) | (Failed, Idle) => true,
+            // Cancelling drops the running capture straight back to idle
+            (Capturing, Idle) | (Stitching, Idle) => true,
+            (Capturing, Stitching) | (Stitching, Capturing) => true,
+            (Capturing, Encoding) | (Stitching, Encoding) => true,
+            (Encoding, Done) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Payload of the `capture-state-changed` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateChange {
+    pub from: CaptureState,
+    pub to: CaptureState,
+    pub message: Option<String>,
+    /// Capture the change belongs to, `None` outside of a capture (e.g. region selection)
+    pub session_id: Option<String>,
+}
+
+/// Identity and control flags of one capture.
+/// Cloned into the capture task and the stop shortcut handler, so raising or checking
+/// a flag never has to wait for the session lock.
+#[derive(Debug, Clone)]
+pub struct SessionHandle {
+    pub id: String,
+    stop: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+    // Stitches to take back, counted so quick repeated presses all get applied
+    undo: Arc<AtomicU32>,
+    // Wakes the capture loop so it reacts to a request right away instead of on its next tick
+    wake: Arc<Notify>,
+}
+
+impl SessionHandle {
+    fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            stop: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
+            undo: Arc::new(AtomicU32::new(0)),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    pub fn request_cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub fn cancel_requested(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    pub fn request_undo(&self) {
+        self.undo.fetch_add(1, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    /// Number of undo requests since the last call
+    pub fn take_undo_requests(&self) -> u32 {
+        self.undo.swap(0, Ordering::SeqCst)
+    }
+
+    /// Resolves once stop, cancel or undo has been requested (immediately if it already was)
+    pub async fn requested(&self) {
+        self.wake.notified().await
+    }
+}
+
+struct CaptureSession {
+    state: CaptureState,
+    /// The most recent capture, kept after it finishes so late stop requests get a clear error
+    current: Option<SessionHandle>,
+}
+
+lazy_static! {
+    // Only one capture can run at a time, so a single session is enough
+    static ref SESSION: Mutex<CaptureSession> = Mutex::new(CaptureSession {
+        state: CaptureState::Idle,
+        current: None,
+    });
+}
+
+pub fn current_state() -> CaptureState {
+    SESSION.lock().unwrap().state
+}
+
+/// Move to `to`, emitting `capture-state-changed`. Invalid transitions are rejected.
+pub fn transition(app: &AppHandle, to: CaptureState) -> Result<(), CaptureError> {
+    transition_with_message(app, to, None)
+}
+
+fn transition_with_message(app: &AppHandle, to: CaptureState, message: Option<String>) -> Result<(), CaptureError> {
+    let (from, session_id) = {
+        let mut session = SESSION.lock().unwrap();
+        let from = session.state;
+        if !from.can_transition_to(to) {
+            return Err(CaptureError::InvalidState(format!("{:?} -> {:?}", from, to)));
+        }
+        session.state = to;
+        // Selecting belongs to the next capture, not the previous one
+        let session_id = match to {
+            CaptureState::Idle | CaptureState::Selecting => None,
+            _ => session.current.as_ref().map(|handle| handle.id.clone()),
+        };
+        (from, session_id)
+    };
+
+    idle::touch();
+    debug!("Capture state: {:?} -> {:?}", from, to);
+    let _ = app.emit("capture-state-changed", StateChange { from, to, message, session_id });
+    Ok(())
+}
+
+/// Claim the session for a new capture and hand out its handle with fresh flags.
+/// Checking and switching happens under one lock, so two concurrent `start_scroll_capture` calls can't both win.
+pub fn begin_capture(app: &AppHandle) -> Result<SessionHandle, CaptureError> {
+    let handle = SessionHandle::new();
+    let from = {
+        let mut session = SESSION.lock().unwrap();
+        if !session.state.is_finished() {
+            return Err(CaptureError::AlreadyRunning);
+        }
+        let from = session.state;
+        session.state = CaptureState::Capturing;
+        session.current = Some(handle.clone());
+        from
+    };
+
+    idle::touch();
+    debug!("Capture state: {:?} -> {:?} (session {})", from, CaptureState::Capturing, handle.id);
+    let _ = app.emit("capture-state-changed", StateChange {
+        from,
+        to: CaptureState::Capturing,
+        message: None,
+        session_id: Some(handle.id.clone()),
+    });
+    Ok(handle)
+}
+
+/// Mark the session as failed with a reason shown to the user
+pub fn fail(app: &AppHandle, message: String) {
+    let _ = transition_with_message(app, CaptureState::Failed, Some(message));
+}
+
+/// Handle of the capture with `session_id`, as long as it is still running.
+/// Requests for an older or unknown session are rejected instead of hitting whatever runs now.
+pub fn running_session(session_id: &str) -> Result<SessionHandle, CaptureError> {
+    let session = SESSION.lock().unwrap();
+    match &session.current {
+        Some(handle) if handle.id == session_id && !session.state.is_finished() => Ok(handle.clone()),
+        _ => Err(CaptureError::InvalidState(format!("no running capture with session id {}", session_id))),
+    }
+}
+
+/// Return to idle after a cancelled capture
+pub fn finish_cancel(app: &AppHandle) -> Result<(), CaptureError> {
+    transition(app, CaptureState::Idle)
+}
+
+#[tauri::command]
+pub fn get_capture_state() -> CaptureState {
+    current_state()
+}
+
+/// Called by the frontend when the selection overlay opens (`entering = true`) or is dismissed
+#[tauri::command]
+pub fn set_region_selection(app: AppHandle, entering: bool) -> Result<(), CaptureError> {
+    let to = if entering { CaptureState::Selecting } else { CaptureState::Idle };
+    transition(&app, to)
+}
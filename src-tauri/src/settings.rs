@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use crate::autosave::AutosaveSettings;
+use crate::autostart::{self, AutostartSettings};
+use crate::baseline::BaselineSettings;
+use crate::canvas::MemorySettings;
+use crate::capture::CaptureOptions;
+use crate::clipboard::ClipboardSettings;
+use crate::composite::CompositeOptions;
+use crate::decorate::ExportOptions;
+use crate::encryption::EncryptionSettings;
+use crate::error::CaptureError;
+use crate::history;
+use crate::hook::HookConfig;
+use crate::hotkeys;
+use crate::idle::IdleSettings;
+use crate::logging::{self, LogLevel};
+use crate::notify::NotificationSettings;
+use crate::pipeline::PipelineStep;
+use crate::privacy::PrivacySettings;
+use crate::retention::RetentionSettings;
+use crate::script::ScriptConfig;
+use crate::search::SearchSettings;
+use crate::sound::SoundSettings;
+use crate::updater::UpdateSettings;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User settings persisted as JSON in the app config directory.
+/// Every field has a default so older settings files keep loading.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub capture: CaptureOptions,
+    pub post_capture_hook: Option<HookConfig>,
+    /// Global accelerator for `capture_last_region`, e.g. "CommandOrControl+Shift+R"
+    pub repeat_capture_shortcut: Option<String>,
+    /// Trim uniform-color margins (window chrome, empty gutters) from finished captures
+    pub autocrop: bool,
+    /// Watermark and caption strip added when saving or copying
+    pub export: ExportOptions,
+    /// Thresholds for `compare_to_baseline`
+    pub baseline: BaselineSettings,
+    /// Save every captured fragment and the stitch decisions to `<app data>/debug/<session id>/`
+    pub debug_dump: bool,
+    /// OS notifications when a capture finishes or fails
+    pub notifications: NotificationSettings,
+    /// Shutter, tick and chime sounds during a capture
+    pub sound: SoundSettings,
+    /// Program for `open_with`, a name on the PATH like "gimp" or the full path to one.
+    /// The system's default app for PNGs when unset.
+    pub external_editor: Option<String>,
+    /// Retries while the clipboard is locked, and keeping copies alive on Linux
+    pub clipboard: ClipboardSettings,
+    /// Checkpoints of running captures for `recover_session`
+    pub autosave: AutosaveSettings,
+    /// Level of the console and log file output, see `get_recent_logs`
+    pub log_level: LogLevel,
+    /// Alignment and padding of fragments that don't have the capture's width
+    pub composite: CompositeOptions,
+    /// How much of a capture is kept in memory, and what happens past that
+    pub memory: MemorySettings,
+    /// Windows that must not end up in a capture unnoticed, checked before every scroll capture
+    pub privacy: PrivacySettings,
+    /// Whether history and autosave files are encrypted on disk
+    pub encryption: EncryptionSettings,
+    /// Steps run on every new capture before it is shown, e.g. crop, redact, watermark and export
+    pub pipeline: Vec<PipelineStep>,
+    /// User scripts run on captures at the post-stitch and pre-export hook points
+    pub scripts: Vec<ScriptConfig>,
+    /// Saved options per exporter name, used by `export_capture` calls that bring none
+    pub exporters: HashMap<String, serde_json::Value>,
+    /// Text recognition of history entries for `search_history`
+    pub search: SearchSettings,
+    /// How many history entries are kept, by count, age and disk space
+    pub retention: RetentionSettings,
+    /// Start at login, and whether to start to the tray
+    pub autostart: AutostartSettings,
+    /// Low-power mode after a while without captures
+    pub idle: IdleSettings,
+    /// Release channel and update checks
+    pub updates: UpdateSettings,
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Settings> = Mutex::new(Settings::default());
+}
+
+/// Snapshot of the current settings
+pub fn current() -> Settings {
+    SETTINGS.lock().unwrap().clone()
+}
+
+/// Load settings from disk, called once from `setup`.
+/// A missing or broken file just leaves the defaults in place.
+pub fn load(app: &AppHandle) {
+    let Some(path) = settings_path(app) else {
+        return;
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<Settings>(&content) {
+            Ok(settings) => {
+                info!("Loaded settings from {}", path.display());
+                logging::apply_level(settings.log_level);
+                *SETTINGS.lock().unwrap() = settings;
+            }
+            Err(e) => warn!("Ignoring invalid settings file {}: {}", path.display(), e),
+        },
+        Err(_) => info!("No settings file found, using defaults"),
+    }
+}
+
+fn settings_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_config_dir().ok().map(|dir| dir.join(SETTINGS_FILE))
+}
+
+#[tauri::command]
+pub fn get_settings() -> Settings {
+    current()
+}
+
+/// Save and apply `settings`. Turning encryption on encrypts the whole history first, so this
+/// runs on the blocking pool.
+#[tauri::command]
+pub async fn set_settings(app: AppHandle, settings: Settings) -> Result<(), CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || save(&app, settings))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("settings task failed: {}", e)))?
+}
+
+/// `set_settings` on the calling thread
+pub fn save(app: &AppHandle, settings: Settings) -> Result<(), CaptureError> {
+    let path = settings_path(app)
+        .ok_or_else(|| CaptureError::Internal("Could not resolve app config directory".to_string()))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Once it's on, unencrypted history files are refused
+    if settings.encryption.enabled && !current().encryption.enabled {
+        history::encrypt_all(app)?;
+    }
+
+    let content = serde_json::to_string_pretty(&settings)
+        .map_err(|e| CaptureError::Internal(e.to_string()))?;
+    fs::write(&path, content)?;
+
+    logging::apply_level(settings.log_level);
+    *SETTINGS.lock().unwrap() = settings;
+    hotkeys::apply(app);
+    autostart::apply();
+    Ok(())
+}
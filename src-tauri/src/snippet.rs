@@ -0,0 +1,86 @@
+use serde::Deserialize;
+use tauri::AppHandle;
+use tracing::info;
+use crate::clipboard;
+use crate::error::CaptureError;
+use crate::store;
+use crate::upload::{self, UploadTarget};
+use crate::utils;
+
+/// Where the image referenced by a snippet lives.
+/// The frontend sends this as `{ "type": "file", "path": ... }` or `{ "type": "upload", "target": ... }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SnippetSource {
+    /// Save like `save_image` (never replacing an existing file) and link the path
+    File { path: String },
+    /// Upload and link the share URL
+    Upload { target: UploadTarget },
+}
+
+/// Save or upload the capture `id` and copy `![alt](url)` for pasting into wikis and READMEs.
+/// Returns the snippet.
+#[tauri::command]
+pub async fn copy_as_markdown(
+    app: AppHandle,
+    id: String,
+    source: SnippetSource,
+    alt: Option<String>,
+) -> Result<String, CaptureError> {
+    let (url, _) = publish(app, id, source).await?;
+    copy(markdown(&url, &alt.unwrap_or_default()))
+}
+
+/// Same as `copy_as_markdown`, but copies an `<img>` tag with the image size
+#[tauri::command]
+pub async fn copy_as_html(
+    app: AppHandle,
+    id: String,
+    source: SnippetSource,
+    alt: Option<String>,
+) -> Result<String, CaptureError> {
+    let (url, (width, height)) = publish(app, id, source).await?;
+    copy(html(&url, &alt.unwrap_or_default(), width, height))
+}
+
+/// The URL to link and the image size, (0, 0) if that can't be read.
+/// Saved files are measured after saving, the export options may have resized them.
+async fn publish(app: AppHandle, id: String, source: SnippetSource) -> Result<(String, (u32, u32)), CaptureError> {
+    match source {
+        SnippetSource::File { path } => {
+            let path = utils::save_image(app, id, path, Some(true), None).await?;
+            let size = image::image_dimensions(&path).unwrap_or((0, 0));
+            // Markdown and HTML both want forward slashes, Windows accepts them too
+            Ok((path.replace('\\', "/"), size))
+        }
+        SnippetSource::Upload { target } => {
+            let (png, size) = store::edit(id, |canvas| Ok((canvas.encode_png(|_| {})?, (canvas.width(), canvas.height())))).await?;
+            Ok((upload::upload(png, target).await?, size))
+        }
+    }
+}
+
+fn copy(snippet: String) -> Result<String, CaptureError> {
+    clipboard::set_text(snippet.clone())?;
+    info!("Copied snippet {}", snippet);
+    Ok(snippet)
+}
+
+fn markdown(url: &str, alt: &str) -> String {
+    let alt = alt.replace('[', "\\[").replace(']', "\\]");
+    // Angle brackets keep local paths with spaces or parentheses in one link
+    if url.contains([' ', '(', ')']) {
+        format!("![{}](<{}>)", alt, url)
+    } else {
+        format!("![{}]({})", alt, url)
+    }
+}
+
+fn html(url: &str, alt: &str, width: u32, height: u32) -> String {
+    let size = if width > 0 && height > 0 { format!(" width=\"{}\" height=\"{}\"", width, height) } else { String::new() };
+    format!("<img src=\"{}\" alt=\"{}\"{}>", escape_attribute(url), escape_attribute(alt), size)
+}
+
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
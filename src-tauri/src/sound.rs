@@ -0,0 +1,113 @@
+use rodio::source::{SineWave, Source};
+use rodio::{OutputStream, Sink};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use lazy_static::lazy_static;
+use tracing::warn;
+use crate::settings;
+
+/// Audio feedback during a capture. Off by default; useful when the captured content
+/// fills the screen and there is nothing else showing that a stitch happened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundSettings {
+    pub enabled: bool,
+    /// 0.0 to 1.0
+    pub volume: f32,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self { enabled: false, volume: 0.5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Cue {
+    /// Capture started
+    Shutter,
+    /// A fragment was stitched
+    Tick,
+    /// Capture finished
+    Chime,
+}
+
+impl Cue {
+    /// Tones played one after the other, (frequency in Hz, length in ms).
+    /// Generated instead of shipping sound files.
+    fn tones(self) -> &'static [(f32, u64)] {
+        match self {
+            Cue::Shutter => &[(1800.0, 30), (1200.0, 45)],
+            Cue::Tick => &[(2200.0, 15)],
+            Cue::Chime => &[(660.0, 120), (880.0, 220)],
+        }
+    }
+}
+
+lazy_static! {
+    // The output stream can't leave the thread it was opened on, so one thread owns it
+    // and plays whatever is sent here. Started with the first cue.
+    static ref PLAYER: Mutex<Option<Sender<(Cue, f32)>>> = Mutex::new(None);
+}
+
+/// Play `cue` if sounds are enabled. Never blocks and never fails, a missing audio
+/// device only means silence.
+pub fn play(cue: Cue) {
+    let sound = settings::current().sound;
+    if !sound.enabled {
+        return;
+    }
+
+    let mut player = PLAYER.lock().unwrap();
+    let sender = player.get_or_insert_with(start_player);
+    if sender.send((cue, sound.volume.clamp(0.0, 1.0))).is_err() {
+        // The player thread gave up (no output device), don't try again
+        warn!("No audio output, skipping sound cue {:?}", cue);
+    }
+}
+
+/// Close the audio output, it keeps the device (and on laptops the audio chip) awake.
+/// The next cue opens it again.
+pub fn release() {
+    // The player thread ends once its channel is closed
+    PLAYER.lock().unwrap().take();
+}
+
+fn start_player() -> Sender<(Cue, f32)> {
+    let (sender, receiver) = mpsc::channel::<(Cue, f32)>();
+    thread::spawn(move || {
+        let (_stream, handle) = match OutputStream::try_default() {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("Could not open audio output: {}", e);
+                return;
+            }
+        };
+
+        for (cue, volume) in receiver {
+            let sink = match Sink::try_new(&handle) {
+                Ok(sink) => sink,
+                Err(e) => {
+                    warn!("Could not play sound cue {:?}: {}", cue, e);
+                    continue;
+                }
+            };
+            for &(frequency, millis) in cue.tones() {
+                let duration = Duration::from_millis(millis);
+                // Short fade in so the tones don't click
+                sink.append(
+                    SineWave::new(frequency)
+                        .take_duration(duration)
+                        .fade_in(Duration::from_millis(5))
+                        .amplify(volume * 0.3),
+                );
+            }
+            // Keeps playing after the sink is dropped, cues may overlap
+            sink.detach();
+        }
+    });
+    sender
+}
@@ -0,0 +1,152 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+use crate::capture::StopReason;
+use crate::error::CaptureError;
+use crate::history;
+
+/// One line per scroll capture, in the app data folder
+const SESSIONS_FILE: &str = "sessions.jsonl";
+/// Past this the log is cut to its newer half, a few thousand captures
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// How one scroll capture went, appended to the session log when it ends
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecord {
+    pub session_id: String,
+    /// RFC 3339 local time
+    pub finished_at: String,
+    /// `None` when the capture ended with an error instead
+    pub stop_reason: Option<StopReason>,
+    /// Error code that ended or interrupted the capture, see `CaptureError::code`
+    pub error: Option<String>,
+    pub frames: u32,
+    pub stitches: u32,
+    /// Frames dropped for looking mid-repaint
+    pub torn: u32,
+    /// Frames that didn't overlap the canvas when they came in
+    pub unmatched: u32,
+    /// Unmatched fragments that never connected and were left out
+    pub discarded: u32,
+    /// Size of the stitched image, 0 when there is none
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SessionRecord {
+    pub fn new(session_id: &str) -> Self {
+        Self { session_id: session_id.to_string(), ..Default::default() }
+    }
+}
+
+/// Result of `get_stats`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureStats {
+    /// Entries in the history
+    pub total_captures: usize,
+    /// Width × height over all history entries
+    pub total_pixels: u64,
+    pub average_height: f64,
+    /// History entries by what produced them, e.g. "capture" or "schedule:<name>"
+    pub captures_by_source: BTreeMap<String, usize>,
+    /// Scroll captures in the session log
+    pub sessions: usize,
+    /// Width × height of the images scroll captures stitched together
+    pub stitched_pixels: u64,
+    pub average_stitches: f64,
+    pub stop_reasons: BTreeMap<StopReason, usize>,
+    /// Error codes that ended or interrupted scroll captures
+    pub failure_reasons: BTreeMap<String, usize>,
+    pub torn_frames: u64,
+    pub unmatched_frames: u64,
+    pub discarded_fragments: u64,
+}
+
+/// Append `record` to the session log. Failing only costs the statistics, so it's just logged.
+pub fn record(app: &AppHandle, mut record: SessionRecord) {
+    record.finished_at = Local::now().to_rfc3339();
+    if let Err(e) = append(app, &record) {
+        warn!("Could not write the session log: {}", e);
+    }
+}
+
+/// Totals over the history and the session log, for a statistics page. Shows e.g. whether
+/// captures of some app keep getting torn frames or never reach the end.
+#[tauri::command]
+pub async fn get_stats(app: AppHandle) -> Result<CaptureStats, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || collect(&app))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("stats task failed: {}", e)))?
+}
+
+fn collect(app: &AppHandle) -> Result<CaptureStats, CaptureError> {
+    let mut stats = CaptureStats::default();
+
+    let entries = history::list_history(app.clone(), None, None)?;
+    stats.total_captures = entries.len();
+    for entry in &entries {
+        stats.total_pixels += entry.width as u64 * entry.height as u64;
+        *stats.captures_by_source.entry(entry.source.clone()).or_default() += 1;
+    }
+    if !entries.is_empty() {
+        stats.average_height = entries.iter().map(|entry| entry.height as f64).sum::<f64>() / entries.len() as f64;
+    }
+
+    let sessions = read_log(app)?;
+    stats.sessions = sessions.len();
+    for session in &sessions {
+        stats.stitched_pixels += session.width as u64 * session.height as u64;
+        if let Some(reason) = session.stop_reason {
+            *stats.stop_reasons.entry(reason).or_default() += 1;
+        }
+        if let Some(error) = &session.error {
+            *stats.failure_reasons.entry(error.clone()).or_default() += 1;
+        }
+        stats.torn_frames += session.torn as u64;
+        stats.unmatched_frames += session.unmatched as u64;
+        stats.discarded_fragments += session.discarded as u64;
+    }
+    if !sessions.is_empty() {
+        stats.average_stitches = sessions.iter().map(|session| session.stitches as f64).sum::<f64>() / sessions.len() as f64;
+    }
+    Ok(stats)
+}
+
+fn append(app: &AppHandle, record: &SessionRecord) -> Result<(), CaptureError> {
+    let path = log_path(app)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(&path).is_ok_and(|meta| meta.len() > MAX_LOG_BYTES) {
+        let content = fs::read_to_string(&path)?;
+        let lines: Vec<&str> = content.lines().collect();
+        fs::write(&path, lines[lines.len() / 2..].join("\n") + "\n")?;
+    }
+    let line = serde_json::to_string(record).map_err(|e| CaptureError::Internal(e.to_string()))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+fn read_log(app: &AppHandle) -> Result<Vec<SessionRecord>, CaptureError> {
+    match fs::read_to_string(log_path(app)?) {
+        // A line cut off by a crash shouldn't hide all the others
+        Ok(content) => Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn log_path(app: &AppHandle) -> Result<PathBuf, CaptureError> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(SESSIONS_FILE))
+        .map_err(|e| CaptureError::Internal(format!("Could not resolve app data directory: {}", e)))
+}
@@ -1,29 +1,211 @@
-use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use tracing::{debug, warn};
+
+/// Downsampling factors of the overlap search pyramid, coarsest first.
+/// All of them are multiples of `PYRAMID_BASE`.
+const PYRAMID_FACTORS: [u32; 2] = [16, 4];
+
+/// Finest pyramid level, the column groups the others are built from
+const PYRAMID_BASE: u32 = 4;
+
+/// Best offsets of one pyramid level looked at more closely on the next
+const PYRAMID_CANDIDATES: usize = 4;
+
+/// Change of a channel's mean or spread, in 0-255 levels, that counts as a brightness shift
+const NORMALIZE_MIN_SHIFT: f32 = 6.0;
+
+/// Every n-th pixel goes into the color statistics of a frame
+const NORMALIZE_SAMPLE_STEP: usize = 7;
+
+/// How far down the new fragment `calculate_overlap` looks for the end of the previous one
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlapSearch {
+    /// Share of the fragment height searched first, 1 to 100
+    pub scan_depth_percent: u32,
+    /// Search the rest of the fragment before giving up. Small scroll steps leave the previous
+    /// fragment's bottom rows far down in the new one, below a shallow scan depth.
+    pub full_height_fallback: bool,
+    /// When nothing matches and the new fragment is brighter or darker overall, match its
+    /// colors to the previous one and search again. For pages fading in or switching between
+    /// dark and light themes mid-capture, which otherwise never match within the tolerance.
+    pub normalize_brightness: bool,
+}
+
+impl Default for OverlapSearch {
+    fn default() -> Self {
+        Self { scan_depth_percent: 50, full_height_fallback: true, normalize_brightness: true }
+    }
+}
+
+/// Calculate the overlap height between two images with the default search
+pub fn calculate_overlap(prev_img: &DynamicImage, curr_img: &DynamicImage) -> u32 {
+    calculate_overlap_with(prev_img, curr_img, OverlapSearch::default())
+}
 
 /// Calculate the overlap height between two images
 /// prev_img: The previous screenshot (we look at the bottom of this)
 /// curr_img: The new screenshot (we look at the top of this)
 /// Returns: The Y-coordinate in `curr_img` where the content starts to *differ* from `prev_img` bottom.
 ///          Effectively, this is the height of the overlapping region in `curr_img`.
-pub fn calculate_overlap(prev_img: &DynamicImage, curr_img: &DynamicImage) -> u32 {
+pub fn calculate_overlap_with(prev_img: &DynamicImage, curr_img: &DynamicImage, search: OverlapSearch) -> u32 {
+    let overlap = search_overlap(prev_img, curr_img, search);
+    if overlap > 0 || !search.normalize_brightness {
+        return overlap;
+    }
+
+    for normalized in normalized_candidates(prev_img, curr_img) {
+        let overlap = search_overlap(prev_img, &normalized, search);
+        // The estimated colors are only roughly right, which on sparse pages lets empty areas
+        // pass the block comparison. Only accept the match if the content matches as well.
+        if overlap > 0 && content_matches(prev_img, curr_img, overlap) {
+            debug!("Stitch Match: found after normalizing brightness, overlap height={}", overlap);
+            return overlap;
+        }
+    }
+    0
+}
+
+/// Whether the overlap found on a brightness-normalized frame holds up: with the color mapping
+/// fitted on the overlapping rows themselves, nearly all pixels that aren't background in
+/// `prev_img` have to match. Overlaps without enough such pixels can't be told apart from
+/// any other offset and don't count.
+fn content_matches(prev_img: &DynamicImage, curr_img: &DynamicImage, overlap: u32) -> bool {
+    let pairs = overlap_pairs(prev_img, curr_img, overlap, 2);
+    let Some(fit) = ColorFit::new(&pairs) else {
+        return false;
+    };
+
+    // Background is the most common brightness in the previous frame's rows
+    let mut histogram = [0u32; 256];
+    for (p, _) in &pairs {
+        histogram[luminance(*p).clamp(0, 255) as usize] += 1;
+    }
+    let background = (0..256).max_by_key(|&i| histogram[i]).unwrap_or(0) as i32;
+
+    let (mut content, mut content_matched, mut mismatched) = (0usize, 0usize, 0usize);
+    for &(p, q) in &pairs {
+        let matched = pixels_are_similar(p, fit.apply(q), 12);
+        if !matched {
+            mismatched += 1;
+        }
+        if (luminance(p) - background).abs() > 24 {
+            content += 1;
+            if matched {
+                content_matched += 1;
+            }
+        }
+    }
+    content >= (pairs.len() / 500).max(32)
+        && content_matched * 10 >= content * 9
+        && mismatched * 50 <= pairs.len()
+}
+
+/// Every `step`-th pixel of the overlapping rows, from `prev_img` and `curr_img`
+fn overlap_pairs(prev_img: &DynamicImage, curr_img: &DynamicImage, overlap: u32, step: usize) -> Vec<(Rgba<u8>, Rgba<u8>)> {
+    let width = prev_img.width().min(curr_img.width());
+    let overlap = overlap.min(prev_img.height()).min(curr_img.height());
+    let start = prev_img.height() - overlap;
+    let (prev, curr) = (rgba(prev_img), rgba(curr_img));
+    let mut pairs = Vec::new();
+    for y in (0..overlap).step_by(step) {
+        for x in (0..width).step_by(step) {
+            pairs.push((*prev.get_pixel(x, start + y), *curr.get_pixel(x, y)));
+        }
+    }
+    pairs
+}
+
+/// Per-channel `gain * value + offset` that maps colors of a later frame onto an earlier one,
+/// least squares fitted on pixel pairs that show the same content
+struct ColorFit([(f32, f32); 3]);
+
+impl ColorFit {
+    fn new(pairs: &[(Rgba<u8>, Rgba<u8>)]) -> Option<Self> {
+        if pairs.is_empty() {
+            return None;
+        }
+        let n = pairs.len() as f64;
+        let mut fit = [(0f32, 0f32); 3];
+        for (c, channel) in fit.iter_mut().enumerate() {
+            let (mut sum_p, mut sum_q, mut sum_qq, mut sum_qp) = (0f64, 0f64, 0f64, 0f64);
+            for (p, q) in pairs {
+                let (p, q) = (p[c] as f64, q[c] as f64);
+                sum_p += p;
+                sum_q += q;
+                sum_qq += q * q;
+                sum_qp += q * p;
+            }
+            let (mean_p, mean_q) = (sum_p / n, sum_q / n);
+            let variance = sum_qq / n - mean_q * mean_q;
+            // A flat channel only gets an offset
+            let gain = if variance < 1.0 { 0.0 } else { (sum_qp / n - mean_q * mean_p) / variance };
+            *channel = (gain as f32, (mean_p - gain * mean_q) as f32);
+        }
+        Some(Self(fit))
+    }
+
+    fn apply(&self, pixel: Rgba<u8>) -> Rgba<u8> {
+        let mut out = pixel;
+        for c in 0..3 {
+            let (gain, offset) = self.0[c];
+            out[c] = (pixel[c] as f32 * gain + offset).round().clamp(0.0, 255.0) as u8;
+        }
+        out
+    }
+}
+
+fn search_overlap(prev_img: &DynamicImage, curr_img: &DynamicImage, search: OverlapSearch) -> u32 {
     let width = prev_img.width();
     let prev_height = prev_img.height();
     let curr_height = curr_img.height();
-    
-    // We only scan the top 50% of the new image to find where the previous image ended.
-    // If the user scrolled more than a screen height, we can't stitch anyway.
-    let scan_depth = (curr_height / 2).min(prev_height / 2);
 
     // We use a large block for signature matching to avoid false positives with repeated patterns (like code lines).
     // Let's use the bottom 20% of the previous image, or at least 50 pixels.
     let signature_height = (prev_height / 5).max(50).min(prev_height);
     let signature_start_y = prev_height - signature_height;
-    
-    // Safety check
-    if width == 0 || prev_height == 0 || curr_height == 0 {
+
+    // Safety check, rows are compared pixel by pixel so the widths have to agree
+    if width == 0 || width != curr_img.width() || prev_height == 0 || curr_height == 0 || signature_height > curr_height {
         return 0;
     }
 
+    // First look at the top `scan_depth_percent` of the new image to find where the previous image ended,
+    // then optionally at every other offset that still fits the signature block.
+    let percent = search.scan_depth_percent.clamp(1, 100);
+    let scan_depth = (curr_height * percent / 100).min(prev_height * percent / 100);
+    let max_offset = curr_height - signature_height;
+    let mut ranges = Vec::with_capacity(2);
+    ranges.push(0..scan_depth.min(max_offset + 1));
+    if search.full_height_fallback && scan_depth <= max_offset {
+        ranges.push(scan_depth..max_offset + 1);
+    }
+
+    // Coarse-to-fine first, most of the work happens at 1/16 and 1/4 of the resolution.
+    // The full scan below only runs if that finds nothing.
+    if let Some(overlap) = pyramid_overlap(prev_img, curr_img, signature_start_y, signature_height, &ranges) {
+        debug!("Stitch Match (pyramid): overlap height={}", overlap);
+        return overlap;
+    }
+
+    full_scan_overlap(prev_img, curr_img, signature_start_y, signature_height, &ranges).unwrap_or(0)
+}
+
+/// Check every offset in `ranges` for the signature block of `prev_img`, the first one that
+/// matches wins. Returns the overlap height.
+fn full_scan_overlap(
+    prev_img: &DynamicImage,
+    curr_img: &DynamicImage,
+    signature_start_y: u32,
+    signature_height: u32,
+    ranges: &[Range<u32>],
+) -> Option<u32> {
+    let width = prev_img.width();
+
     // Optimization: Instead of checking every pixel, we check a grid.
     // If a candidate row matches, we do a full verify.
     
@@ -39,103 +221,918 @@ pub fn calculate_overlap(prev_img: &DynamicImage, curr_img: &DynamicImage) -> u3
     // The new content starts at `y + signature_height`.
     
     // We iterate `y` representing the top-shift of the signature in the new image.
-    for y in 0..scan_depth {
-        // If the signature block would go out of bounds in curr_img, stop.
-        if y + signature_height > curr_height {
-            break;
-        }
-        
-        // Fast check: Compare the first, middle, and last row of the signature block
-        if check_row_match(prev_img, signature_start_y, curr_img, y, width) &&
-           check_row_match(prev_img, signature_start_y + signature_height / 2, curr_img, y + signature_height / 2, width) &&
-           check_row_match(prev_img, signature_start_y + signature_height - 1, curr_img, y + signature_height - 1, width) 
+    let (prev, curr) = (rgba(prev_img), rgba(curr_img));
+    for (pass, range) in ranges.iter().enumerate() {
+        for y in range.clone() {
+            // Fast check: Compare the first, middle, and last row of the signature block
+            if check_row_match(&prev, signature_start_y, &curr, y, width) &&
+               check_row_match(&prev, signature_start_y + signature_height / 2, &curr, y + signature_height / 2, width) &&
+               check_row_match(&prev, signature_start_y + signature_height - 1, &curr, y + signature_height - 1, width) 
+            {
+                // Potential match found, do strict full block comparison
+                if compare_blocks_strict(&prev, signature_start_y, &curr, y, width, signature_height) {
+                    if pass > 0 {
+                        debug!("Stitch Match: found below the scan depth of {}px", range.start);
+                    }
+                    debug!("Stitch Match: Found overlap at y={}, overlap height={}", y, y + signature_height);
+                    return Some(y + signature_height);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Find where the signature block of `prev_img` sits in `curr_img` on downsampled copies first,
+/// coarsest level first, and only look at full resolution in a narrow window around the best
+/// candidates. `ranges` are the offsets of the signature's top in `curr_img`, searched one after
+/// the other. Matches are verified exactly like in the full scan. `None` if no candidate
+/// verified, or the images are too small for the pyramid to help.
+fn pyramid_overlap(
+    prev_img: &DynamicImage,
+    curr_img: &DynamicImage,
+    signature_start_y: u32,
+    signature_height: u32,
+    ranges: &[Range<u32>],
+) -> Option<u32> {
+    let width = prev_img.width();
+    if curr_img.width() != width || curr_img.height() < signature_height {
+        return None;
+    }
+    let factors: Vec<u32> = PYRAMID_FACTORS
+        .iter()
+        .copied()
+        .filter(|&factor| signature_height / factor >= 4 && width / factor >= 8)
+        .collect();
+    if factors.is_empty() {
+        return None;
+    }
+
+    // With a GPU every offset is scored at the finest level in one go, no coarse pass needed
+    #[cfg(feature = "gpu")]
+    if let Some(scores) = crate::gpu::offset_scores(&rgba(prev_img), &rgba(curr_img), signature_start_y, signature_height) {
+        return ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .find_map(|range| {
+                let mut scored: Vec<(u64, u32)> = range.clone().map(|offset| (scores[offset as usize] as u64, offset)).collect();
+                scored.sort_unstable();
+                let candidates = best_separated(&scored, PYRAMID_BASE);
+                verify_candidates(prev_img, curr_img, signature_start_y, signature_height, &candidates, PYRAMID_BASE, range)
+            })
+            .map(|y| y + signature_height);
+    }
+
+    // Only the signature rows of the previous frame are ever compared
+    let prev_sums = BoxSums::new(&rgba(prev_img), signature_start_y..signature_start_y + signature_height);
+    let curr_sums = BoxSums::new(&rgba(curr_img), 0..curr_img.height());
+    let levels: Vec<(u32, BoxRows, BoxRows)> = factors
+        .into_iter()
+        .map(|factor| (factor, prev_sums.level(factor), curr_sums.level(factor)))
+        .collect();
+
+    ranges.iter().filter(|range| !range.is_empty()).find_map(|range| {
+        let last = range.end - 1;
+        let mut candidates: Option<(Vec<u32>, u32)> = None;
+        for (factor, prev_boxes, curr_boxes) in &levels {
+            let rows = signature_height / factor;
+
+            // Boxes start at every row, so candidates are off by a few rows at most.
+            // A quarter of a coarse box either way covers that.
+            let offsets: BTreeSet<u32> = match &candidates {
+                None => range.clone().collect(),
+                Some((previous, previous_factor)) => previous
+                    .iter()
+                    .flat_map(|&y| y.saturating_sub(previous_factor / 4).max(range.start)..=(y + previous_factor / 4).min(last))
+                    .collect(),
+            };
+            let mut scored: Vec<(u64, u32)> = offsets
+                .into_iter()
+                .filter_map(|offset| Some((prev_boxes.distance(0, curr_boxes, offset, rows)?, offset)))
+                .collect();
+            // Ties keep the smaller offset first, like the full scan
+            scored.sort_unstable();
+            candidates = Some((best_separated(&scored, *factor), *factor));
+        }
+
+        let (candidates, factor) = candidates?;
+        verify_candidates(prev_img, curr_img, signature_start_y, signature_height, &candidates, factor, range)
+    })
+    .map(|y| y + signature_height)
+}
+
+/// First offset within `factor` rows of one of the `candidates` where the signature block
+/// matches at full resolution, checked like in the full scan
+fn verify_candidates(
+    prev_img: &DynamicImage,
+    curr_img: &DynamicImage,
+    signature_start_y: u32,
+    signature_height: u32,
+    candidates: &[u32],
+    factor: u32,
+    range: &Range<u32>,
+) -> Option<u32> {
+    let width = prev_img.width();
+    let last = range.end - 1;
+    let window: BTreeSet<u32> = candidates
+        .iter()
+        .flat_map(|&y| y.saturating_sub(factor).max(range.start)..=(y + factor).min(last))
+        .collect();
+    let (prev, curr) = (rgba(prev_img), rgba(curr_img));
+    window.into_iter().find(|&y| {
+        check_row_match(&prev, signature_start_y, &curr, y, width)
+            && check_row_match(&prev, signature_start_y + signature_height - 1, &curr, y + signature_height - 1, width)
+            && compare_blocks_strict(&prev, signature_start_y, &curr, y, width, signature_height)
+    })
+}
+
+/// `curr_img` with its colors mapped onto those of `prev_img`, for frames whose brightness
+/// shifted as a whole (a fade, a theme switch). Each channel is stretched so its mean and
+/// spread match `prev_img`. A second candidate maps it inverted, for a switch between dark
+/// and light themes. Empty if the brightness didn't shift noticeably.
+fn normalized_candidates(prev_img: &DynamicImage, curr_img: &DynamicImage) -> Vec<DynamicImage> {
+    let prev_stats = channel_stats(prev_img);
+    let curr_stats = channel_stats(curr_img);
+    if !stats_shifted(&prev_stats, &curr_stats) {
+        return Vec::new();
+    }
+
+    let map = |inverted: bool| {
+        let mut lut = [[0u8; 256]; 3];
+        for (c, table) in lut.iter_mut().enumerate() {
+            let (prev_mean, prev_spread) = prev_stats[c];
+            let (curr_mean, curr_spread) = curr_stats[c];
+            let gain = if curr_spread < 1.0 { 0.0 } else { prev_spread / curr_spread };
+            let gain = if inverted { -gain } else { gain };
+            for (value, mapped) in table.iter_mut().enumerate() {
+                *mapped = ((value as f32 - curr_mean) * gain + prev_mean).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        let mut img = rgba(curr_img).into_owned();
+        for pixel in img.pixels_mut() {
+            for c in 0..3 {
+                pixel[c] = lut[c][pixel[c] as usize];
+            }
+        }
+        DynamicImage::ImageRgba8(img)
+    };
+
+    let mut candidates = vec![map(false)];
+    // Light content turned dark or the other way round
+    let mid = |stats: &[(f32, f32); 3]| stats.iter().map(|&(mean, _)| mean).sum::<f32>() / 3.0 - 127.5;
+    if mid(&prev_stats) * mid(&curr_stats) < 0.0 {
+        candidates.push(map(true));
+    }
+    candidates
+}
+
+/// Whether the colors of `curr_img` shifted as a whole against `prev_img`, see `normalized_candidates`
+fn brightness_shifted(prev_img: &DynamicImage, curr_img: &DynamicImage) -> bool {
+    stats_shifted(&channel_stats(prev_img), &channel_stats(curr_img))
+}
+
+fn stats_shifted(prev: &[(f32, f32); 3], curr: &[(f32, f32); 3]) -> bool {
+    (0..3).any(|c| (prev[c].0 - curr[c].0).abs() >= NORMALIZE_MIN_SHIFT || (prev[c].1 - curr[c].1).abs() >= NORMALIZE_MIN_SHIFT)
+}
+
+/// Mean and standard deviation of each color channel, over a sample of the pixels
+fn channel_stats(img: &DynamicImage) -> [(f32, f32); 3] {
+    let img = rgba(img);
+    let (mut sum, mut squares, mut count) = ([0f64; 3], [0f64; 3], 0f64);
+    for pixel in img.pixels().step_by(NORMALIZE_SAMPLE_STEP) {
+        for c in 0..3 {
+            let value = pixel[c] as f64;
+            sum[c] += value;
+            squares[c] += value * value;
+        }
+        count += 1.0;
+    }
+    let count = count.max(1.0);
+    let stats = |c: usize| {
+        let mean = sum[c] / count;
+        ((mean) as f32, (squares[c] / count - mean * mean).max(0.0).sqrt() as f32)
+    };
+    [stats(0), stats(1), stats(2)]
+}
+
+/// The `PYRAMID_CANDIDATES` cheapest offsets at least `spacing` apart. Neighbours of a good offset
+/// score almost as well, without the spacing they would crowd out the other candidates.
+fn best_separated(scored: &[(u64, u32)], spacing: u32) -> Vec<u32> {
+    let mut best: Vec<u32> = Vec::with_capacity(PYRAMID_CANDIDATES);
+    for &(_, offset) in scored {
+        if best.iter().all(|&chosen| chosen.abs_diff(offset) >= spacing) {
+            best.push(offset);
+            if best.len() == PYRAMID_CANDIDATES {
+                break;
+            }
+        }
+    }
+    best
+}
+
+/// Brightness (r + 2g + b) of some rows, summed over groups of `PYRAMID_BASE` columns and
+/// accumulated down the rows. Any box of whole groups starting at any row is then two lookups
+/// per group, so a pyramid level doesn't depend on how an offset lines up with a fixed grid.
+struct BoxSums {
+    columns: usize,
+    rows: usize,
+    /// `rows + 1` rows of running totals, the first one all zeros
+    totals: Vec<u32>,
+}
+
+impl BoxSums {
+    fn new(img: &RgbaImage, rows: std::ops::Range<u32>) -> Self {
+        const GROUP_BYTES: usize = PYRAMID_BASE as usize * 4;
+        let columns = img.width() as usize / PYRAMID_BASE as usize;
+        let row_bytes = img.width() as usize * 4;
+        let raw = img.as_raw();
+        let mut totals = vec![0u32; (rows.len() + 1) * columns];
+        for (i, y) in rows.clone().enumerate() {
+            let row = &raw[y as usize * row_bytes..][..columns * GROUP_BYTES];
+            let (above, below) = totals.split_at_mut((i + 1) * columns);
+            let above = &above[i * columns..];
+            for ((group, total), previous) in row.chunks_exact(GROUP_BYTES).zip(&mut below[..columns]).zip(above) {
+                let (mut red_blue, mut green) = (0u32, 0u32);
+                for px in group.chunks_exact(4) {
+                    red_blue += px[0] as u32 + px[2] as u32;
+                    green += px[1] as u32;
+                }
+                *total = previous + red_blue + 2 * green;
+            }
+        }
+        Self { columns, rows: rows.len(), totals }
+    }
+
+    /// Sums of `factor` x `factor` boxes starting at every row
+    fn level(&self, factor: u32) -> BoxRows {
+        let f = factor as usize;
+        let merge = f / PYRAMID_BASE as usize;
+        let columns = self.columns / merge;
+        let rows = (self.rows + 1).saturating_sub(f);
+        let mut sums = Vec::with_capacity(rows * columns);
+        for y in 0..rows {
+            let top = &self.totals[y * self.columns..][..columns * merge];
+            let bottom = &self.totals[(y + f) * self.columns..][..columns * merge];
+            if merge == 1 {
+                sums.extend(bottom.iter().zip(top).map(|(bottom, top)| bottom - top));
+            } else {
+                sums.extend(
+                    bottom
+                        .chunks_exact(merge)
+                        .zip(top.chunks_exact(merge))
+                        .map(|(bottom, top)| bottom.iter().sum::<u32>() - top.iter().sum::<u32>()),
+                );
+            }
+        }
+        BoxRows { factor: f, columns, rows, sums }
+    }
+}
+
+/// Box sums of one pyramid level, see `BoxSums::level`
+struct BoxRows {
+    factor: usize,
+    columns: usize,
+    rows: usize,
+    sums: Vec<u32>,
+}
+
+impl BoxRows {
+    /// Sum of absolute differences between `count` boxes stacked from row `row` here and from
+    /// `other_row` in `other`, `None` if they don't fit
+    fn distance(&self, row: u32, other: &BoxRows, other_row: u32, count: u32) -> Option<u64> {
+        let (row, other_row) = (row as usize, other_row as usize);
+        let span = (count as usize).saturating_sub(1) * self.factor;
+        if row + span >= self.rows || other_row + span >= other.rows || self.columns != other.columns {
+            return None;
+        }
+        let mut total = 0u64;
+        for i in 0..count as usize {
+            let a = (row + i * self.factor) * self.columns;
+            let b = (other_row + i * self.factor) * other.columns;
+            total += self.sums[a..a + self.columns]
+                .iter()
+                .zip(&other.sums[b..b + other.columns])
+                .map(|(a, b)| a.abs_diff(*b) as u64)
+                .sum::<u64>();
+        }
+        Some(total)
+    }
+}
+
+/// Captures are RGBA already, only convert if something else slipped in
+fn rgba(img: &DynamicImage) -> Cow<'_, RgbaImage> {
+    match img {
+        DynamicImage::ImageRgba8(rgba) => Cow::Borrowed(rgba),
+        other => Cow::Owned(other.to_rgba8()),
+    }
+}
+
+/// Whether `curr_img` looks like it was captured mid-repaint, given the overlap `calculate_overlap` found.
+/// `calculate_overlap` only matches a signature block, so here the rest of the overlapping rows are
+/// checked too: in a clean scroll they all equal `prev_img` shifted up by the scroll distance.
+/// Rows that didn't move are tolerated in the top quarter (sticky headers), anywhere else they mean
+/// part of the frame still shows an older (or newer) scroll position.
+pub fn is_torn_frame(prev_img: &DynamicImage, curr_img: &DynamicImage, overlap: u32) -> bool {
+    let band = 8;
+    let width = prev_img.width();
+    let prev_height = prev_img.height();
+    if overlap == 0 || overlap > prev_height || overlap > curr_img.height() || width != curr_img.width() {
+        return false;
+    }
+
+    let shift = prev_height - overlap;
+    let sticky_limit = curr_img.height() / 4;
+
+    // Walk up from the bottom of the overlap in small bands, the first mismatch decides
+    let (prev, curr) = (rgba(prev_img), rgba(curr_img));
+    let mut y = overlap;
+    while y > 0 {
+        let h = band.min(y);
+        let top = y - h;
+        if !compare_blocks_strict(&prev, top + shift, &curr, top, width, h) {
+            // A frame that only got brighter or darker as a whole isn't torn
+            return y > sticky_limit && !(brightness_shifted(prev_img, curr_img) && content_matches(prev_img, curr_img, overlap));
+        }
+        y = top;
+    }
+    false
+}
+
+/// Share of the overlapping rows that actually match, from 0.0 to 1.0.
+/// `calculate_overlap` only needs the signature block to match, a low value here points at a
+/// questionable seam (animated content, a repeating pattern matched at the wrong offset).
+pub fn overlap_confidence(prev_img: &DynamicImage, curr_img: &DynamicImage, overlap: u32) -> f32 {
+    let width = prev_img.width().min(curr_img.width());
+    let overlap = overlap.min(prev_img.height()).min(curr_img.height());
+    if width == 0 || overlap == 0 {
+        return 0.0;
+    }
+
+    // A sample is plenty for a score
+    let pairs = overlap_pairs(prev_img, curr_img, overlap, 4);
+    // Colors of a frame that got brighter or darker as a whole are compared after mapping them back
+    let fit = if brightness_shifted(prev_img, curr_img) { ColorFit::new(&pairs) } else { None };
+    let matched = pairs
+        .iter()
+        .filter(|&&(p, q)| pixels_are_similar(p, fit.as_ref().map_or(q, |fit| fit.apply(q)), 10))
+        .count();
+    matched as f32 / pairs.len() as f32
+}
+
+/// Whether two consecutive fragments show the same content (nothing was scrolled)
+pub fn is_same_frame(prev_img: &DynamicImage, curr_img: &DynamicImage) -> bool {
+    if prev_img.dimensions() != curr_img.dimensions() || prev_img.height() == 0 {
+        return false;
+    }
+    compare_blocks_strict(&rgba(prev_img), 0, &rgba(curr_img), 0, prev_img.width(), prev_img.height())
+}
+
+/// Put screenshots of one page into top-to-bottom order, for stitching images that weren't
+/// captured by us and may come in any order (file names don't always sort by time).
+/// Returns `(index, overlap with the previous image)` pairs. Images that don't overlap with
+/// anything are kept in their given order with an overlap of 0, so nothing gets dropped.
+pub fn order_fragments(images: &[DynamicImage]) -> Vec<(usize, u32)> {
+    let n = images.len();
+    // overlaps[i][j]: how far `j` continues below `i`
+    let overlaps: Vec<Vec<u32>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0 } else { calculate_overlap(&images[i], &images[j]) }).collect())
+        .collect();
+
+    let mut used = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    while order.len() < n {
+        // Start a chain at the first image nothing else leads into, or just the first unused one
+        // (a loop of matches, e.g. a page of identical rows)
+        let start = (0..n)
+            .find(|&j| !used[j] && (0..n).all(|i| used[i] || overlaps[i][j] == 0))
+            .or_else(|| (0..n).find(|&j| !used[j]))
+            .unwrap();
+        used[start] = true;
+        if !order.is_empty() {
+            warn!("Stitch files: image {} doesn't connect to the previous one, appending it as is", start);
+        }
+        order.push((start, 0));
+
+        // Follow the largest overlap, a larger overlap is the nearer scroll position
+        let mut current = start;
+        while let Some(next) = (0..n)
+            .filter(|&j| !used[j] && overlaps[current][j] > 0)
+            .max_by_key(|&j| overlaps[current][j])
         {
-            // Potential match found, do strict full block comparison
-            if compare_blocks_strict(prev_img, signature_start_y, curr_img, y, width, signature_height) {
-                println!("Stitch Match: Found overlap at y={}, overlap height={}", y, y + signature_height);
-                return y + signature_height;
+            used[next] = true;
+            order.push((next, overlaps[current][next]));
+            current = next;
+        }
+    }
+    order
+}
+
+/// Look for a vertical scrollbar along the right edge of the fragment.
+/// Returns `Some(true)` if its thumb sits at the bottom of the track, `Some(false)` if a thumb
+/// was found elsewhere, and `None` if no scrollbar-like column could be identified.
+pub fn scrollbar_at_bottom(img: &DynamicImage) -> Option<bool> {
+    let width = img.width();
+    let height = img.height();
+    if width == 0 || height < 50 {
+        return None;
+    }
+
+    // Scrollbars are at most ~20px wide even at 200% scaling, scan a bit more than that
+    let band = width.min(24);
+    for x in (width - band..width).rev() {
+        if let Some(at_bottom) = scrollbar_column_at_bottom(img, x) {
+            return Some(at_bottom);
+        }
+    }
+    None
+}
+
+fn scrollbar_column_at_bottom(img: &DynamicImage, x: u32) -> Option<bool> {
+    let height = img.height();
+    let luma: Vec<i32> = (0..height).map(|y| luminance(img.get_pixel(x, y))).collect();
+
+    // The track is the most common value in the column
+    let mut histogram = [0u32; 256];
+    for &l in &luma {
+        histogram[l as usize] += 1;
+    }
+    let track = (0..256).max_by_key(|&i| histogram[i]).unwrap_or(0) as i32;
+
+    // The thumb is the longest contiguous run of rows clearly different from the track
+    let is_thumb = |l: i32| (l - track).abs() > 24;
+    let (mut start, mut end) = (0, 0);
+    let mut run_start = None;
+    for y in 0..=height {
+        let thumb_row = y < height && is_thumb(luma[y as usize]);
+        match (thumb_row, run_start) {
+            (true, None) => run_start = Some(y),
+            (false, Some(s)) => {
+                if y - s > end - start {
+                    (start, end) = (s, y);
+                }
+                run_start = None;
             }
+            _ => {}
         }
     }
 
-    // No match found
-    0
+    let thumb_len = end - start;
+    if thumb_len < height / 20 || thumb_len > height * 19 / 20 {
+        return None;
+    }
+
+    // A real scrollbar column is mostly clean track apart from the thumb (and arrow buttons)
+    let track_rows = luma.iter().filter(|&&l| (l - track).abs() <= 8).count() as u32;
+    if track_rows + thumb_len < height * 9 / 10 {
+        return None;
+    }
+
+    // At the bottom when no track is visible below the thumb, only e.g. a down-arrow button
+    let track_below = luma[end as usize..].iter().filter(|&&l| (l - track).abs() <= 8).count();
+    Some(track_below < 3)
+}
+
+fn luminance(p: Rgba<u8>) -> i32 {
+    (p[0] as i32 * 299 + p[1] as i32 * 587 + p[2] as i32 * 114) / 1000
 }
 
-fn check_row_match(img1: &DynamicImage, y1: u32, img2: &DynamicImage, y2: u32, width: u32) -> bool {
+fn check_row_match(img1: &RgbaImage, y1: u32, img2: &RgbaImage, y2: u32, width: u32) -> bool {
     let step = 10; // Check every 10th pixel for speed
     let tolerance = 5; // Very strict tolerance
-    
-    for x in (0..width).step_by(step) {
-        let p1 = img1.get_pixel(x, y1);
-        let p2 = img2.get_pixel(x, y2);
-        if !pixels_are_similar(p1, p2, tolerance) {
-            return false;
-        }
-    }
-    true
+
+    // Most offsets fail on the first few pixels, so this stops at the first mismatch
+    row(img1, y1, width)
+        .chunks_exact(4)
+        .zip(row(img2, y2, width).chunks_exact(4))
+        .step_by(step)
+        .all(|(p1, p2)| rgb_similar(p1, p2, tolerance))
 }
 
-fn compare_blocks_strict(img1: &DynamicImage, y1: u32, img2: &DynamicImage, y2: u32, width: u32, height: u32) -> bool {
+fn compare_blocks_strict(img1: &RgbaImage, y1: u32, img2: &RgbaImage, y2: u32, width: u32, height: u32) -> bool {
     let step = 2; // Check every 2nd pixel
     let tolerance = 10; // Strict tolerance
     let mut diff_count = 0;
-    let max_diff = (width * height / step / step) / 100; // Allow max 1% different pixels (noise)
-    
+    let max_diff = (width * height / step / step) as usize / 100; // Allow max 1% different pixels (noise)
+
     for h in (0..height).step_by(step as usize) {
-        for x in (0..width).step_by(step as usize) {
-            let p1 = img1.get_pixel(x, y1 + h);
-            let p2 = img2.get_pixel(x, y2 + h);
-            
-            if !pixels_are_similar(p1, p2, tolerance) {
-                diff_count += 1;
-                if diff_count > max_diff {
-                    return false;
-                }
-            }
+        diff_count += row_mismatches(row(img1, y1 + h, width), row(img2, y2 + h, width), step as usize, tolerance);
+        if diff_count > max_diff {
+            return false;
         }
     }
     true
 }
 
-fn pixels_are_similar(p1: Rgba<u8>, p2: Rgba<u8>, tolerance: i32) -> bool {
-    let r_diff = (p1[0] as i32 - p2[0] as i32).abs();
-    let g_diff = (p1[1] as i32 - p2[1] as i32).abs();
-    let b_diff = (p1[2] as i32 - p2[2] as i32).abs();
-    
-    r_diff <= tolerance && g_diff <= tolerance && b_diff <= tolerance
+/// The first `width` pixels of row `y` as RGBA bytes
+fn row(img: &RgbaImage, y: u32, width: u32) -> &[u8] {
+    let start = y as usize * img.width() as usize * 4;
+    &img.as_raw()[start..start + width as usize * 4]
 }
 
-pub fn append_image(base: &DynamicImage, new_part: &DynamicImage, overlap_height: u32) -> DynamicImage {
-    let width = base.width();
-    let base_height = base.height();
-    let new_height = new_part.height();
-    
-    // We only keep the part of `new_part` that is NOT in the overlap.
-    // overlap_height is the amount of pixels in `new_part` (from top) that duplicates `base`.
-    // So we want `new_part` from `overlap_height` to end.
-    
-    // Safety check
-    if overlap_height >= new_height {
-        // The entire new image is a duplicate? Return base.
-        return base.clone();
+/// How many of every `step`-th pixel differ by more than `tolerance` in red, green or blue
+/// between two rows of RGBA bytes. Four pixels are compared at once where the CPU allows.
+fn row_mismatches(row1: &[u8], row2: &[u8], step: usize, tolerance: u8) -> usize {
+    let len = row1.len().min(row2.len()) / 16 * 16;
+    let (mut count, mut x) = (0, 0);
+    // Which of four consecutive pixels are sampled, every chunk starts at a multiple of 4
+    let lanes = match step {
+        1 => Some(0b1111),
+        2 => Some(0b0101),
+        4 => Some(0b0001),
+        _ => None,
+    };
+    if let Some(lanes) = lanes {
+        count = simd_mismatches(&row1[..len], &row2[..len], lanes, tolerance);
+        x = len;
     }
-    
-    let append_height = new_height - overlap_height;
-    let final_height = base_height + append_height;
+    count
+        + row1[x..]
+            .chunks_exact(4)
+            .zip(row2[x..].chunks_exact(4))
+            .step_by(step)
+            .filter(|(p1, p2)| !rgb_similar(p1, p2, tolerance))
+            .count()
+}
 
-    let mut final_img = DynamicImage::new_rgba8(width, final_height);
-    
-    // Copy base image
-    let _ = final_img.copy_from(base, 0, 0);
-    
-    // Copy non-overlapping part of new image
-    // Source rect: x=0, y=overlap_height, w=width, h=append_height
-    // Dest: x=0, y=base_height
-    let crop = new_part.view(0, overlap_height, width, append_height);
-    // Convert SubImage to DynamicImage (ImageBuffer) to satisfy GenericImageView trait for copy_from
-    let crop_img = crop.to_image();
-    let _ = final_img.copy_from(&crop_img, 0, base_height);
-    
-    final_img
+/// `row_mismatches` over whole 16-byte chunks, counting the pixels set in `lanes`
+#[cfg(any(target_arch = "x86_64", all(target_arch = "x86", target_feature = "sse2")))]
+fn simd_mismatches(row1: &[u8], row2: &[u8], lanes: u32, tolerance: u8) -> usize {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    let mut count = 0;
+    // SAFETY: SSE2 is part of the target, loads are unaligned and within the 16-byte chunks
+    unsafe {
+        let tolerance = _mm_set1_epi8(tolerance as i8);
+        let rgb = _mm_set1_epi32(0x00ff_ffff);
+        let zero = _mm_setzero_si128();
+        for (a, b) in row1.chunks_exact(16).zip(row2.chunks_exact(16)) {
+            let a = _mm_loadu_si128(a.as_ptr() as *const __m128i);
+            let b = _mm_loadu_si128(b.as_ptr() as *const __m128i);
+            let diff = _mm_or_si128(_mm_subs_epu8(a, b), _mm_subs_epu8(b, a));
+            // Non-zero where a channel is further apart than the tolerance, alpha ignored
+            let over = _mm_and_si128(_mm_subs_epu8(diff, tolerance), rgb);
+            let similar = _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpeq_epi32(over, zero))) as u32;
+            count += (!similar & lanes).count_ones() as usize;
+        }
+    }
+    count
+}
+
+/// `row_mismatches` over whole 16-byte chunks, counting the pixels set in `lanes`
+#[cfg(target_arch = "aarch64")]
+fn simd_mismatches(row1: &[u8], row2: &[u8], lanes: u32, tolerance: u8) -> usize {
+    use std::arch::aarch64::*;
+
+    let mut count = 0;
+    // SAFETY: NEON is part of the target, loads are within the 16-byte chunks
+    unsafe {
+        let tolerance = vdupq_n_u8(tolerance);
+        let rgb = vdupq_n_u32(0x00ff_ffff);
+        let lane_bits = [lanes & 1, (lanes >> 1) & 1, (lanes >> 2) & 1, (lanes >> 3) & 1];
+        let lanes = vld1q_u32(lane_bits.as_ptr());
+        for (a, b) in row1.chunks_exact(16).zip(row2.chunks_exact(16)) {
+            let diff = vabdq_u8(vld1q_u8(a.as_ptr()), vld1q_u8(b.as_ptr()));
+            // Non-zero where a channel is further apart than the tolerance, alpha ignored
+            let over = vandq_u32(vreinterpretq_u32_u8(vqsubq_u8(diff, tolerance)), rgb);
+            count += vaddvq_u32(vandq_u32(vtstq_u32(over, over), lanes)) as usize;
+        }
+    }
+    count
+}
+
+#[cfg(not(any(target_arch = "x86_64", all(target_arch = "x86", target_feature = "sse2"), target_arch = "aarch64")))]
+fn simd_mismatches(row1: &[u8], row2: &[u8], lanes: u32, tolerance: u8) -> usize {
+    row1.chunks_exact(4)
+        .zip(row2.chunks_exact(4))
+        .enumerate()
+        .filter(|(i, (p1, p2))| lanes & (1 << (i % 4)) != 0 && !rgb_similar(p1, p2, tolerance))
+        .count()
+}
+
+fn pixels_are_similar(p1: Rgba<u8>, p2: Rgba<u8>, tolerance: u8) -> bool {
+    rgb_similar(&p1.0, &p2.0, tolerance)
+}
+
+/// Whether red, green and blue of two RGBA pixels are each within `tolerance`
+fn rgb_similar(p1: &[u8], p2: &[u8], tolerance: u8) -> bool {
+    p1[0].abs_diff(p2[0]) <= tolerance && p1[1].abs_diff(p2[1]) <= tolerance && p1[2].abs_diff(p2[2]) <= tolerance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64, reproducible noise without pulling in `rand`
+    struct Noise(u64);
+
+    impl Noise {
+        fn byte(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u8
+        }
+    }
+
+    /// `row_mismatches` one pixel at a time
+    fn scalar_mismatches(row1: &[u8], row2: &[u8], step: usize, tolerance: u8) -> usize {
+        row1.chunks_exact(4)
+            .zip(row2.chunks_exact(4))
+            .step_by(step)
+            .filter(|(p1, p2)| !rgb_similar(p1, p2, tolerance))
+            .count()
+    }
+
+    /// A random row of `pixels` pixels and a copy with its channels moved by 0, `tolerance`,
+    /// `tolerance + 1` or anything, so many differences sit right at the tolerance
+    fn row_pair(noise: &mut Noise, pixels: usize, tolerance: u8) -> (Vec<u8>, Vec<u8>) {
+        let row1: Vec<u8> = (0..pixels * 4).map(|_| noise.byte()).collect();
+        let row2 = row1
+            .iter()
+            .map(|&value| {
+                let delta = match noise.byte() % 4 {
+                    0 => 0,
+                    1 => tolerance,
+                    2 => tolerance.saturating_add(1),
+                    _ => noise.byte(),
+                };
+                if noise.byte().is_multiple_of(2) { value.saturating_add(delta) } else { value.saturating_sub(delta) }
+            })
+            .collect();
+        (row1, row2)
+    }
+
+    /// Layout of a synthetic page: text lines of `LINE_HEIGHT` rows, glyph cells of 2x2 pixels
+    const LINE_HEIGHT: u32 = 16;
+    const BACKGROUND: [u8; 3] = [250, 250, 245];
+
+    /// Deterministic hash of a glyph cell, picks the cells that are ink and their color
+    fn cell_hash(seed: u64, x: u32, y: u32) -> u64 {
+        let mut h = seed ^ ((x as u64) << 32 | y as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        h ^= h >> 31;
+        h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h ^ (h >> 29)
+    }
+
+    /// A page of dark "text" on a light background. `period` repeats the same line every
+    /// `period` lines (code, tables), `sparse` leaves most lines and the right of the page empty.
+    fn page(width: u32, height: u32, seed: u64, period: Option<u32>, sparse: bool) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let line = y / LINE_HEIGHT;
+            let line = period.map_or(line, |period| line % period);
+            let row = y % LINE_HEIGHT;
+            let blank = !(3..13).contains(&row) || (sparse && (!line.is_multiple_of(3) || x > width / 2));
+            let h = cell_hash(seed, x / 2, line * LINE_HEIGHT + row / 2);
+            if blank || !h.is_multiple_of(3) {
+                let [r, g, b] = BACKGROUND;
+                Rgba([r, g, b, 255])
+            } else {
+                Rgba([30 + (h >> 8) as u8 % 40, 30 + (h >> 16) as u8 % 40, 60 + (h >> 24) as u8 % 60, 255])
+            }
+        })
+    }
+
+    /// The `height` rows of `page` from `top`, as a captured frame
+    fn frame(page: &RgbaImage, top: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::imageops::crop_imm(page, 0, top, page.width(), height).to_image())
+    }
+
+    /// Both searches over every offset, the way `search_overlap` sets them up with the default
+    /// scan depth
+    fn both_searches(prev: &DynamicImage, curr: &DynamicImage) -> (Option<u32>, Option<u32>) {
+        let signature_height = (prev.height() / 5).max(50).min(prev.height());
+        let signature_start_y = prev.height() - signature_height;
+        let max_offset = curr.height() - signature_height;
+        let scan_depth = curr.height() / 2;
+        let ranges = [0..scan_depth, scan_depth..max_offset + 1];
+        (
+            pyramid_overlap(prev, curr, signature_start_y, signature_height, &ranges),
+            full_scan_overlap(prev, curr, signature_start_y, signature_height, &ranges),
+        )
+    }
+
+    const FRAME_WIDTH: u32 = 256;
+    const FRAME_HEIGHT: u32 = 320;
+    /// Scroll steps up to this leave the signature block in the new frame
+    const MAX_SCROLL: u32 = FRAME_HEIGHT - FRAME_HEIGHT / 5;
+
+    #[test]
+    fn pyramid_finds_every_scroll_step_like_the_full_scan() {
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 3, 1, None, false);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        // 0 is a frame that didn't move, `MAX_SCROLL` puts the signature at the top of the new one
+        for scrolled in 0..=MAX_SCROLL {
+            let curr = frame(&page, scrolled, FRAME_HEIGHT);
+            let (pyramid, full_scan) = both_searches(&prev, &curr);
+            assert_eq!(full_scan, Some(FRAME_HEIGHT - scrolled), "scrolled by {}", scrolled);
+            assert_eq!(pyramid, full_scan, "scrolled by {}", scrolled);
+        }
+    }
+
+    #[test]
+    fn pyramid_matches_full_scan_with_noise() {
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 3, 2, None, false);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        let mut noise = Noise(0x5851_f42d_4c95_7f2d);
+        for scrolled in (0..=MAX_SCROLL).step_by(7) {
+            let DynamicImage::ImageRgba8(mut curr) = frame(&page, scrolled, FRAME_HEIGHT) else { unreachable!() };
+            // Within the strict comparison's tolerance, like capture noise or dithering
+            for value in curr.iter_mut() {
+                *value = value.saturating_add(noise.byte() % 4).saturating_sub(2);
+            }
+            let (pyramid, full_scan) = both_searches(&prev, &DynamicImage::ImageRgba8(curr));
+            assert_eq!(full_scan, Some(FRAME_HEIGHT - scrolled), "scrolled by {}", scrolled);
+            assert_eq!(pyramid, full_scan, "scrolled by {}", scrolled);
+        }
+    }
+
+    #[test]
+    fn pyramid_picks_the_first_repeat_like_the_full_scan() {
+        // Every third line is the same, so each offset matches again `period` rows further down
+        let period = 3 * LINE_HEIGHT;
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 3, 3, Some(3), false);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        let signature_height = FRAME_HEIGHT / 5;
+        for scrolled in 0..=MAX_SCROLL {
+            let curr = frame(&page, scrolled, FRAME_HEIGHT);
+            let (pyramid, full_scan) = both_searches(&prev, &curr);
+            let first_repeat = (FRAME_HEIGHT - signature_height - scrolled) % period;
+            assert_eq!(full_scan, Some(first_repeat + signature_height), "scrolled by {}", scrolled);
+            assert_eq!(pyramid, full_scan, "scrolled by {}", scrolled);
+        }
+    }
+
+    #[test]
+    fn unrelated_frames_match_nowhere() {
+        let prev = frame(&page(FRAME_WIDTH, FRAME_HEIGHT, 4, None, false), 0, FRAME_HEIGHT);
+        let curr = frame(&page(FRAME_WIDTH, FRAME_HEIGHT, 5, None, false), 0, FRAME_HEIGHT);
+        assert_eq!(both_searches(&prev, &curr), (None, None));
+        assert_eq!(calculate_overlap(&prev, &curr), 0);
+    }
+
+    /// `img` with `map(channel, value)` applied to red, green and blue
+    fn recolor(img: &DynamicImage, map: impl Fn(usize, u8) -> u8) -> DynamicImage {
+        let mut img = img.to_rgba8();
+        for pixel in img.pixels_mut() {
+            for c in 0..3 {
+                pixel[c] = map(c, pixel[c]);
+            }
+        }
+        DynamicImage::ImageRgba8(img)
+    }
+
+    fn fade(_: usize, value: u8) -> u8 {
+        (value as f32 * 0.8 + 20.0) as u8
+    }
+
+    fn tint(channel: usize, value: u8) -> u8 {
+        (value as f32 * [0.9, 0.75, 0.6][channel] + [10.0, 5.0, 30.0][channel]) as u8
+    }
+
+    fn invert(_: usize, value: u8) -> u8 {
+        255 - value
+    }
+
+    const NO_NORMALIZING: OverlapSearch = OverlapSearch { scan_depth_percent: 50, full_height_fallback: true, normalize_brightness: false };
+
+    #[test]
+    fn brightness_shifts_still_overlap() {
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 3, 6, None, false);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        for (name, map) in [("fade", fade as fn(usize, u8) -> u8), ("tint", tint), ("inversion", invert)] {
+            for scrolled in (0..=MAX_SCROLL).step_by(23) {
+                let curr = recolor(&frame(&page, scrolled, FRAME_HEIGHT), map);
+                assert!(brightness_shifted(&prev, &curr), "{}", name);
+                assert_eq!(calculate_overlap_with(&prev, &curr, NO_NORMALIZING), 0, "{} scrolled by {}", name, scrolled);
+                let overlap = calculate_overlap(&prev, &curr);
+                assert_eq!(overlap, FRAME_HEIGHT - scrolled, "{} scrolled by {}", name, scrolled);
+                // A clean scroll that only changed brightness isn't a repaint or a bad seam
+                assert!(!is_torn_frame(&prev, &curr, overlap), "{} scrolled by {}", name, scrolled);
+                assert!(overlap_confidence(&prev, &curr, overlap) > 0.9, "{} scrolled by {}", name, scrolled);
+            }
+        }
+    }
+
+    #[test]
+    fn small_brightness_changes_match_without_normalizing() {
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 2, 7, None, false);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        let curr = recolor(&frame(&page, 40, FRAME_HEIGHT), |_, value| value.saturating_sub(3));
+        assert!(!brightness_shifted(&prev, &curr));
+        assert_eq!(calculate_overlap_with(&prev, &curr, NO_NORMALIZING), FRAME_HEIGHT - 40);
+    }
+
+    #[test]
+    fn sparse_pages_never_match_at_the_wrong_offset() {
+        let page = page(FRAME_WIDTH, FRAME_HEIGHT * 3, 8, None, true);
+        let prev = frame(&page, 0, FRAME_HEIGHT);
+        for map in [fade as fn(usize, u8) -> u8, tint, invert] {
+            let mut found = 0;
+            for scrolled in (0..=MAX_SCROLL).step_by(5) {
+                let overlap = calculate_overlap(&prev, &recolor(&frame(&page, scrolled, FRAME_HEIGHT), map));
+                // Blank stretches may leave no content to go by, then there's no overlap at all
+                assert!(overlap == 0 || overlap == FRAME_HEIGHT - scrolled, "scrolled by {} matched {}", scrolled, overlap);
+                if overlap > 0 {
+                    found += 1;
+                }
+            }
+            assert!(found > 0);
+        }
+    }
+
+    #[test]
+    fn unrelated_frames_dont_match_after_normalizing() {
+        let prev = frame(&page(FRAME_WIDTH, FRAME_HEIGHT, 9, None, false), 0, FRAME_HEIGHT);
+        let other = frame(&page(FRAME_WIDTH, FRAME_HEIGHT, 10, None, false), 0, FRAME_HEIGHT);
+        for map in [fade as fn(usize, u8) -> u8, tint, invert] {
+            assert_eq!(calculate_overlap(&prev, &recolor(&other, map)), 0);
+        }
+    }
+
+    const TOLERANCES: [u8; 8] = [0, 1, 5, 10, 127, 128, 254, 255];
+
+    #[test]
+    fn row_mismatches_match_scalar() {
+        let mut noise = Noise(0x9e37_79b9_7f4a_7c15);
+        // Lengths around and between whole 16-byte chunks, so the tail is 0 to 3 pixels
+        for pixels in 0..=41 {
+            for step in 1..=5 {
+                for tolerance in TOLERANCES {
+                    for _ in 0..4 {
+                        let (row1, row2) = row_pair(&mut noise, pixels, tolerance);
+                        assert_eq!(
+                            row_mismatches(&row1, &row2, step, tolerance),
+                            scalar_mismatches(&row1, &row2, step, tolerance),
+                            "{} pixels, step {}, tolerance {}",
+                            pixels,
+                            step,
+                            tolerance
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn simd_mismatches_match_scalar() {
+        let mut noise = Noise(0x2545_f491_4f6c_dd1d);
+        for pixels in (4..=64).step_by(4) {
+            for (step, lanes) in [(1, 0b1111), (2, 0b0101), (4, 0b0001)] {
+                for tolerance in TOLERANCES {
+                    let (row1, row2) = row_pair(&mut noise, pixels, tolerance);
+                    assert_eq!(
+                        simd_mismatches(&row1, &row2, lanes, tolerance),
+                        scalar_mismatches(&row1, &row2, step, tolerance),
+                        "{} pixels, step {}, tolerance {}",
+                        pixels,
+                        step,
+                        tolerance
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tolerance_is_inclusive_in_every_lane_and_channel() {
+        for tolerance in TOLERANCES {
+            for pixel in 0..4 {
+                for channel in 0..3 {
+                    for (base, up) in [(0u8, true), (255, false)] {
+                        let row1 = vec![base; 16];
+                        let moved = |delta: u8| {
+                            let mut row = row1.clone();
+                            row[pixel * 4 + channel] = if up { base + delta } else { base - delta };
+                            row
+                        };
+                        assert_eq!(simd_mismatches(&row1, &moved(tolerance), 0b1111, tolerance), 0);
+                        if tolerance < 255 {
+                            assert_eq!(
+                                simd_mismatches(&row1, &moved(tolerance + 1), 0b1111, tolerance),
+                                1,
+                                "pixel {}, channel {}, tolerance {}",
+                                pixel,
+                                channel,
+                                tolerance
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_is_ignored() {
+        let row1: Vec<u8> = (0..32).map(|i| if i % 4 == 3 { 0 } else { 100 }).collect();
+        let row2: Vec<u8> = (0..32).map(|i| if i % 4 == 3 { 255 } else { 100 }).collect();
+        for step in 1..=4 {
+            assert_eq!(row_mismatches(&row1, &row2, step, 0), 0);
+        }
+    }
 }
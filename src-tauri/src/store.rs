@@ -0,0 +1,339 @@
+use image::{imageops, Rgba, RgbaImage};
+use serde::Serialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use tauri::AppHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::canvas::{Canvas, ParkedCanvas, Segment};
+use crate::clipboard;
+use crate::display::Rect;
+use crate::error::CaptureError;
+use crate::exporter::Exporter;
+use crate::history;
+use crate::metadata::CaptureMetadata;
+use crate::metrics::MetricsSummary;
+use crate::utils;
+
+/// Captures kept in memory, the ones used longest ago are parked on disk
+const MAX_LOADED: usize = 2;
+/// Captures kept at all, the oldest one is dropped when another comes in
+const MAX_CAPTURES: usize = 16;
+/// Temp folder for parked captures, emptied on startup
+const PARKED_DIR: &str = "scroll-snap-captures";
+/// Width of the preview in `CaptureResult`, narrower captures keep their width
+const PREVIEW_WIDTH: u32 = 400;
+/// Height of the preview at most, only the top of tall captures is shown
+const PREVIEW_MAX_HEIGHT: u32 = 600;
+const PREVIEW_QUALITY: u8 = 80;
+/// Rows `get_capture_tile` returns at most
+const MAX_TILE_HEIGHT: u32 = 4096;
+
+/// A finished capture. Exports and edits read it straight from here, so the full-resolution
+/// image never has to round-trip through the webview as base64.
+struct StoredCapture {
+    pixels: Pixels,
+    /// Provenance, embedded on export. Survives edits like cropping.
+    metadata: Option<CaptureMetadata>,
+}
+
+enum Pixels {
+    Loaded(Canvas),
+    Parked(ParkedCanvas),
+}
+
+impl StoredCapture {
+    fn canvas(&mut self) -> Result<&mut Canvas, CaptureError> {
+        if let Pixels::Parked(parked) = &self.pixels {
+            self.pixels = Pixels::Loaded(parked.restore()?);
+        }
+        match &mut self.pixels {
+            Pixels::Loaded(canvas) => Ok(canvas),
+            Pixels::Parked(_) => unreachable!(),
+        }
+    }
+}
+
+lazy_static! {
+    // Captures by id, the one used most recently last
+    static ref CAPTURES: Mutex<Vec<(String, Arc<Mutex<StoredCapture>>)>> = Mutex::new(Vec::new());
+}
+
+/// Payload of `capture-complete` and result of the single-shot captures.
+/// Everything else (saving, copying, edits) takes the `id`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureResult {
+    pub id: String,
+    pub width: u32,
+    pub height: u32,
+    /// JPEG data URL of the top of the capture, `PREVIEW_WIDTH` wide
+    pub preview: String,
+}
+
+/// Dimensions of a capture after an edit
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageSize {
+    pub fn of(canvas: &Canvas) -> Self {
+        Self { width: canvas.width(), height: canvas.height() }
+    }
+}
+
+/// A horizontal strip of a capture, see `get_capture_tile`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureTile {
+    pub y: u32,
+    pub width: u32,
+    /// Fewer rows than asked for at the bottom of the capture
+    pub height: u32,
+    /// PNG data URL
+    pub image: String,
+}
+
+/// Result of `get_capture_report`: where every fragment ended up and how well it matched
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureReport {
+    pub width: u32,
+    pub height: u32,
+    pub segments: Vec<Segment>,
+    /// PNG data URL of the capture with the seams drawn in, when requested
+    pub overlay: Option<String>,
+    /// Capture loop timings, for captures taken from the screen
+    pub metrics: Option<MetricsSummary>,
+}
+
+/// Seam line colors, by how well the overlap matched
+const SEAM_GOOD: Rgba<u8> = Rgba([34, 197, 94, 255]);
+const SEAM_DOUBTFUL: Rgba<u8> = Rgba([245, 158, 11, 255]);
+const SEAM_BAD: Rgba<u8> = Rgba([239, 68, 68, 255]);
+
+/// Remove captures parked by a previous run
+pub fn init() {
+    let dir = parked_dir();
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("Could not clean up parked captures in {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Store a finished capture under a new id. Blocks for a moment, the preview is encoded here.
+pub fn insert(mut canvas: Canvas, metadata: Option<CaptureMetadata>) -> Result<CaptureResult, CaptureError> {
+    let (width, height) = (canvas.width(), canvas.height());
+    let preview = utils::jpeg_data_url(&preview(&mut canvas)?);
+    let id = Uuid::new_v4().to_string();
+    let capture = StoredCapture { pixels: Pixels::Loaded(canvas), metadata };
+
+    let mut captures = CAPTURES.lock().unwrap();
+    captures.push((id.clone(), Arc::new(Mutex::new(capture))));
+    if captures.len() > MAX_CAPTURES {
+        let (dropped, _) = captures.remove(0);
+        info!("Dropped capture {}, keeping the last {}", dropped, MAX_CAPTURES);
+    }
+    drop(captures);
+    park_idle();
+
+    info!("Stored capture {} ({}x{})", id, width, height);
+    Ok(CaptureResult { id, width, height, preview })
+}
+
+/// Metadata of the capture `id`
+pub fn metadata(id: &str) -> Result<Option<CaptureMetadata>, CaptureError> {
+    Ok(find(id)?.lock().unwrap().metadata.clone())
+}
+
+/// Change the metadata of the capture `id`, `None` for captures stored without any
+pub fn update_metadata(id: &str, f: impl FnOnce(&mut Option<CaptureMetadata>)) -> Result<(), CaptureError> {
+    f(&mut find(id)?.lock().unwrap().metadata);
+    Ok(())
+}
+
+/// Run `f` on the capture `id`, loading it back from disk if it was parked.
+/// Assigning a new canvas through the reference replaces the stored one.
+pub fn with_capture<T>(id: &str, f: impl FnOnce(&mut Canvas) -> Result<T, CaptureError>) -> Result<T, CaptureError> {
+    let capture = find(id)?;
+    let result = f(capture.lock().unwrap().canvas()?);
+    park_idle();
+    result
+}
+
+/// `with_capture` on the blocking pool, edits read and write whole images
+pub async fn edit<T: Send + 'static>(
+    id: String,
+    f: impl FnOnce(&mut Canvas) -> Result<T, CaptureError> + Send + 'static,
+) -> Result<T, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || with_capture(&id, f))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("edit task failed: {}", e)))?
+}
+
+/// The capture `id`, moved to the end as the one used most recently
+fn find(id: &str) -> Result<Arc<Mutex<StoredCapture>>, CaptureError> {
+    let mut captures = CAPTURES.lock().unwrap();
+    let index = captures.iter().position(|(stored, _)| stored == id)
+        .ok_or_else(|| CaptureError::InvalidState(format!("no capture with id {}", id)))?;
+    let entry = captures.remove(index);
+    let capture = entry.1.clone();
+    captures.push(entry);
+    Ok(capture)
+}
+
+/// Move all but the `MAX_LOADED` most recently used captures to disk. Ones that are in use
+/// right now are left alone, they get parked on a later call.
+fn park_idle() {
+    let idle: Vec<_> = {
+        let captures = CAPTURES.lock().unwrap();
+        let keep = captures.len().saturating_sub(MAX_LOADED);
+        captures[..keep].to_vec()
+    };
+    for (id, capture) in idle {
+        let Ok(mut capture) = capture.try_lock() else {
+            continue;
+        };
+        let Pixels::Loaded(canvas) = &mut capture.pixels else {
+            continue;
+        };
+        let dir = parked_dir();
+        let parked = fs::create_dir_all(&dir).map_err(CaptureError::from)
+            .and_then(|_| canvas.park(dir.join(format!("{}.rgba", id))));
+        match parked {
+            Ok(parked) => capture.pixels = Pixels::Parked(parked),
+            Err(e) => warn!("Could not park capture {}, keeping it in memory: {}", id, e),
+        }
+    }
+}
+
+fn parked_dir() -> PathBuf {
+    std::env::temp_dir().join(PARKED_DIR)
+}
+
+/// The top of the capture, scaled down to `PREVIEW_WIDTH`, as JPEG
+fn preview(canvas: &mut Canvas) -> Result<Vec<u8>, CaptureError> {
+    let (width, height) = (canvas.width(), canvas.height());
+    let preview_width = width.clamp(1, PREVIEW_WIDTH);
+    let rows = ((PREVIEW_MAX_HEIGHT as u64 * width as u64 / preview_width as u64) as u32).min(height);
+    let top = RgbaImage::from_raw(width, rows, canvas.rows(0, rows)?)
+        .ok_or_else(|| CaptureError::Internal("preview rows do not match the capture width".to_string()))?;
+    let preview_height = ((rows as u64 * preview_width as u64 / width.max(1) as u64) as u32).max(1);
+    utils::encode_jpeg(&imageops::thumbnail(&top, preview_width, preview_height), PREVIEW_QUALITY)
+}
+
+/// Crop the capture `id` to a rectangle in image pixels, e.g. drawn in the editor
+#[tauri::command]
+pub async fn crop_image(id: String, x: i32, y: i32, width: u32, height: u32) -> Result<ImageSize, CaptureError> {
+    edit(id, move |canvas| {
+        *canvas = canvas.cropped(Rect { x, y, width, height })?;
+        info!("Cropped capture to {}x{} at ({}, {})", width, height, x, y);
+        Ok(ImageSize::of(canvas))
+    })
+    .await
+}
+
+/// The capture `id` as a PNG data URL, used to show it in the editor and refresh it after an edit
+#[tauri::command]
+pub async fn get_captured_image(id: String) -> Result<String, CaptureError> {
+    edit(id, |canvas| {
+        let png = canvas.encode_png(|_| {})?;
+        Ok(utils::png_data_url(&png))
+    })
+    .await
+}
+
+/// Rows `y..y + height` of the capture `id` as PNG, so the result view can render huge
+/// captures a tile at a time instead of decoding one enormous data URL.
+/// `height` is capped at `MAX_TILE_HEIGHT`.
+#[tauri::command]
+pub async fn get_capture_tile(id: String, y: u32, height: u32) -> Result<CaptureTile, CaptureError> {
+    edit(id, move |canvas| {
+        let (width, total) = (canvas.width(), canvas.height());
+        if height == 0 || y >= total {
+            return Err(CaptureError::InvalidRegion(format!(
+                "rows {}..{} are outside the {}px tall capture", y, y.saturating_add(height), total
+            )));
+        }
+        let height = height.min(MAX_TILE_HEIGHT).min(total - y);
+        let rows = canvas.rows(y, height)?;
+        let png = utils::encode_png_blocks(width, height, std::iter::once(Ok(Cow::Owned(rows))), |_| {})?;
+        Ok(CaptureTile { y, width, height, image: utils::png_data_url(&png) })
+    })
+    .await
+}
+
+/// Copy the capture `id` to the clipboard straight from memory, without a base64 string
+/// that for tall captures is hundreds of MB. Ids of history entries work too.
+#[tauri::command]
+pub async fn copy_capture_to_clipboard(app: AppHandle, id: String) -> Result<(), CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || ClipboardExporter.export(&app, &id, &Value::Null).map(|_| ()))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("clipboard task failed: {}", e)))?
+}
+
+/// The "clipboard" exporter, see `copy_capture_to_clipboard`. Takes no options.
+pub struct ClipboardExporter;
+
+impl Exporter for ClipboardExporter {
+    fn name(&self) -> &'static str {
+        "clipboard"
+    }
+
+    fn mime(&self) -> &'static str {
+        "image/png"
+    }
+
+    fn export(&self, app: &AppHandle, id: &str, _options: &Value) -> Result<String, CaptureError> {
+        if find(id).is_ok() {
+            with_capture(id, |canvas| clipboard::set_image(canvas.load_image()?))?;
+        } else {
+            clipboard::set_image(history::find(app, id)?.load_image()?.to_rgba8())?;
+        }
+        Ok("clipboard".to_string())
+    }
+}
+
+/// Seams of the capture `id`, for diagnosing bad stitches.
+/// With `with_overlay` the capture is also returned with a colored line at every seam.
+#[tauri::command]
+pub async fn get_capture_report(id: String, with_overlay: Option<bool>) -> Result<CaptureReport, CaptureError> {
+    let metrics = metadata(&id)?.and_then(|metadata| metadata.metrics);
+    edit(id, move |canvas| {
+        let segments = canvas.segments().to_vec();
+        let overlay = if with_overlay.unwrap_or(false) {
+            let mut img = canvas.load_image()?;
+            draw_seams(&mut img, &segments);
+            Some(utils::png_data_url(&utils::encode_png(&img, |_| {})?))
+        } else {
+            None
+        };
+        Ok(CaptureReport { width: canvas.width(), height: canvas.height(), segments, overlay, metrics })
+    })
+    .await
+}
+
+fn draw_seams(img: &mut RgbaImage, segments: &[Segment]) {
+    // The first segment starts at the top of the image, there is no seam to show
+    for segment in segments.iter().filter(|segment| segment.row > 0) {
+        let color = match segment.stitch.confidence {
+            c if c >= 0.95 => SEAM_GOOD,
+            c if c >= 0.8 => SEAM_DOUBTFUL,
+            _ => SEAM_BAD,
+        };
+        // 2px so the line survives the editor scaling the image down
+        for y in segment.row.saturating_sub(1)..(segment.row + 1).min(img.height()) {
+            for x in 0..img.width() {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+}
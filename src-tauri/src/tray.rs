@@ -0,0 +1,55 @@
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Wry};
+use tracing::warn;
+use crate::i18n;
+use crate::instance;
+
+const SHOW_ID: &str = "show";
+const CAPTURE_LAST_ID: &str = "capture-last";
+const QUIT_ID: &str = "quit";
+const TRAY_ID: &str = "main";
+
+/// The tray icon, the way back to the app when it was started with `--hidden`.
+/// Left click shows the main window (not on Linux, where only the menu opens).
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(i18n::text("tray.tooltip", &[]))
+        .menu(&menu(app)?)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| match event.id.as_ref() {
+            SHOW_ID => instance::focus_main_window(app),
+            CAPTURE_LAST_ID => instance::capture_last(app),
+            QUIT_ID => app.exit(0),
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                instance::focus_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+/// Rebuild the menu in the current locale, after `set_locale`
+pub fn relabel(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let result = menu(app).and_then(|menu| tray.set_menu(Some(menu))).and_then(|()| tray.set_tooltip(Some(i18n::text("tray.tooltip", &[]))));
+    if let Err(e) = result {
+        warn!("Could not relabel the tray menu: {}", e);
+    }
+}
+
+fn menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let show = MenuItem::with_id(app, SHOW_ID, i18n::text("tray.show", &[]), true, None::<&str>)?;
+    let capture_last = MenuItem::with_id(app, CAPTURE_LAST_ID, i18n::text("tray.capture_last", &[]), true, None::<&str>)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let quit = MenuItem::with_id(app, QUIT_ID, i18n::text("tray.quit", &[]), true, None::<&str>)?;
+    Menu::with_items(app, &[&show, &capture_last, &separator, &quit])
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_updater::{Update, UpdaterExt};
+use tracing::{info, warn};
+use url::Url;
+use crate::error::CaptureError;
+use crate::settings;
+
+/// Update manifests in the format of the Tauri updater ("latest.json"), one per channel
+const STABLE_ENDPOINT: &str = "https://github.com/Goblin-Master/scroll-snap/releases/latest/download/latest.json";
+const BETA_ENDPOINT: &str = "https://github.com/Goblin-Master/scroll-snap/releases/download/beta/latest.json";
+/// The startup check waits a bit, it's not worth slowing the launch down for
+const STARTUP_DELAY: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// `update-download-progress` is sent at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    /// The update found by the last check, with its package once downloaded and verified
+    static ref PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+}
+
+struct Pending {
+    update: Update,
+    package: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Pre-releases, stitching fixes land here first
+    Beta,
+}
+
+/// Where and when to look for new versions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    pub channel: UpdateChannel,
+    pub check_on_startup: bool,
+    /// Manifest URL instead of the channel's, e.g. for a company mirror
+    pub endpoint: Option<String>,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self { channel: UpdateChannel::Stable, check_on_startup: true, endpoint: None }
+    }
+}
+
+/// A newer version, the result of `check_for_update` and payload of the `update-changelog` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    pub channel: UpdateChannel,
+    /// Release notes, usually Markdown
+    pub notes: String,
+    /// RFC 3339
+    pub date: Option<String>,
+}
+
+/// Payload of `update-download-progress`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress {
+    downloaded: u64,
+    /// `None` when the server doesn't say
+    total: Option<u64>,
+}
+
+/// Check for a new version once after startup, if enabled. Called once from `setup`.
+pub fn start(app: &AppHandle) {
+    if !settings::current().updates.check_on_startup {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(STARTUP_DELAY).await;
+        if let Err(e) = check_for_update(app).await {
+            warn!("Update check failed: {}", e);
+        }
+    });
+}
+
+/// Look for a version newer than this one on the configured channel. When there is one it is
+/// returned and its release notes are sent as `update-changelog`, `None` when up to date.
+/// `download_update` fetches the version found.
+#[tauri::command]
+pub async fn check_for_update(app: AppHandle) -> Result<Option<UpdateInfo>, CaptureError> {
+    let updates = settings::current().updates;
+    let endpoint = updates.endpoint.clone().unwrap_or_else(|| {
+        match updates.channel {
+            UpdateChannel::Stable => STABLE_ENDPOINT,
+            UpdateChannel::Beta => BETA_ENDPOINT,
+        }
+        .to_string()
+    });
+    let endpoint = Url::parse(&endpoint)
+        .map_err(|e| CaptureError::InvalidState(format!("invalid update endpoint '{}': {}", endpoint, e)))?;
+
+    let found = app
+        .updater_builder()
+        .endpoints(vec![endpoint])
+        .and_then(|builder| builder.timeout(REQUEST_TIMEOUT).build())
+        .map_err(|e| CaptureError::Internal(format!("update check failed: {}", e)))?
+        .check()
+        .await
+        .map_err(|e| CaptureError::Internal(format!("update check failed: {}", e)))?;
+    let Some(update) = found else {
+        info!("No update on the {:?} channel ({} is the latest)", updates.channel, app.package_info().version);
+        *PENDING.lock().unwrap() = None;
+        return Ok(None);
+    };
+
+    let info = UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        channel: updates.channel,
+        notes: update.body.clone().unwrap_or_default(),
+        date: update.raw_json.get("pub_date").and_then(|date| date.as_str()).map(str::to_string),
+    };
+    info!("Update available on the {:?} channel: {} -> {}", info.channel, info.current_version, info.version);
+    *PENDING.lock().unwrap() = Some(Pending { update, package: None });
+    let _ = app.emit("update-changelog", &info);
+    Ok(Some(info))
+}
+
+/// Download the version `check_for_update` found and verify its signature against the
+/// public key in `tauri.conf.json`, sending `update-download-progress` on the way.
+/// `install_update` installs it.
+#[tauri::command]
+pub async fn download_update(app: AppHandle) -> Result<(), CaptureError> {
+    let update = PENDING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|pending| pending.update.clone())
+        .ok_or_else(|| CaptureError::InvalidState("there is no update to download, check for one first".to_string()))?;
+    if signing_key(&app).is_none() {
+        return Err(CaptureError::InvalidState("this build has no update signing key, updates can't be verified".to_string()));
+    }
+
+    let mut downloaded = 0u64;
+    let mut last_progress: Option<Instant> = None;
+    let package = update
+        .download(
+            |chunk, total| {
+                downloaded += chunk as u64;
+                if last_progress.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL) {
+                    last_progress = Some(Instant::now());
+                    let _ = app.emit("update-download-progress", DownloadProgress { downloaded, total });
+                }
+            },
+            || {},
+        )
+        .await
+        .map_err(|e| CaptureError::Internal(format!("update download failed: {}", e)))?;
+
+    info!("Downloaded and verified version {} ({} bytes)", update.version, package.len());
+    let mut pending = PENDING.lock().unwrap();
+    match pending.as_mut() {
+        Some(pending) if pending.update.version == update.version => pending.package = Some(package),
+        // A check in the meantime found another version
+        _ => return Err(CaptureError::InvalidState("the update changed during the download, try again".to_string())),
+    }
+    Ok(())
+}
+
+/// Install the package `download_update` verified and restart into the new version.
+/// On Windows the installer takes over and closes the app itself.
+#[tauri::command]
+pub async fn install_update(app: AppHandle) -> Result<(), CaptureError> {
+    let Some(Pending { update, package: Some(package) }) = PENDING.lock().unwrap().take() else {
+        return Err(CaptureError::InvalidState("there is no downloaded update to install".to_string()));
+    };
+    info!("Installing version {}", update.version);
+    tauri::async_runtime::spawn_blocking(move || update.install(package))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("install task failed: {}", e)))?
+        .map_err(|e| CaptureError::Internal(format!("installing the update failed: {}", e)))?;
+    app.restart()
+}
+
+/// The minisign public key releases are checked against, `None` when the build has none
+fn signing_key(app: &AppHandle) -> Option<String> {
+    app.config()
+        .plugins
+        .0
+        .get("updater")
+        .and_then(|updater| updater.get("pubkey"))
+        .and_then(|pubkey| pubkey.as_str())
+        .filter(|pubkey| !pubkey.trim().is_empty())
+        .map(str::to_string)
+}
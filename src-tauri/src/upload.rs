@@ -0,0 +1,605 @@
+use arboard::Clipboard;
+use chrono::{Local, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, LOCATION};
+use reqwest::multipart::{Form, Part};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::process::Command;
+use std::time::Duration;
+use tauri::AppHandle;
+use tracing::info;
+use url::Url;
+use uuid::Uuid;
+use crate::clipboard;
+use crate::error::CaptureError;
+use crate::exporter::{self, Exporter};
+use crate::hook;
+use crate::store;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Per `ssh` and `scp` run, covers slow connections and large captures
+const SCP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where an uploaded capture should go.
+/// The frontend sends this as `{ "type": "imgur", ... }` etc.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UploadTarget {
+    /// Anonymous Imgur upload, only needs the public Client-ID of a registered app
+    Imgur {
+        client_id: String,
+    },
+    /// Any S3-compatible bucket (AWS, MinIO, R2, ...)
+    S3 {
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        /// Custom endpoint for S3-compatible services. Uses path-style addressing when set.
+        endpoint: Option<String>,
+        /// Prepended to the generated object name, e.g. "screenshots/"
+        key_prefix: Option<String>,
+        /// Public base URL (CDN, bucket website) used to build the share link
+        public_url: Option<String>,
+    },
+    /// Generic HTTP endpoint accepting the PNG either as raw body or as a multipart field
+    Http {
+        url: String,
+        #[serde(default)]
+        method: HttpMethod,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// If set, the image is sent as multipart/form-data under this field name
+        form_field: Option<String>,
+        /// Dot separated path to the share URL in a JSON response, e.g. "data.link".
+        /// Falls back to the Location header, then to the plain response body.
+        url_json_path: Option<String>,
+    },
+    /// WebDAV server, e.g. Nextcloud or ownCloud
+    Webdav {
+        /// WebDAV root of the user's files, e.g. "https://cloud.example.com/remote.php/dav/files/alice"
+        url: String,
+        username: String,
+        /// Better an app password than the account password
+        password: String,
+        /// Where the file goes below `url`, see `render_path`. Missing folders are created.
+        #[serde(default = "default_webdav_path")]
+        path: String,
+        /// Create a public share link (Nextcloud and ownCloud) and return it instead of the file URL
+        #[serde(default)]
+        share: bool,
+    },
+    /// Own server over SSH, through the system's OpenSSH client (`scp` speaks SFTP since OpenSSH 9).
+    /// Key authentication only: the key file, the SSH agent or `~/.ssh/config`, there is no password prompt.
+    Scp {
+        host: String,
+        port: Option<u16>,
+        user: Option<String>,
+        /// Private key file
+        identity_file: Option<String>,
+        /// Absolute path on the server, see `render_path`. Missing folders are created.
+        remote_path: String,
+        /// Link to return, with the same placeholders, e.g. "https://example.com/shots/{name}"
+        url: String,
+    },
+}
+
+fn default_webdav_path() -> String {
+    "ScrollSnap/{name}".to_string()
+}
+
+/// Fill in `{date}` (2024-01-31), `{time}` (235959) and `{name}` (the generated file name)
+fn render_path(template: &str, name: &str) -> String {
+    let now = Local::now();
+    template
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H%M%S").to_string())
+        .replace("{name}", name)
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Put,
+    #[default]
+    Post,
+}
+
+/// Upload the capture `id` as PNG and return the share URL
+#[tauri::command]
+pub async fn upload_image(id: String, target: UploadTarget, copy_url: bool) -> Result<String, CaptureError> {
+    let png = store::edit(id, |canvas| canvas.encode_png(|_| {})).await?;
+    let url = upload(png, target).await?;
+
+    if copy_url {
+        let mut clipboard = Clipboard::new().map_err(|e| CaptureError::ClipboardBusy(e.to_string()))?;
+        clipboard.set_text(url.clone()).map_err(|e| CaptureError::ClipboardBusy(e.to_string()))?;
+    }
+
+    Ok(url)
+}
+
+/// Upload PNG bytes and return the share URL
+pub async fn upload(bytes: Vec<u8>, target: UploadTarget) -> Result<String, CaptureError> {
+    info!("Uploading capture ({} bytes)", bytes.len());
+
+    let url = match target {
+        UploadTarget::Imgur { client_id } => upload_imgur(&client_id, bytes).await?,
+        UploadTarget::S3 { bucket, region, access_key, secret_key, endpoint, key_prefix, public_url } => {
+            let key = format!("{}{}", key_prefix.unwrap_or_default(), default_object_name());
+            let config = S3Config { bucket, region, access_key, secret_key, endpoint };
+            upload_s3(&config, &key, bytes, public_url.as_deref()).await?
+        }
+        UploadTarget::Http { url, method, headers, form_field, url_json_path } => {
+            upload_http(&url, method, &headers, form_field.as_deref(), url_json_path.as_deref(), bytes).await?
+        }
+        UploadTarget::Webdav { url, username, password, path, share } => {
+            let path = render_path(&path, &default_object_name());
+            let config = WebdavConfig { url, username, password };
+            upload_webdav(&config, path.trim_matches('/'), bytes, share).await?
+        }
+        UploadTarget::Scp { host, port, user, identity_file, remote_path, url } => {
+            let name = default_object_name();
+            let (remote_path, url) = (render_path(&remote_path, &name), render_path(&url, &name));
+            let config = ScpConfig { host, port, user, identity_file };
+            tauri::async_runtime::spawn_blocking(move || upload_scp(&config, &remote_path, &bytes))
+                .await
+                .map_err(|e| CaptureError::Internal(format!("scp task failed: {}", e)))??;
+            url
+        }
+    };
+
+    info!("Upload finished: {}", url);
+    Ok(url)
+}
+
+/// Options of the "upload" exporter, the target as for `upload_image`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadExportOptions {
+    #[serde(flatten)]
+    pub target: UploadTarget,
+    #[serde(default)]
+    pub copy_url: bool,
+}
+
+/// The "upload" exporter, uploads like `upload_image`
+pub struct UploadExporter;
+
+impl Exporter for UploadExporter {
+    fn name(&self) -> &'static str {
+        "upload"
+    }
+
+    fn mime(&self) -> &'static str {
+        "image/png"
+    }
+
+    fn export(&self, _app: &AppHandle, id: &str, options: &Value) -> Result<String, CaptureError> {
+        let options: UploadExportOptions = exporter::parse_options(self.name(), options)?;
+        let png = store::with_capture(id, |canvas| canvas.encode_png(|_| {}))?;
+        let url = tauri::async_runtime::block_on(upload(png, options.target))?;
+        if options.copy_url {
+            clipboard::set_text(url.clone())?;
+        }
+        Ok(url)
+    }
+}
+
+fn default_object_name() -> String {
+    format!("scroll-snap-{}.png", Utc::now().format("%Y%m%d-%H%M%S"))
+}
+
+async fn upload_imgur(client_id: &str, bytes: Vec<u8>) -> Result<String, CaptureError> {
+    let part = Part::bytes(bytes)
+        .file_name("capture.png")
+        .mime_str("image/png")
+        .map_err(|e| CaptureError::Internal(e.to_string()))?;
+    let form = Form::new().part("image", part).text("type", "file");
+
+    let response = reqwest::Client::new()
+        .post("https://api.imgur.com/3/image")
+        .header("Authorization", format!("Client-ID {}", client_id))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| CaptureError::UploadFailed(format!("Imgur: {}", e)))?;
+
+    let status = response.status();
+    let body: serde_json::Value = response.json().await
+        .map_err(|e| CaptureError::UploadFailed(format!("invalid Imgur response: {}", e)))?;
+
+    if !status.is_success() {
+        return Err(CaptureError::UploadFailed(format!("Imgur returned {}: {}", status, body)));
+    }
+
+    json_path(&body, "data.link")
+        .ok_or_else(|| CaptureError::UploadFailed("Imgur response did not contain a link".to_string()))
+}
+
+/// Bucket and credentials of an `UploadTarget::S3`
+struct S3Config {
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    endpoint: Option<String>,
+}
+
+/// Where an S3 object goes: the host and path the request is signed for, and the full URL
+struct S3Object {
+    host: String,
+    path: String,
+    url: String,
+}
+
+/// Upload with a single AWS Signature V4 signed PUT request
+async fn upload_s3(config: &S3Config, key: &str, bytes: Vec<u8>, public_url: Option<&str>) -> Result<String, CaptureError> {
+    let encoded_key = uri_encode_path(key);
+    let object = s3_object(config, &encoded_key)?;
+
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(&bytes));
+
+    let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = canonical_request(&object, &payload_hash, &amz_date, signed_headers);
+
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let k_date = hmac_sha256(format!("AWS4{}", config.secret_key).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, config.region.as_bytes());
+    let k_service = hmac_sha256(&k_region, b"s3");
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key, scope, signed_headers, signature
+    );
+
+    let response = reqwest::Client::new()
+        .put(&object.url)
+        .header(CONTENT_TYPE, "image/png")
+        .header("x-amz-content-sha256", &payload_hash)
+        .header("x-amz-date", &amz_date)
+        .header("Authorization", authorization)
+        .body(bytes)
+        .send()
+        .await
+        .map_err(|e| CaptureError::UploadFailed(format!("S3: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(CaptureError::UploadFailed(format!("S3 returned {}: {}", status, body)));
+    }
+
+    Ok(match public_url {
+        Some(base) => format!("{}/{}", base.trim_end_matches('/'), encoded_key),
+        None => object.url,
+    })
+}
+
+/// Host, path and URL of the object at `encoded_key`
+fn s3_object(config: &S3Config, encoded_key: &str) -> Result<S3Object, CaptureError> {
+    // Custom endpoints (MinIO etc.) usually only support path-style addressing
+    Ok(match &config.endpoint {
+        Some(endpoint) => {
+            let url = Url::parse(endpoint)
+                .map_err(|e| CaptureError::UploadFailed(format!("invalid S3 endpoint '{}': {}", endpoint, e)))?;
+            let Some(host) = url.host_str().filter(|_| matches!(url.scheme(), "http" | "https")) else {
+                return Err(CaptureError::UploadFailed(format!("invalid S3 endpoint '{}', expected an http(s) URL", endpoint)));
+            };
+            // Signed like reqwest sends it: the port only when it isn't the scheme's default
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            // Endpoints behind a reverse proxy may have a path of their own
+            let path = format!("{}/{}/{}", url.path().trim_end_matches('/'), config.bucket, encoded_key);
+            let url = format!("{}://{}{}", url.scheme(), host, path);
+            S3Object { host, path, url }
+        }
+        None => {
+            let host = format!("{}.s3.{}.amazonaws.com", config.bucket, config.region);
+            let path = format!("/{}", encoded_key);
+            let url = format!("https://{}{}", host, path);
+            S3Object { host, path, url }
+        }
+    })
+}
+
+/// The canonical request of Signature V4 for a PUT of `object`
+fn canonical_request(object: &S3Object, payload_hash: &str, amz_date: &str, signed_headers: &str) -> String {
+    format!(
+        "PUT\n{}\n\ncontent-type:image/png\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        object.path, object.host, payload_hash, amz_date, signed_headers, payload_hash
+    )
+}
+
+async fn upload_http(
+    url: &str,
+    method: HttpMethod,
+    headers: &HashMap<String, String>,
+    form_field: Option<&str>,
+    url_json_path: Option<&str>,
+    bytes: Vec<u8>,
+) -> Result<String, CaptureError> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| CaptureError::UploadFailed(format!("invalid header name '{}': {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| CaptureError::UploadFailed(format!("invalid header value for '{}': {}", name, e)))?;
+        header_map.insert(name, value);
+    }
+
+    let client = reqwest::Client::new();
+    let request = match method {
+        HttpMethod::Put => client.put(url),
+        HttpMethod::Post => client.post(url),
+    }.headers(header_map);
+
+    let request = match form_field {
+        Some(field) => {
+            let part = Part::bytes(bytes)
+                .file_name(default_object_name())
+                .mime_str("image/png")
+                .map_err(|e| CaptureError::Internal(e.to_string()))?;
+            request.multipart(Form::new().part(field.to_string(), part))
+        }
+        None => request.header(CONTENT_TYPE, "image/png").body(bytes),
+    };
+
+    let response = request.send().await.map_err(|e| CaptureError::UploadFailed(e.to_string()))?;
+    let status = response.status();
+    let location = response.headers().get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let body = response.text().await.unwrap_or_default();
+
+    if !status.is_success() {
+        return Err(CaptureError::UploadFailed(format!("server returned {}: {}", status, body)));
+    }
+
+    if let Some(path) = url_json_path {
+        let json: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| CaptureError::UploadFailed(format!("response is not JSON: {}", e)))?;
+        return json_path(&json, path)
+            .ok_or_else(|| CaptureError::UploadFailed(format!("response has no string at '{}'", path)));
+    }
+
+    if let Some(location) = location {
+        return Ok(location);
+    }
+
+    let body = body.trim();
+    if body.starts_with("http://") || body.starts_with("https://") {
+        return Ok(body.to_string());
+    }
+
+    Err(CaptureError::UploadFailed("response did not contain a URL".to_string()))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, so this can't fail
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Server and login of an `UploadTarget::Webdav`
+struct WebdavConfig {
+    url: String,
+    username: String,
+    password: String,
+}
+
+/// PUT the file after creating its folders, then share it through the OCS API if asked to
+async fn upload_webdav(config: &WebdavConfig, path: &str, bytes: Vec<u8>, share: bool) -> Result<String, CaptureError> {
+    let client = reqwest::Client::new();
+    let root = config.url.trim_end_matches('/');
+    let mkcol = reqwest::Method::from_bytes(b"MKCOL").unwrap();
+
+    // MKCOL only creates one level, and answers 405 for folders that already exist
+    let folders: Vec<&str> = path.split('/').collect();
+    for depth in 1..folders.len() {
+        let folder = folders[..depth].join("/");
+        let response = client
+            .request(mkcol.clone(), format!("{}/{}", root, uri_encode_path(&folder)))
+            .basic_auth(&config.username, Some(&config.password))
+            .send()
+            .await
+            .map_err(|e| CaptureError::UploadFailed(format!("WebDAV: {}", e)))?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+            return Err(CaptureError::UploadFailed(format!("WebDAV could not create folder {}: {}", folder, status)));
+        }
+    }
+
+    let file_url = format!("{}/{}", root, uri_encode_path(path));
+    let response = client
+        .put(&file_url)
+        .basic_auth(&config.username, Some(&config.password))
+        .header(CONTENT_TYPE, "image/png")
+        .body(bytes)
+        .send()
+        .await
+        .map_err(|e| CaptureError::UploadFailed(format!("WebDAV: {}", e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(CaptureError::UploadFailed(format!("WebDAV returned {}: {}", status, text)));
+    }
+    if !share {
+        return Ok(file_url);
+    }
+
+    // The OCS API sits next to the DAV endpoint, paths there are relative to the user's files
+    let server = root.split("/remote.php/").next().filter(|server| *server != root).ok_or_else(|| {
+        CaptureError::UploadFailed("public links need a Nextcloud or ownCloud URL with /remote.php/ in it".to_string())
+    })?;
+    let response = client
+        .post(format!("{}/ocs/v2.php/apps/files_sharing/api/v1/shares?format=json", server))
+        .basic_auth(&config.username, Some(&config.password))
+        .header("OCS-APIRequest", "true")
+        .form(&[("path", format!("/{}", path)), ("shareType", "3".to_string())])
+        .send()
+        .await
+        .map_err(|e| CaptureError::UploadFailed(format!("sharing failed: {}", e)))?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().await
+        .map_err(|e| CaptureError::UploadFailed(format!("invalid share response: {}", e)))?;
+    if !status.is_success() {
+        return Err(CaptureError::UploadFailed(format!("sharing returned {}: {}", status, body)));
+    }
+    json_path(&body, "ocs.data.url")
+        .ok_or_else(|| CaptureError::UploadFailed("share response did not contain a link".to_string()))
+}
+
+/// Host and login of an `UploadTarget::Scp`
+struct ScpConfig {
+    host: String,
+    port: Option<u16>,
+    user: Option<String>,
+    identity_file: Option<String>,
+}
+
+impl ScpConfig {
+    fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    /// `ssh` or `scp` with the options both share. Batch mode fails instead of asking for
+    /// passwords or confirming unknown host keys, nobody would see the prompt.
+    fn command(&self, program: &str, port_flag: &str) -> Command {
+        let mut command = hook::command(program);
+        // The agent is found through this, `hook::command` doesn't pass our environment on
+        if let Ok(socket) = std::env::var("SSH_AUTH_SOCK") {
+            command.env("SSH_AUTH_SOCK", socket);
+        }
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.args([port_flag, &port.to_string()]);
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        command
+    }
+}
+
+/// Create the remote folder with `ssh mkdir -p`, then copy the file with `scp`
+fn upload_scp(config: &ScpConfig, remote_path: &str, bytes: &[u8]) -> Result<(), CaptureError> {
+    if !remote_path.starts_with('/') {
+        return Err(CaptureError::UploadFailed(format!("the remote path '{}' must be absolute", remote_path)));
+    }
+    if let Some((folder, _)) = remote_path.rsplit_once('/').filter(|(folder, _)| !folder.is_empty()) {
+        let mut mkdir = config.command("ssh", "-p");
+        mkdir.arg(config.destination()).arg(format!("mkdir -p {}", shell_quote(folder)));
+        hook::run(&mut mkdir, &[], SCP_TIMEOUT)
+            .map_err(|e| CaptureError::UploadFailed(format!("ssh could not create {}: {}", folder, e)))?;
+    }
+
+    let local = std::env::temp_dir().join(format!("scroll-snap-upload-{}.png", &Uuid::new_v4().to_string()[..8]));
+    std::fs::write(&local, bytes)?;
+    let mut scp = config.command("scp", "-P");
+    scp.arg(&local).arg(format!("{}:{}", config.destination(), remote_path));
+    let result = hook::run(&mut scp, &[], SCP_TIMEOUT);
+    let _ = std::fs::remove_file(&local);
+    result.map_err(|e| CaptureError::UploadFailed(format!("scp: {}", e)))?;
+    Ok(())
+}
+
+/// Single-quote `value` for the remote POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Percent-encode an S3 object key, keeping '/' as the path separator
+fn uri_encode_path(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// The string at a dot separated `path` in `value`, e.g. "data.link"
+pub fn json_path(value: &serde_json::Value, path: &str) -> Option<String> {
+    path.split('.')
+        .try_fold(value, |v, key| v.get(key))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(endpoint: Option<&str>) -> S3Config {
+        S3Config {
+            bucket: "shots".to_string(),
+            region: "eu-central-1".to_string(),
+            access_key: "key".to_string(),
+            secret_key: "secret".to_string(),
+            endpoint: endpoint.map(str::to_string),
+        }
+    }
+
+    /// The `host` line of the canonical request, what the signature covers
+    fn signed_host(object: &S3Object) -> String {
+        let request = canonical_request(object, "hash", "20240131T235959Z", "host");
+        request.lines().find_map(|line| line.strip_prefix("host:")).unwrap().to_string()
+    }
+
+    #[test]
+    fn signs_the_host_reqwest_sends() {
+        let key = uri_encode_path("2024/a b.png");
+        let cases = [
+            (None, "shots.s3.eu-central-1.amazonaws.com", "/2024/a%20b.png"),
+            (Some("https://minio.example.com"), "minio.example.com", "/shots/2024/a%20b.png"),
+            (Some("http://localhost:9000"), "localhost:9000", "/shots/2024/a%20b.png"),
+            // Default ports are left out of the Host header
+            (Some("https://minio.example.com:443"), "minio.example.com", "/shots/2024/a%20b.png"),
+            (Some("https://example.com:8443/s3/"), "example.com:8443", "/s3/shots/2024/a%20b.png"),
+        ];
+        for (endpoint, host, path) in cases {
+            let object = s3_object(&config(endpoint), &key).unwrap();
+            assert_eq!(signed_host(&object), host, "{:?}", endpoint);
+            assert_eq!(object.path, path, "{:?}", endpoint);
+
+            let url = Url::parse(&object.url).unwrap();
+            let sent_host = match url.port() {
+                Some(port) => format!("{}:{}", url.host_str().unwrap(), port),
+                None => url.host_str().unwrap().to_string(),
+            };
+            assert_eq!(sent_host, host, "{:?}", endpoint);
+            assert_eq!(url.path(), path, "{:?}", endpoint);
+        }
+    }
+
+    #[test]
+    fn rejects_endpoints_that_are_not_http() {
+        for endpoint in ["ftp://example.com", "not a url", "file:///tmp"] {
+            assert!(matches!(s3_object(&config(Some(endpoint)), "a.png"), Err(CaptureError::UploadFailed(_))), "{}", endpoint);
+        }
+    }
+}
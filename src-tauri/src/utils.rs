@@ -1,43 +1,278 @@
-use arboard::Clipboard;
-use image::load_from_memory;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ExtendedColorType, ImageEncoder, Rgb, RgbImage, RgbaImage};
 use base64::{Engine as _, engine::general_purpose};
+use serde::Deserialize;
+use serde_json::Value;
 use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use uuid::Uuid;
+use tracing::info;
+use crate::decorate::{self, AvifOptions};
+use crate::error::CaptureError;
+use crate::exporter::{self, Exporter};
+use crate::history;
+use crate::hook::{self, CaptureInfo};
+use crate::metadata::{self, CaptureMetadata, Sidecar};
+use crate::optimize;
+use crate::script::{self, HookPoint};
+use crate::settings;
+use crate::store;
 
+/// Write the capture `id` to `path` and return where it ended up. Missing folders are created.
+/// A path ending in `.avif` is saved as AVIF with the quality from the export options, otherwise PNG.
+/// With `rename_on_conflict` an existing file is kept and the image goes to "name (2).png"
+/// and so on instead of replacing it. `note` is stored with the metadata, when that is embedded,
+/// in place of the one from `annotate_capture`. The history entry of the capture gets the
+/// image as saved, edits included.
 #[tauri::command]
-pub fn copy_to_clipboard(base64_image: String) -> Result<(), String> {
-    // Remove header if present
-    let b64 = base64_image.trim_start_matches("data:image/png;base64,");
-    let bytes = general_purpose::STANDARD.decode(b64)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
-        
-    let img = load_from_memory(&bytes)
-        .map_err(|e| format!("Failed to load image: {}", e))?;
-    
-    let rgba = img.to_rgba8();
-    let (w, h) = rgba.dimensions();
-    let image_data = arboard::ImageData {
-        width: w as usize,
-        height: h as usize,
-        bytes: Cow::from(rgba.into_raw()),
-    };
+pub async fn save_image(
+    app: AppHandle,
+    id: String,
+    path: String,
+    rename_on_conflict: Option<bool>,
+    note: Option<String>,
+) -> Result<String, CaptureError> {
+    tauri::async_runtime::spawn_blocking(move || save(app, id, path, rename_on_conflict, note))
+        .await
+        .map_err(|e| CaptureError::Internal(format!("save task failed: {}", e)))?
+}
+
+/// `save_image` on the calling thread, for exporters that already run off the async runtime
+fn save(app: AppHandle, id: String, path: String, rename_on_conflict: Option<bool>, note: Option<String>) -> Result<String, CaptureError> {
+    let path = write_image(&app, &id, path, rename_on_conflict, note)?;
+    history::save_capture_in_background(app, id, "capture");
+    Ok(path)
+}
+
+/// `save_image` without updating the history, for copies the pipeline exports
+pub fn write_image(
+    app: &AppHandle,
+    id: &str,
+    path: String,
+    rename_on_conflict: Option<bool>,
+    note: Option<String>,
+) -> Result<String, CaptureError> {
+    let is_avif = Path::new(&path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("avif"));
+
+    // Export options, scripts and AVIF need the whole image, otherwise the canvas is encoded as is
+    let export = settings::current().export;
+    let scripts = script::has_scripts(HookPoint::PreExport);
+    let capture_metadata = store::metadata(id)?
+        .map(|capture_metadata| CaptureMetadata { note: note.or(capture_metadata.note.clone()), ..capture_metadata });
+    let (mut bytes, width, height, segments) = store::with_capture(id, |canvas| {
+        let segments = canvas.segments().to_vec();
+        if !export.is_enabled() && !is_avif && !scripts {
+            return Ok((canvas.encode_png(|_| {})?, canvas.width(), canvas.height(), segments));
+        }
+        let mut img = canvas.load_image()?;
+        if export.is_enabled() {
+            img = decorate::apply(img, &export)?;
+        }
+        if scripts {
+            img = script::run(app, HookPoint::PreExport, &img, capture_metadata.as_ref()).unwrap_or(img);
+        }
+        let bytes = if is_avif { encode_avif(&img, &export.avif)? } else { encode_png(&img, |_| {})? };
+        Ok((bytes, img.width(), img.height(), segments))
+    })?;
+
+    // Metadata goes into PNG text chunks, AVIF files only get the sidecar
+    if export.embed_metadata && !is_avif {
+        if let Some(capture_metadata) = &capture_metadata {
+            bytes = metadata::embed(&bytes, capture_metadata)?;
+        }
+    }
+
+    let mut target = PathBuf::from(&path);
+    if rename_on_conflict.unwrap_or(false) {
+        target = unique_path(&target);
+    }
+    write_atomic(&target, &bytes)?;
+    let path = target.display().to_string();
+    info!("Saved capture to {}", path);
+
+    if export.sidecar {
+        let sidecar = Sidecar {
+            image: target.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            width,
+            height,
+            metadata: capture_metadata,
+            segments,
+            export: export.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&sidecar).map_err(|e| CaptureError::Internal(e.to_string()))?;
+        write_atomic(&target.with_extension("json"), &json)?;
+    }
     
-    let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
-    clipboard.set_image(image_data).map_err(|e| e.to_string())?;
+    // With optimizing, the hook runs on the final file once that's done
+    let hook_config = settings::current().post_capture_hook;
+    let info = CaptureInfo { path: path.clone(), width, height, bytes: bytes.len() as u64 };
+    if export.optimize_png && !is_avif {
+        let app = app.clone();
+        optimize::optimize_in_background(app.clone(), target, bytes, export.optimize_level, move |bytes| {
+            if let Some(hook_config) = hook_config {
+                hook::spawn_post_capture_hook(app, hook_config, CaptureInfo { bytes, ..info });
+            }
+        });
+    } else if let Some(hook_config) = hook_config {
+        hook::spawn_post_capture_hook(app.clone(), hook_config, info);
+    }
     
+    Ok(path)
+}
+
+/// Options of the "file" exporter, like the arguments of `save_image`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FileExportOptions {
+    pub path: String,
+    pub rename_on_conflict: bool,
+}
+
+/// The "file" exporter, saves like `save_image`
+pub struct FileExporter;
+
+impl Exporter for FileExporter {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn mime(&self) -> &'static str {
+        "image/png"
+    }
+
+    fn export(&self, app: &AppHandle, id: &str, options: &Value) -> Result<String, CaptureError> {
+        let options: FileExportOptions = exporter::parse_options(self.name(), options)?;
+        if options.path.trim().is_empty() {
+            return Err(CaptureError::InvalidState("the file exporter needs a path".to_string()));
+        }
+        save(app.clone(), id.to_string(), options.path, Some(options.rename_on_conflict), None)
+    }
+}
+
+/// Write through a temp file in the same folder and rename it into place, so a crash or a
+/// full disk never leaves a truncated image behind (or destroys the one being replaced)
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), CaptureError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = dir.join(format!(".{}.{}.tmp", name, &Uuid::new_v4().to_string()[..8]));
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
     Ok(())
 }
 
-#[tauri::command]
-pub fn save_image(path: String, base64_image: String) -> Result<(), String> {
-    use std::fs::File;
-    use std::io::Write;
-    
+/// `path`, or the first of "name (2).ext", "name (3).ext", ... that doesn't exist yet
+pub fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("ran out of file names")
+}
+
+/// Encode as AVIF. Far smaller than PNG for long text-heavy captures while looking the same.
+pub fn encode_avif(img: &RgbaImage, options: &AvifOptions) -> Result<Vec<u8>, CaptureError> {
+    let mut out = Vec::new();
+    AvifEncoder::new_with_speed_quality(&mut out, options.speed, options.quality)
+        .write_image(img.as_raw(), img.width(), img.height(), ExtendedColorType::Rgba8)
+        .map_err(|e| CaptureError::EncodeFailed(format!("AVIF: {}", e)))?;
+    Ok(out)
+}
+
+/// Decode a base64 PNG, with or without the data URL header, e.g. screenshots from DevTools
+pub fn decode_base64_image(base64_image: &str) -> Result<Vec<u8>, CaptureError> {
+    // Remove header if present
     let b64 = base64_image.trim_start_matches("data:image/png;base64,");
-    let bytes = general_purpose::STANDARD.decode(b64)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
-        
-    let mut file = File::create(path).map_err(|e| e.to_string())?;
-    file.write_all(&bytes).map_err(|e| e.to_string())?;
-    
-    Ok(())
+    general_purpose::STANDARD.decode(b64)
+        .map_err(|e| CaptureError::DecodeFailed(format!("invalid base64: {}", e)))
+}
+
+/// Wrap encoded PNG bytes in a data URL for the webview
+pub fn png_data_url(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png))
+}
+
+/// Wrap encoded JPEG bytes in a data URL for the webview
+pub fn jpeg_data_url(jpeg: &[u8]) -> String {
+    format!("data:image/jpeg;base64,{}", general_purpose::STANDARD.encode(jpeg))
+}
+
+/// Encode as JPEG for previews, transparent pixels are put on white
+pub fn encode_jpeg(img: &RgbaImage, quality: u8) -> Result<Vec<u8>, CaptureError> {
+    let rgb = RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    });
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, quality)
+        .write_image(rgb.as_raw(), rgb.width(), rgb.height(), ExtendedColorType::Rgb8)
+        .map_err(|e| CaptureError::EncodeFailed(format!("JPEG: {}", e)))?;
+    Ok(out)
+}
+
+/// Encode an RGBA image as PNG, reporting progress in percent as rows are written
+pub fn encode_png(img: &RgbaImage, on_progress: impl FnMut(u32)) -> Result<Vec<u8>, CaptureError> {
+    let (width, height) = img.dimensions();
+    let block_bytes = (width as usize * 4 * 256).max(1);
+    let blocks = img.as_raw().chunks(block_bytes).map(|block| Ok(Cow::Borrowed(block)));
+    encode_png_blocks(width, height, blocks, on_progress)
+}
+
+/// Encode raw RGBA rows, supplied in blocks of whole rows, as PNG.
+/// Uses the png crate directly with fast compression: captures are tens of thousands of pixels tall
+/// and mostly flat UI, where the default settings spend seconds for a few percent smaller files.
+pub fn encode_png_blocks<'a>(
+    width: u32,
+    height: u32,
+    blocks: impl Iterator<Item = Result<Cow<'a, [u8]>, CaptureError>>,
+    mut on_progress: impl FnMut(u32),
+) -> Result<Vec<u8>, CaptureError> {
+    let err = |e: png::EncodingError| CaptureError::EncodeFailed(e.to_string());
+    let mut out = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Fast);
+
+    let mut writer = encoder.write_header().map_err(err)?;
+    let mut stream = writer.stream_writer().map_err(err)?;
+
+    // Report progress in between blocks
+    let total_bytes = (width as u64 * height as u64 * 4).max(1);
+    let mut written = 0u64;
+    let mut last_percent = 0;
+    for block in blocks {
+        let block = block?;
+        stream.write_all(&block).map_err(|e| CaptureError::EncodeFailed(e.to_string()))?;
+        written += block.len() as u64;
+        let percent = (written * 100 / total_bytes) as u32;
+        if percent != last_percent {
+            on_progress(percent);
+            last_percent = percent;
+        }
+    }
+
+    stream.finish().map_err(err)?;
+    writer.finish().map_err(err)?;
+    Ok(out)
 }
@@ -0,0 +1,177 @@
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+use tracing::{info, warn};
+use crate::canvas::Canvas;
+use crate::capture;
+use crate::display::{self, Rect};
+use crate::error::CaptureError;
+use crate::history::{self, HistoryEntry};
+use crate::permission;
+use crate::privacy;
+
+/// Width samples are scaled down to before comparing. Keeps sampling cheap and
+/// ignores single-pixel noise like a blinking cursor.
+const SAMPLE_WIDTH: u32 = 96;
+
+/// Per-channel difference for a sample pixel to count as changed
+const PIXEL_TOLERANCE: u8 = 24;
+
+/// Fastest a watcher may sample at, anything quicker would just be a screen recorder
+const MIN_INTERVAL_MS: u64 = 1000;
+
+lazy_static! {
+    // Stop flags of the running watchers by id
+    static ref WATCHERS: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+/// What to do when the watched region changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatchAction {
+    /// Take a full-resolution capture into the history
+    #[default]
+    Capture,
+    /// Only fire the `region-changed` event
+    Event,
+}
+
+/// Payload of the `region-changed` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionChanged {
+    pub watch_id: String,
+    pub change_percent: f32,
+    /// Set when the action is `capture`
+    pub entry: Option<HistoryEntry>,
+}
+
+/// Sample `region` (physical pixels, defaults to the last capture region) every `interval_ms`
+/// and react once more than `threshold_percent` of it changed since the last sample.
+/// Samples go through the privacy guard, `allow_sensitive` as for `start_scroll_capture`.
+/// Returns the watcher id for `unwatch_region`.
+#[tauri::command]
+pub fn watch_region(
+    app: AppHandle,
+    region: Option<Rect>,
+    interval_ms: Option<u64>,
+    threshold_percent: Option<f32>,
+    action: Option<WatchAction>,
+    allow_sensitive: Option<bool>,
+) -> Result<String, CaptureError> {
+    permission::ensure_capture_permission()?;
+    let region = match region {
+        Some(region) => region,
+        None => capture::last_region().ok_or(CaptureError::NoPreviousRegion)?,
+    };
+    let region = display::validate_region(region)?;
+    // Asks now, while the UI that started the watcher is there to confirm
+    let allow_sensitive = allow_sensitive.unwrap_or(false);
+    privacy::check(&region, allow_sensitive)?;
+    let interval = Duration::from_millis(interval_ms.unwrap_or(5000).max(MIN_INTERVAL_MS));
+    let threshold = threshold_percent.unwrap_or(1.0);
+    let action = action.unwrap_or_default();
+
+    let id = Uuid::new_v4().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    WATCHERS.lock().unwrap().insert(id.clone(), stop.clone());
+    info!("Watching region {:?} every {:?} (threshold {}%, id {})", region, interval, threshold, id);
+
+    let watch_id = id.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut previous: Option<RgbaImage> = None;
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let result = tauri::async_runtime::spawn_blocking(move || -> Result<_, CaptureError> {
+                // Sensitive windows may have been opened over the region since
+                let image = capture::capture_checked(&region, allow_sensitive)?;
+                Ok((sample(&image), image))
+            })
+            .await
+            .map_err(|e| CaptureError::Internal(format!("watch task failed: {}", e)))
+            .and_then(|result| result);
+
+            let (current, image) = match result {
+                Ok(sampled) => sampled,
+                Err(e) => {
+                    // Usually temporary (screen locked, display asleep), keep watching
+                    warn!("Watcher {} could not sample: {}", watch_id, e);
+                    continue;
+                }
+            };
+
+            let change = previous.as_ref().map_or(0.0, |previous| change_percent(previous, &current));
+            previous = Some(current);
+            if change <= threshold {
+                continue;
+            }
+
+            info!("Watched region changed by {:.2}%", change);
+            let entry = match action {
+                WatchAction::Capture => {
+                    let app = app.clone();
+                    let saved = tauri::async_runtime::spawn_blocking(move || {
+                        history::save(&app, &mut Canvas::new(&image), "watch")
+                    })
+                    .await;
+                    match saved {
+                        Ok(Ok(entry)) => Some(entry),
+                        Ok(Err(e)) => {
+                            warn!("Watcher {} could not save the capture: {}", watch_id, e);
+                            None
+                        }
+                        Err(e) => {
+                            warn!("Watcher {} save task failed: {}", watch_id, e);
+                            None
+                        }
+                    }
+                }
+                WatchAction::Event => None,
+            };
+            let _ = app.emit("region-changed", RegionChanged { watch_id: watch_id.clone(), change_percent: change, entry });
+        }
+        info!("Watcher {} stopped", watch_id);
+    });
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn unwatch_region(id: String) -> Result<(), CaptureError> {
+    let stop = WATCHERS.lock().unwrap().remove(&id)
+        .ok_or_else(|| CaptureError::InvalidState(format!("no watcher with id {}", id)))?;
+    stop.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Small copy of the image to compare
+fn sample(image: &DynamicImage) -> RgbaImage {
+    let width = image.width().clamp(1, SAMPLE_WIDTH);
+    let height = ((image.height() as u64 * width as u64 / image.width().max(1) as u64) as u32).max(1);
+    imageops::thumbnail(image, width, height)
+}
+
+/// Share of sample pixels that changed, in percent
+fn change_percent(previous: &RgbaImage, current: &RgbaImage) -> f32 {
+    if previous.dimensions() != current.dimensions() {
+        return 100.0;
+    }
+    let total = previous.pixels().len().max(1);
+    let changed = previous
+        .pixels()
+        .zip(current.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > PIXEL_TOLERANCE))
+        .count();
+    changed as f32 * 100.0 / total as f32
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+use xcap::Window;
+use crate::display::Rect;
+use crate::error::CaptureError;
+
+/// A top-level window as reported to the selection overlay, for snapping the selection to it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowRect {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+    /// Outer bounds in physical desktop pixels. On Windows without the invisible resize border.
+    pub rect: Rect,
+    /// The window without title bar and borders, on Windows only
+    pub client_rect: Option<Rect>,
+    /// Stacking order, 0 is the topmost window
+    pub z_order: u32,
+    pub is_focused: bool,
+}
+
+/// Visible top-level windows of other apps, topmost first. Minimized windows are left out.
+#[tauri::command]
+pub fn get_window_rects() -> Result<Vec<WindowRect>, CaptureError> {
+    let windows = Window::all()
+        .map_err(|e| CaptureError::CaptureFailed(format!("failed to list windows: {}", e)))?;
+
+    let own_pid = std::process::id();
+    let mut rects = Vec::new();
+    for window in windows {
+        // Windows can close while we go through them, those are skipped
+        let Ok(id) = window.id() else {
+            continue;
+        };
+        if window.pid().is_ok_and(|pid| pid == own_pid) || window.is_minimized().unwrap_or(false) {
+            continue;
+        }
+        let (Ok(x), Ok(y), Ok(width), Ok(height)) = (window.x(), window.y(), window.width(), window.height()) else {
+            continue;
+        };
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        rects.push(WindowRect {
+            id,
+            title: window.title().unwrap_or_default(),
+            app_name: window.app_name().unwrap_or_default(),
+            rect: Rect { x, y, width, height },
+            client_rect: client_rect(id),
+            z_order: rects.len() as u32,
+            is_focused: window.is_focused().unwrap_or(false),
+        });
+    }
+    Ok(rects)
+}
+
+/// Client area of the window with handle `id` in physical desktop pixels
+#[cfg(target_os = "windows")]
+fn client_rect(id: u32) -> Option<Rect> {
+    use windows::Win32::Foundation::{HWND, POINT, RECT};
+    use windows::Win32::Graphics::Gdi::ClientToScreen;
+    use windows::Win32::UI::WindowsAndMessaging::GetClientRect;
+
+    let hwnd = HWND(id as isize as _);
+    let mut rect = RECT::default();
+    let mut origin = POINT::default();
+    unsafe {
+        GetClientRect(hwnd, &mut rect).ok()?;
+        if !ClientToScreen(hwnd, &mut origin).as_bool() {
+            return None;
+        }
+    }
+    let (width, height) = ((rect.right - rect.left).max(0) as u32, (rect.bottom - rect.top).max(0) as u32);
+    (width > 0 && height > 0).then_some(Rect { x: origin.x, y: origin.y, width, height })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn client_rect(_id: u32) -> Option<Rect> {
+    None
+}
@@ -1,16 +1,73 @@
-import { useAppStore } from './store';
+import { useEffect } from 'react';
+import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/core';
+import { useAppStore, CaptureResult } from './store';
 import { Overlay } from './components/Overlay';
 import { Editor } from './components/Editor';
 import { Camera } from 'lucide-react';
 
 function App() {
-  const { isCapturing, capturedImage, setIsCapturing } = useAppStore();
+  const { isCapturing, capture, setIsCapturing, setCapture } = useAppStore();
+
+  // Captures can also be started from a global hotkey (repeat last region),
+  // in which case the selection overlay isn't mounted to receive the result.
+  useEffect(() => {
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    if (!(window as any).__TAURI_INTERNALS__) return;
+
+    const unlisten = listen<CaptureResult>('capture-complete', (event) => {
+      setCapture(event.payload);
+      setIsCapturing(false);
+    });
+
+    return () => {
+      unlisten.then(f => f());
+    };
+  }, [setCapture, setIsCapturing]);
+
+  // A capture from a hotkey, the tray or a schedule ran into a password manager or the like,
+  // the backend holds it until the user confirms
+  useEffect(() => {
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    if (!(window as any).__TAURI_INTERNALS__) return;
+
+    const unlisten = listen<{ capture: unknown, message: string }>('privacy-confirm', async (event) => {
+      if (!confirm(`${event.payload.message}\n\nCapture anyway?`)) return;
+      try {
+        await invoke('confirm_capture', { capture: event.payload.capture });
+      } catch (e) {
+        console.error("Confirmed capture failed:", e);
+      }
+    });
+
+    return () => {
+      unlisten.then(f => f());
+    };
+  }, []);
+
+  // A capture that was still running when the app crashed left checkpoints behind
+  useEffect(() => {
+    // eslint-disable-next-line @typescript-eslint/no-explicit-any
+    if (!(window as any).__TAURI_INTERNALS__) return;
+
+    const offerRecovery = async () => {
+      const session = await invoke<{ width: number, height: number, savedAt: string } | null>('get_recoverable_session');
+      if (!session) return;
+      const savedAt = new Date(session.savedAt).toLocaleString();
+      if (confirm(`A capture (${session.width}x${session.height}) was interrupted at ${savedAt}.\n\nRecover it?`)) {
+        setCapture(await invoke<CaptureResult>('recover_session'));
+      } else {
+        await invoke('discard_recovered_session');
+      }
+    };
+    offerRecovery().catch(e => console.error("Failed to recover capture:", e));
+  }, [setCapture]);
 
   if (isCapturing) {
     return <Overlay />;
   }
 
-  if (capturedImage) {
+  if (capture) {
     return <Editor />;
   }
 
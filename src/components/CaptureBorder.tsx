@@ -0,0 +1,13 @@
+// Rendered in the click-through `capture-border` window created by the backend.
+// The backend sizes that window 4 physical pixels larger than the captured region on every
+// side, so the border width is converted to CSS pixels to stay outside the region.
+export const CaptureBorder = () => {
+  const width = 4 / (window.devicePixelRatio || 1);
+
+  return (
+    <div
+      className="fixed inset-0 border-green-500 pointer-events-none"
+      style={{ borderWidth: width, borderStyle: 'solid' }}
+    />
+  );
+};
@@ -0,0 +1,65 @@
+import { useEffect, useState } from 'react';
+import { listen } from '@tauri-apps/api/event';
+
+interface CaptureProgress {
+  height: number
+  stitchCount: number
+  scrollPercent: number | null
+}
+
+// The undo shortcut is CommandOrControl+Backspace on the backend
+const UNDO_KEYS = navigator.userAgent.includes('Mac') ? '⌘+Backspace' : 'Ctrl+Backspace';
+
+// Rendered in the `capture-hud` window the backend places next to the capture region
+export const CaptureHud = () => {
+  const [progress, setProgress] = useState<CaptureProgress>({ height: 0, stitchCount: 0, scrollPercent: null });
+  // Scroll speed feedback from the capture loop, cleared by the next successful stitch
+  const [hint, setHint] = useState<string | null>(null);
+
+  useEffect(() => {
+    const unlisteners = [
+      listen<CaptureProgress>('capture-progress', (event) => {
+        setProgress(event.payload);
+        setHint(null);
+      }),
+      listen('scroll-too-fast', () => {
+        setHint('Scrolling too fast, scroll back a little');
+      }),
+      listen('waiting-for-scroll', () => {
+        setHint('Waiting for you to scroll…');
+      }),
+      listen<{ width: number, height: number, fit: string }>('fragment-resized', (event) => {
+        const { width, height, fit } = event.payload;
+        setHint(`Screen size changed to ${width}x${height}, ${fit === 'rescaled' ? 'rescaling' : 'letterboxing'} fragments`);
+      }),
+      listen<{ action: string, scale: number, capMb: number }>('memory-pressure', (event) => {
+        const { action, scale, capMb } = event.payload;
+        setHint(action === 'downscale'
+          ? `Capture passed ${capMb} MB, continuing at 1/${scale} resolution`
+          : `Capture passed ${capMb} MB, keeping the rest on disk`);
+      }),
+    ];
+
+    return () => {
+      unlisteners.forEach(unlisten => unlisten.then(f => f()));
+    };
+  }, []);
+
+  return (
+    <div className="fixed inset-0 flex items-center gap-3 px-4 bg-zinc-900/90 text-white rounded-lg border border-zinc-700 text-sm select-none">
+      <span className="relative flex h-3 w-3">
+        <span className="animate-ping absolute inline-flex h-full w-full rounded-full bg-green-400 opacity-75"></span>
+        <span className="relative inline-flex rounded-full h-3 w-3 bg-green-500"></span>
+      </span>
+      <div className="flex flex-col">
+        <span className="font-medium">
+          {progress.height}px · {progress.stitchCount} stitches
+          {progress.scrollPercent !== null && ` · ${Math.round(progress.scrollPercent)}%`}
+        </span>
+        {hint
+          ? <span className="text-amber-400">{hint}</span>
+          : <span className="text-zinc-400">Esc to finish · {UNDO_KEYS} to undo</span>}
+      </div>
+    </div>
+  );
+};
@@ -1,38 +1,36 @@
-import { useAppStore } from '../store';
+import { useState } from 'react';
+import { useAppStore, errorMessage } from '../store';
 import { invoke } from '@tauri-apps/api/core';
 import { save } from '@tauri-apps/plugin-dialog';
-import { Download, Copy, X } from 'lucide-react';
+import { Download, Copy, Crop, Frame, X } from 'lucide-react';
+import { TiledImage } from './TiledImage';
 
 export const Editor = () => {
-  const { capturedImage, setCapturedImage } = useAppStore();
+  const { capture, setCapture } = useAppStore();
+  // Bumped after every edit so the tiles are fetched again
+  const [revision, setRevision] = useState(0);
 
-  if (!capturedImage) return null;
+  if (!capture) return null;
+  const { id } = capture;
 
   const handleCopy = async () => {
     try {
-        await invoke('copy_to_clipboard', { base64Image: capturedImage });
+        await invoke('copy_capture_to_clipboard', { id });
         alert('Copied to clipboard!');
     } catch (e) {
-        alert('Failed to copy: ' + e);
+        alert('Failed to copy: ' + errorMessage(e));
     }
   };
 
   const handleSave = async () => {
     try {
-        // Remove data:image/png;base64, prefix
-        const base64Data = capturedImage.split(',')[1];
-        
-        // Convert base64 to binary
-        const binaryString = atob(base64Data);
-        const bytes = new Uint8Array(binaryString.length);
-        for (let i = 0; i < binaryString.length; i++) {
-            bytes[i] = binaryString.charCodeAt(i);
-        }
-
         const path = await save({
             filters: [{
-                name: 'Image',
+                name: 'PNG Image',
                 extensions: ['png']
+            }, {
+                name: 'AVIF Image (smaller)',
+                extensions: ['avif']
             }],
             defaultPath: `scrollsnap-${Date.now()}.png`
         });
@@ -44,24 +42,57 @@ export const Editor = () => {
             // Let's use invoke to write? No, plugin-fs is better.
             // But wait, user only installed dialog plugin.
             // Let's add `tauri-plugin-fs` too.
-            // Or just use a custom command `save_image(path, base64)`.
+            // Or just use a custom command `save_image(id, path)`.
             // Custom command is safer if we don't want to expose full FS access.
             // But let's assume we can add FS plugin.
             // Actually, we can use the `save` API? No, `save` just returns a path.
             
             // Let's implement a simple `save_image` command in Rust to avoid setting up FS permissions complexity for now.
             // It's cleaner.
-            await invoke('save_image', { path, base64Image: capturedImage });
-            alert('Saved successfully!');
+            const savedPath = await invoke<string>('save_image', { id, path });
+            if (confirm(`Saved to ${savedPath}\n\nShow it in the folder?`)) {
+                await invoke('reveal_in_folder', { path: savedPath });
+            }
         }
     } catch (e) {
         console.error(e);
-        alert('Failed to save: ' + e);
+        alert('Failed to save: ' + errorMessage(e));
+    }
+  };
+
+  const handleAutocrop = async () => {
+    try {
+        // The full-resolution capture is kept in Rust, only the new size comes back
+        const size = await invoke<{ width: number, height: number }>('autocrop', { id });
+        setCapture({ ...capture, ...size });
+        setRevision(r => r + 1);
+    } catch (e) {
+        alert('Failed to crop: ' + errorMessage(e));
+    }
+  };
+
+  const handleFrame = async () => {
+    try {
+        const size = await invoke<{ width: number, height: number }>('frame_image', { id });
+        setCapture({ ...capture, ...size });
+        setRevision(r => r + 1);
+    } catch (e) {
+        alert('Failed to add frame: ' + errorMessage(e));
+    }
+  };
+
+  // The webview's own drag would only carry the data URL, the native one drags a real PNG file
+  const handleDragStart = async (e: React.DragEvent) => {
+    e.preventDefault();
+    try {
+        await invoke('start_drag', { id });
+    } catch (e) {
+        alert('Failed to drag: ' + errorMessage(e));
     }
   };
 
   const handleClose = () => {
-    setCapturedImage(null);
+    setCapture(null);
   };
 
   return (
@@ -75,14 +106,18 @@ export const Editor = () => {
             <button onClick={handleSave} className="p-2 hover:bg-zinc-700 rounded-md transition-colors" title="Save">
                 <Download className="w-5 h-5" />
             </button>
+            <button onClick={handleAutocrop} className="p-2 hover:bg-zinc-700 rounded-md transition-colors" title="Trim Margins">
+                <Crop className="w-5 h-5" />
+            </button>
+            <button onClick={handleFrame} className="p-2 hover:bg-zinc-700 rounded-md transition-colors" title="Add Frame">
+                <Frame className="w-5 h-5" />
+            </button>
             <button onClick={handleClose} className="p-2 hover:bg-zinc-700 rounded-md transition-colors" title="Close">
                 <X className="w-5 h-5" />
             </button>
         </div>
       </div>
-      <div className="flex-1 overflow-auto p-8 flex justify-center items-start bg-zinc-950">
-        <img src={capturedImage} alt="Captured" className="max-w-full shadow-2xl rounded-md border border-zinc-800" />
-      </div>
+      <TiledImage key={`${id}-${revision}`} id={id} width={capture.width} height={capture.height} onDragStart={handleDragStart} />
     </div>
   );
 };
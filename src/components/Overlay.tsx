@@ -2,13 +2,32 @@ import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { listen } from '@tauri-apps/api/event';
-import { useAppStore } from '../store';
+import { useAppStore, errorMessage, CaptureError, CaptureResult } from '../store';
+
+// Physical desktop pixels when it comes from the backend
+interface Rect {
+  x: number
+  y: number
+  width: number
+  height: number
+}
+
+interface WindowRect {
+  id: number
+  title: string
+  rect: Rect
+  clientRect: Rect | null
+  zOrder: number
+}
 
 export const Overlay = () => {
   const [startPos, setStartPos] = useState<{x: number, y: number, sx: number, sy: number} | null>(null);
   const [selection, setSelection] = useState<{x: number, y: number, w: number, h: number, sx: number, sy: number} | null>(null);
   const [isProcessing, setIsProcessing] = useState(false);
-  const { setCapturedImage, setIsCapturing } = useAppStore();
+  // Window under the mouse before dragging starts, a click captures it
+  const [windows, setWindows] = useState<WindowRect[]>([]);
+  const [hovered, setHovered] = useState<Rect | null>(null);
+  const { setCapture, setIsCapturing } = useAppStore();
 
   useEffect(() => {
     const initOverlay = async () => {
@@ -27,6 +46,9 @@ export const Overlay = () => {
       }
     };
     initOverlay();
+    invoke<WindowRect[]>('get_window_rects')
+      .then(setWindows)
+      .catch(e => console.warn("Window snapping unavailable:", e));
   }, []);
 
   // Listen for capture events
@@ -34,25 +56,39 @@ export const Overlay = () => {
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
     if (!(window as any).__TAURI_INTERNALS__) return;
 
-    const unlistenComplete = listen<string>('capture-complete', async (event) => {
-        console.log("Capture complete");
-        setCapturedImage(event.payload);
+    const unlistenComplete = listen<CaptureResult>('capture-complete', async (event) => {
+        console.log("Capture complete", event.payload.id);
+        setCapture(event.payload);
         setIsCapturing(false);
         await restoreWindow();
     });
 
-    const unlistenError = listen<string>('capture-error', async (event) => {
+    const unlistenError = listen<CaptureError>('capture-error', async (event) => {
         console.error("Capture error:", event.payload);
-        alert('Capture failed: ' + event.payload);
+        alert('Capture failed: ' + event.payload.message);
         setIsCapturing(false);
         await restoreWindow();
     });
 
+    const unlistenCancelled = listen('capture-cancelled', async () => {
+        console.log("Capture cancelled");
+        setIsCapturing(false);
+        await restoreWindow();
+    });
+
+    // The capture still completes with what was stitched so far, just let the user know why it ended early
+    const unlistenInterrupted = listen<{ reason: string, message: string }>('capture-interrupted', (event) => {
+        console.warn("Capture interrupted:", event.payload);
+        alert('Capture stopped early: ' + event.payload.message);
+    });
+
     return () => {
         unlistenComplete.then(f => f());
         unlistenError.then(f => f());
+        unlistenCancelled.then(f => f());
+        unlistenInterrupted.then(f => f());
     };
-  }, [setCapturedImage, setIsCapturing]);
+  }, [setCapture, setIsCapturing]);
 
   const restoreWindow = async () => {
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
@@ -73,7 +109,15 @@ export const Overlay = () => {
 
   const handleMouseMove = (e: React.MouseEvent) => {
     if (isProcessing) return;
-    if (!startPos) return;
+    if (!startPos) {
+      // Topmost window under the mouse, its client area where known
+      const dpr = window.devicePixelRatio || 1;
+      const px = e.screenX * dpr, py = e.screenY * dpr;
+      const under = windows.find(({ rect }) =>
+        px >= rect.x && px < rect.x + rect.width && py >= rect.y && py < rect.y + rect.height);
+      setHovered(under ? (under.clientRect ?? under.rect) : null);
+      return;
+    }
     
     const w = Math.abs(e.clientX - startPos.x);
     const h = Math.abs(e.clientY - startPos.y);
@@ -88,8 +132,37 @@ export const Overlay = () => {
 
   const handleMouseUp = async () => {
     if (isProcessing) return;
+    const dpr = window.devicePixelRatio || 1;
     if (selection && selection.h > 10) {
+      await startCapture({ x: selection.sx, y: selection.sy, width: selection.w, height: selection.h });
+    } else if (hovered) {
+      // A click without dragging takes the scrolling part of the window under the mouse,
+      // or all of it when nothing scrolls
       setIsProcessing(true);
+      const area = await invoke<Rect | null>('detect_scroll_area', { region: hovered })
+        .catch(e => {
+          console.error('Scroll area detection failed:', e);
+          return null;
+        }) ?? hovered;
+      setSelection({
+        x: area.x / dpr - window.screenX,
+        y: area.y / dpr - window.screenY,
+        w: area.width / dpr,
+        h: area.height / dpr,
+        sx: area.x / dpr,
+        sy: area.y / dpr,
+      });
+      await startCapture({ x: area.x / dpr, y: area.y / dpr, width: area.width / dpr, height: area.height / dpr });
+    } else {
+        setStartPos(null);
+        setSelection(null);
+    }
+  };
+
+  // `rect` is in logical (CSS) pixels of the desktop, like e.screenX/Y
+  const startCapture = async (rect: Rect) => {
+      setIsProcessing(true);
+      setHovered(null);
       
       try {
         // eslint-disable-next-line @typescript-eslint/no-explicit-any
@@ -99,36 +172,41 @@ export const Overlay = () => {
              return;
         }
         
-        // Calculate physical coordinates
+        // The backend converts to physical pixels using the scale factor of the monitor
+        // this overlay is on, which matters on mixed-DPI setups.
         const dpr = window.devicePixelRatio || 1;
         
-        // selection.sx/sy are e.screenX/Y (Logical global coordinates usually)
-        // converting them to physical is tricky across browsers/OS
-        // But multiplying by DPR is the standard way to get physical pixels from logical CSS pixels
-        
         const captureRect = {
-            x: Math.round(selection.sx * dpr),
-            y: Math.round(selection.sy * dpr),
-            width: Math.round(selection.w * dpr),
-            height: Math.round(selection.h * dpr)
+            x: Math.round(rect.x),
+            y: Math.round(rect.y),
+            width: Math.round(rect.width),
+            height: Math.round(rect.height),
+            scaleFactor: dpr
         };
         
-        console.log(`Capture Rect (Physical): ${JSON.stringify(captureRect)}, DPR: ${dpr}`);
+        console.log(`Capture Rect (Logical): ${JSON.stringify(captureRect)}`);
         
         // Call backend
         // Backend will handle hiding the window to ensure it's synced with capture start
-        await invoke('start_scroll_capture', captureRect);
+        try {
+          await invoke('start_scroll_capture', captureRect);
+        } catch (e) {
+          // The privacy guard found a password manager or the like in the region, ask before going on
+          if ((e as CaptureError)?.code !== 'SENSITIVE_WINDOW') throw e;
+          if (!confirm(`${errorMessage(e)}\n\nCapture anyway?`)) {
+            await restoreWindow();
+            setIsCapturing(false);
+            return;
+          }
+          await invoke('start_scroll_capture', { ...captureRect, allowSensitive: true });
+        }
         
       } catch (e) {
         console.error(e);
         await restoreWindow();
-        alert('启动截图失败: ' + e);
+        alert('启动截图失败: ' + errorMessage(e));
         setIsCapturing(false);
       }
-    } else {
-        setStartPos(null);
-        setSelection(null);
-    }
   };
   
   return (
@@ -164,9 +242,21 @@ export const Overlay = () => {
         </div>
       )}
       
+      {hovered && !selection && !isProcessing && (
+        <div
+            className="absolute border-2 border-dashed border-indigo-400 bg-indigo-500/10 pointer-events-none"
+            style={{
+                left: hovered.x / (window.devicePixelRatio || 1) - window.screenX,
+                top: hovered.y / (window.devicePixelRatio || 1) - window.screenY,
+                width: hovered.width / (window.devicePixelRatio || 1),
+                height: hovered.height / (window.devicePixelRatio || 1),
+            }}
+        />
+      )}
+
       {!isProcessing && (
        <div className="absolute top-4 left-1/2 -translate-x-1/2 px-4 py-2 bg-zinc-900 text-white rounded-full text-sm font-medium shadow-lg border border-zinc-700 pointer-events-none">
-        拖拽框选区域，松开鼠标开始录制
+        拖拽框选区域，松开鼠标开始录制；单击可直接选择窗口
       </div>
       )}
     </div>
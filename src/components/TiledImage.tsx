@@ -0,0 +1,113 @@
+import { useCallback, useEffect, useRef, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+
+// Rows per tile, small enough to decode quickly while scrolling
+const TILE_HEIGHT = 1024;
+// Tiles within this many screen pixels of the viewport are loaded ahead
+const OVERSCAN = 1500;
+// Matches the p-8 padding around the image
+const PADDING = 32;
+
+interface CaptureTile {
+  y: number
+  width: number
+  height: number
+  image: string
+}
+
+interface TiledImageProps {
+  id: string
+  width: number
+  height: number
+  onDragStart?: (e: React.DragEvent) => void
+}
+
+// Shows a capture as tiles fetched from Rust as they scroll into view, one data URL for a
+// capture tens of thousands of pixels tall can take the webview down.
+// Remount (change the key) after an edit to fetch the tiles again.
+export const TiledImage = ({ id, width, height, onDragStart }: TiledImageProps) => {
+  const scrollRef = useRef<HTMLDivElement>(null);
+  const requested = useRef(new Set<number>());
+  const [scale, setScale] = useState(1);
+  const [visible, setVisible] = useState<number[]>([]);
+  const [tiles, setTiles] = useState<Record<number, string>>({});
+  const tileCount = Math.ceil(height / TILE_HEIGHT);
+
+  const updateVisible = useCallback(() => {
+    const el = scrollRef.current;
+    if (!el) return;
+    const newScale = Math.min(1, Math.max(1, el.clientWidth - 2 * PADDING) / width);
+    const tileSize = TILE_HEIGHT * newScale;
+    const top = Math.max(0, el.scrollTop - PADDING - OVERSCAN);
+    const bottom = el.scrollTop - PADDING + el.clientHeight + OVERSCAN;
+    const first = Math.floor(top / tileSize);
+    const last = Math.min(tileCount - 1, Math.floor(bottom / tileSize));
+    const indices = [];
+    for (let i = first; i <= last; i++) indices.push(i);
+    setScale(newScale);
+    setVisible(indices);
+  }, [width, tileCount]);
+
+  useEffect(() => {
+    const el = scrollRef.current;
+    if (!el) return;
+    updateVisible();
+    const observer = new ResizeObserver(updateVisible);
+    observer.observe(el);
+    return () => observer.disconnect();
+  }, [updateVisible]);
+
+  useEffect(() => {
+    for (const index of visible) {
+      if (requested.current.has(index)) continue;
+      requested.current.add(index);
+      invoke<CaptureTile>('get_capture_tile', { id, y: index * TILE_HEIGHT, height: TILE_HEIGHT })
+        .then(tile => {
+          if (requested.current.has(index)) setTiles(current => ({ ...current, [index]: tile.image }));
+        })
+        .catch(e => {
+          console.error(`Failed to load tile ${index}:`, e);
+          requested.current.delete(index);
+        });
+    }
+    // Tiles far out of view are dropped again, so memory stays flat however long the capture is
+    setTiles(current => {
+      const kept: Record<number, string> = {};
+      for (const index of visible) {
+        if (current[index]) kept[index] = current[index];
+      }
+      return kept;
+    });
+    for (const index of requested.current) {
+      if (!visible.includes(index)) requested.current.delete(index);
+    }
+  }, [id, visible]);
+
+  return (
+    <div ref={scrollRef} onScroll={updateVisible} className="flex-1 overflow-auto p-8 flex justify-center items-start bg-zinc-950">
+      <div
+        draggable
+        onDragStart={onDragStart}
+        title="Drag into another app"
+        style={{ position: 'relative', width: width * scale, height: height * scale, flexShrink: 0 }}
+        className="shadow-2xl rounded-md border border-zinc-800 cursor-grab overflow-hidden bg-zinc-900"
+      >
+        {visible.filter(index => tiles[index]).map(index => (
+          <img
+            key={index}
+            src={tiles[index]}
+            alt=""
+            draggable={false}
+            style={{
+              position: 'absolute',
+              left: 0,
+              top: index * TILE_HEIGHT * scale,
+              width: width * scale,
+              height: Math.min(TILE_HEIGHT, height - index * TILE_HEIGHT) * scale,
+            }}
+          />
+        ))}
+      </div>
+    </div>
+  );
+};
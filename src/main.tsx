@@ -1,10 +1,15 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import { CaptureBorder } from "./components/CaptureBorder";
+import { CaptureHud } from "./components/CaptureHud";
 import "./index.css";
 
+// Auxiliary windows created from Rust load the same bundle with a hash route
+const route = window.location.hash;
+
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    {route === "#/border" ? <CaptureBorder /> : route === "#/hud" ? <CaptureHud /> : <App />}
   </React.StrictMode>,
 );
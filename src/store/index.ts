@@ -1,15 +1,32 @@
 import { create } from 'zustand'
 
+// Errors from Rust commands/events are serialized as { code, message }
+export interface CaptureError {
+  code: string
+  message: string
+}
+
+export const errorMessage = (e: unknown): string =>
+  typeof e === 'object' && e !== null && 'message' in e ? String((e as CaptureError).message) : String(e)
+
+// A finished capture, kept in Rust. Commands take the id, `preview` is a small JPEG data URL.
+export interface CaptureResult {
+  id: string
+  width: number
+  height: number
+  preview: string
+}
+
 interface AppState {
   isCapturing: boolean
-  capturedImage: string | null
+  capture: CaptureResult | null
   setIsCapturing: (isCapturing: boolean) => void
-  setCapturedImage: (image: string | null) => void
+  setCapture: (capture: CaptureResult | null) => void
 }
 
 export const useAppStore = create<AppState>((set) => ({
   isCapturing: false,
-  capturedImage: null,
+  capture: null,
   setIsCapturing: (isCapturing) => set({ isCapturing }),
-  setCapturedImage: (capturedImage) => set({ capturedImage }),
+  setCapture: (capture) => set({ capture }),
 }))